@@ -0,0 +1,43 @@
+use std::time::Instant;
+
+use zkevm_test_harness::sha3::{Digest, Keccak256};
+use zkevm_test_harness::witness::tree::*;
+
+// sequential vs batched insertion into the testing tree. Run with
+// `cargo bench --bench tree_batch_insert`
+fn main() {
+    for (num_leafs, num_distinct_keys) in
+        [(1000, 1000), (10000, 10000), (10000, 2000), (50000, 50000)]
+    {
+        let mut indexes = vec![];
+        let mut leafs = vec![];
+        for i in 0..num_leafs {
+            let key_preimage = ((i % num_distinct_keys) as u64).to_be_bytes();
+            let mut index = [0u8; 32];
+            index.copy_from_slice(Keccak256::digest(&key_preimage).as_slice());
+            let mut value = [0u8; 32];
+            value[24..].copy_from_slice(&(i as u64).to_be_bytes());
+
+            indexes.push(index);
+            leafs.push(ZkSyncStorageLeaf::from_value(value));
+        }
+
+        let mut sequential = ZKSyncTestingTree::empty();
+        let now = Instant::now();
+        for (index, leaf) in indexes.iter().zip(leafs.iter()) {
+            let _ = sequential.insert_leaf(index, *leaf);
+        }
+        let sequential_time = now.elapsed();
+
+        let mut batched = ZKSyncTestingTree::empty();
+        let now = Instant::now();
+        let _ = batched.insert_many_leafs_batched(&indexes, leafs);
+        let batched_time = now.elapsed();
+
+        assert_eq!(sequential.root, batched.root);
+        println!(
+            "{} leafs ({} distinct keys): sequential {:?}, batched {:?}",
+            num_leafs, num_distinct_keys, sequential_time, batched_time
+        );
+    }
+}
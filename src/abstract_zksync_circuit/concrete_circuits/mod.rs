@@ -27,6 +27,8 @@ pub mod node_aggregation;
 
 pub mod scheduler;
 
+pub mod padding;
+
 pub use self::code_decommitter::CodeDecommitterInstanceSynthesisFunction;
 pub use self::ecrecover::ECRecoverFunctionInstanceSynthesisFunction;
 pub use self::events_sort_dedup::EventsAndL1MessagesSortAndDedupInstanceSynthesisFunction;
@@ -48,6 +50,8 @@ pub use self::node_aggregation::NodeAggregationInstanceSynthesisFunction;
 
 pub use self::scheduler::SchedulerInstanceSynthesisFunction;
 
+pub use self::padding::PaddingInstanceSynthesisFunction;
+
 // Type definitions for circuits, so one can easily form circuits with witness, and their definition
 // will take care of particular synthesis function. There is already an implementation of Circuit<E> for ZkSyncUniformCircuitCircuitInstance,
 // so as soon as the structure is instantiated it is ready for proving
@@ -96,6 +100,9 @@ pub type NodeAggregationCircuit<E> =
 pub type SchedulerCircuit<E> =
     ZkSyncUniformCircuitCircuitInstance<E, SchedulerInstanceSynthesisFunction>;
 
+pub type PaddingCircuit<E> =
+    ZkSyncUniformCircuitCircuitInstance<E, PaddingInstanceSynthesisFunction>;
+
 /// NOTE: It DOES implement Circuit<E>, but one would need to load the
 /// setup for it's INNER contents somehow, so do NOT synthesise it directly
 /// unless you know what you are doing!
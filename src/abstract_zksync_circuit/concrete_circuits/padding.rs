@@ -0,0 +1,63 @@
+use derivative::*;
+
+use super::*;
+
+use sync_vm::glue::traits::GenericHasher;
+use sync_vm::rescue_poseidon::RescueParams;
+
+// Circuit that is only used to produce padding proofs for recursive aggregation.
+// It has exactly the same gates and tables as any other circuit, so it's verification key
+// has the same shape, but it's tiny. Public input is a witness value raised to the power of 2^config
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, Default(bound = ""))]
+pub struct PaddingInstanceSynthesisFunction;
+
+fn padding_circuit_entry_point<
+    E: Engine,
+    CS: ConstraintSystem<E>,
+    R: CircuitArithmeticRoundFunction<E, 2, 3, StateElement = Num<E>>,
+>(
+    cs: &mut CS,
+    witness: Option<u64>,
+    _round_function: &R,
+    num_squarings: usize,
+) -> Result<AllocatedNum<E>, SynthesisError> {
+    use crate::bellman::PrimeField;
+
+    add_all_tables(cs)?;
+
+    let value = witness.map(|el| E::Fr::from_str(&el.to_string()).unwrap());
+    let mut current = AllocatedNum::alloc(cs, || value.ok_or(SynthesisError::AssignmentMissing))?;
+    for _ in 0..num_squarings {
+        current = current.square(cs)?;
+    }
+
+    let public_input = AllocatedNum::alloc_input(cs, || {
+        current.get_value().ok_or(SynthesisError::AssignmentMissing)
+    })?;
+    Num::Variable(public_input).enforce_equal(cs, &Num::Variable(current))?;
+
+    Ok(public_input)
+}
+
+impl<E: Engine> ZkSyncUniformSynthesisFunction<E> for PaddingInstanceSynthesisFunction {
+    type Witness = u64;
+    type Config = usize;
+    type RoundFunction = GenericHasher<E, RescueParams<E, 2, 3>, 2, 3>;
+
+    fn description() -> String {
+        "Padding".to_string()
+    }
+
+    fn get_synthesis_function_dyn<'a, CS: ConstraintSystem<E> + 'a>() -> Box<
+        dyn FnOnce(
+                &mut CS,
+                Option<Self::Witness>,
+                &Self::RoundFunction,
+                Self::Config,
+            ) -> Result<AllocatedNum<E>, SynthesisError>
+            + 'a,
+    > {
+        Box::new(padding_circuit_entry_point)
+    }
+}
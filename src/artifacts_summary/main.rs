@@ -0,0 +1,48 @@
+use structopt::StructOpt;
+use zkevm_test_harness::witness::artifacts_summary::ArtifactsSummary;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "Block artifacts summary",
+    about = "Tool for printing and comparing summaries of the block queues"
+)]
+struct Opt {
+    /// Summary file, saved with `ArtifactsSummary::write_json` after the block processing.
+    #[structopt(long)]
+    summary: String,
+    /// Summary of the same block made by other harness version.
+    #[structopt(long)]
+    compare_with: Option<String>,
+}
+
+fn read_summary(path: &str) -> ArtifactsSummary {
+    let summary = ArtifactsSummary::read_json(path);
+    assert!(
+        summary.fingerprint_is_valid(),
+        "fingerprint of {} doesn't match its content",
+        path
+    );
+
+    summary
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let summary = read_summary(&opt.summary);
+    summary.print();
+
+    if let Some(other_path) = opt.compare_with {
+        let other = read_summary(&other_path);
+        let differences = summary.differences(&other);
+        if differences.is_empty() {
+            println!("Summaries are equal");
+        } else {
+            println!("Different queues:");
+            for name in differences.iter() {
+                println!("{}", name);
+            }
+            println!("fingerprint of {}: {}", other_path, other.fingerprint);
+            std::process::exit(1);
+        }
+    }
+}
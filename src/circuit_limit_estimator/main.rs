@@ -2,7 +2,9 @@ use std::fs::File;
 use std::io::Write;
 
 use structopt::StructOpt;
-use zkevm_test_harness::circuit_limit_estimator::get_circuit_capacity;
+use zkevm_test_harness::circuit_limit_estimator::{
+    get_circuit_capacity, get_storage_application_capacities, StorageApplicationCapacity,
+};
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -13,6 +15,11 @@ struct Opt {
     /// Numeric circuit type valid value from [3-17].
     #[structopt(long)]
     numeric_circuit: u8,
+    /// For storage application circuit (12) also estimate the limit for every storage tree hasher:
+    /// blake2s, blake2s_with_extra_tables and rescue. Rescue is not implemented by the circuit,
+    /// so only a lower bound estimate is printed for it and no limit file is written.
+    #[structopt(long)]
+    all_storage_tree_hashers: bool,
 }
 
 fn save_circuit_limit(limit: usize, filepath: String) {
@@ -36,4 +43,30 @@ fn main() {
         "Estimated circuit limit is {} for circuit {}",
         circuit_limit, opt.numeric_circuit
     );
+
+    if opt.numeric_circuit == 12 && opt.all_storage_tree_hashers {
+        let capacities = get_storage_application_capacities();
+        for (hasher, capacity) in capacities.iter() {
+            let name = hasher.name();
+            match capacity {
+                StorageApplicationCapacity::Limit(circuit_limit) => {
+                    save_circuit_limit(
+                        *circuit_limit,
+                        format!("circuit_limit_{}_{}.txt", opt.numeric_circuit, name),
+                    );
+                    println!(
+                        "Estimated circuit limit is {} for circuit {} with {} storage tree hasher",
+                        circuit_limit, opt.numeric_circuit, name
+                    );
+                }
+                // the circuit doesn't implement this hasher, so there is no limit to save
+                StorageApplicationCapacity::LowerBoundEstimate(cycles) => {
+                    println!(
+                        "Estimated lower bound (not a circuit limit) is {} for circuit {} with {} storage tree hasher",
+                        cycles, opt.numeric_circuit, name
+                    );
+                }
+            }
+        }
+    }
 }
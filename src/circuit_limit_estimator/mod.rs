@@ -25,18 +25,16 @@ fn ensure_cycle_within_2_26_limit(cycles: usize, gates: usize, additive: usize)
     (two_power_26 - additive) / gates
 }
 
-fn compute_inner<
+// returns costs of a single cycle and O(1) costs using the linear approximation
+fn estimate_gates<
     SF: ZkSyncUniformSynthesisFunction<
         Bn256,
         RoundFunction = GenericHasher<Bn256, RescueParams<Bn256, 2, 3>, 2, 3>,
     >,
     F: Fn(usize) -> SF::Config,
 >(
-    config_fn: F,
-    optional_circuit_limit_generation_mode_fn: Option<fn(usize) -> usize>,
-) -> usize {
-    let max = 1 << 26;
-
+    config_fn: &F,
+) -> (usize, usize) {
     let typical_sizes = vec![16, 32];
     let mut gates = vec![];
 
@@ -79,6 +77,16 @@ fn compute_inner<
 
     println!("O(1) costs = {}", additive);
 
+    (per_round_gates, additive)
+}
+
+fn cycles_for_gates(
+    per_round_gates: usize,
+    additive: usize,
+    optional_circuit_limit_generation_mode_fn: Option<fn(usize) -> usize>,
+) -> usize {
+    let max = 1 << 26;
+
     let mut cycles = (max - additive) / per_round_gates;
     cycles = ensure_cycle_within_2_26_limit(cycles, per_round_gates + 2, additive);
     match optional_circuit_limit_generation_mode_fn {
@@ -87,6 +95,26 @@ fn compute_inner<
             cycles = circuit_limit_generation_mode_fn(cycles);
         }
     }
+
+    cycles
+}
+
+fn compute_inner<
+    SF: ZkSyncUniformSynthesisFunction<
+        Bn256,
+        RoundFunction = GenericHasher<Bn256, RescueParams<Bn256, 2, 3>, 2, 3>,
+    >,
+    F: Fn(usize) -> SF::Config,
+>(
+    config_fn: F,
+    optional_circuit_limit_generation_mode_fn: Option<fn(usize) -> usize>,
+) -> usize {
+    let (per_round_gates, additive) = estimate_gates::<SF, F>(&config_fn);
+    let cycles = cycles_for_gates(
+        per_round_gates,
+        additive,
+        optional_circuit_limit_generation_mode_fn,
+    );
     println!(
         "Can fit {} cycles for circuit type {}",
         cycles,
@@ -133,10 +161,7 @@ pub fn get_circuit_capacity(circuit_type: u8) -> usize {
         9 => compute_inner::<ECRecoverFunctionInstanceSynthesisFunction, _>(|x: usize| x, None),
         10 => compute_inner::<RAMPermutationInstanceSynthesisFunction, _>(|x: usize| x, None),
         11 => compute_inner::<StorageSortAndDedupInstanceSynthesisFunction, _>(|x: usize| x, None),
-        12 => compute_inner::<StorageApplicationInstanceSynthesisFunction, _>(
-            |x: usize| (x, USE_BLAKE2S_EXTRA_TABLES),
-            None,
-        ),
+        12 => get_storage_application_capacity(StorageTreeHasher::default()),
         13 => compute_inner::<StorageInitialWritesRehasherInstanceSynthesisFunction, _>(
             |x: usize| x,
             None,
@@ -162,3 +187,169 @@ pub fn get_circuit_capacity(circuit_type: u8) -> usize {
         ),
     }
 }
+
+// Hash function of the storage tree. Storage application circuit only implements Blake2s,
+// so capacity for Rescue is derived from it by replacing the costs of leaf and node hashes.
+// Nodes are still packed into bytes there, so it's a lower bound
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageTreeHasher {
+    Blake2s,
+    Blake2sWithExtraTables,
+    Rescue,
+}
+
+impl StorageTreeHasher {
+    pub const ALL: [Self; 3] = [
+        StorageTreeHasher::Blake2s,
+        StorageTreeHasher::Blake2sWithExtraTables,
+        StorageTreeHasher::Rescue,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StorageTreeHasher::Blake2s => "blake2s",
+            StorageTreeHasher::Blake2sWithExtraTables => "blake2s_with_extra_tables",
+            StorageTreeHasher::Rescue => "rescue",
+        }
+    }
+}
+
+impl Default for StorageTreeHasher {
+    fn default() -> Self {
+        if USE_BLAKE2S_EXTRA_TABLES {
+            StorageTreeHasher::Blake2sWithExtraTables
+        } else {
+            StorageTreeHasher::Blake2s
+        }
+    }
+}
+
+// capacity of the storage application circuit. Only the limit for a hasher that is implemented
+// by the circuit is exact, otherwise it's a lower bound estimate and is not a valid circuit limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageApplicationCapacity {
+    Limit(usize),
+    LowerBoundEstimate(usize),
+}
+
+impl StorageApplicationCapacity {
+    pub fn cycles(&self) -> usize {
+        match self {
+            StorageApplicationCapacity::Limit(cycles) => *cycles,
+            StorageApplicationCapacity::LowerBoundEstimate(cycles) => *cycles,
+        }
+    }
+
+    pub fn is_estimate(&self) -> bool {
+        matches!(self, StorageApplicationCapacity::LowerBoundEstimate(_))
+    }
+}
+
+const STORAGE_TREE_DEPTH: usize = 256;
+
+fn single_hash_gates(hasher: StorageTreeHasher) -> usize {
+    use crate::bellman::plonk::better_better_cs::cs::ConstraintSystem;
+    use crate::ff::Field;
+    use crate::sync_vm::circuit_structures::traits::CircuitArithmeticRoundFunction;
+    use crate::sync_vm::franklin_crypto::plonk::circuit::allocated_num::{AllocatedNum, Num};
+    use crate::sync_vm::franklin_crypto::plonk::circuit::hashes_with_tables::blake2s::gadgets::Blake2sGadget;
+
+    let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
+
+    let mut setup_assembly = SetupAssembly::<
+        _,
+        PlonkCsWidth4WithNextStepAndCustomGatesParams,
+        SelectorOptimizedWidth4MainGateWithDNext,
+    >::new();
+
+    let (input, blake2s_gadget) = match hasher {
+        StorageTreeHasher::Blake2s | StorageTreeHasher::Blake2sWithExtraTables => {
+            // two nodes as 32-bit words
+            let gadget = Blake2sGadget::new(
+                &mut setup_assembly,
+                hasher == StorageTreeHasher::Blake2sWithExtraTables,
+            )
+            .unwrap();
+            (16, Some(gadget))
+        }
+        // two nodes as field elements
+        StorageTreeHasher::Rescue => (2, None),
+    };
+
+    let input: Vec<_> = (0..input)
+        .map(|_| {
+            Num::Variable(AllocatedNum::alloc(&mut setup_assembly, || Ok(Field::zero())).unwrap())
+        })
+        .collect();
+
+    let gates_before = setup_assembly.n();
+    match blake2s_gadget {
+        Some(gadget) => {
+            let _ = gadget.digest(&mut setup_assembly, &input).unwrap();
+        }
+        None => {
+            let _ = round_function
+                .round_function_absorb_nums_multiple_rounds(
+                    &mut setup_assembly,
+                    [Num::zero(); 3],
+                    &input,
+                )
+                .unwrap();
+        }
+    }
+
+    setup_assembly.n() - gates_before
+}
+
+pub fn get_storage_application_capacity(hasher: StorageTreeHasher) -> StorageApplicationCapacity {
+    match hasher {
+        StorageTreeHasher::Blake2s | StorageTreeHasher::Blake2sWithExtraTables => {
+            let use_extra_tables = hasher == StorageTreeHasher::Blake2sWithExtraTables;
+            let cycles = compute_inner::<StorageApplicationInstanceSynthesisFunction, _>(
+                |x: usize| (x, use_extra_tables),
+                None,
+            );
+
+            StorageApplicationCapacity::Limit(cycles)
+        }
+        StorageTreeHasher::Rescue => {
+            let (blake2s_per_round_gates, additive) = estimate_gates::<
+                StorageApplicationInstanceSynthesisFunction,
+                _,
+            >(&|x: usize| (x, false));
+            let blake2s_hash_gates = single_hash_gates(StorageTreeHasher::Blake2s);
+            let rescue_hash_gates = single_hash_gates(StorageTreeHasher::Rescue);
+            println!(
+                "Single node hash takes {} gates for Blake2s and {} gates for Rescue",
+                blake2s_hash_gates, rescue_hash_gates
+            );
+
+            // every cycle recomputes the root twice: before and after the write
+            let hashes_per_cycle = 2 * (STORAGE_TREE_DEPTH + 1);
+            assert!(blake2s_hash_gates * hashes_per_cycle < blake2s_per_round_gates);
+            let per_round_gates = blake2s_per_round_gates - blake2s_hash_gates * hashes_per_cycle
+                + rescue_hash_gates * hashes_per_cycle;
+            println!("Single cycle takes {} gates with Rescue", per_round_gates);
+
+            let cycles = cycles_for_gates(per_round_gates, additive, None);
+            println!(
+                "Estimated to fit at least {} cycles for circuit type {} with Rescue tree",
+                cycles,
+                <StorageApplicationInstanceSynthesisFunction as ZkSyncUniformSynthesisFunction<
+                    Bn256,
+                >>::description()
+            );
+
+            StorageApplicationCapacity::LowerBoundEstimate(cycles)
+        }
+    }
+}
+
+// capacity of the storage application circuit for every choice of the tree hasher
+pub fn get_storage_application_capacities() -> Vec<(StorageTreeHasher, StorageApplicationCapacity)>
+{
+    StorageTreeHasher::ALL
+        .iter()
+        .map(|hasher| (*hasher, get_storage_application_capacity(*hasher)))
+        .collect()
+}
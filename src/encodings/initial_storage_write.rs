@@ -6,8 +6,8 @@ pub trait BytesSerializable<const N: usize>: Clone {
     fn serialize(&self) -> [u8; N];
 }
 
-#[derive(Derivative)]
-#[derivative(Clone, Copy, Debug)]
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct InitialStorageWrite {
     pub key: [u8; 32],
     pub value: [u8; 32],
@@ -2,8 +2,8 @@ use super::*;
 use sync_vm::utils::compute_shifts;
 use sync_vm::vm::vm_state::saved_contract_context::scale_and_accumulate;
 
-#[derive(Derivative)]
-#[derivative(Clone, Copy, Debug)]
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct RepeatedStorageWrite {
     pub index: u64,
     pub value: [u8; 32],
@@ -235,6 +235,7 @@ pub fn create_in_circuit_global_context<E: Engine>(
 use crate::witness::oracle::VmInCircuitAuxilaryParameters;
 use crate::witness::oracle::VmInstanceWitness;
 use crate::witness::oracle::VmWitnessOracle;
+use sync_vm::vm::vm_cycle::witness_oracle::WitnessOracle;
 
 // pub fn create_in_circuit_vm<
 //     E: Engine,
@@ -315,6 +316,40 @@ pub fn run_vm_instance<
     in_circuit_global_context: &GlobalContext<E>,
     snapshot_data: VmInstanceWitness<E, VmWitnessOracle<E>>,
 ) -> sync_vm::vm::vm_state::VmGlobalState<E, 3> {
+    let final_state = snapshot_data.final_state.clone();
+    let auxilary_final_parameters = snapshot_data.auxilary_final_parameters.clone();
+
+    let (final_state_no_pending, _oracle) = run_vm_instance_with_oracle(
+        cs,
+        round_function,
+        in_circuit_global_context,
+        snapshot_data,
+        |_, _| {},
+    );
+
+    assert_expected_final_state(
+        &final_state_no_pending,
+        final_state,
+        auxilary_final_parameters,
+    );
+
+    final_state_no_pending
+}
+
+// runs the cycles of the instance with any oracle, and calls `before_cycle` with the
+// oracle and monotonic cycle counter before every cycle. Final state is not checked
+pub fn run_vm_instance_with_oracle<
+    E: Engine,
+    CS: ConstraintSystem<E>,
+    R: CircuitArithmeticRoundFunction<E, 2, 3, StateElement = Num<E>>,
+    O: WitnessOracle<E>,
+>(
+    cs: &mut CS,
+    round_function: &R,
+    in_circuit_global_context: &GlobalContext<E>,
+    snapshot_data: VmInstanceWitness<E, O>,
+    mut before_cycle: impl FnMut(&mut O, u32),
+) -> (sync_vm::vm::vm_state::VmGlobalState<E, 3>, O) {
     // we need to prepare some global state and push initial context
     // use sync_vm::vm::vm_cycle::register_view::Register;
     use sync_vm::vm::primitives::register_view::Register;
@@ -326,8 +361,8 @@ pub fn run_vm_instance<
         witness_oracle,
         auxilary_initial_parameters,
         cycles_range,
-        final_state,
-        auxilary_final_parameters,
+        final_state: _,
+        auxilary_final_parameters: _,
     } = snapshot_data;
 
     let VmInCircuitAuxilaryParameters {
@@ -440,7 +475,8 @@ pub fn run_vm_instance<
     let mut oracle = witness_oracle;
     use sync_vm::vm::vm_cycle::cycle::vm_cycle;
 
-    for _ in cycles_range {
+    for cycle in cycles_range {
+        before_cycle(&mut oracle, cycle);
         state = vm_cycle(
             cs,
             state,
@@ -453,13 +489,7 @@ pub fn run_vm_instance<
 
     let final_state_no_pending = vm_process_pending(cs, state, round_function).unwrap();
 
-    assert_expected_final_state(
-        &final_state_no_pending,
-        final_state,
-        auxilary_final_parameters,
-    );
-
-    final_state_no_pending
+    (final_state_no_pending, oracle)
 }
 
 pub fn assert_expected_final_state<E: Engine>(
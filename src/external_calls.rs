@@ -3,9 +3,14 @@ use crate::entry_point::*;
 use crate::franklin_crypto::plonk::circuit::allocated_num::Num;
 use crate::toolset::create_tools;
 use crate::toolset::GeometryConfig;
+use crate::witness::block_header::{
+    BlockAuxilaryOutput, BlockContentHeader, BlockMetaParameters, BlockPassthroughData,
+    PerShardState,
+};
 use crate::witness::full_block_artifact::BlockBasicCircuits;
 use crate::witness::full_block_artifact::BlockBasicCircuitsPublicInputs;
-use crate::witness::oracle::create_artifacts_from_tracer;
+use crate::witness::large_vec::DEFAULT_SPILL_THRESHOLD_BYTES;
+use crate::witness::oracle::create_artifacts_from_tracer_with_processing;
 use crate::witness::tree::BinarySparseStorageTree;
 use crate::witness::tree::ZKSyncTestingTree;
 use crate::witness::tree::ZkSyncStorageLeaf;
@@ -63,6 +68,65 @@ pub fn run<
     BlockBasicCircuits<Bn256>,
     BlockBasicCircuitsPublicInputs<Bn256>,
     SchedulerCircuitInstanceWitness<Bn256>,
+    BlockContentHeader,
+) {
+    run_with_processing(
+        caller,
+        entry_point_address,
+        entry_point_code,
+        initial_heap_content,
+        zk_porter_is_available,
+        default_aa_code_hash,
+        used_bytecodes,
+        ram_verification_queries,
+        cycle_limit,
+        round_function,
+        geometry,
+        storage,
+        memory,
+        tree,
+        DEFAULT_SPILL_THRESHOLD_BYTES,
+        |artifacts, round_function, geometry, tree, num_non_deterministic_heap_queries| {
+            artifacts.process(
+                round_function,
+                geometry,
+                tree,
+                num_non_deterministic_heap_queries,
+            )
+        },
+    )
+}
+
+// same as `run`, but block artifacts are processed by the given function. It's also the place
+// to inspect processed artifacts, e.g. to save their summary. Memory related vectors of
+// the artifacts are moved into files when they exceed `spill_threshold_bytes`
+pub fn run_with_processing<
+    R: CircuitArithmeticRoundFunction<Bn256, 2, 3, StateElement = Num<Bn256>>,
+    S: Storage,
+    M: Memory,
+    T: BinarySparseStorageTree<256, 32, 32, 8, 32, Blake2s256, ZkSyncStorageLeaf>,
+>(
+    caller: Address,                 // for real block must be zero
+    entry_point_address: Address,    // for real block must be the bootloader
+    entry_point_code: Vec<[u8; 32]>, // for read lobkc must be a bootloader code
+    initial_heap_content: Vec<u8>,   // bootloader starts with non-deterministic heap
+    zk_porter_is_available: bool,
+    default_aa_code_hash: U256,
+    used_bytecodes: std::collections::HashMap<U256, Vec<[u8; 32]>>, // auxilary information to avoid passing a full set of all used codes
+    ram_verification_queries: Vec<(u32, U256)>, // we may need to check that after the bootloader's memory is filled
+    cycle_limit: usize,
+    round_function: R, // used for all queues implementation
+    geometry: GeometryConfig,
+    storage: S,
+    memory: M,
+    tree: &mut T,
+    spill_threshold_bytes: usize,
+    process: impl FnOnce(&mut FullBlockArtifacts<Bn256>, &R, &GeometryConfig, &mut T, usize),
+) -> (
+    BlockBasicCircuits<Bn256>,
+    BlockBasicCircuitsPublicInputs<Bn256>,
+    SchedulerCircuitInstanceWitness<Bn256>,
+    BlockContentHeader,
 ) {
     assert!(zk_porter_is_available == false);
     assert_eq!(
@@ -251,7 +315,7 @@ pub fn run<
 
     // dbg!(tools.witness_tracer.vm_snapshots.len());
 
-    let (instance_oracles, artifacts) = create_artifacts_from_tracer(
+    let (instance_oracles, artifacts) = create_artifacts_from_tracer_with_processing(
         tools.witness_tracer,
         &round_function,
         &geometry,
@@ -261,15 +325,23 @@ pub fn run<
         ),
         tree,
         num_non_deterministic_heap_queries,
+        spill_threshold_bytes,
+        process,
     );
 
+    // tree is updated by storage application
+    let final_rollup_root = tree.root();
+    let final_rollup_enumeration_counter = tree.next_enumeration_index();
+
     assert!(artifacts.special_initial_decommittment_queries.len() == 1);
+
     use sync_vm::scheduler::queues::SpongeLikeQueueStateWitness;
     let memory_state_after_bootloader_heap_writes = if num_non_deterministic_heap_queries == 0 {
         // empty
         SpongeLikeQueueStateWitness::<Bn256, 3>::empty()
     } else {
-        let full_info = &artifacts.all_memory_queue_states[num_non_deterministic_heap_queries - 1];
+        // VM part of the memory queue is kept after processing, and heap writes come first in it
+        let full_info = &artifacts.vm_memory_queue_states[num_non_deterministic_heap_queries - 1].2;
         let sponge_state = full_info.tail;
         let length = full_info.num_items;
 
@@ -325,39 +397,6 @@ pub fn run<
             _marker: std::marker::PhantomData,
         };
 
-        // aux
-        let _aux_data = BlockAuxilaryOutputWitness {
-            l1_messages_linear_hash: basic_circuits
-                .l1_messages_merklizer_circuit
-                .clone_witness()
-                .unwrap()
-                .closed_form_input
-                .observable_output
-                .linear_hash,
-            l1_messages_root: basic_circuits
-                .l1_messages_merklizer_circuit
-                .clone_witness()
-                .unwrap()
-                .closed_form_input
-                .observable_output
-                .root_hash,
-            rollup_initital_writes_pubdata_hash: basic_circuits
-                .initial_writes_hasher_circuit
-                .clone_witness()
-                .unwrap()
-                .closed_form_input
-                .observable_output
-                .pubdata_hash,
-            rollup_repeated_writes_pubdata_hash: basic_circuits
-                .repeated_writes_hasher_circuit
-                .clone_witness()
-                .unwrap()
-                .closed_form_input
-                .observable_output
-                .pubdata_hash,
-            _marker: std::marker::PhantomData,
-        };
-
         let per_circuit_inputs = compact_form_witnesses.clone().into_flattened_set();
 
         let ram_permutation_full_sorted_state = basic_circuits
@@ -585,10 +624,69 @@ pub fn run<
         scheduler_circuit_witness
     };
 
+    // header of the new block, in the same form as it's computed by the scheduler
+    let block_header = {
+        let mut bootloader_code_hash = [0u8; 32];
+        entry_point_code_hash_as_u256.to_big_endian(&mut bootloader_code_hash);
+        let mut default_aa_code_hash_bytes = [0u8; 32];
+        default_aa_code_hash.to_big_endian(&mut default_aa_code_hash_bytes);
+
+        let l1_messages_merklizer_output = basic_circuits
+            .l1_messages_merklizer_circuit
+            .clone_witness()
+            .unwrap()
+            .closed_form_input
+            .observable_output;
+        let initial_writes_hasher_output = basic_circuits
+            .initial_writes_hasher_circuit
+            .clone_witness()
+            .unwrap()
+            .closed_form_input
+            .observable_output;
+        let repeated_writes_hasher_output = basic_circuits
+            .repeated_writes_hasher_circuit
+            .clone_witness()
+            .unwrap()
+            .closed_form_input
+            .observable_output;
+
+        BlockContentHeader {
+            block_data: BlockPassthroughData {
+                per_shard_states: [
+                    PerShardState {
+                        enumeration_counter: final_rollup_enumeration_counter,
+                        state_root: final_rollup_root,
+                    },
+                    // porter is not available
+                    PerShardState {
+                        enumeration_counter: 0,
+                        state_root: [0u8; 32],
+                    },
+                ],
+            },
+            block_meta: BlockMetaParameters {
+                zkporter_is_available: zk_porter_is_available,
+                bootloader_code_hash,
+                default_aa_code_hash: default_aa_code_hash_bytes,
+            },
+            auxilary_output: BlockAuxilaryOutput {
+                l1_messages_root: l1_messages_merklizer_output.root_hash.inner,
+                l1_messages_linear_hash: l1_messages_merklizer_output.linear_hash.inner,
+                rollup_initital_writes_pubdata_hash: initial_writes_hasher_output
+                    .pubdata_hash
+                    .inner,
+                rollup_repeated_writes_pubdata_hash: repeated_writes_hasher_output
+                    .pubdata_hash
+                    .inner,
+            },
+        }
+    };
+
     (
         basic_circuits,
         basic_circuits_inputs,
         scheduler_circuit_witness,
+        block_header,
     )
 }
 
@@ -610,6 +708,7 @@ pub fn run_with_fixed_params<S: Storage, M: Memory>(
     BlockBasicCircuits<Bn256>,
     BlockBasicCircuitsPublicInputs<Bn256>,
     SchedulerCircuitInstanceWitness<Bn256>,
+    BlockContentHeader,
 ) {
     let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
     run(
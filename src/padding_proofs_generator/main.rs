@@ -0,0 +1,47 @@
+use std::fs::File;
+use std::io::BufReader;
+
+use structopt::StructOpt;
+use zkevm_test_harness::bellman::kate_commitment::{Crs, CrsForMonomialForm};
+use zkevm_test_harness::pairing::bn256::Bn256;
+use zkevm_test_harness::witness::recursive_aggregation::{generate_paddings, write_paddings};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "Generate padding proofs",
+    about = "Tool for generating padding VK and proofs used by recursive aggregation"
+)]
+struct Opt {
+    /// Path to the CRS in monomial form. If not set then the CRS from circuit testing is used.
+    #[structopt(long)]
+    crs_file: Option<String>,
+    /// Log2 of the CRS size if it's not read from the file.
+    #[structopt(long, default_value = "26")]
+    crs_size_log: usize,
+    /// Directory to write padding VK and proofs into. Point ZKSYNC_PADDING_PROOFS_DIR to it to use them.
+    #[structopt(long)]
+    output_dir: String,
+}
+
+fn main() {
+    let opt = Opt::from_args();
+
+    let crs = match opt.crs_file.as_ref() {
+        Some(path) => {
+            println!("Reading CRS from {}", path);
+            let file = File::open(path).expect("Unable to open CRS file");
+            Crs::<Bn256, CrsForMonomialForm>::read(BufReader::new(file))
+                .expect("Unable to read CRS")
+        }
+        None => {
+            println!("Using CRS of size 2^{}", opt.crs_size_log);
+            circuit_testing::get_trusted_setup::<Bn256>(1 << opt.crs_size_log)
+        }
+    };
+
+    let (padding_vk, padding_proofs) = generate_paddings(&crs);
+
+    write_paddings(&opt.output_dir, &padding_vk, &padding_proofs);
+
+    println!("Padding VK and proofs are written into {}", opt.output_dir);
+}
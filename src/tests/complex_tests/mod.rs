@@ -40,7 +40,8 @@ use zkevm_assembly::Assembly;
 use zk_evm::testing::storage::InMemoryStorage;
 use zk_evm::reference_impls::memory::SimpleMemory;
 use crate::toolset::create_tools;
-use utils::{read_test_artifact, TestArtifact};
+use crate::toolset::GeometryConfig;
+use utils::{read_test_artifact, read_test_output, write_test_output, CircuitOutput, TestArtifact};
 use crate::witness::tree::{ZKSyncTestingTree, BinarySparseStorageTree};
 
 const ACCOUNT_CODE_STORAGE_ADDRESS: Address = H160([
@@ -131,17 +132,8 @@ pub(crate) fn save_predeployed_contracts(
     }
 }
 
-fn run_and_try_create_witness_inner(mut test_artifact: TestArtifact, cycle_limit: usize) {
-    use zk_evm::zkevm_opcode_defs::system_params::BOOTLOADER_FORMAL_ADDRESS;
-
-    use crate::external_calls::run;
-
-    use sync_vm::testing::create_test_artifacts_with_optimized_gate;
-    let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
-
-    use crate::toolset::GeometryConfig;
-
-    let geometry = GeometryConfig {
+fn basic_test_geometry() -> GeometryConfig {
+    GeometryConfig {
         // cycles_per_vm_snapshot: 16, // 24, 26
         cycles_per_vm_snapshot: 1024,
         cycles_per_ram_permutation: 1024,
@@ -159,10 +151,19 @@ fn run_and_try_create_witness_inner(mut test_artifact: TestArtifact, cycle_limit
         limit_for_repeated_writes_pubdata_hasher: 16,
         limit_for_l1_messages_merklizer: 32,
         limit_for_l1_messages_pudata_hasher: 32,
-    };
+    }
+}
 
+// storage and tree with the predeployed contracts, known bytecodes and the default AA code hash
+fn prepare_basic_test_state(
+    test_artifact: &mut TestArtifact,
+) -> (
+    InMemoryStorage,
+    ZKSyncTestingTree,
+    HashMap<U256, Vec<[u8; 32]>>,
+    U256,
+) {
     let mut storage_impl = InMemoryStorage::new();
-    let mut memory_impl = SimpleMemory::new_without_preallocations();
     let mut tree = ZKSyncTestingTree::empty();
 
     test_artifact.entry_point_address =
@@ -201,6 +202,30 @@ fn run_and_try_create_witness_inner(mut test_artifact: TestArtifact, cycle_limit
     for (k, _) in used_bytecodes.iter() {
         println!("Have bytecode hash 0x{:x}", k);
     }
+
+    let default_account_codehash =
+        bytecode_to_code_hash(&test_artifact.default_account_code).unwrap();
+    let default_account_codehash = U256::from_big_endian(&default_account_codehash);
+
+    println!("Default AA code hash 0x{:x}", default_account_codehash);
+
+    (storage_impl, tree, used_bytecodes, default_account_codehash)
+}
+
+fn run_and_try_create_witness_inner(mut test_artifact: TestArtifact, cycle_limit: usize) {
+    use zk_evm::zkevm_opcode_defs::system_params::BOOTLOADER_FORMAL_ADDRESS;
+
+    use crate::external_calls::run;
+
+    use sync_vm::testing::create_test_artifacts_with_optimized_gate;
+    let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
+
+    let geometry = basic_test_geometry();
+
+    let (storage_impl, mut tree, used_bytecodes, default_account_codehash) =
+        prepare_basic_test_state(&mut test_artifact);
+    let mut memory_impl = SimpleMemory::new_without_preallocations();
+
     use sha3::{Digest, Keccak256};
 
     let previous_enumeration_index = tree.next_enumeration_index();
@@ -229,13 +254,14 @@ fn run_and_try_create_witness_inner(mut test_artifact: TestArtifact, cycle_limit
     let mut previous_content_hash = [0u8; 32];
     (&mut previous_content_hash[..]).copy_from_slice(&hasher.finalize().as_slice());
 
-    let default_account_codehash =
-        bytecode_to_code_hash(&test_artifact.default_account_code).unwrap();
-    let default_account_codehash = U256::from_big_endian(&default_account_codehash);
-
-    println!("Default AA code hash 0x{:x}", default_account_codehash);
+    let previous_block_tree = ZKSyncTestingTree::from_dump(&tree.dump());
 
-    let (basic_block_circuits, basic_block_circuits_inputs, mut scheduler_partial_input) = run(
+    let (
+        basic_block_circuits,
+        basic_block_circuits_inputs,
+        mut scheduler_partial_input,
+        block_header,
+    ) = run(
         Address::zero(),
         test_artifact.entry_point_address,
         test_artifact.entry_point_code,
@@ -252,6 +278,55 @@ fn run_and_try_create_witness_inner(mut test_artifact: TestArtifact, cycle_limit
         &mut tree,
     );
 
+    // new state must be recoverable from the published data only
+    use crate::witness::state_reconstruction::reconstruct_block_state;
+
+    let mut reconstructed_tree = previous_block_tree;
+    let (reconstructed_root, reconstructed_enumeration_index) =
+        reconstruct_block_state(&mut reconstructed_tree, &basic_block_circuits);
+    assert_eq!(reconstructed_root, tree.root());
+    assert_eq!(reconstructed_enumeration_index, tree.next_enumeration_index());
+
+    // compact form of the main VM witnesses must round trip and be smaller than the serde one
+    use crate::witness::oracle_codec::*;
+
+    let mut serde_size = 0;
+    let mut compact_size = 0;
+    let mut compressed_size = 0;
+    for circuit in basic_block_circuits.main_vm_circuits.iter() {
+        let witness = circuit.clone_witness().unwrap();
+        let serialized = bincode::serialize(&witness).unwrap();
+        let compact = encode_vm_circuit_witness(&witness, &round_function, None);
+        let compressed = encode_vm_circuit_witness(
+            &witness,
+            &round_function,
+            Some(DEFAULT_COMPRESSION_BLOCK_SIZE),
+        );
+        for encoded in [&compact, &compressed] {
+            let decoded = decode_vm_circuit_witness::<Bn256, _>(encoded, &round_function);
+            assert_eq!(bincode::serialize(&decoded).unwrap(), serialized);
+            for ((_, (_, a)), (_, (_, b))) in witness
+                .witness_oracle
+                .callstack_values_witnesses
+                .iter()
+                .zip(decoded.witness_oracle.callstack_values_witnesses.iter())
+            {
+                assert_eq!(
+                    a.round_function_execution_pairs,
+                    b.round_function_execution_pairs
+                );
+            }
+        }
+        serde_size += serialized.len();
+        compact_size += compact.len();
+        compressed_size += compressed.len();
+    }
+    println!(
+        "Main VM witnesses take {} bytes with bincode, {} bytes in compact form, {} bytes compressed",
+        serde_size, compact_size, compressed_size
+    );
+    assert!(compact_size < serde_size);
+
     use crate::bellman::plonk::better_better_cs::cs::PlonkCsWidth4WithNextStepAndCustomGatesParams;
     use sync_vm::recursion::transcript::GenericTranscriptGadget;
 
@@ -1340,6 +1415,17 @@ fn run_and_try_create_witness_inner(mut test_artifact: TestArtifact, cycle_limit
 
     dbg!(&proof.inputs[0]);
 
+    use crate::witness::block_proof_bundle::aggregation_result_into_bytes;
+    use crate::witness::block_proof_bundle::scheduler_public_input;
+    let expected_scheduler_public_input = scheduler_public_input(
+        previous_content_hash,
+        block_header,
+        all_circuit_types_committment_for_leaf_agg,
+        node_vk_committment,
+        leaf_vk_committment,
+        aggregation_result_into_bytes(&output),
+    );
+
     scheduler_partial_input.aggregation_result = output;
     scheduler_partial_input.proof_witnesses = vec![proof];
     let vk_in_rns = VkInRns {
@@ -1395,7 +1481,7 @@ fn run_and_try_create_witness_inner(mut test_artifact: TestArtifact, cycle_limit
     use crate::abstract_zksync_circuit::concrete_circuits::SchedulerCircuit;
 
     let (mut cs, _, _) = create_test_artifacts_with_optimized_gate();
-    let _ = scheduler_function(
+    let in_circuit_public_input = scheduler_function(
         &mut cs,
         Some(scheduler_partial_input.clone()),
         None,
@@ -1408,6 +1494,13 @@ fn run_and_try_create_witness_inner(mut test_artifact: TestArtifact, cycle_limit
             padding_proofs[0].clone(),
             g2_points.clone(),
         ),
+    )
+    .unwrap();
+
+    assert_eq!(
+        in_circuit_public_input.get_value().unwrap(),
+        expected_scheduler_public_input,
+        "out of circuit block header diverged from the scheduler"
     );
 
     let circuit = SchedulerCircuit::new(
@@ -1446,6 +1539,122 @@ fn run_and_try_create_witness_inner(mut test_artifact: TestArtifact, cycle_limit
     println!("Done");
 }
 
+// public input and digest of every basic circuit of the basic test, in the order of the scheduler
+fn basic_test_output() -> Vec<CircuitOutput> {
+    use crate::blake2::Digest;
+    use crate::external_calls::run;
+    use crate::ff::{PrimeField, PrimeFieldRepr};
+
+    let mut test_artifact = read_test_artifact("basic_test");
+    let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
+    let (storage_impl, mut tree, used_bytecodes, default_account_codehash) =
+        prepare_basic_test_state(&mut test_artifact);
+
+    let (basic_block_circuits, basic_block_circuits_inputs, _, _) = run(
+        Address::zero(),
+        test_artifact.entry_point_address,
+        test_artifact.entry_point_code,
+        vec![],
+        false,
+        default_account_codehash,
+        used_bytecodes,
+        vec![],
+        20000,
+        round_function,
+        basic_test_geometry(),
+        storage_impl,
+        SimpleMemory::new_without_preallocations(),
+        &mut tree,
+    );
+
+    let circuits = basic_block_circuits.into_flattened_set();
+    let public_inputs = basic_block_circuits_inputs.into_flattened_set();
+    assert_eq!(circuits.len(), public_inputs.len());
+
+    circuits
+        .into_iter()
+        .zip(public_inputs.into_iter())
+        .map(|(circuit, public_input)| {
+            let mut buffer = vec![];
+            public_input.into_repr().write_be(&mut buffer).unwrap();
+            let witness_digest = Blake2s256::digest(&bincode::serialize(&circuit).unwrap());
+
+            CircuitOutput {
+                circuit_type: circuit.short_description().to_owned(),
+                public_input: format!("0x{}", hex::encode(&buffer)),
+                witness_digest: format!("0x{}", hex::encode(witness_digest)),
+            }
+        })
+        .collect()
+}
+
+// reference output was made by the harness before the precompiles decomposition and the
+// concurrent processing of the artifacts, so circuits must not change at all
+#[test]
+fn test_basic_test_output_matches_reference() {
+    let reference = read_test_output("basic_test");
+    let output = basic_test_output();
+
+    assert_eq!(output.len(), reference.len());
+    for (idx, (circuit, reference_circuit)) in output.iter().zip(reference.iter()).enumerate() {
+        assert_eq!(
+            circuit, reference_circuit,
+            "circuit {} diverged from the reference",
+            idx
+        );
+    }
+}
+
+// only for an intentional change of the circuits
+#[test]
+#[ignore]
+fn save_basic_test_output_reference() {
+    write_test_output("basic_test", &basic_test_output());
+}
+
+// saves the queues summary of the basic test to compare it with other harness version
+#[test]
+#[ignore]
+fn save_basic_test_artifacts_summary() {
+    use crate::external_calls::run_with_processing;
+    use crate::witness::large_vec::DEFAULT_SPILL_THRESHOLD_BYTES;
+
+    let mut test_artifact = read_test_artifact("basic_test");
+    let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
+    let (storage_impl, mut tree, used_bytecodes, default_account_codehash) =
+        prepare_basic_test_state(&mut test_artifact);
+
+    let _ = run_with_processing(
+        Address::zero(),
+        test_artifact.entry_point_address,
+        test_artifact.entry_point_code,
+        vec![],
+        false,
+        default_account_codehash,
+        used_bytecodes,
+        vec![],
+        20000,
+        round_function,
+        basic_test_geometry(),
+        storage_impl,
+        SimpleMemory::new_without_preallocations(),
+        &mut tree,
+        DEFAULT_SPILL_THRESHOLD_BYTES,
+        |artifacts, round_function, geometry, tree, num_non_deterministic_heap_queries| {
+            artifacts.process(
+                round_function,
+                geometry,
+                tree,
+                num_non_deterministic_heap_queries,
+            );
+
+            let summary = artifacts.summary();
+            println!("Block artifacts fingerprint is {}", summary.fingerprint);
+            summary.write_json("basic_test_summary.json");
+        },
+    );
+}
+
 #[test]
 fn get_circuit_capacity() {
     use crate::abstract_zksync_circuit::concrete_circuits::*;
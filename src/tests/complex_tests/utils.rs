@@ -36,3 +36,38 @@ pub fn read_test_artifact(test_name: &str) -> TestArtifact {
 
     // serde_json::from_str(text.as_str()).unwrap_or_else(|_| panic!("Failed to deserialize the test artifact"))
 }
+
+// public input of a basic circuit and the digest of the serialized circuit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitOutput {
+    pub circuit_type: String,
+    pub public_input: String,
+    pub witness_digest: String,
+}
+
+fn test_output_path(test_name: &str) -> PathBuf {
+    let mut path = PathBuf::from(TEST_ARTIFACTS_DIR);
+
+    path.push(format!("{}_output", test_name));
+    path.set_extension("json");
+
+    path
+}
+
+pub fn read_test_output(test_name: &str) -> Vec<CircuitOutput> {
+    let path = test_output_path(test_name);
+
+    if !path.exists() {
+        panic!("The reference output {:?} does not exist", path.as_path());
+    }
+
+    let text = fs::read_to_string(path.as_path())
+        .unwrap_or_else(|_| panic!("Failed to read the reference output"));
+    serde_json::from_str(text.as_str()).unwrap()
+}
+
+pub fn write_test_output(test_name: &str, output: &[CircuitOutput]) {
+    let text = serde_json::to_string_pretty(output).unwrap();
+    fs::write(test_output_path(test_name), text)
+        .unwrap_or_else(|_| panic!("Failed to write the reference output"));
+}
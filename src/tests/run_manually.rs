@@ -3,13 +3,18 @@ use std::collections::HashMap;
 use super::*;
 use crate::entry_point::create_out_of_circuit_global_context;
 
+use crate::blake2::Blake2s256;
 use crate::ethereum_types::*;
 use crate::pairing::bn256::Bn256;
 use crate::toolset::create_tools;
+use crate::witness::block_header::BlockContentHeader;
+use crate::witness::full_block_artifact::{BlockBasicCircuits, BlockBasicCircuitsPublicInputs};
 use crate::witness::oracle::create_artifacts_from_tracer;
 use crate::witness::oracle::VmWitnessOracle;
+use crate::witness::tree::{BinarySparseStorageTree, ZkSyncStorageLeaf};
 use sync_vm::glue::traits::GenericHasher;
 use sync_vm::rescue_poseidon::rescue::params::RescueParams;
+use sync_vm::scheduler::SchedulerCircuitInstanceWitness;
 use sync_vm::traits::CSWitnessable;
 use sync_vm::vm::vm_cycle::cycle::vm_cycle;
 use sync_vm::vm::vm_cycle::witness_oracle::u256_to_biguint;
@@ -143,6 +148,52 @@ fn run_and_try_create_witness() {
     run_and_try_create_witness_inner(asm, 50);
 }
 
+#[test]
+fn test_artifacts_processing_is_deterministic() {
+    let asm = r#"
+        .text
+        .file	"Test_26"
+        .rodata.cst32
+        .p2align	5
+        .text
+        .globl	__entry
+    __entry:
+    .main:
+        add 10000, r0, r1
+        add 1000, r0, r10
+        sstore r1, r10
+        sload r1, r2
+        event.first r1, r10
+        to_l1.first r0, r1
+        ret.ok r0
+    "#;
+
+    let mut assembly = Assembly::try_from(asm.to_owned()).unwrap();
+    let bytecode = assembly.compile_to_bytecode().unwrap();
+
+    // stages of the old sequential processing are the reference, and concurrent processing
+    // must not depend on the number of threads
+    let (_, reference, _, _) =
+        generate_block_for_extended_state(bytecode.clone(), vec![], 50, true);
+    let reference = reference.into_flattened_set();
+
+    for num_threads in [1, 4] {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .unwrap();
+        let (_, inputs, _, _) =
+            pool.install(|| generate_block_for_extended_state(bytecode.clone(), vec![], 50, false));
+
+        assert_eq!(
+            inputs.into_flattened_set(),
+            reference,
+            "public inputs diverged with {} threads",
+            num_threads
+        );
+    }
+}
+
 pub fn assert_equal_state(
     out_of_circuit: &zk_evm::vm_state::VmLocalState,
     in_circuit: &sync_vm::vm::vm_state::VmLocalState<Bn256, 3>,
@@ -215,12 +266,113 @@ pub(crate) fn run_and_try_create_witness_inner(asm: &str, cycle_limit: usize) {
     run_and_try_create_witness_for_extended_state(bytecode, vec![], cycle_limit)
 }
 
-pub(crate) fn run_and_try_create_witness_for_extended_state(
+pub(crate) fn generate_witness_for_extended_state(
+    entry_point_bytecode: Vec<[u8; 32]>,
+    other_contracts: Vec<(H160, Vec<[u8; 32]>)>,
+    cycle_limit: usize,
+) -> (
+    BlockBasicCircuits<Bn256>,
+    BlockBasicCircuitsPublicInputs<Bn256>,
+) {
+    let (basic_block_circuits, basic_block_circuits_inputs, _scheduler_input, _block_header) =
+        generate_block_for_extended_state(
+            entry_point_bytecode,
+            other_contracts,
+            cycle_limit,
+            false,
+        );
+
+    (basic_block_circuits, basic_block_circuits_inputs)
+}
+
+// same as `generate_witness_for_extended_state`, but also returns the scheduler witness and block header.
+// Artifacts processing stages are run one by one if `sequential_processing` is set
+pub(crate) fn generate_block_for_extended_state(
+    entry_point_bytecode: Vec<[u8; 32]>,
+    other_contracts: Vec<(H160, Vec<[u8; 32]>)>,
+    cycle_limit: usize,
+    sequential_processing: bool,
+) -> (
+    BlockBasicCircuits<Bn256>,
+    BlockBasicCircuitsPublicInputs<Bn256>,
+    SchedulerCircuitInstanceWitness<Bn256>,
+    BlockContentHeader,
+) {
+    use crate::witness::large_vec::DEFAULT_SPILL_THRESHOLD_BYTES;
+
+    generate_block_for_extended_state_with_spill_threshold(
+        entry_point_bytecode,
+        other_contracts,
+        cycle_limit,
+        sequential_processing,
+        DEFAULT_SPILL_THRESHOLD_BYTES,
+    )
+}
+
+pub(crate) fn generate_block_for_extended_state_with_spill_threshold(
+    entry_point_bytecode: Vec<[u8; 32]>,
+    other_contracts: Vec<(H160, Vec<[u8; 32]>)>,
+    cycle_limit: usize,
+    sequential_processing: bool,
+    spill_threshold_bytes: usize,
+) -> (
+    BlockBasicCircuits<Bn256>,
+    BlockBasicCircuitsPublicInputs<Bn256>,
+    SchedulerCircuitInstanceWitness<Bn256>,
+    BlockContentHeader,
+) {
+    use crate::witness::tree::ZKSyncTestingTree;
+
+    generate_block_inner(
+        entry_point_bytecode,
+        other_contracts,
+        cycle_limit,
+        sequential_processing,
+        spill_threshold_bytes,
+        &mut ZKSyncTestingTree::empty(),
+    )
+}
+
+// runs the block on the given tree, that is updated as by the real block
+pub(crate) fn generate_block_for_extended_state_with_tree(
+    entry_point_bytecode: Vec<[u8; 32]>,
+    other_contracts: Vec<(H160, Vec<[u8; 32]>)>,
+    cycle_limit: usize,
+    tree: &mut impl BinarySparseStorageTree<256, 32, 32, 8, 32, Blake2s256, ZkSyncStorageLeaf>,
+) -> (
+    BlockBasicCircuits<Bn256>,
+    BlockBasicCircuitsPublicInputs<Bn256>,
+    SchedulerCircuitInstanceWitness<Bn256>,
+    BlockContentHeader,
+) {
+    use crate::witness::large_vec::DEFAULT_SPILL_THRESHOLD_BYTES;
+
+    generate_block_inner(
+        entry_point_bytecode,
+        other_contracts,
+        cycle_limit,
+        false,
+        DEFAULT_SPILL_THRESHOLD_BYTES,
+        tree,
+    )
+}
+
+fn generate_block_inner<
+    T: BinarySparseStorageTree<256, 32, 32, 8, 32, Blake2s256, ZkSyncStorageLeaf>,
+>(
     entry_point_bytecode: Vec<[u8; 32]>,
     other_contracts: Vec<(H160, Vec<[u8; 32]>)>,
     cycle_limit: usize,
+    sequential_processing: bool,
+    spill_threshold_bytes: usize,
+    tree: &mut T,
+) -> (
+    BlockBasicCircuits<Bn256>,
+    BlockBasicCircuitsPublicInputs<Bn256>,
+    SchedulerCircuitInstanceWitness<Bn256>,
+    BlockContentHeader,
 ) {
-    use crate::external_calls::run;
+    use crate::external_calls::run_with_processing;
     use sync_vm::testing::create_test_artifacts_with_optimized_gate;
     use zk_evm::zkevm_opcode_defs::system_params::BOOTLOADER_FORMAL_ADDRESS;
     let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
@@ -245,9 +397,6 @@ pub(crate) fn run_and_try_create_witness_for_extended_state(
         limit_for_l1_messages_pudata_hasher: 8,
     };
 
-    use crate::witness::tree::BinarySparseStorageTree;
-    use crate::witness::tree::ZKSyncTestingTree;
-
     let mut used_bytecodes_and_hashes = HashMap::new();
     used_bytecodes_and_hashes.extend(other_contracts.iter().cloned().map(|(_, code)| {
         let code_hash = bytecode_to_code_hash(&code).unwrap();
@@ -257,18 +406,16 @@ pub(crate) fn run_and_try_create_witness_for_extended_state(
 
     let mut storage_impl = InMemoryStorage::new();
     let memory_impl = SimpleMemory::new_without_preallocations();
-    let mut tree = ZKSyncTestingTree::empty();
-
     let mut known_contracts = HashMap::new();
     known_contracts.extend(other_contracts.iter().cloned());
 
     crate::tests::complex_tests::save_predeployed_contracts(
         &mut storage_impl,
-        &mut tree,
+        tree,
         &known_contracts,
     );
 
-    let (basic_block_circuits, basic_block_circuits_inputs, scheduler_input) = run(
+    run_with_processing(
         Address::zero(),
         *BOOTLOADER_FORMAL_ADDRESS,
         entry_point_bytecode,
@@ -282,8 +429,35 @@ pub(crate) fn run_and_try_create_witness_for_extended_state(
         geometry,
         storage_impl,
         memory_impl,
-        &mut tree,
-    );
+        tree,
+        spill_threshold_bytes,
+        |artifacts, round_function, geometry, tree, num_non_deterministic_heap_queries| {
+            if sequential_processing {
+                artifacts.process_sequentially(
+                    round_function,
+                    geometry,
+                    tree,
+                    num_non_deterministic_heap_queries,
+                )
+            } else {
+                artifacts.process(
+                    round_function,
+                    geometry,
+                    tree,
+                    num_non_deterministic_heap_queries,
+                )
+            }
+        },
+    )
+}
+
+pub(crate) fn run_and_try_create_witness_for_extended_state(
+    entry_point_bytecode: Vec<[u8; 32]>,
+    other_contracts: Vec<(H160, Vec<[u8; 32]>)>,
+    cycle_limit: usize,
+) {
+    let (basic_block_circuits, basic_block_circuits_inputs) =
+        generate_witness_for_extended_state(entry_point_bytecode, other_contracts, cycle_limit);
 
     println!("Simulation and witness creation are completed");
 
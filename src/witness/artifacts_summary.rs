@@ -0,0 +1,325 @@
+// Short description of every queue of the processed block: its length, head and tail
+// and the number of circuits that consume it, plus a fingerprint over all of them.
+// Comparing summaries made by two harness versions on the same block is a quick way to
+// find which part of the processing diverged
+
+use crate::blake2::{Blake2s256, Digest};
+use crate::encodings::SpongeLikeQueueIntermediateStates;
+use crate::ff::{PrimeField, PrimeFieldRepr};
+use crate::pairing::Engine;
+use crate::witness::full_block_artifact::FullBlockArtifacts;
+use derivative::Derivative;
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct QueueSummary {
+    pub name: String,
+    pub length: u32,
+    pub head: Vec<String>,
+    pub tail: Vec<String>,
+    pub num_circuits: usize,
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct ArtifactsSummary {
+    pub queues: Vec<QueueSummary>,
+    pub fingerprint: String,
+}
+
+fn format_fe<F: PrimeField>(el: &F) -> String {
+    let mut buffer = vec![];
+    el.into_repr().write_be(&mut buffer).unwrap();
+    format!("0x{}", hex::encode(&buffer))
+}
+
+impl QueueSummary {
+    fn new<F: PrimeField>(
+        name: &str,
+        length: u32,
+        head: &[F],
+        tail: &[F],
+        num_circuits: usize,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            length,
+            head: head.iter().map(format_fe).collect(),
+            tail: tail.iter().map(format_fe).collect(),
+            num_circuits,
+        }
+    }
+}
+
+fn sponge_state_summary<E: Engine, const SW: usize, const ROUNDS: usize>(
+    name: &str,
+    state: Option<&SpongeLikeQueueIntermediateStates<E, SW, ROUNDS>>,
+    num_circuits: usize,
+) -> QueueSummary {
+    use crate::ff::Field;
+
+    match state {
+        Some(state) => QueueSummary::new(
+            name,
+            state.num_items,
+            &state.head,
+            &state.tail,
+            num_circuits,
+        ),
+        None => {
+            let empty = [E::Fr::zero(); SW];
+            QueueSummary::new(name, 0, &empty, &empty, num_circuits)
+        }
+    }
+}
+
+// every variable length part is prefixed by its length, so different summaries
+// can not produce the same byte stream
+pub fn compute_fingerprint(queues: &[QueueSummary]) -> String {
+    fn update_with_str(hasher: &mut Blake2s256, value: &str) {
+        hasher.update(&(value.len() as u64).to_le_bytes());
+        hasher.update(value.as_bytes());
+    }
+
+    let mut hasher = Blake2s256::new();
+    hasher.update(&(queues.len() as u64).to_le_bytes());
+    for queue in queues.iter() {
+        update_with_str(&mut hasher, &queue.name);
+        hasher.update(&queue.length.to_le_bytes());
+        for elements in [&queue.head, &queue.tail] {
+            hasher.update(&(elements.len() as u64).to_le_bytes());
+            for el in elements.iter() {
+                update_with_str(&mut hasher, el);
+            }
+        }
+        hasher.update(&(queue.num_circuits as u64).to_le_bytes());
+    }
+
+    format!("0x{}", hex::encode(hasher.finalize()))
+}
+
+impl ArtifactsSummary {
+    pub fn new(queues: Vec<QueueSummary>) -> Self {
+        let fingerprint = compute_fingerprint(&queues);
+
+        Self {
+            queues,
+            fingerprint,
+        }
+    }
+
+    // e.g. for a summary read from a file
+    pub fn fingerprint_is_valid(&self) -> bool {
+        compute_fingerprint(&self.queues) == self.fingerprint
+    }
+
+    // names of the queues that are different or present only in one of the summaries
+    pub fn differences(&self, other: &Self) -> Vec<String> {
+        let mut result = vec![];
+        for queue in self.queues.iter() {
+            match other.queues.iter().find(|el| el.name == queue.name) {
+                Some(other_queue) if other_queue == queue => {}
+                _ => result.push(queue.name.clone()),
+            }
+        }
+        for queue in other.queues.iter() {
+            if self.queues.iter().all(|el| el.name != queue.name) {
+                result.push(queue.name.clone());
+            }
+        }
+
+        result
+    }
+
+    pub fn write_json<P: AsRef<std::path::Path>>(&self, path: P) {
+        let file = std::fs::File::create(path).expect("must create a summary file");
+        serde_json::to_writer_pretty(file, self).expect("must write a summary");
+    }
+
+    pub fn read_json<P: AsRef<std::path::Path>>(path: P) -> Self {
+        let file = std::fs::File::open(path).expect("summary file must exist");
+        serde_json::from_reader(file).expect("must be a valid summary")
+    }
+
+    pub fn print(&self) {
+        println!(
+            "{:<32} {:>10} {:>10}  {:<68} {}",
+            "queue", "length", "circuits", "head", "tail"
+        );
+        for queue in self.queues.iter() {
+            let num_lines = std::cmp::max(std::cmp::max(queue.head.len(), queue.tail.len()), 1);
+            for idx in 0..num_lines {
+                let head = queue.head.get(idx).map(|el| el.as_str()).unwrap_or("");
+                let tail = queue.tail.get(idx).map(|el| el.as_str()).unwrap_or("");
+                if idx == 0 {
+                    println!(
+                        "{:<32} {:>10} {:>10}  {:<68} {}",
+                        queue.name, queue.length, queue.num_circuits, head, tail
+                    );
+                } else {
+                    println!("{:<32} {:>10} {:>10}  {:<68} {}", "", "", "", head, tail);
+                }
+            }
+        }
+        println!("fingerprint: {}", self.fingerprint);
+    }
+}
+
+impl<E: Engine> FullBlockArtifacts<E> {
+    pub fn summary(&self) -> ArtifactsSummary {
+        assert!(
+            self.is_processed,
+            "summary is only available for processed artifacts"
+        );
+
+        use crate::ff::Field;
+
+        macro_rules! log_queue_summary {
+            ($name:expr, $simulator:expr, $num_circuits:expr) => {
+                QueueSummary::new(
+                    $name,
+                    $simulator.num_items,
+                    &[$simulator.head],
+                    &[$simulator.tail],
+                    $num_circuits,
+                )
+            };
+        }
+
+        let queues = vec![
+            QueueSummary::new(
+                "memory",
+                self.memory_queue_simulator.num_items,
+                &self.memory_queue_simulator.head,
+                &self.memory_queue_simulator.tail,
+                self.ram_permutation_circuits_data.len(),
+            ),
+            sponge_state_summary(
+                "sorted_memory",
+                self.sorted_memory_queue_final_state.as_ref(),
+                self.ram_permutation_circuits_data.len(),
+            ),
+            sponge_state_summary(
+                "decommittments",
+                self.all_decommittment_queue_states
+                    .last()
+                    .map(|(_, state)| state),
+                self.decommittments_deduplicator_circuits_data.len(),
+            ),
+            sponge_state_summary(
+                "sorted_decommittments",
+                self.sorted_decommittment_queue_states.last(),
+                self.decommittments_deduplicator_circuits_data.len(),
+            ),
+            sponge_state_summary(
+                "deduplicated_decommittments",
+                self.deduplicated_decommittment_queue_states.last(),
+                self.code_decommitter_circuits_data.len(),
+            ),
+            log_queue_summary!(
+                "original_log",
+                self.original_log_queue_simulator,
+                self.log_demuxer_circuit_data.len()
+            ),
+            log_queue_summary!(
+                "demuxed_rollup_storage",
+                self.demuxed_rollup_storage_queue_simulator,
+                self.storage_deduplicator_circuit_data.len()
+            ),
+            log_queue_summary!(
+                "demuxed_porter_storage",
+                self.demuxed_porter_storage_queue_simulator,
+                0
+            ),
+            log_queue_summary!(
+                "demuxed_events",
+                self.demuxed_events_queue_simulator,
+                self.events_deduplicator_circuit_data.len()
+            ),
+            log_queue_summary!(
+                "demuxed_to_l1",
+                self.demuxed_to_l1_queue_simulator,
+                self.l1_messages_deduplicator_circuit_data.len()
+            ),
+            log_queue_summary!(
+                "demuxed_keccak_precompile",
+                self.demuxed_keccak_precompile_queue_simulator,
+                self.keccak256_circuits_data.len()
+            ),
+            log_queue_summary!(
+                "demuxed_sha256_precompile",
+                self.demuxed_sha256_precompile_queue_simulator,
+                self.sha256_circuits_data.len()
+            ),
+            log_queue_summary!(
+                "demuxed_ecrecover_precompile",
+                self.demuxed_ecrecover_queue_simulator,
+                self.ecrecover_circuits_data.len()
+            ),
+            log_queue_summary!(
+                "deduplicated_rollup_storage",
+                self.deduplicated_rollup_storage_queue_simulator,
+                self.rollup_storage_application_circuit_data.len()
+            ),
+            // porter storage is not supported yet, so there is no simulator for the deduplicated queue
+            match self.deduplicated_porter_storage_queue_states.last() {
+                Some(state) => log_queue_summary!("deduplicated_porter_storage", state, 0),
+                None => QueueSummary::new(
+                    "deduplicated_porter_storage",
+                    0,
+                    &[E::Fr::zero()],
+                    &[E::Fr::zero()],
+                    0,
+                ),
+            },
+            log_queue_summary!(
+                "deduplicated_events",
+                self.deduplicated_event_queue_simulator,
+                0
+            ),
+            log_queue_summary!(
+                "deduplicated_to_l1",
+                self.deduplicated_to_l1_queue_simulator,
+                self.l1_messages_linear_hash_data.len() + self.l1_messages_merklizer_data.len()
+            ),
+        ];
+
+        ArtifactsSummary::new(queues)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pairing::bn256::Bn256;
+
+    #[test]
+    fn test_artifacts_summary_fingerprint() {
+        let mut artifacts = FullBlockArtifacts::<Bn256>::default();
+        artifacts.is_processed = true;
+        let summary = artifacts.summary();
+        assert!(summary.fingerprint_is_valid());
+        assert_eq!(summary, artifacts.summary());
+
+        let serialized = serde_json::to_string(&summary).unwrap();
+        let deserialized: ArtifactsSummary = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, summary);
+        assert!(deserialized.fingerprint_is_valid());
+
+        artifacts.demuxed_events_queue_simulator.num_items = 1;
+        let other = artifacts.summary();
+        assert_ne!(other.fingerprint, summary.fingerprint);
+        assert_eq!(
+            summary.differences(&other),
+            vec!["demuxed_events".to_owned()]
+        );
+
+        let mut truncated = summary.clone();
+        truncated.queues.pop();
+        assert!(!truncated.fingerprint_is_valid());
+        assert_eq!(
+            truncated.differences(&summary),
+            vec!["deduplicated_to_l1".to_owned()]
+        );
+    }
+}
@@ -0,0 +1,821 @@
+use std::collections::HashSet;
+
+use super::block_header::*;
+use super::full_block_artifact::BlockBasicCircuitsPublicInputs;
+use super::recursive_aggregation::*;
+use super::*;
+use crate::abstract_zksync_circuit::concrete_circuits::{ZkSyncProof, ZkSyncVerificationKey};
+use crate::bellman::pairing::bn256::{Fq, G1Affine, G2Affine};
+use crate::bellman::{CurveAffine, Engine, Field, PrimeField, PrimeFieldRepr};
+use crate::encodings::recursion_request::*;
+use crate::encodings::QueueSimulator;
+use crate::witness::utils::{
+    simulate_public_input_value_from_witness, take_queue_state_from_simulator,
+};
+use derivative::*;
+use sync_vm::recursion::get_prefered_committer;
+use sync_vm::recursion::get_prefered_rns_params;
+use sync_vm::recursion::leaf_aggregation::LeafAggregationOutputDataWitness;
+use sync_vm::recursion::node_aggregation::NodeAggregationOutputDataWitness;
+use sync_vm::recursion::recursion_tree::NUM_LIMBS;
+use sync_vm::scheduler::queues::FixedWidthEncodingGenericQueueStateWitness;
+use sync_vm::scheduler::CircuitType;
+use sync_vm::testing::{Bn256, Fr};
+
+/// Proof together with the circuit type it was produced for and it's index
+/// at the corresponding level (position in the flattened set for basic circuits,
+/// position in the layer for aggregation circuits)
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone)]
+pub struct IndexedProof {
+    pub circuit_type: u8,
+    pub index: usize,
+    pub proof: ZkSyncProof<Bn256>,
+}
+
+/// Everything that is produced by proving a single block: proofs of basic circuits,
+/// every layer of recursive aggregation and the scheduler, as well as the
+/// data required to recompute public inputs of all of them
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone)]
+pub struct BlockProofBundle {
+    pub splitting_factor_for_leafs: usize,
+    pub splitting_factor_for_nodes: usize,
+    // basic circuits, in the order of `BlockBasicCircuits::into_flattened_set`
+    pub basic_circuits_inputs: BlockBasicCircuitsPublicInputs<Bn256>,
+    pub basic_circuits_proofs: Vec<IndexedProof>,
+    // leaf aggregation layer
+    pub leaf_aggregation_proofs: Vec<IndexedProof>,
+    pub leaf_aggregation_outputs: Vec<LeafAggregationOutputDataWitness<Bn256>>,
+    // node aggregation layers, starting from depth 0
+    pub node_aggregation_proofs: Vec<Vec<IndexedProof>>,
+    pub node_aggregation_outputs: Vec<Vec<NodeAggregationOutputDataWitness<Bn256>>>,
+    // scheduler
+    pub scheduler_proof: IndexedProof,
+    // as 32 bytes BE for every cordinate as [pair_with_generator_x, pair_with_generator_y, pair_with_x_x, pair_with_x_y]
+    pub aggregation_result: [[u8; 32]; 4],
+    // header data
+    pub previous_block_formal_hash: [u8; 32],
+    pub block_header: BlockContentHeader,
+}
+
+impl BlockProofBundle {
+    pub fn all_proofs(&self) -> Vec<&IndexedProof> {
+        let mut result = vec![];
+        result.extend(self.basic_circuits_proofs.iter());
+        result.extend(self.leaf_aggregation_proofs.iter());
+        for layer in self.node_aggregation_proofs.iter() {
+            result.extend(layer.iter());
+        }
+        result.push(&self.scheduler_proof);
+
+        result
+    }
+}
+
+pub fn fe_to_be_bytes(value: Fr) -> [u8; 32] {
+    let mut buffer = vec![];
+    value.into_repr().write_be(&mut buffer).unwrap();
+    assert_eq!(buffer.len(), 32);
+
+    buffer.try_into().unwrap()
+}
+
+pub fn leaf_aggregation_public_input(
+    initial_log_queue_state: FixedWidthEncodingGenericQueueStateWitness<Bn256>,
+    leaf_vks_committment: Fr,
+    output: LeafAggregationOutputDataWitness<Bn256>,
+) -> Fr {
+    use sync_vm::recursion::leaf_aggregation::*;
+
+    let closed_form_input = LeafAggregationInputOutputWitness {
+        start_flag: true,
+        completion_flag: true,
+        hidden_fsm_input: (),
+        hidden_fsm_output: (),
+        observable_input: LeafAggregationInputDataWitness {
+            initial_log_queue_state,
+            leaf_vk_committment: leaf_vks_committment,
+            _marker: std::marker::PhantomData,
+        },
+        observable_output: output,
+        _marker_e: (),
+        _marker: std::marker::PhantomData,
+    };
+
+    let (public_input, _) = simulate_public_input_value_from_witness(closed_form_input);
+
+    public_input
+}
+
+pub fn node_aggregation_public_input(
+    initial_log_queue_state: FixedWidthEncodingGenericQueueStateWitness<Bn256>,
+    leaf_vks_committment: Fr,
+    node_aggregation_vk_committment: Fr,
+    leaf_aggregation_vk_committment: Fr,
+    output: NodeAggregationOutputDataWitness<Bn256>,
+) -> Fr {
+    use sync_vm::recursion::node_aggregation::*;
+
+    let closed_form_input = NodeAggregationInputOutputWitness {
+        start_flag: true,
+        completion_flag: true,
+        hidden_fsm_input: (),
+        hidden_fsm_output: (),
+        observable_input: NodeAggregationInputDataWitness {
+            initial_log_queue_state,
+            leaf_vk_committment: leaf_aggregation_vk_committment,
+            node_vk_committment: node_aggregation_vk_committment,
+            all_circuit_types_committment_for_leaf: leaf_vks_committment,
+            _marker: std::marker::PhantomData,
+        },
+        observable_output: output,
+        _marker_e: (),
+        _marker: std::marker::PhantomData,
+    };
+
+    let (public_input, _) = simulate_public_input_value_from_witness(closed_form_input);
+
+    public_input
+}
+
+// final aggregation result in the form that is used by the scheduler, as 32 bytes BE for every cordinate
+// as [pair_with_generator_x, pair_with_generator_y, pair_with_x_x, pair_with_x_y]
+pub fn aggregation_result_into_bytes(
+    output: &NodeAggregationOutputDataWitness<Bn256>,
+) -> [[u8; 32]; 4] {
+    let rns_params = get_prefered_rns_params();
+    let limb_size_bits = rns_params.binary_limbs_params.limb_size_bits;
+
+    let mut shift = Fq::one();
+    for _ in 0..limb_size_bits {
+        shift.double();
+    }
+
+    let all_limbs = [
+        &output.pair_with_generator_x,
+        &output.pair_with_generator_y,
+        &output.pair_with_x_x,
+        &output.pair_with_x_y,
+    ];
+
+    let mut result = [[0u8; 32]; 4];
+    for (dst, limbs) in result.iter_mut().zip(all_limbs.iter()) {
+        let mut value = Fq::zero();
+        for limb in limbs.iter().rev() {
+            let limb_bytes = fe_to_be_bytes(*limb);
+            let mut repr = <Fq as PrimeField>::Repr::default();
+            repr.read_be(&limb_bytes[..]).unwrap();
+            let limb = Fq::from_repr(repr).expect("limb must fit into the base field");
+
+            value.mul_assign(&shift);
+            value.add_assign(&limb);
+        }
+
+        let mut buffer = vec![];
+        value.into_repr().write_be(&mut buffer).unwrap();
+        dst.copy_from_slice(&buffer);
+
+        assert!(check_limbs_against_coordinate(limbs, dst));
+    }
+
+    result
+}
+
+// public input of the scheduler circuit, exactly as it's computed in circuit
+pub fn scheduler_public_input(
+    previous_block_formal_hash: [u8; 32],
+    block_header: BlockContentHeader,
+    leaf_vks_committment: Fr,
+    node_aggregation_vk_committment: Fr,
+    leaf_aggregation_vk_committment: Fr,
+    aggregation_result: [[u8; 32]; 4],
+) -> Fr {
+    let (this_block_formal_hash, _) = block_header.into_formal_block_hash();
+
+    let public_input = block_proof_input(
+        previous_block_formal_hash,
+        this_block_formal_hash,
+        fe_to_be_bytes(node_aggregation_vk_committment),
+        fe_to_be_bytes(leaf_aggregation_vk_committment),
+        fe_to_be_bytes(leaf_vks_committment),
+        aggregation_result,
+    );
+
+    crate::utils::u256_to_fe::<Fr>(public_input)
+}
+
+#[derive(Derivative)]
+#[derivative(Clone, Debug)]
+pub struct VerificationKeysCommittments {
+    pub leaf_vks_committments_set: Vec<Fr>,
+    pub leaf_vks_committment: Fr,
+    pub leaf_aggregation_vk_committment: Fr,
+    pub node_aggregation_vk_committment: Fr,
+    pub g2_points: [G2Affine; 2],
+}
+
+impl VerificationKeysCommittments {
+    // computes all the committments used through recursion from the set of
+    // verification keys, one per circuit type
+    pub fn from_verification_keys(verification_keys: &[ZkSyncVerificationKey<Bn256>]) -> Self {
+        let mut basic_vks: Vec<_> = verification_keys
+            .iter()
+            .filter(|el| el.numeric_circuit_type() >= CircuitType::VM as u8)
+            .cloned()
+            .collect();
+        basic_vks.sort_by_key(|el| el.numeric_circuit_type());
+        let basic_vks: Vec<_> = basic_vks
+            .into_iter()
+            .map(|el| el.into_verification_key())
+            .collect();
+
+        let (leaf_vks_committments_set, leaf_vks_committment, g2_points) =
+            form_base_circuits_committment(basic_vks);
+
+        let leaf_vk = find_verification_key(verification_keys, CircuitType::Leaf as u8);
+        let (_, leaf_aggregation_vk_committment) = compute_vk_encoding_and_committment(
+            erase_vk_type(leaf_vk.clone().into_verification_key()),
+        );
+
+        let node_vk = find_verification_key(verification_keys, CircuitType::IntermidiateNode as u8);
+        let (_, node_aggregation_vk_committment) = compute_vk_encoding_and_committment(
+            erase_vk_type(node_vk.clone().into_verification_key()),
+        );
+
+        Self {
+            leaf_vks_committments_set,
+            leaf_vks_committment,
+            leaf_aggregation_vk_committment,
+            node_aggregation_vk_committment,
+            g2_points,
+        }
+    }
+}
+
+pub fn find_verification_key(
+    verification_keys: &[ZkSyncVerificationKey<Bn256>],
+    circuit_type: u8,
+) -> &ZkSyncVerificationKey<Bn256> {
+    verification_keys
+        .iter()
+        .find(|el| el.numeric_circuit_type() == circuit_type)
+        .unwrap_or_else(|| panic!("no verification key for circuit type {}", circuit_type))
+}
+
+#[derive(Derivative)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BundleLayer {
+    Basic,
+    Leaf,
+    // depth
+    Node(usize),
+    Scheduler,
+}
+
+#[derive(Derivative)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub enum BlockProofBundleError {
+    InvalidSplittingFactor,
+    NoBasicProofs,
+    MissingVerificationKey(u8),
+    InvalidVerificationKeysSet,
+    InvalidNumberOfProofs {
+        layer: BundleLayer,
+        expected: usize,
+        received: usize,
+    },
+    InvalidNumberOfOutputs {
+        layer: BundleLayer,
+        expected: usize,
+        received: usize,
+    },
+    UnorderedProof {
+        layer: BundleLayer,
+        index: usize,
+    },
+    UnorderedBasicCircuits {
+        index: usize,
+    },
+    WrongCircuitType {
+        layer: BundleLayer,
+        index: usize,
+    },
+    WrongNumberOfInputs {
+        layer: BundleLayer,
+        index: usize,
+    },
+    MismatchingInput {
+        layer: BundleLayer,
+        index: usize,
+    },
+    InvalidProof {
+        layer: BundleLayer,
+        index: usize,
+    },
+    // index of the coordinate
+    InvalidAggregationResult(usize),
+    AggregationResultMismatch(usize),
+    PairingCheckFailed,
+}
+
+fn try_find_verification_key(
+    verification_keys: &[ZkSyncVerificationKey<Bn256>],
+    circuit_type: u8,
+) -> Result<&ZkSyncVerificationKey<Bn256>, BlockProofBundleError> {
+    verification_keys
+        .iter()
+        .find(|el| el.numeric_circuit_type() == circuit_type)
+        .ok_or(BlockProofBundleError::MissingVerificationKey(circuit_type))
+}
+
+// the only public input of the proof
+fn proof_public_input(
+    layer: BundleLayer,
+    index: usize,
+    proof: &ZkSyncProof<Bn256>,
+) -> Result<Fr, BlockProofBundleError> {
+    match &proof.as_proof().inputs[..] {
+        [public_input] => Ok(*public_input),
+        _ => Err(BlockProofBundleError::WrongNumberOfInputs { layer, index }),
+    }
+}
+
+fn verify_layer(
+    layer: BundleLayer,
+    proofs: &[IndexedProof],
+    expected_circuit_type: Option<u8>,
+    verification_keys: &[ZkSyncVerificationKey<Bn256>],
+    verification: ProofsVerification,
+) -> Result<(), BlockProofBundleError> {
+    for (idx, el) in proofs.iter().enumerate() {
+        if el.index != idx {
+            return Err(BlockProofBundleError::UnorderedProof { layer, index: idx });
+        }
+        let circuit_type_is_valid = match expected_circuit_type {
+            Some(expected_circuit_type) => el.circuit_type == expected_circuit_type,
+            None => true,
+        };
+        if el.circuit_type != el.proof.numeric_circuit_type() || !circuit_type_is_valid {
+            return Err(BlockProofBundleError::WrongCircuitType { layer, index: idx });
+        }
+        let _ = proof_public_input(layer, idx, &el.proof)?;
+
+        let vk = try_find_verification_key(verification_keys, el.circuit_type)?;
+        if verification == ProofsVerification::Verify && vk.verify_proof(&el.proof) == false {
+            return Err(BlockProofBundleError::InvalidProof { layer, index: idx });
+        }
+    }
+
+    Ok(())
+}
+
+fn check_number_of_proofs(
+    layer: BundleLayer,
+    expected: usize,
+    received: usize,
+) -> Result<(), BlockProofBundleError> {
+    if expected != received {
+        return Err(BlockProofBundleError::InvalidNumberOfProofs {
+            layer,
+            expected,
+            received,
+        });
+    }
+
+    Ok(())
+}
+
+fn check_number_of_outputs(
+    layer: BundleLayer,
+    expected: usize,
+    received: usize,
+) -> Result<(), BlockProofBundleError> {
+    if expected != received {
+        return Err(BlockProofBundleError::InvalidNumberOfOutputs {
+            layer,
+            expected,
+            received,
+        });
+    }
+
+    Ok(())
+}
+
+fn fq_from_be_bytes(value: &[u8; 32]) -> Option<Fq> {
+    let mut repr = <Fq as PrimeField>::Repr::default();
+    repr.read_be(&value[..]).unwrap();
+
+    Fq::from_repr(repr).ok()
+}
+
+fn g1_point_from_be_coordinates(x: &[u8; 32], y: &[u8; 32]) -> Option<G1Affine> {
+    let x = fq_from_be_bytes(x)?;
+    let y = fq_from_be_bytes(y)?;
+
+    G1Affine::from_xy_checked(x, y).ok()
+}
+
+fn check_limbs_against_coordinate(limbs: &[Fr; NUM_LIMBS], coordinate: Fq) -> bool {
+    use sync_vm::franklin_crypto::plonk::circuit::bigint::split_into_limbs;
+
+    let rns_params = get_prefered_rns_params();
+    let (expected_limbs, _) = split_into_limbs(coordinate, &rns_params);
+
+    &expected_limbs[..] == &limbs[..]
+}
+
+// checks every proof in the bundle against the set of verification keys (one per circuit type,
+// e.g. as produced from `circuits_for_vk_generation`), and that all the public inputs are consistent:
+// - basic circuits inputs match the proofs and are split into leaf aggregations in the same way as scheduler does it
+// - node aggregations consume previous layer in the same way as `prepare_node_aggregations` does it
+// - final aggregation result satisfies the pairing check and the scheduler's public input is formed from the header.
+// Returns the first inconsistency
+pub fn verify_block_proof_bundle(
+    bundle: &BlockProofBundle,
+    verification_keys: &[ZkSyncVerificationKey<Bn256>],
+) -> Result<(), BlockProofBundleError> {
+    verify_block_proof_bundle_with_verification(
+        bundle,
+        verification_keys,
+        ProofsVerification::Verify,
+    )
+}
+
+// same as `verify_block_proof_bundle`, but proofs are treated according to `verification`.
+// Bundle of mock proofs has mock aggregation results, so the pairing check is skipped too
+pub fn verify_block_proof_bundle_with_verification(
+    bundle: &BlockProofBundle,
+    verification_keys: &[ZkSyncVerificationKey<Bn256>],
+    verification: ProofsVerification,
+) -> Result<(), BlockProofBundleError> {
+    let BlockProofBundle {
+        splitting_factor_for_leafs,
+        splitting_factor_for_nodes,
+        basic_circuits_inputs,
+        basic_circuits_proofs,
+        leaf_aggregation_proofs,
+        leaf_aggregation_outputs,
+        node_aggregation_proofs,
+        node_aggregation_outputs,
+        scheduler_proof,
+        aggregation_result,
+        previous_block_formal_hash,
+        block_header,
+    } = bundle;
+
+    let splitting_factor_for_leafs = *splitting_factor_for_leafs;
+    let splitting_factor_for_nodes = *splitting_factor_for_nodes;
+    if splitting_factor_for_leafs == 0 || splitting_factor_for_nodes == 0 {
+        return Err(BlockProofBundleError::InvalidSplittingFactor);
+    }
+    // otherwise there are no leafs to aggregate
+    if basic_circuits_proofs.is_empty() {
+        return Err(BlockProofBundleError::NoBasicProofs);
+    }
+
+    let round_function = get_prefered_committer();
+
+    for circuit_type in [
+        CircuitType::Scheduler as u8,
+        CircuitType::IntermidiateNode as u8,
+        CircuitType::Leaf as u8,
+    ] {
+        let _ = try_find_verification_key(verification_keys, circuit_type)?;
+    }
+    let basic_circuit_types: HashSet<_> = verification_keys
+        .iter()
+        .map(|el| el.numeric_circuit_type())
+        .filter(|el| *el >= CircuitType::VM as u8)
+        .collect();
+    if basic_circuit_types.len() != sync_vm::scheduler::NUM_CIRCUIT_TYPES_TO_SCHEDULE
+        || verification_keys.len() != basic_circuit_types.len() + 3
+    {
+        return Err(BlockProofBundleError::InvalidVerificationKeysSet);
+    }
+
+    let VerificationKeysCommittments {
+        leaf_vks_committment,
+        leaf_aggregation_vk_committment,
+        node_aggregation_vk_committment,
+        g2_points,
+        ..
+    } = VerificationKeysCommittments::from_verification_keys(verification_keys);
+
+    // basic circuits
+
+    let flattened_expected_inputs = basic_circuits_inputs.clone().into_flattened_set();
+    check_number_of_proofs(
+        BundleLayer::Basic,
+        flattened_expected_inputs.len(),
+        basic_circuits_proofs.len(),
+    )?;
+
+    verify_layer(
+        BundleLayer::Basic,
+        basic_circuits_proofs,
+        None,
+        verification_keys,
+        verification,
+    )?;
+
+    let mut recursion_requests_queue_simulator = RecursionQueueSimulator::empty();
+    let mut previous_circuit_type = CircuitType::VM as u8;
+
+    for (idx, (el, expected_public_input)) in basic_circuits_proofs
+        .iter()
+        .zip(flattened_expected_inputs.into_iter())
+        .enumerate()
+    {
+        if el.circuit_type < previous_circuit_type {
+            return Err(BlockProofBundleError::UnorderedBasicCircuits { index: idx });
+        }
+        previous_circuit_type = el.circuit_type;
+
+        if proof_public_input(BundleLayer::Basic, idx, &el.proof)? != expected_public_input {
+            return Err(BlockProofBundleError::MismatchingInput {
+                layer: BundleLayer::Basic,
+                index: idx,
+            });
+        }
+
+        let req = RecursionRequest {
+            circuit_type: el.circuit_type,
+            public_input: expected_public_input,
+        };
+        let _ = recursion_requests_queue_simulator.push(req, &round_function);
+    }
+
+    // leafs
+
+    let num_leafs =
+        (basic_circuits_proofs.len() + splitting_factor_for_leafs - 1) / splitting_factor_for_leafs;
+    check_number_of_proofs(BundleLayer::Leaf, num_leafs, leaf_aggregation_proofs.len())?;
+    check_number_of_outputs(BundleLayer::Leaf, num_leafs, leaf_aggregation_outputs.len())?;
+
+    verify_layer(
+        BundleLayer::Leaf,
+        leaf_aggregation_proofs,
+        Some(CircuitType::Leaf as u8),
+        verification_keys,
+        verification,
+    )?;
+
+    let mut leaf_layer_subqueues = vec![];
+    let mut queue = recursion_requests_queue_simulator;
+    for _ in 0..(num_leafs - 1) {
+        let (chunk, rest) = queue.split(splitting_factor_for_leafs as u32);
+        leaf_layer_subqueues.push(chunk);
+        queue = rest;
+    }
+    leaf_layer_subqueues.push(queue);
+
+    for (idx, ((el, subqueue), output)) in leaf_aggregation_proofs
+        .iter()
+        .zip(leaf_layer_subqueues.iter())
+        .zip(leaf_aggregation_outputs.iter())
+        .enumerate()
+    {
+        let expected_public_input = leaf_aggregation_public_input(
+            take_queue_state_from_simulator(subqueue),
+            leaf_vks_committment,
+            output.clone(),
+        );
+
+        if proof_public_input(BundleLayer::Leaf, idx, &el.proof)? != expected_public_input {
+            return Err(BlockProofBundleError::MismatchingInput {
+                layer: BundleLayer::Leaf,
+                index: idx,
+            });
+        }
+    }
+
+    // nodes
+
+    if node_aggregation_proofs.is_empty() {
+        return Err(BlockProofBundleError::InvalidNumberOfProofs {
+            layer: BundleLayer::Node(0),
+            expected: 1,
+            received: 0,
+        });
+    }
+    check_number_of_outputs(
+        BundleLayer::Node(0),
+        node_aggregation_proofs.len(),
+        node_aggregation_outputs.len(),
+    )?;
+
+    let mut previous_sequence = leaf_layer_subqueues;
+
+    for (depth, (proofs, outputs)) in node_aggregation_proofs
+        .iter()
+        .zip(node_aggregation_outputs.iter())
+        .enumerate()
+    {
+        let layer = BundleLayer::Node(depth);
+
+        let mut merged = vec![];
+        for chunk in previous_sequence.chunks(splitting_factor_for_nodes) {
+            let mut first = chunk[0].clone();
+            for second in chunk[1..].iter().cloned() {
+                first = QueueSimulator::merge(first, second);
+            }
+
+            merged.push(first);
+        }
+
+        check_number_of_proofs(layer, merged.len(), proofs.len())?;
+        check_number_of_outputs(layer, merged.len(), outputs.len())?;
+
+        verify_layer(
+            layer,
+            proofs,
+            Some(CircuitType::IntermidiateNode as u8),
+            verification_keys,
+            verification,
+        )?;
+
+        for (idx, ((el, subqueue), output)) in proofs
+            .iter()
+            .zip(merged.iter())
+            .zip(outputs.iter())
+            .enumerate()
+        {
+            let expected_public_input = node_aggregation_public_input(
+                take_queue_state_from_simulator(subqueue),
+                leaf_vks_committment,
+                node_aggregation_vk_committment,
+                leaf_aggregation_vk_committment,
+                output.clone(),
+            );
+
+            if proof_public_input(layer, idx, &el.proof)? != expected_public_input {
+                return Err(BlockProofBundleError::MismatchingInput { layer, index: idx });
+            }
+        }
+
+        previous_sequence = merged;
+    }
+
+    // node aggregation must end with a single proof
+    check_number_of_proofs(
+        BundleLayer::Node(node_aggregation_proofs.len() - 1),
+        1,
+        previous_sequence.len(),
+    )?;
+
+    // final aggregation result
+
+    let final_node_aggregation = &node_aggregation_outputs.last().unwrap()[0];
+    let final_limbs = [
+        &final_node_aggregation.pair_with_generator_x,
+        &final_node_aggregation.pair_with_generator_y,
+        &final_node_aggregation.pair_with_x_x,
+        &final_node_aggregation.pair_with_x_y,
+    ];
+    for (idx, (limbs, coordinate)) in final_limbs
+        .iter()
+        .zip(aggregation_result.iter())
+        .enumerate()
+    {
+        let coordinate = fq_from_be_bytes(coordinate)
+            .ok_or(BlockProofBundleError::InvalidAggregationResult(idx))?;
+        if !check_limbs_against_coordinate(limbs, coordinate) {
+            return Err(BlockProofBundleError::AggregationResultMismatch(idx));
+        }
+    }
+
+    if verification != ProofsVerification::Mock {
+        let pair_with_generator =
+            g1_point_from_be_coordinates(&aggregation_result[0], &aggregation_result[1])
+                .ok_or(BlockProofBundleError::InvalidAggregationResult(0))?;
+        let pair_with_x =
+            g1_point_from_be_coordinates(&aggregation_result[2], &aggregation_result[3])
+                .ok_or(BlockProofBundleError::InvalidAggregationResult(2))?;
+        let [g2_generator, g2_x]: [G2Affine; 2] = g2_points;
+
+        let pairing_result = Bn256::final_exponentiation(&Bn256::miller_loop(&[
+            (&pair_with_generator.prepare(), &g2_generator.prepare()),
+            (&pair_with_x.prepare(), &g2_x.prepare()),
+        ]));
+        if pairing_result != Some(<Bn256 as Engine>::Fqk::one()) {
+            return Err(BlockProofBundleError::PairingCheckFailed);
+        }
+    }
+
+    // scheduler
+
+    verify_layer(
+        BundleLayer::Scheduler,
+        std::slice::from_ref(scheduler_proof),
+        Some(CircuitType::Scheduler as u8),
+        verification_keys,
+        verification,
+    )?;
+
+    let expected_public_input = scheduler_public_input(
+        *previous_block_formal_hash,
+        *block_header,
+        leaf_vks_committment,
+        node_aggregation_vk_committment,
+        leaf_aggregation_vk_committment,
+        *aggregation_result,
+    );
+
+    if proof_public_input(BundleLayer::Scheduler, 0, &scheduler_proof.proof)?
+        != expected_public_input
+    {
+        return Err(BlockProofBundleError::MismatchingInput {
+            layer: BundleLayer::Scheduler,
+            index: 0,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::witness::proving_jobs::create_test_block_executor;
+
+    // bundle of a small block proven with `MockProver`, and the verification keys
+    fn mock_block_proof_bundle() -> (BlockProofBundle, Vec<ZkSyncVerificationKey<Bn256>>) {
+        let (mut executor, prover) = create_test_block_executor();
+        while !executor.is_complete() {
+            for (id, circuit) in executor.take_ready_circuits().into_iter() {
+                executor.submit_proof(id, prover.prove(circuit)).unwrap();
+            }
+        }
+
+        (
+            executor.into_block_proof_bundle().unwrap(),
+            prover.create_verification_keys(),
+        )
+    }
+
+    #[test]
+    fn test_verify_block_proof_bundle() {
+        let (bundle, verification_keys) = mock_block_proof_bundle();
+        let verify = |bundle: &BlockProofBundle| {
+            verify_block_proof_bundle_with_verification(
+                bundle,
+                &verification_keys,
+                ProofsVerification::Mock,
+            )
+        };
+
+        assert_eq!(verify(&bundle), Ok(()));
+        // mock proofs never pass a real verification
+        assert_eq!(
+            verify_block_proof_bundle(&bundle, &verification_keys),
+            Err(BlockProofBundleError::InvalidProof {
+                layer: BundleLayer::Basic,
+                index: 0
+            })
+        );
+
+        let mut tampered = bundle.clone();
+        tampered.basic_circuits_inputs.main_vm_circuits[0] = Fr::zero();
+        assert_eq!(
+            verify(&tampered),
+            Err(BlockProofBundleError::MismatchingInput {
+                layer: BundleLayer::Basic,
+                index: 0
+            })
+        );
+
+        let mut tampered = bundle.clone();
+        let num_leafs = tampered.leaf_aggregation_proofs.len();
+        tampered.leaf_aggregation_proofs.pop();
+        assert_eq!(
+            verify(&tampered),
+            Err(BlockProofBundleError::InvalidNumberOfProofs {
+                layer: BundleLayer::Leaf,
+                expected: num_leafs,
+                received: num_leafs - 1
+            })
+        );
+
+        let mut tampered = bundle.clone();
+        tampered.aggregation_result[1][31] ^= 1;
+        assert_eq!(
+            verify(&tampered),
+            Err(BlockProofBundleError::AggregationResultMismatch(1))
+        );
+
+        let mut tampered = bundle.clone();
+        tampered.previous_block_formal_hash[0] ^= 1;
+        assert_eq!(
+            verify(&tampered),
+            Err(BlockProofBundleError::MismatchingInput {
+                layer: BundleLayer::Scheduler,
+                index: 0
+            })
+        );
+
+        let mut tampered = bundle.clone();
+        tampered.basic_circuits_proofs.clear();
+        assert_eq!(verify(&tampered), Err(BlockProofBundleError::NoBasicProofs));
+    }
+}
@@ -0,0 +1,402 @@
+use crate::witness::callstack_handler::*;
+use crate::witness::tracer::{QueryMarker, WitnessTracer};
+use derivative::Derivative;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use zk_evm::aux_structures::LogQuery;
+use zk_evm::ethereum_types::{Address, U256};
+use zk_evm::vm_state::CallStackEntry;
+use zk_evm::zkevm_opcode_defs::system_params::{
+    EVENT_AUX_BYTE, L1_MESSAGE_AUX_BYTE, STORAGE_AUX_BYTE,
+};
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CallOutcome {
+    Ok,
+    Reverted,
+    // frame has exited because of the exception (e.g. out of ergs). Explicit `ret.panic`
+    // can not be distinguished from the revert by the tracer, so it's reported as such
+    Panicked,
+    // frame didn't exit before the end of the execution (e.g. the root one)
+    Unfinished,
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct CallTreeLog {
+    pub cycle: u32,
+    pub address: String,
+    pub key: String,
+    pub value: String,
+    pub rolled_back: bool,
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct CallTreeNode {
+    pub frame_index: usize,
+    pub caller: String,
+    pub callee: String,
+    pub code_address: String,
+    pub is_near_call: bool,
+    pub is_static: bool,
+    pub beginning_cycle: u32,
+    pub end_cycle: u32,
+    // including children
+    pub cycles: u32,
+    pub ergs_passed: u32,
+    // at the beginning of the last cycle of the frame, if it was executed at all
+    pub ergs_remaining: Option<u32>,
+    pub outcome: CallOutcome,
+    pub storage_writes: Vec<CallTreeLog>,
+    pub events: Vec<CallTreeLog>,
+    pub l1_messages: Vec<CallTreeLog>,
+    // of the logs above
+    pub num_rolled_back: usize,
+    pub children: Vec<CallTreeNode>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FoldedStackWeight {
+    Cycles,
+    Ergs,
+}
+
+// Tree of the calls (both far and near) reconstructed from the history of the callstack
+// of the witness tracer
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct CallTree {
+    pub root: CallTreeNode,
+}
+
+pub(crate) fn format_address(address: &Address) -> String {
+    format!("0x{}", hex::encode(address.as_bytes()))
+}
+
+pub(crate) fn format_u256(value: &U256) -> String {
+    let mut buffer = [0u8; 32];
+    value.to_big_endian(&mut buffer);
+    format!("0x{}", hex::encode(&buffer))
+}
+
+impl CallTreeNode {
+    fn new(frame_index: usize, entry: &CallStackEntry, beginning_cycle: u32) -> Self {
+        Self {
+            frame_index,
+            caller: format_address(&entry.msg_sender),
+            callee: format_address(&entry.this_address),
+            code_address: format_address(&entry.code_address),
+            is_near_call: entry.is_local_frame,
+            is_static: entry.is_static,
+            beginning_cycle,
+            end_cycle: beginning_cycle,
+            cycles: 0,
+            ergs_passed: entry.ergs_remaining,
+            ergs_remaining: None,
+            outcome: CallOutcome::Unfinished,
+            storage_writes: vec![],
+            events: vec![],
+            l1_messages: vec![],
+            num_rolled_back: 0,
+            children: vec![],
+        }
+    }
+
+    pub fn self_cycles(&self) -> u32 {
+        let children_cycles: u32 = self.children.iter().map(|el| el.cycles).sum();
+        self.cycles.saturating_sub(children_cycles)
+    }
+
+    pub fn ergs_spent(&self) -> u32 {
+        match self.ergs_remaining {
+            Some(ergs_remaining) => self.ergs_passed.saturating_sub(ergs_remaining),
+            None => 0,
+        }
+    }
+
+    pub fn self_ergs_spent(&self) -> u32 {
+        let children_ergs: u32 = self.children.iter().map(|el| el.ergs_spent()).sum();
+        self.ergs_spent().saturating_sub(children_ergs)
+    }
+
+    fn name(&self) -> String {
+        if self.frame_index == 0 {
+            return "root".to_string();
+        }
+        let mut name = self.callee.clone();
+        if self.code_address != self.callee {
+            name.push_str(&format!("[{}]", self.code_address));
+        }
+        if self.is_near_call {
+            name.push_str(":near");
+        }
+
+        name
+    }
+
+    fn walk<'a>(&'a self, dst: &mut Vec<&'a CallTreeNode>) {
+        dst.push(self);
+        for child in self.children.iter() {
+            child.walk(dst);
+        }
+    }
+
+    fn write_folded_stacks(&self, prefix: &str, weight: FoldedStackWeight, dst: &mut String) {
+        let stack = if prefix.is_empty() {
+            self.name()
+        } else {
+            format!("{};{}", prefix, self.name())
+        };
+        let value = match weight {
+            FoldedStackWeight::Cycles => self.self_cycles(),
+            FoldedStackWeight::Ergs => self.self_ergs_spent(),
+        };
+        if value != 0 {
+            dst.push_str(&format!("{} {}\n", stack, value));
+        }
+        for child in self.children.iter() {
+            child.write_folded_stacks(&stack, weight, dst);
+        }
+    }
+}
+
+impl CallTree {
+    pub fn from_tracer(tracer: &WitnessTracer) -> Self {
+        Self::from_callstack(&tracer.callstack_with_aux_data)
+    }
+
+    pub fn from_callstack(callstack: &CallstackWithAuxData) -> Self {
+        assert!(
+            callstack.depth == 0,
+            "call tree can only be built after the execution is complete"
+        );
+
+        // logs of every frame. All the frames are merged into the root one by now, and
+        // applied rollbacks are in the forward queue
+        let mut logs: HashMap<usize, Vec<(usize, u32, LogQuery)>> = HashMap::new();
+        let mut rolled_back = HashSet::new();
+        for el in callstack.current_entry.forward_queue.iter() {
+            match el {
+                ExtendedLogQuery::Query {
+                    marker:
+                        QueryMarker::Forward {
+                            in_frame, index, ..
+                        },
+                    cycle,
+                    query,
+                } => {
+                    logs.entry(*in_frame)
+                        .or_default()
+                        .push((*index, *cycle, *query));
+                }
+                ExtendedLogQuery::Query {
+                    marker:
+                        QueryMarker::Rollback {
+                            in_frame, index, ..
+                        },
+                    ..
+                } => {
+                    rolled_back.insert((*in_frame, *index));
+                }
+                _ => {}
+            }
+        }
+
+        let mut stack: Vec<CallTreeNode> = vec![];
+        let mut last_cycle = 0u32;
+        for el in callstack.full_history.iter() {
+            match el.action {
+                CallstackAction::OutOfScope(OutOfScopeReason::Fresh) => {
+                    stack.push(CallTreeNode::new(
+                        el.frame_index,
+                        &el.affected_entry,
+                        el.beginning_cycle,
+                    ));
+                }
+                CallstackAction::OutOfScope(OutOfScopeReason::Exited { panic }) => {
+                    let mut node = stack.pop().expect("exited frame must be in the stack");
+                    assert_eq!(node.frame_index, el.frame_index);
+                    let end_cycle = el.end_cycle.expect("frame must end");
+                    last_cycle = std::cmp::max(last_cycle, end_cycle);
+
+                    node.end_cycle = end_cycle;
+                    node.ergs_remaining = el.last_cycle_state.map(|el| el.ergs_remaining);
+                    let pending_exception = el
+                        .last_cycle_state
+                        .map(|el| el.pending_exception)
+                        .unwrap_or(false);
+                    node.outcome = match (panic, pending_exception) {
+                        (false, _) => CallOutcome::Ok,
+                        (true, false) => CallOutcome::Reverted,
+                        (true, true) => CallOutcome::Panicked,
+                    };
+
+                    let parent = stack.last_mut().expect("root frame never exits");
+                    parent.children.push(node);
+                }
+                CallstackAction::PushToStack | CallstackAction::PopFromStack { .. } => {}
+            }
+        }
+
+        // close the frames that didn't exit
+        let mut root = None;
+        while let Some(mut node) = stack.pop() {
+            node.end_cycle = last_cycle;
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => root = Some(node),
+            }
+        }
+        let mut root = root.expect("callstack history must contain the root frame");
+
+        fn finalize(
+            node: &mut CallTreeNode,
+            logs: &mut HashMap<usize, Vec<(usize, u32, LogQuery)>>,
+            rolled_back: &HashSet<(usize, usize)>,
+        ) {
+            node.cycles = node.end_cycle - node.beginning_cycle;
+            for (index, cycle, query) in logs.remove(&node.frame_index).unwrap_or_default() {
+                let log = CallTreeLog {
+                    cycle,
+                    address: format_address(&query.address),
+                    key: format_u256(&query.key),
+                    value: format_u256(&query.written_value),
+                    rolled_back: rolled_back.contains(&(node.frame_index, index)),
+                };
+                // other rollbackable queries are not interesting
+                let dst = match query.aux_byte {
+                    STORAGE_AUX_BYTE => &mut node.storage_writes,
+                    EVENT_AUX_BYTE => &mut node.events,
+                    L1_MESSAGE_AUX_BYTE => &mut node.l1_messages,
+                    _ => continue,
+                };
+                if log.rolled_back {
+                    node.num_rolled_back += 1;
+                }
+                dst.push(log);
+            }
+            for child in node.children.iter_mut() {
+                finalize(child, logs, rolled_back);
+            }
+        }
+
+        let mut logs = logs;
+        finalize(&mut root, &mut logs, &rolled_back);
+        assert!(logs.is_empty(), "some logs are emitted by unknown frames");
+
+        Self { root }
+    }
+
+    // all the nodes in the depth-first order
+    pub fn nodes(&self) -> Vec<&CallTreeNode> {
+        let mut result = vec![];
+        self.root.walk(&mut result);
+
+        result
+    }
+
+    pub fn write_json<W: Write>(&self, dst: W) {
+        serde_json::to_writer_pretty(dst, self).expect("must serialize the call tree");
+    }
+
+    // in the format of `stackcollapse` scripts, so can be used with flamegraph tools directly.
+    // Every frame is weighted by it's own cost, without children
+    pub fn to_folded_stacks(&self, weight: FoldedStackWeight) -> String {
+        let mut result = String::new();
+        self.root.write_folded_stacks("", weight, &mut result);
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry_point::initial_out_of_circuit_context;
+    use zk_evm::aux_structures::Timestamp;
+
+    fn log_query(aux_byte: u8, key: u64) -> LogQuery {
+        LogQuery {
+            timestamp: Timestamp(0),
+            tx_number_in_block: 0,
+            aux_byte,
+            shard_id: 0,
+            address: Address::from_low_u64_be(0x8000),
+            key: U256::from(key),
+            read_value: U256::zero(),
+            written_value: U256::from(key + 1),
+            rw_flag: true,
+            rollback: false,
+            is_service: false,
+        }
+    }
+
+    #[test]
+    fn test_call_tree_from_callstack() {
+        let bootloader = Address::from_low_u64_be(0x8001);
+        let contract = Address::from_low_u64_be(0x10000);
+        let bootloader_entry =
+            initial_out_of_circuit_context(0, 1000, bootloader, Address::zero(), bootloader);
+        let contract_entry = initial_out_of_circuit_context(0, 300, contract, bootloader, contract);
+
+        let mut callstack = CallstackWithAuxData::from_initial_callstack(0, bootloader_entry);
+        callstack.observe_cycle(1, 1000, false);
+        callstack.add_log_query(1, log_query(STORAGE_AUX_BYTE, 1));
+        callstack.push_entry(2, bootloader_entry, contract_entry);
+        callstack.observe_cycle(3, 300, false);
+        callstack.add_log_query(3, log_query(EVENT_AUX_BYTE, 2));
+        callstack.add_log_query(3, log_query(STORAGE_AUX_BYTE, 3));
+        callstack.observe_cycle(5, 0, true);
+        let _ = callstack.pop_entry(5, true);
+        callstack.observe_cycle(6, 700, false);
+        callstack.add_log_query(6, log_query(L1_MESSAGE_AUX_BYTE, 4));
+        callstack.observe_cycle(10, 600, false);
+        let _ = callstack.pop_entry(10, false);
+
+        let call_tree = CallTree::from_callstack(&callstack);
+        assert_eq!(call_tree.nodes().len(), 3);
+        assert_eq!(call_tree.root.outcome, CallOutcome::Unfinished);
+
+        let bootloader_node = &call_tree.root.children[0];
+        assert_eq!(bootloader_node.outcome, CallOutcome::Ok);
+        assert_eq!(bootloader_node.cycles, 10);
+        assert_eq!(bootloader_node.self_cycles(), 7);
+        assert_eq!(bootloader_node.ergs_passed, 1000);
+        assert_eq!(bootloader_node.ergs_spent(), 400);
+        assert_eq!(bootloader_node.storage_writes.len(), 1);
+        assert_eq!(bootloader_node.l1_messages.len(), 1);
+        assert_eq!(bootloader_node.num_rolled_back, 0);
+
+        let contract_node = &bootloader_node.children[0];
+        assert_eq!(contract_node.callee, format_address(&contract));
+        assert_eq!(contract_node.caller, format_address(&bootloader));
+        assert_eq!(contract_node.outcome, CallOutcome::Panicked);
+        assert_eq!(contract_node.cycles, 3);
+        assert_eq!(contract_node.ergs_spent(), 300);
+        assert_eq!(contract_node.events.len(), 1);
+        assert_eq!(contract_node.storage_writes.len(), 1);
+        assert_eq!(contract_node.num_rolled_back, 2);
+        assert!(contract_node.events[0].rolled_back);
+
+        let folded = call_tree.to_folded_stacks(FoldedStackWeight::Cycles);
+        let bootloader_name = format_address(&bootloader);
+        assert_eq!(
+            folded,
+            format!(
+                "root;{} 7\nroot;{};{} 3\n",
+                bootloader_name,
+                bootloader_name,
+                format_address(&contract)
+            )
+        );
+        let folded = call_tree.to_folded_stacks(FoldedStackWeight::Ergs);
+        assert!(folded.starts_with(&format!("root;{} 100\n", bootloader_name)));
+
+        let mut buffer = vec![];
+        call_tree.write_json(&mut buffer);
+        let decoded: CallTree = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(decoded, call_tree);
+    }
+}
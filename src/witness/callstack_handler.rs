@@ -71,6 +71,14 @@ pub enum CallstackAction {
     PopFromStack { panic: bool },
 }
 
+// state of the frame at the beginning of the latest cycle when it was executed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FrameCycleState {
+    pub cycle: u32,
+    pub ergs_remaining: u32,
+    pub pending_exception: bool,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct CallstackActionHistoryEntry {
     pub action: CallstackAction,
@@ -79,6 +87,7 @@ pub struct CallstackActionHistoryEntry {
     pub beginning_cycle: u32,
     pub end_cycle: Option<u32>,
     pub actions: Vec<(u32, LogAction)>,
+    pub last_cycle_state: Option<FrameCycleState>,
 }
 
 impl CallstackActionHistoryEntry {
@@ -91,6 +100,7 @@ impl CallstackActionHistoryEntry {
             end_cycle: None,
             // last_action_of_parent: None,
             actions: vec![],
+            last_cycle_state: None,
         }
     }
 }
@@ -137,6 +147,7 @@ impl CallstackWithAuxData {
             beginning_cycle: 0,
             end_cycle: None,
             actions: vec![],
+            last_cycle_state: None,
         };
 
         let new = Self {
@@ -198,6 +209,7 @@ impl CallstackWithAuxData {
                 beginning_cycle: monotonic_cycle_counter,
                 end_cycle: None,
                 actions: vec![],
+                last_cycle_state: None,
             },
             parent_frame_index: current_frame_index,
             frame_index: new_counter,
@@ -344,6 +356,21 @@ impl CallstackWithAuxData {
         current.entry
     }
 
+    // should be called at the beginning of every cycle, so the state of the frame
+    // at the moment of exit is known
+    pub fn observe_cycle(
+        &mut self,
+        monotonic_cycle_counter: u32,
+        ergs_remaining: u32,
+        pending_exception: bool,
+    ) {
+        self.current_entry.current_history_record.last_cycle_state = Some(FrameCycleState {
+            cycle: monotonic_cycle_counter,
+            ergs_remaining,
+            pending_exception,
+        });
+    }
+
     pub fn add_log_query(&mut self, monotonic_cycle_counter: u32, log_query: LogQuery) {
         let current_frame_index = self.current_entry.frame_index;
         let unique_query_id = self.unique_query_id_counter;
@@ -0,0 +1,548 @@
+use crate::witness::call_tree::{format_address, format_u256};
+use derivative::Derivative;
+use std::collections::HashSet;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use zk_evm::aux_structures::{DecommittmentQuery, LogQuery, MemoryQuery};
+use zk_evm::ethereum_types::Address;
+use zk_evm::vm_state::VmLocalState;
+use zk_evm::zkevm_opcode_defs::decoding::{EncodingModeProduction, VmEncodingMode};
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct CycleTraceRegister {
+    pub value: String,
+    pub is_pointer: bool,
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct CycleTraceFlags {
+    pub overflow_or_less_than: bool,
+    pub equal: bool,
+    pub greater_than: bool,
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct CycleTraceMemoryQuery {
+    pub timestamp: u32,
+    pub memory_type: String,
+    pub page: u32,
+    pub index: u32,
+    pub value: String,
+    pub value_is_pointer: bool,
+    pub rw_flag: bool,
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct CycleTraceLogQuery {
+    pub timestamp: u32,
+    pub aux_byte: u8,
+    pub shard_id: u8,
+    pub address: String,
+    pub key: String,
+    pub read_value: String,
+    pub written_value: String,
+    pub rw_flag: bool,
+    pub rollback: bool,
+    pub is_service: bool,
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct CycleTraceDecommittmentQuery {
+    pub timestamp: u32,
+    pub hash: String,
+    pub memory_page: u32,
+    pub decommitted_length: u16,
+    pub is_fresh: bool,
+}
+
+// One line of the trace. Opcode is taken from the code word that was used by the cycle,
+// so it's absent if the cycle didn't fetch anything (e.g. it only processed the pending exception)
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct CycleTraceRecord {
+    pub cycle: u32,
+    pub pc: u16,
+    pub raw_opcode: Option<String>,
+    pub opcode: Option<String>,
+    pub this_address: String,
+    pub code_address: String,
+    pub ergs_remaining: u32,
+    pub registers: Vec<CycleTraceRegister>,
+    pub flags: CycleTraceFlags,
+    pub pending_exception: bool,
+    pub memory_queries: Vec<CycleTraceMemoryQuery>,
+    pub log_queries: Vec<CycleTraceLogQuery>,
+    pub decommittment_queries: Vec<CycleTraceDecommittmentQuery>,
+}
+
+// Cycles are traced if they are in the range (if any), and executed in the frame of one of the
+// addresses (if any). Address is the `this` address of the frame, not the code address
+#[derive(Derivative)]
+#[derivative(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CycleTraceFilter {
+    pub cycles: Option<Range<u32>>,
+    pub addresses: Option<HashSet<Address>>,
+}
+
+impl CycleTraceFilter {
+    pub fn matches(&self, cycle: u32, address: &Address) -> bool {
+        if let Some(cycles) = self.cycles.as_ref() {
+            if !cycles.contains(&cycle) {
+                return false;
+            }
+        }
+        if let Some(addresses) = self.addresses.as_ref() {
+            if !addresses.contains(address) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+// Sink for the per-cycle trace of the witness tracer. Default one has no writer and
+// does nothing. Writer is shared between the clones of the tracer
+#[derive(Derivative)]
+#[derivative(Clone, Debug, Default)]
+pub struct CycleTraceSink {
+    #[derivative(Debug = "ignore")]
+    writer: Option<Arc<Mutex<Box<dyn Write + Send>>>>,
+    pub filter: CycleTraceFilter,
+    current: Option<CycleTraceRecord>,
+    // code page of the frame the current cycle has started in
+    current_code_page: u32,
+}
+
+// raw opcode at `pc` from the cached code word. Cache is invalidated (and the word may be
+// reset) by calls and returns, so it's only valid if no frame change happened after the fetch
+fn raw_opcode_at(state: &VmLocalState, pc: u16) -> Option<u64> {
+    if state.did_call_or_ret_recently || state.previous_super_pc != pc >> 2 {
+        return None;
+    }
+    let sub_pc = (pc & 3) as usize;
+
+    Some(state.previous_code_word.0[3 - sub_pc])
+}
+
+fn decode_opcode(raw_opcode: u64) -> String {
+    let (variant, _) =
+        <EncodingModeProduction as VmEncodingMode<8>>::parse_preliminary_variant_and_absolute_number(
+            raw_opcode,
+        );
+
+    format!("{:?}", variant)
+}
+
+impl CycleTraceRecord {
+    fn set_opcode(&mut self, raw_opcode: u64) {
+        self.raw_opcode = Some(format!("0x{:016x}", raw_opcode));
+        self.opcode = Some(decode_opcode(raw_opcode));
+    }
+}
+
+impl CycleTraceSink {
+    pub fn new(writer: impl Write + Send + 'static, filter: CycleTraceFilter) -> Self {
+        Self {
+            writer: Some(Arc::new(Mutex::new(Box::new(writer)))),
+            filter,
+            current: None,
+            current_code_page: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn begin_cycle(&mut self, state: &VmLocalState) {
+        if !self.is_enabled() {
+            return;
+        }
+        assert!(
+            self.current.is_none(),
+            "cycle {} was not finished",
+            self.current.as_ref().unwrap().cycle
+        );
+        let frame = &state.callstack.current;
+        if !self
+            .filter
+            .matches(state.monotonic_cycle_counter, &frame.this_address)
+        {
+            return;
+        }
+
+        let registers = state
+            .registers
+            .iter()
+            .map(|el| CycleTraceRegister {
+                value: format_u256(&el.value),
+                is_pointer: el.is_pointer,
+            })
+            .collect();
+
+        let mut record = CycleTraceRecord {
+            cycle: state.monotonic_cycle_counter,
+            pc: frame.pc,
+            raw_opcode: None,
+            opcode: None,
+            this_address: format_address(&frame.this_address),
+            code_address: format_address(&frame.code_address),
+            ergs_remaining: frame.ergs_remaining,
+            registers,
+            flags: CycleTraceFlags {
+                overflow_or_less_than: state.flags.overflow_or_less_than_flag,
+                equal: state.flags.equality_flag,
+                greater_than: state.flags.greater_than_flag,
+            },
+            pending_exception: state.pending_exception,
+            memory_queries: vec![],
+            log_queries: vec![],
+            decommittment_queries: vec![],
+        };
+        // cycle with a pending exception does not fetch the code. Otherwise a valid cached
+        // word is the one that the cycle will use, and it's taken now as the cycle may change the frame
+        if !state.pending_exception {
+            if let Some(raw_opcode) = raw_opcode_at(state, frame.pc) {
+                record.set_opcode(raw_opcode);
+            }
+        }
+
+        self.current = Some(record);
+        self.current_code_page = frame.code_page.0;
+    }
+
+    // queries issued outside of the traced cycle are ignored
+    fn record_for_cycle(&mut self, cycle: u32) -> Option<&mut CycleTraceRecord> {
+        self.current.as_mut().filter(|el| el.cycle == cycle)
+    }
+
+    pub fn add_memory_query(&mut self, cycle: u32, query: &MemoryQuery) {
+        if let Some(record) = self.record_for_cycle(cycle) {
+            record.memory_queries.push(CycleTraceMemoryQuery {
+                timestamp: query.timestamp.0,
+                memory_type: format!("{:?}", query.location.memory_type),
+                page: query.location.page.0,
+                index: query.location.index.0,
+                value: format_u256(&query.value),
+                value_is_pointer: query.value_is_pointer,
+                rw_flag: query.rw_flag,
+            });
+        }
+    }
+
+    pub fn add_log_query(&mut self, cycle: u32, query: &LogQuery) {
+        if let Some(record) = self.record_for_cycle(cycle) {
+            record.log_queries.push(CycleTraceLogQuery {
+                timestamp: query.timestamp.0,
+                aux_byte: query.aux_byte,
+                shard_id: query.shard_id,
+                address: format_address(&query.address),
+                key: format_u256(&query.key),
+                read_value: format_u256(&query.read_value),
+                written_value: format_u256(&query.written_value),
+                rw_flag: query.rw_flag,
+                rollback: query.rollback,
+                is_service: query.is_service,
+            });
+        }
+    }
+
+    pub fn add_decommittment(&mut self, cycle: u32, query: &DecommittmentQuery) {
+        if let Some(record) = self.record_for_cycle(cycle) {
+            record
+                .decommittment_queries
+                .push(CycleTraceDecommittmentQuery {
+                    timestamp: query.timestamp.0,
+                    hash: format_u256(&query.hash),
+                    memory_page: query.memory_page.0,
+                    decommitted_length: query.decommitted_length,
+                    is_fresh: query.is_fresh,
+                });
+        }
+    }
+
+    pub fn end_cycle(&mut self, state: &VmLocalState) {
+        let Some(mut record) = self.current.take() else {
+            return;
+        };
+        // otherwise the word was fetched by this cycle, and is still valid if it did not
+        // change the frame
+        if record.raw_opcode.is_none()
+            && !record.pending_exception
+            && state.callstack.current.code_page.0 == self.current_code_page
+        {
+            if let Some(raw_opcode) = raw_opcode_at(state, record.pc) {
+                record.set_opcode(raw_opcode);
+            }
+        }
+
+        self.write_record(&record);
+    }
+
+    pub fn write_record(&self, record: &CycleTraceRecord) {
+        let Some(writer) = self.writer.as_ref() else {
+            return;
+        };
+        let mut writer = writer.lock().unwrap();
+        serde_json::to_writer(&mut *writer, record).expect("must write the trace record");
+        writer
+            .write_all(b"\n")
+            .expect("must write the trace record");
+    }
+
+    pub fn flush(&self) {
+        if let Some(writer) = self.writer.as_ref() {
+            writer
+                .lock()
+                .unwrap()
+                .flush()
+                .expect("must flush the trace");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use zk_evm::ethereum_types::U256;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn memory_query() -> MemoryQuery {
+        use zk_evm::aux_structures::*;
+
+        MemoryQuery {
+            timestamp: Timestamp(0),
+            location: MemoryLocation {
+                memory_type: MemoryType::Heap,
+                page: MemoryPage(0),
+                index: MemoryIndex(0),
+            },
+            rw_flag: true,
+            is_pended: false,
+            value: U256::from(1u64),
+            value_is_pointer: false,
+        }
+    }
+
+    fn log_query() -> LogQuery {
+        use zk_evm::aux_structures::Timestamp;
+
+        LogQuery {
+            timestamp: Timestamp(0),
+            tx_number_in_block: 0,
+            aux_byte: 0,
+            shard_id: 0,
+            address: Address::zero(),
+            key: U256::zero(),
+            read_value: U256::zero(),
+            written_value: U256::zero(),
+            rw_flag: false,
+            rollback: false,
+            is_service: false,
+        }
+    }
+
+    fn record(cycle: u32) -> CycleTraceRecord {
+        CycleTraceRecord {
+            cycle,
+            pc: 0,
+            raw_opcode: None,
+            opcode: None,
+            this_address: format_address(&Address::zero()),
+            code_address: format_address(&Address::zero()),
+            ergs_remaining: 0,
+            registers: vec![],
+            flags: CycleTraceFlags {
+                overflow_or_less_than: false,
+                equal: false,
+                greater_than: false,
+            },
+            pending_exception: false,
+            memory_queries: vec![],
+            log_queries: vec![],
+            decommittment_queries: vec![],
+        }
+    }
+
+    #[test]
+    fn test_cycle_trace_sink() {
+        let bootloader = Address::from_low_u64_be(0x8001);
+        let filter = CycleTraceFilter {
+            cycles: Some(10..20),
+            addresses: Some(HashSet::from([bootloader])),
+        };
+        assert!(filter.matches(10, &bootloader));
+        assert!(!filter.matches(20, &bootloader));
+        assert!(!filter.matches(15, &Address::zero()));
+        assert!(CycleTraceFilter::default().matches(0, &Address::zero()));
+
+        let mut disabled = CycleTraceSink::default();
+        assert!(!disabled.is_enabled());
+        disabled.add_memory_query(0, &memory_query());
+        disabled.write_record(&record(0));
+
+        let buffer = SharedBuffer::default();
+        let mut sink = CycleTraceSink::new(buffer.clone(), CycleTraceFilter::default());
+        sink.current = Some(record(5));
+        sink.add_memory_query(5, &memory_query());
+        // not the current cycle
+        sink.add_memory_query(4, &memory_query());
+        sink.add_log_query(5, &log_query());
+        let traced = sink.current.take().unwrap();
+        sink.write_record(&traced);
+        sink.clone().write_record(&record(6));
+        sink.flush();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<CycleTraceRecord> = output
+            .lines()
+            .map(|el| serde_json::from_str(el).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], traced);
+        assert_eq!(lines[0].memory_queries.len(), 1);
+        assert_eq!(lines[0].log_queries.len(), 1);
+        assert_eq!(lines[1].cycle, 6);
+    }
+
+    #[test]
+    fn test_cycle_trace_of_real_execution() {
+        use crate::entry_point::create_out_of_circuit_global_context;
+        use crate::toolset::{create_out_of_circuit_vm, create_tools, GeometryConfig};
+        use zk_evm::aux_structures::*;
+        use zk_evm::reference_impls::memory::SimpleMemory;
+        use zk_evm::testing::storage::InMemoryStorage;
+        use zk_evm::utils::{bytecode_to_code_hash, contract_bytecode_to_words};
+        use zk_evm::witness_trace::VmWitnessTracer;
+        use zk_evm::GenericNoopTracer;
+        use zkevm_assembly::Assembly;
+
+        let asm = r#"
+            .text
+            .file	"Test_26"
+            .rodata.cst32
+            .p2align	5
+            .text
+            .globl	__entry
+        __entry:
+        .main:
+            add 1, r0, r1
+            add 2, r1, r2
+            sub.s 1, r2, r3
+            near_call r0, @.inner, @.inner
+            ret.ok r0
+        .inner:
+            add 3, r0, r4
+            ret.ok r0
+        "#;
+        let mut assembly = Assembly::try_from(asm.to_owned()).unwrap();
+        let bytecode = assembly.compile_to_bytecode().unwrap();
+        let code_hash = U256::from_big_endian(&bytecode_to_code_hash(&bytecode).unwrap());
+
+        let geometry = GeometryConfig {
+            cycles_per_vm_snapshot: 1024,
+            ..Default::default()
+        };
+        let mut tools = create_tools(
+            InMemoryStorage::new(),
+            SimpleMemory::new_without_preallocations(),
+            &geometry,
+        );
+        let buffer = SharedBuffer::default();
+        tools.witness_tracer.cycle_trace_sink =
+            CycleTraceSink::new(buffer.clone(), CycleTraceFilter::default());
+
+        tools
+            .decommittment_processor
+            .populate(vec![(code_hash, contract_bytecode_to_words(&bytecode))]);
+        let query = DecommittmentQuery {
+            hash: code_hash,
+            timestamp: Timestamp(sync_vm::scheduler::SCHEDULER_TIMESTAMP),
+            memory_page: MemoryPage(zk_evm::zkevm_opcode_defs::BOOTLOADER_CODE_PAGE),
+            decommitted_length: bytecode.len() as u16,
+            is_fresh: true,
+        };
+        let (query, witness) =
+            tools
+                .decommittment_processor
+                .decommit_into_memory(0, query, &mut tools.memory);
+        tools
+            .witness_tracer
+            .add_decommittment(0, query, witness.unwrap());
+
+        let block_properties = create_out_of_circuit_global_context(false, U256::zero());
+        let entry_point_address = Address::from_low_u64_be(0x8001);
+        let mut vm = create_out_of_circuit_vm(
+            &mut tools,
+            &block_properties,
+            Address::zero(),
+            entry_point_address,
+        );
+        let mut tracer = GenericNoopTracer::<_>::new();
+        for _ in 0..16 {
+            if vm.execution_has_ended() {
+                break;
+            }
+            vm.cycle(&mut tracer);
+        }
+        assert!(vm.execution_has_ended());
+        drop(vm);
+        tools.witness_tracer.cycle_trace_sink.flush();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<CycleTraceRecord> = output
+            .lines()
+            .map(|el| serde_json::from_str(el).unwrap())
+            .collect();
+
+        // main, inner function, and then main again after the near call
+        let pcs: Vec<_> = lines.iter().map(|el| el.pc).collect();
+        assert_eq!(pcs, vec![0, 1, 2, 3, 5, 6, 4]);
+
+        let expected_opcodes = ["Add", "Add", "Sub", "NearCall", "Add", "Ret"];
+        for (line, expected) in lines.iter().zip(expected_opcodes.iter()) {
+            assert!(!line.pending_exception);
+            let opcode = line
+                .opcode
+                .as_ref()
+                .unwrap_or_else(|| panic!("no opcode for pc {}", line.pc));
+            assert!(
+                opcode.contains(expected),
+                "expected {} at pc {}, got {}",
+                expected,
+                line.pc,
+                opcode
+            );
+            let raw_opcode =
+                u64::from_str_radix(&line.raw_opcode.as_ref().unwrap()[2..], 16).unwrap();
+            assert_eq!(&decode_opcode(raw_opcode), opcode);
+        }
+        // the final return fetches the word after the near return, and then leaves the frame,
+        // so the cached word can not be trusted anymore
+        assert!(lines[6].opcode.is_none());
+        assert!(lines[6].raw_opcode.is_none());
+        for line in lines.iter() {
+            assert_eq!(line.this_address, format_address(&entry_point_address));
+        }
+        assert_eq!(lines[1].registers[0].value, format_u256(&U256::from(1u64)));
+        assert_eq!(lines[2].registers[1].value, format_u256(&U256::from(3u64)));
+    }
+}
@@ -9,6 +9,7 @@ use crate::encodings::memory_query::MemoryQueueState;
 use crate::ethereum_types::U256;
 use crate::pairing::Engine;
 use crate::toolset::GeometryConfig;
+use crate::witness::large_vec::LargeVec;
 use derivative::Derivative;
 use rayon::slice::ParallelSliceMut;
 use std::cmp::Ordering;
@@ -45,14 +46,16 @@ pub struct FullBlockArtifacts<E: Engine> {
     pub memory_queue_simulator: MemoryQueueSimulator<E>,
 
     // all the RAM (without accumulation into the queue)
-    pub vm_memory_queries_accumulated: Vec<(u32, MemoryQuery)>,
-    pub vm_memory_queue_states: Vec<(u32, bool, MemoryQueueState<E>)>,
+    pub vm_memory_queries_accumulated: LargeVec<(u32, MemoryQuery)>,
+    pub vm_memory_queue_states: LargeVec<(u32, bool, MemoryQueueState<E>)>,
     //
-    pub all_memory_queries_accumulated: Vec<MemoryQuery>,
-    pub sorted_memory_queries_accumulated: Vec<MemoryQuery>,
+    pub all_memory_queries_accumulated: LargeVec<MemoryQuery>,
+    pub sorted_memory_queries_accumulated: LargeVec<MemoryQuery>,
     // all the RAM queue states
-    pub all_memory_queue_states: Vec<MemoryQueueState<E>>,
-    pub sorted_memory_queue_states: Vec<MemoryQueueState<E>>,
+    pub all_memory_queue_states: LargeVec<MemoryQueueState<E>>,
+    pub sorted_memory_queue_states: LargeVec<MemoryQueueState<E>>,
+    // kept after the sorted queue states are released
+    pub sorted_memory_queue_final_state: Option<MemoryQueueState<E>>,
     // decommittment queue
     pub all_decommittment_queries: Vec<(u32, DecommittmentQuery, Vec<U256>)>,
     pub sorted_decommittment_queries: Vec<DecommittmentQuery>,
@@ -115,6 +118,9 @@ pub struct FullBlockArtifacts<E: Engine> {
     //
     pub special_initial_decommittment_queries: Vec<(DecommittmentQuery, Vec<U256>)>,
 
+    // transaction numbers and the ranges of VM cycles they were executed in
+    pub tx_cycle_ranges: Vec<(u16, std::ops::Range<u32>)>,
+
     // keep precompile round functions data
     pub keccak_round_function_witnesses: Vec<(u32, LogQuery, Vec<Keccak256RoundWitness>)>,
     pub sha256_round_function_witnesses: Vec<(u32, LogQuery, Vec<Sha256RoundWitness>)>,
@@ -177,15 +183,229 @@ pub struct FullBlockArtifacts<E: Engine> {
 }
 
 use crate::witness::tree::*;
-use blake2::Blake2s256;
+
+// declares a part of the artifacts that is owned by a single processing stage, together with
+// functions that move the listed fields out of the full artifacts and back
+macro_rules! artifacts_part {
+    ($name:ident, $take:ident, $put:ident, { $($field:ident: $ty:ty),+ $(,)? }) => {
+        #[derive(Derivative)]
+        #[derivative(Default(bound = ""))]
+        pub struct $name<E: Engine> {
+            $(pub $field: $ty,)+
+        }
+
+        impl<E: Engine> FullBlockArtifacts<E> {
+            fn $take(&mut self) -> $name<E> {
+                $name {
+                    $($field: std::mem::take(&mut self.$field),)+
+                }
+            }
+
+            fn $put(&mut self, part: $name<E>) {
+                $(self.$field = part.$field;)+
+            }
+        }
+    };
+}
+
+artifacts_part!(LogDemuxArtifacts, take_log_demux, put_log_demux, {
+    original_log_queue_simulator: LogQueueSimulator<E>,
+    original_log_queue_states: Vec<(u32, LogQueueState<E>)>,
+    log_demuxer_circuit_data: Vec<LogDemuxerCircuitInstanceWitness<E>>,
+});
+
+artifacts_part!(DemuxedPrecompileQueues, take_precompile_queues, put_precompile_queues, {
+    demuxed_keccak_precompile_queries: Vec<LogQuery>,
+    demuxed_keccak_precompile_queue_simulator: LogQueueSimulator<E>,
+    demuxed_keccak_precompile_queue_states: Vec<LogQueueState<E>>,
+    demuxed_sha256_precompile_queries: Vec<LogQuery>,
+    demuxed_sha256_precompile_queue_simulator: LogQueueSimulator<E>,
+    demuxed_sha256_precompile_queue_states: Vec<LogQueueState<E>>,
+    demuxed_ecrecover_queries: Vec<LogQuery>,
+    demuxed_ecrecover_queue_simulator: LogQueueSimulator<E>,
+    demuxed_ecrecover_queue_states: Vec<LogQueueState<E>>,
+});
+
+artifacts_part!(RollupStorageArtifacts, take_rollup_storage, put_rollup_storage, {
+    demuxed_rollup_storage_queries: Vec<LogQuery>,
+    demuxed_rollup_storage_queue_states: Vec<LogQueueState<E>>,
+    demuxed_rollup_storage_queue_simulator: LogQueueSimulator<E>,
+    deduplicated_rollup_storage_queries: Vec<LogQuery>,
+    deduplicated_rollup_storage_queue_simulator: LogQueueSimulator<E>,
+    storage_deduplicator_circuit_data: Vec<StorageDeduplicatorInstanceWitness<E>>,
+    initial_writes_pubdata_hasher_circuit_data:
+        Vec<PubdataHasherInstanceWitness<E, 3, 64, InitialStorageWriteData<E>>>,
+    repeated_writes_pubdata_hasher_circuit_data:
+        Vec<PubdataHasherInstanceWitness<E, 2, 40, RepeatedStorageWriteData<E>>>,
+    rollup_storage_application_circuit_data: Vec<StorageApplicationCircuitInstanceWitness<E>>,
+});
+
+artifacts_part!(EventsArtifacts, take_events, put_events, {
+    demuxed_event_queries: Vec<LogQuery>,
+    demuxed_events_queue_simulator: LogQueueSimulator<E>,
+    demuxed_event_queue_states: Vec<LogQueueState<E>>,
+    deduplicated_event_queries: Vec<LogQuery>,
+    deduplicated_event_queue_simulator: LogQueueSimulator<E>,
+    events_deduplicator_circuit_data: Vec<EventsDeduplicatorInstanceWitness<E>>,
+});
+
+artifacts_part!(L1MessagesArtifacts, take_l1_messages, put_l1_messages, {
+    demuxed_to_l1_queries: Vec<LogQuery>,
+    demuxed_to_l1_queue_simulator: LogQueueSimulator<E>,
+    demuxed_to_l1_queue_states: Vec<LogQueueState<E>>,
+    deduplicated_to_l1_queries: Vec<LogQuery>,
+    deduplicated_to_l1_queue_simulator: LogQueueSimulator<E>,
+    l1_messages_deduplicator_circuit_data: Vec<EventsDeduplicatorInstanceWitness<E>>,
+    l1_messages_linear_hash_data: Vec<
+        PubdataHasherInstanceWitness<
+            E,
+            5,
+            88,
+            <LogQuery as CircuitEquivalentReflection<E>>::Destination,
+        >,
+    >,
+    l1_messages_merklizer_data: Vec<
+        MessagesMerklizerInstanceWitness<
+            E,
+            5,
+            88,
+            <LogQuery as CircuitEquivalentReflection<E>>::Destination,
+        >,
+    >,
+});
 
 impl<E: Engine> FullBlockArtifacts<E> {
-    pub fn process<R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+    // memory related vectors are moved into files when they exceed the given size
+    pub fn with_spill_threshold(spill_threshold_bytes: usize) -> Self {
+        Self {
+            vm_memory_queries_accumulated: LargeVec::with_spill_threshold(spill_threshold_bytes),
+            vm_memory_queue_states: LargeVec::with_spill_threshold(spill_threshold_bytes),
+            all_memory_queries_accumulated: LargeVec::with_spill_threshold(spill_threshold_bytes),
+            sorted_memory_queries_accumulated: LargeVec::with_spill_threshold(
+                spill_threshold_bytes,
+            ),
+            all_memory_queue_states: LargeVec::with_spill_threshold(spill_threshold_bytes),
+            sorted_memory_queue_states: LargeVec::with_spill_threshold(spill_threshold_bytes),
+            ..Self::default()
+        }
+    }
+
+    pub fn process<R: CircuitArithmeticRoundFunction<E, 2, 3>, H: BinaryHasher<32>>(
         &mut self,
         round_function: &R,
         geometry: &GeometryConfig,
-        tree: &mut impl BinarySparseStorageTree<256, 32, 32, 8, 32, Blake2s256, ZkSyncStorageLeaf>,
+        tree: &mut impl BinarySparseStorageTree<256, 32, 32, 8, 32, H, ZkSyncStorageLeaf>,
         num_non_deterministic_heap_queries: usize,
+    ) {
+        // stages form the following graph, and independent branches run concurrently:
+        // - memory queue -> code decommitter -> keccak -> sha256 -> ecrecover -> RAM permutation,
+        //   where precompiles also wait for the log demux
+        // - log demux -> storage sort -> storage pubdata hashers -> storage application
+        //             -> events sort
+        //             -> L1 messages sort -> L1 messages linear hash and merklization
+        // every stage computes exactly what it would compute if stages were run one by one,
+        // so the result doesn't depend on scheduling
+
+        // log-like queues are moved out into parts owned by the stages that use them,
+        // so branches don't share any mutable state
+        let mut log_demux = self.take_log_demux();
+        let mut precompile_queues = self.take_precompile_queues();
+        let mut rollup_storage = self.take_rollup_storage();
+        let mut events = self.take_events();
+        let mut l1_messages = self.take_l1_messages();
+
+        rayon::join(
+            || self.process_memory_queue_and_decommittments(round_function, geometry),
+            || {
+                log_demux.process(
+                    &mut rollup_storage,
+                    &mut events,
+                    &mut l1_messages,
+                    &mut precompile_queues,
+                    round_function,
+                    geometry,
+                )
+            },
+        );
+
+        // demuxed precompile queues are consumed by the memory branch
+        self.put_precompile_queues(precompile_queues);
+
+        rayon::in_place_scope(|scope| {
+            scope.spawn(|_| {
+                self.process_precompiles_and_ram(
+                    round_function,
+                    geometry,
+                    num_non_deterministic_heap_queries,
+                )
+            });
+
+            scope.spawn(|_| events.process(round_function, geometry));
+            scope.spawn(|_| l1_messages.process(round_function, geometry));
+
+            // tree is not required to be shareable between threads, so storage is processed here
+            rollup_storage.process(round_function, geometry, tree);
+        });
+
+        self.put_log_demux(log_demux);
+        self.put_rollup_storage(rollup_storage);
+        self.put_events(events);
+        self.put_l1_messages(l1_messages);
+
+        self.is_processed = true;
+    }
+
+    // runs the same stages one by one in the order they were run before `process` became
+    // concurrent, so the results of both can be compared
+    pub fn process_sequentially<R: CircuitArithmeticRoundFunction<E, 2, 3>, H: BinaryHasher<32>>(
+        &mut self,
+        round_function: &R,
+        geometry: &GeometryConfig,
+        tree: &mut impl BinarySparseStorageTree<256, 32, 32, 8, 32, H, ZkSyncStorageLeaf>,
+        num_non_deterministic_heap_queries: usize,
+    ) {
+        self.process_memory_queue_and_decommittments(round_function, geometry);
+
+        let mut log_demux = self.take_log_demux();
+        let mut precompile_queues = self.take_precompile_queues();
+        let mut rollup_storage = self.take_rollup_storage();
+        let mut events = self.take_events();
+        let mut l1_messages = self.take_l1_messages();
+
+        log_demux.process(
+            &mut rollup_storage,
+            &mut events,
+            &mut l1_messages,
+            &mut precompile_queues,
+            round_function,
+            geometry,
+        );
+        self.put_precompile_queues(precompile_queues);
+
+        self.process_precompiles_and_ram(
+            round_function,
+            geometry,
+            num_non_deterministic_heap_queries,
+        );
+        rollup_storage.process_sort(round_function, geometry);
+        events.process(round_function, geometry);
+        l1_messages.process(round_function, geometry);
+        rollup_storage.process_application(round_function, geometry, tree);
+
+        self.put_log_demux(log_demux);
+        self.put_rollup_storage(rollup_storage);
+        self.put_events(events);
+        self.put_l1_messages(l1_messages);
+
+        self.is_processed = true;
+    }
+
+    pub(crate) fn process_memory_queue_and_decommittments<
+        R: CircuitArithmeticRoundFunction<E, 2, 3>,
+    >(
+        &mut self,
+        round_function: &R,
+        geometry: &GeometryConfig,
     ) {
         // this is parallelizable internally by the factor of 3 in round function implementation later on
 
@@ -226,20 +446,14 @@ impl<E: Engine> FullBlockArtifacts<E> {
 
         self.code_decommitter_circuits_data = code_decommitter_circuits_data;
         self.decommittments_deduplicator_circuits_data = decommittments_deduplicator_witness;
+    }
 
-        // demux log queue
-        use crate::witness::individual_circuits::log_demux::compute_logs_demux;
-
-        tracing::debug!("Running log demux simulation");
-
-        let log_demuxer_witness = compute_logs_demux(
-            self,
-            geometry.cycles_per_log_demuxer as usize,
-            round_function,
-        );
-
-        self.log_demuxer_circuit_data = log_demuxer_witness;
-
+    fn process_precompiles_and_ram<R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+        &mut self,
+        round_function: &R,
+        geometry: &GeometryConfig,
+        num_non_deterministic_heap_queries: usize,
+    ) {
         // keccak precompile
 
         use crate::witness::individual_circuits::keccak256_round_function::keccak256_decompose_into_per_circuit_witness;
@@ -294,8 +508,63 @@ impl<E: Engine> FullBlockArtifacts<E> {
 
         self.ram_permutation_circuits_data = ram_permutation_circuits_data;
 
-        // now completely parallel process to reconstruct the states, with internally parallelism in each round function
+        // RAM permutation is the last user of the full memory queue, so release it early.
+        // VM part of the queue states is still needed for the VM circuits
+        self.sorted_memory_queue_final_state = self.sorted_memory_queue_states.last().cloned();
+        self.all_memory_queries_accumulated.clear();
+        self.sorted_memory_queries_accumulated.clear();
+        self.all_memory_queue_states.clear();
+        self.sorted_memory_queue_states.clear();
+        self.memory_queue_simulator.witness = std::collections::VecDeque::new();
+    }
+}
 
+impl<E: Engine> LogDemuxArtifacts<E> {
+    fn process<R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+        &mut self,
+        rollup_storage: &mut RollupStorageArtifacts<E>,
+        events: &mut EventsArtifacts<E>,
+        l1_messages: &mut L1MessagesArtifacts<E>,
+        precompile_queues: &mut DemuxedPrecompileQueues<E>,
+        round_function: &R,
+        geometry: &GeometryConfig,
+    ) {
+        // demux log queue
+        use crate::witness::individual_circuits::log_demux::compute_logs_demux;
+
+        tracing::debug!("Running log demux simulation");
+
+        let log_demuxer_witness = compute_logs_demux(
+            &self.original_log_queue_simulator,
+            &self.original_log_queue_states,
+            rollup_storage,
+            events,
+            l1_messages,
+            precompile_queues,
+            geometry.cycles_per_log_demuxer as usize,
+            round_function,
+        );
+
+        self.log_demuxer_circuit_data = log_demuxer_witness;
+    }
+}
+
+impl<E: Engine> RollupStorageArtifacts<E> {
+    fn process<R: CircuitArithmeticRoundFunction<E, 2, 3>, H: BinaryHasher<32>>(
+        &mut self,
+        round_function: &R,
+        geometry: &GeometryConfig,
+        tree: &mut impl BinarySparseStorageTree<256, 32, 32, 8, 32, H, ZkSyncStorageLeaf>,
+    ) {
+        self.process_sort(round_function, geometry);
+        self.process_application(round_function, geometry, tree);
+    }
+
+    fn process_sort<R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+        &mut self,
+        round_function: &R,
+        geometry: &GeometryConfig,
+    ) {
         use crate::witness::individual_circuits::storage_sort_dedup::compute_storage_dedup_and_sort;
 
         tracing::debug!("Running storage deduplication simulation");
@@ -306,7 +575,51 @@ impl<E: Engine> FullBlockArtifacts<E> {
             round_function,
         );
         self.storage_deduplicator_circuit_data = storage_deduplicator_circuit_data;
+    }
+
+    fn process_application<R: CircuitArithmeticRoundFunction<E, 2, 3>, H: BinaryHasher<32>>(
+        &mut self,
+        round_function: &R,
+        geometry: &GeometryConfig,
+        tree: &mut impl BinarySparseStorageTree<256, 32, 32, 8, 32, H, ZkSyncStorageLeaf>,
+    ) {
+        // process the storage application
+
+        // we can quickly determine states witness
+
+        use crate::witness::individual_circuits::get_storage_application_pubdata::compute_storage_application_pubdata_queues;
+
+        let (initial, repeated) = compute_storage_application_pubdata_queues(
+            self,
+            tree,
+            round_function,
+            geometry.limit_for_initial_writes_pubdata_hasher as usize,
+            geometry.limit_for_repeated_writes_pubdata_hasher as usize,
+        );
+
+        self.initial_writes_pubdata_hasher_circuit_data = vec![initial];
+        self.repeated_writes_pubdata_hasher_circuit_data = vec![repeated];
+
+        // and do the actual storage application
+        use crate::witness::individual_circuits::storage_application::decompose_into_storage_application_witnesses;
+
+        let rollup_storage_application_circuit_data = decompose_into_storage_application_witnesses(
+            self,
+            tree,
+            round_function,
+            geometry.cycles_per_storage_application as usize,
+        );
+
+        self.rollup_storage_application_circuit_data = rollup_storage_application_circuit_data;
+    }
+}
 
+impl<E: Engine> EventsArtifacts<E> {
+    fn process<R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+        &mut self,
+        round_function: &R,
+        geometry: &GeometryConfig,
+    ) {
         use crate::witness::individual_circuits::events_sort_dedup::compute_events_dedup_and_sort;
 
         tracing::debug!("Running events deduplication simulation");
@@ -322,6 +635,16 @@ impl<E: Engine> FullBlockArtifacts<E> {
         );
 
         self.events_deduplicator_circuit_data = events_deduplicator_circuit_data;
+    }
+}
+
+impl<E: Engine> L1MessagesArtifacts<E> {
+    fn process<R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+        &mut self,
+        round_function: &R,
+        geometry: &GeometryConfig,
+    ) {
+        use crate::witness::individual_circuits::events_sort_dedup::compute_events_dedup_and_sort;
 
         tracing::debug!("Running L1 messages deduplication simulation");
 
@@ -377,37 +700,6 @@ impl<E: Engine> FullBlockArtifacts<E> {
         );
 
         self.l1_messages_merklizer_data = vec![l1_messages_merklizer_data];
-
-        // process the storage application
-
-        // we can quickly determine states witness
-
-        use crate::witness::individual_circuits::get_storage_application_pubdata::compute_storage_application_pubdata_queues;
-
-        let (initial, repeated) = compute_storage_application_pubdata_queues(
-            self,
-            tree,
-            round_function,
-            geometry.limit_for_initial_writes_pubdata_hasher as usize,
-            geometry.limit_for_repeated_writes_pubdata_hasher as usize,
-        );
-
-        self.initial_writes_pubdata_hasher_circuit_data = vec![initial];
-        self.repeated_writes_pubdata_hasher_circuit_data = vec![repeated];
-
-        // and do the actual storage application
-        use crate::witness::individual_circuits::storage_application::decompose_into_storage_application_witnesses;
-
-        let rollup_storage_application_circuit_data = decompose_into_storage_application_witnesses(
-            self,
-            tree,
-            round_function,
-            geometry.cycles_per_storage_application as usize,
-        );
-
-        self.rollup_storage_application_circuit_data = rollup_storage_application_circuit_data;
-
-        self.is_processed = true;
     }
 }
 
@@ -10,6 +10,30 @@ use sync_vm::glue::pubdata_hasher::storage_write_data::ByteSerializable;
 use sync_vm::glue::traits::*;
 use sync_vm::inputs::ClosedFormInputWitness;
 
+// number of items as u32 BE, followed by serialized items. This is the byte string
+// that is hashed by the pubdata hasher circuits
+pub fn serialize_pubdata<
+    'a,
+    const SERIALIZATION_WIDTH: usize,
+    I: BytesSerializable<SERIALIZATION_WIDTH> + 'a,
+>(
+    items: impl ExactSizeIterator<Item = &'a I>,
+) -> Vec<u8> {
+    let mut full_bytestring = vec![];
+    let num_elements = items.len();
+    assert!(num_elements <= u32::MAX as usize);
+    full_bytestring.extend((num_elements as u32).to_be_bytes());
+
+    // only append meaningful items
+    for el in items {
+        let serialized = el.serialize();
+        assert_eq!(serialized.len(), SERIALIZATION_WIDTH);
+        full_bytestring.extend(serialized);
+    }
+
+    full_bytestring
+}
+
 pub fn compute_pubdata_hasher_witness<
     const SERIALIZATION_WIDTH: usize,
     const ENCODING_ELEMS: usize,
@@ -27,17 +51,7 @@ pub fn compute_pubdata_hasher_witness<
 ) -> PubdataHasherInstanceWitness<E, ENCODING_ELEMS, SERIALIZATION_WIDTH, D> {
     // dbg!(&simulator.num_items);
     assert!(capacity <= u32::MAX as usize);
-    let mut full_bytestring = vec![];
-    let num_elements = simulator.witness.len();
-    assert!(num_elements <= u32::MAX as usize);
-    full_bytestring.extend((num_elements as u32).to_be_bytes());
-
-    // only append meaningful items
-    for (_, _, el) in simulator.witness.iter() {
-        let serialized = el.serialize();
-        assert_eq!(serialized.len(), SERIALIZATION_WIDTH);
-        full_bytestring.extend(serialized);
-    }
+    let full_bytestring = serialize_pubdata(simulator.witness.iter().map(|(_, _, el)| el));
 
     // println!("Hashing over 0x{}", hex::encode(&full_bytestring));
     let pubdata_hash: [u8; 32] = Keccak256::digest(&full_bytestring)
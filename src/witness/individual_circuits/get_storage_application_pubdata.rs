@@ -4,11 +4,10 @@ use crate::encodings::initial_storage_write::*;
 use crate::encodings::repeated_storage_write::*;
 use crate::ff::{Field, PrimeField};
 use crate::pairing::Engine;
-use crate::witness::full_block_artifact::FullBlockArtifacts;
+use crate::witness::full_block_artifact::RollupStorageArtifacts;
 use crate::witness::tree::EnumeratedBinaryLeaf;
 use crate::witness::tree::ZkSyncStorageLeaf;
-use crate::witness::tree::{BinarySparseStorageTree, ZKSyncTestingTree};
-use blake2::Blake2s256;
+use crate::witness::tree::{BinaryHasher, BinarySparseStorageTree};
 use derivative::Derivative;
 use num_bigint::BigUint;
 use sync_vm::circuit_structures::traits::CircuitArithmeticRoundFunction;
@@ -23,9 +22,10 @@ use zk_evm::precompiles::keccak256::BUFFER_SIZE;
 pub fn compute_storage_application_pubdata_queues<
     E: Engine,
     R: CircuitArithmeticRoundFunction<E, 2, 3>,
+    H: BinaryHasher<32>,
 >(
-    artifacts: &mut FullBlockArtifacts<E>,
-    tree: &impl BinarySparseStorageTree<256, 32, 32, 8, 32, Blake2s256, ZkSyncStorageLeaf>,
+    artifacts: &RollupStorageArtifacts<E>,
+    tree: &impl BinarySparseStorageTree<256, 32, 32, 8, 32, H, ZkSyncStorageLeaf>,
     round_function: &R,
     first_writes_capacity: usize,
     repeated_writes_capacity: usize,
@@ -1,512 +1,265 @@
 use super::*;
-use crate::biguint_from_u256;
-use crate::ff::{Field, PrimeField};
+use crate::encodings::log_query::{LogQueueSimulator, LogQueueState};
 use crate::pairing::Engine;
 use crate::witness::full_block_artifact::FullBlockArtifacts;
-use derivative::Derivative;
-use num_bigint::BigUint;
+use crate::witness::individual_circuits::precompile_decomposer::*;
 use sync_vm::circuit_structures::traits::CircuitArithmeticRoundFunction;
 use sync_vm::franklin_crypto::plonk::circuit::utils::u64_to_fe;
 use sync_vm::glue::keccak256_round_function_circuit::input::Keccak256RoundFunctionInstanceWitness;
 use sync_vm::glue::keccak256_round_function_circuit::input::*;
 use sync_vm::glue::keccak256_round_function_circuit::*;
-use sync_vm::precompiles::*;
-use sync_vm::scheduler::queues::FixedWidthEncodingGenericQueueWitness;
-use zk_evm::precompiles::keccak256::BUFFER_SIZE;
-
-#[derive(Derivative)]
-#[derivative(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum Keccak256PrecompileState {
-    GetRequestFromQueue,
-    RunRoundFunction,
-    Finished,
+use sync_vm::precompiles::keccak256::*;
+use sync_vm::traits::CSWitnessable;
+use zk_evm::aux_structures::{LogQuery, MemoryQuery};
+use zk_evm::precompiles::keccak256::{
+    Buffer, Digest, Keccak256, Keccak256RoundWitness, BUFFER_SIZE, KECCAK_RATE_IN_U64_WORDS,
+    NEW_WORDS_PER_CYCLE,
+};
+
+pub struct Keccak256Decomposer;
+
+pub struct Keccak256RequestState {
+    internal_state: Keccak256,
+    // those are refreshed every cycle
+    input_buffer: Buffer,
+    words_buffer: [u64; NEW_WORDS_PER_CYCLE],
+    call_params: PrecompileCallParams,
 }
 
-// we want to simulate splitting of data into many separate instances of the same circuit.
-// So we basically need to reconstruct the FSM state on input/output, and passthrough data.
-// In practice the only difficulty is buffer state, everything else is provided by out-of-circuit VM
-
-pub fn keccak256_decompose_into_per_circuit_witness<
-    E: Engine,
-    R: CircuitArithmeticRoundFunction<E, 2, 3>,
->(
-    artifacts: &mut FullBlockArtifacts<E>,
-    num_rounds_per_circuit: usize,
-    round_function: &R,
-) -> Vec<Keccak256RoundFunctionInstanceWitness<E>> {
-    assert_eq!(
-        artifacts.all_memory_queries_accumulated.len(),
-        artifacts.all_memory_queue_states.len()
-    );
-    assert_eq!(
-        artifacts.all_memory_queries_accumulated.len(),
-        artifacts.memory_queue_simulator.num_items as usize
-    );
-
-    // split into aux witness, don't mix with the memory
-    use zk_evm::precompiles::keccak256::Keccak256RoundWitness;
-
-    for (_cycle, _query, witness) in artifacts.keccak_round_function_witnesses.iter() {
-        for el in witness.iter() {
-            let Keccak256RoundWitness {
-                new_request: _,
-                reads,
-                writes,
-            } = el;
-
-            // we read, then write
-            if let Some(reads) = reads.as_ref() {
-                artifacts.keccak_256_memory_queries.extend_from_slice(reads);
-            }
-
-            if let Some(writes) = writes.as_ref() {
-                artifacts
-                    .keccak_256_memory_queries
-                    .extend_from_slice(writes);
-            }
+fn keccak_internal_state_for_circuit<E: Engine>(state: &Keccak256) -> [E::Fr; 25] {
+    let state_inner = zk_evm::precompiles::keccak256::transmute_state(state.clone());
+    let mut keccak_internal_state = vec![];
+    for i in 0..5 {
+        for j in 0..5 {
+            let el = state_inner[i + 5 * j]; // circuit and non-circuit impls have different order
+            keccak_internal_state.push(el);
         }
     }
 
-    let mut result = vec![];
-
-    let keccak_precompile_calls =
-        std::mem::replace(&mut artifacts.demuxed_keccak_precompile_queries, vec![]);
-    let keccak_precompile_calls_queue_states = std::mem::replace(
-        &mut artifacts.demuxed_keccak_precompile_queue_states,
-        vec![],
-    );
-    let simulator_witness: Vec<_> = artifacts
-        .demuxed_keccak_precompile_queue_simulator
-        .witness
-        .clone()
-        .into();
-    let round_function_witness =
-        std::mem::replace(&mut artifacts.keccak_round_function_witnesses, vec![]);
-
-    let memory_queries = std::mem::replace(&mut artifacts.keccak_256_memory_queries, vec![]);
-
-    // check basic consistency
-    assert_eq!(
-        keccak_precompile_calls.len(),
-        keccak_precompile_calls_queue_states.len()
-    );
-    assert_eq!(keccak_precompile_calls.len(), round_function_witness.len());
-
-    if keccak_precompile_calls.len() == 0 {
-        // we can not skip the circuit (at least for now), so we have to create a dummy on
-        let log_queue_input_state =
-            take_queue_state_from_simulator(&artifacts.demuxed_keccak_precompile_queue_simulator);
-        let memory_queue_input_state =
-            take_sponge_like_queue_state_from_simulator(&artifacts.memory_queue_simulator);
-        let current_memory_queue_state = memory_queue_input_state.clone();
-
-        let mut observable_input_data = PrecompileFunctionInputData::placeholder_witness();
-        observable_input_data.initial_memory_state = memory_queue_input_state.clone();
-        observable_input_data.initial_log_queue_state = log_queue_input_state.clone();
-
-        let mut observable_output_data = PrecompileFunctionOutputData::placeholder_witness();
-        observable_output_data.final_memory_state = current_memory_queue_state.clone();
-
-        let mut hidden_fsm_input_state = KeccakPrecompileState::<E>::placeholder_witness();
-        hidden_fsm_input_state.read_precompile_call = true;
-
-        let mut hidden_fsm_output_state = KeccakPrecompileState::<E>::placeholder_witness();
-        hidden_fsm_output_state.completed = true;
-
-        // internal state is a bit more tricky, it'll be a round over empty input
-        use zk_evm::precompiles::keccak256::Keccak256;
-        let mut internal_state_over_empty_buffer = Keccak256::default();
-        use zk_evm::precompiles::keccak256::KECCAK_RATE_IN_U64_WORDS;
-        let empty_block = [0u8; KECCAK_RATE_IN_U64_WORDS * 8];
-        use zk_evm::precompiles::keccak256::Digest;
-        internal_state_over_empty_buffer.update(&empty_block);
-        let empty_state_inner = zk_evm::precompiles::keccak256::transmute_state(
-            internal_state_over_empty_buffer.clone(),
-        );
-        let mut keccak_internal_state_over_empty_buffer = vec![];
-        for i in 0..5 {
-            for j in 0..5 {
-                let el = empty_state_inner[i + 5 * j]; // circuit and non-circuit impls have different order
-                keccak_internal_state_over_empty_buffer.push(el);
-            }
-        }
-
-        let keccak_internal_state: [E::Fr; 25] = keccak_internal_state_over_empty_buffer
-            .into_iter()
-            .map(|el| u64_to_fe::<E::Fr>(el))
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap();
-
-        hidden_fsm_output_state.keccak_internal_state = keccak_internal_state;
+    keccak_internal_state
+        .into_iter()
+        .map(|el| u64_to_fe::<E::Fr>(el))
+        .collect::<Vec<_>>()
+        .try_into()
+        .unwrap()
+}
 
-        let witness = Keccak256RoundFunctionInstanceWitness::<E> {
-            closed_form_input: Keccak256RoundFunctionInputOutputWitness::<E> {
-                start_flag: true,
-                completion_flag: true,
-                observable_input: observable_input_data,
-                observable_output: observable_output_data,
-                hidden_fsm_input: Keccak256RoundFunctionFSMWitness::<E> {
-                    precompile_state: hidden_fsm_input_state,
-                    log_queue_state: log_queue_input_state.clone(),
+// internal state is a bit more tricky, it'll be a round over empty input
+fn keccak_internal_state_over_empty_buffer<E: Engine>() -> [E::Fr; 25] {
+    let mut internal_state_over_empty_buffer = Keccak256::default();
+    let empty_block = [0u8; KECCAK_RATE_IN_U64_WORDS * 8];
+    internal_state_over_empty_buffer.update(&empty_block);
 
-                    memory_queue_state: memory_queue_input_state.clone(),
-                    _marker: std::marker::PhantomData,
-                },
-                hidden_fsm_output: Keccak256RoundFunctionFSMWitness::<E> {
-                    precompile_state: hidden_fsm_output_state,
-                    log_queue_state: take_queue_state_from_simulator(
-                        &artifacts.demuxed_keccak_precompile_queue_simulator,
-                    ),
-                    memory_queue_state: current_memory_queue_state.clone(),
-                    _marker: std::marker::PhantomData,
-                },
-                _marker_e: (),
-                _marker: std::marker::PhantomData,
-            },
-            requests_queue_witness: FixedWidthEncodingGenericQueueWitness {
-                wit: VecDeque::new(),
-            },
-            memory_reads_witness: vec![],
-        };
-        result.push(witness);
+    keccak_internal_state_for_circuit::<E>(&internal_state_over_empty_buffer)
+}
 
-        return result;
+impl<E: Engine> PrecompileDecomposer<E> for Keccak256Decomposer {
+    type RequestWitness = Vec<Keccak256RoundWitness>;
+    type RoundWitness = Keccak256RoundWitness;
+    type RequestState = Keccak256RequestState;
+    type FsmState = KeccakPrecompileStateWitness<E>;
+    type InstanceWitness = Keccak256RoundFunctionInstanceWitness<E>;
+
+    fn requests_queue_simulator(
+        artifacts: &mut FullBlockArtifacts<E>,
+    ) -> &mut LogQueueSimulator<E> {
+        &mut artifacts.demuxed_keccak_precompile_queue_simulator
     }
 
-    let mut round_counter = 0;
-    let num_requests = keccak_precompile_calls.len();
-
-    // convension
-    let mut log_queue_input_state =
-        take_queue_state_from_simulator(&artifacts.demuxed_keccak_precompile_queue_simulator);
-    use sync_vm::traits::CSWitnessable;
-
-    use sync_vm::precompiles::keccak256::KeccakPrecompileState;
-
-    let mut hidden_fsm_input_state = KeccakPrecompileState::<E>::placeholder_witness();
-    hidden_fsm_input_state.read_precompile_call = true;
-
-    let mut memory_queries_it = memory_queries.into_iter();
-
-    let mut memory_read_witnesses = vec![];
-
-    let mut precompile_state = Keccak256PrecompileState::GetRequestFromQueue;
-
-    let mut request_ranges = vec![];
-    let mut starting_request_idx = 0;
-
-    let mut memory_queue_input_state =
-        take_sponge_like_queue_state_from_simulator(&artifacts.memory_queue_simulator);
-    let mut current_memory_queue_state = memory_queue_input_state.clone();
-
-    for (request_idx, ((request, _queue_transition_state), per_request_work)) in
-        keccak_precompile_calls
-            .into_iter()
-            .zip(keccak_precompile_calls_queue_states.into_iter())
-            .zip(round_function_witness.into_iter())
-            .enumerate()
-    {
-        // request level. Each request can be broken into few rounds
-
-        let _ = artifacts
-            .demuxed_keccak_precompile_queue_simulator
-            .pop_and_output_intermediate_data(round_function);
-
-        use zk_evm::precompiles::keccak256::Keccak256;
-        let mut internal_state = Keccak256::default();
-
-        let mut memory_reads_per_request = vec![];
-
-        assert_eq!(
-            precompile_state,
-            Keccak256PrecompileState::GetRequestFromQueue
-        );
+    fn take_requests(
+        artifacts: &mut FullBlockArtifacts<E>,
+    ) -> (
+        Vec<LogQuery>,
+        Vec<LogQueueState<E>>,
+        Vec<(u32, LogQuery, Self::RequestWitness)>,
+    ) {
+        (
+            std::mem::replace(&mut artifacts.demuxed_keccak_precompile_queries, vec![]),
+            std::mem::replace(
+                &mut artifacts.demuxed_keccak_precompile_queue_states,
+                vec![],
+            ),
+            std::mem::replace(&mut artifacts.keccak_round_function_witnesses, vec![]),
+        )
+    }
 
-        let (_cycle, _req, round_witness) = per_request_work;
-        assert_eq!(request, _req);
+    fn memory_queries(artifacts: &mut FullBlockArtifacts<E>) -> &mut Vec<MemoryQuery> {
+        &mut artifacts.keccak_256_memory_queries
+    }
 
-        // those are refreshed every cycle
-        let mut input_buffer = zk_evm::precompiles::keccak256::Buffer::new();
-        use zk_evm::precompiles::keccak256::NEW_WORDS_PER_CYCLE;
-        let mut words_buffer = [0u64; NEW_WORDS_PER_CYCLE];
+    fn into_rounds(witness: Self::RequestWitness) -> Vec<Self::RoundWitness> {
+        witness
+    }
 
-        use zk_evm::precompiles::precompile_abi_in_log;
-        let mut precompile_request = precompile_abi_in_log(request);
-        let num_rounds = precompile_request.precompile_interpreted_data as usize;
-        assert_eq!(num_rounds, round_witness.len());
+    fn round_reads(round: &Self::RoundWitness) -> &[MemoryQuery] {
+        round.reads.as_ref().map(|el| &el[..]).unwrap_or(&[])
+    }
 
-        let mut num_rounds_left = num_rounds;
+    fn round_writes(round: &Self::RoundWitness) -> &[MemoryQuery] {
+        round.writes.as_ref().map(|el| &el[..]).unwrap_or(&[])
+    }
 
-        let is_last_request = request_idx == num_requests - 1;
+    fn num_rounds(request: &LogQuery) -> usize {
+        num_rounds_from_request(request)
+    }
 
-        precompile_state = Keccak256PrecompileState::RunRoundFunction;
+    fn new_request_state(request: &LogQuery) -> Self::RequestState {
+        Keccak256RequestState {
+            internal_state: Keccak256::default(),
+            input_buffer: Buffer::new(),
+            words_buffer: [0u64; NEW_WORDS_PER_CYCLE],
+            call_params: PrecompileCallParams::from_request(request),
+        }
+    }
 
-        for (round_idx, round) in round_witness.into_iter().enumerate() {
-            // we proceed the request as long as we can
-            if round_idx == 0 {
-                assert!(round.new_request.is_some());
-            }
+    fn process_round(
+        state: &mut Self::RequestState,
+        round: &Self::RoundWitness,
+        round_idx: usize,
+        is_last_round: bool,
+    ) {
+        if round_idx == 0 {
+            assert!(round.new_request.is_some());
+        }
 
-            // simulate absorb
-            if input_buffer.can_read_into() {
-                use zk_evm::precompiles::keccak256::NUM_WORDS_PER_QUERY;
-                assert!(round.reads.is_some());
-                let reads = round.reads.unwrap();
-                for (query_index, read) in reads.into_iter().enumerate() {
-                    let data = read.value;
-                    let mut bytes32_buffer = [0u8; 32];
-                    data.to_big_endian(&mut bytes32_buffer[..]);
-                    for (i, chunk) in bytes32_buffer.chunks(8).enumerate() {
-                        let as_u64 = u64::from_le_bytes(chunk.try_into().unwrap());
-                        words_buffer[query_index * NUM_WORDS_PER_QUERY + i] = as_u64;
-                    }
-
-                    let read_query = memory_queries_it.next().unwrap();
-                    assert_eq!(read, read_query);
-                    memory_reads_per_request.push(biguint_from_u256(read_query.value));
-
-                    artifacts.all_memory_queries_accumulated.push(read);
-                    let (_, intermediate_info) = artifacts
-                        .memory_queue_simulator
-                        .push_and_output_intermediate_data(read, round_function);
-                    artifacts.all_memory_queue_states.push(intermediate_info);
-                    current_memory_queue_state = take_sponge_like_queue_state_from_simulator(
-                        &artifacts.memory_queue_simulator,
-                    );
-
-                    precompile_request.input_memory_offset += 1;
+        // simulate absorb
+        if state.input_buffer.can_read_into() {
+            use zk_evm::precompiles::keccak256::NUM_WORDS_PER_QUERY;
+            assert!(round.reads.is_some());
+            let reads = round.reads.as_ref().unwrap();
+            for (query_index, read) in reads.iter().enumerate() {
+                let data = read.value;
+                let mut bytes32_buffer = [0u8; 32];
+                data.to_big_endian(&mut bytes32_buffer[..]);
+                for (i, chunk) in bytes32_buffer.chunks(8).enumerate() {
+                    let as_u64 = u64::from_le_bytes(chunk.try_into().unwrap());
+                    state.words_buffer[query_index * NUM_WORDS_PER_QUERY + i] = as_u64;
                 }
 
-                input_buffer.append(&words_buffer);
+                state.call_params.input_offset += 1;
             }
 
-            let words = input_buffer.consume_rate();
-            use zk_evm::precompiles::keccak256::KECCAK_RATE_IN_U64_WORDS;
-            let mut block = [0u8; KECCAK_RATE_IN_U64_WORDS * 8];
-
-            for (i, word) in words.into_iter().enumerate() {
-                block[(i * 8)..(i * 8 + 8)].copy_from_slice(&word.to_le_bytes());
-            }
-            use zk_evm::precompiles::keccak256::Digest;
-            internal_state.update(&block);
-
-            num_rounds_left -= 1;
-
-            let is_last_round = round_idx == num_rounds - 1;
-
-            if is_last_round {
-                assert_eq!(num_rounds_left, 0);
-                assert!(round.writes.is_some());
-                let [write] = round.writes.unwrap();
-                let write_query = memory_queries_it.next().unwrap();
-                assert_eq!(write, write_query);
-
-                artifacts.all_memory_queries_accumulated.push(write);
-                let (_, intermediate_info) = artifacts
-                    .memory_queue_simulator
-                    .push_and_output_intermediate_data(write, round_function);
-                artifacts.all_memory_queue_states.push(intermediate_info);
-                current_memory_queue_state =
-                    take_sponge_like_queue_state_from_simulator(&artifacts.memory_queue_simulator);
-
-                if is_last_request {
-                    precompile_state = Keccak256PrecompileState::Finished;
-                } else {
-                    precompile_state = Keccak256PrecompileState::GetRequestFromQueue;
-                }
-            }
-
-            round_counter += 1;
-
-            if round_counter == num_rounds_per_circuit || (is_last_request && is_last_round) {
-                let early_termination = round_counter != num_rounds_per_circuit;
-                round_counter = 0;
-
-                let finished = is_last_request && is_last_round;
-                if finished {
-                    assert!(memory_queries_it.next().is_none());
-                }
+            state.input_buffer.append(&state.words_buffer);
+        }
 
-                let state_inner =
-                    zk_evm::precompiles::keccak256::transmute_state(internal_state.clone());
-                let mut u64_words_buffer_markers = [false; BUFFER_SIZE];
-                for i in 0..input_buffer.filled {
-                    u64_words_buffer_markers[i] = true;
-                }
+        let words = state.input_buffer.consume_rate();
+        let mut block = [0u8; KECCAK_RATE_IN_U64_WORDS * 8];
 
-                let mut keccak_internal_state = vec![];
-                for i in 0..5 {
-                    for j in 0..5 {
-                        let el = state_inner[i + 5 * j]; // circuit and non-circuit impls have different order
-                        keccak_internal_state.push(el);
-                    }
-                }
+        for (i, word) in words.into_iter().enumerate() {
+            block[(i * 8)..(i * 8 + 8)].copy_from_slice(&word.to_le_bytes());
+        }
+        state.internal_state.update(&block);
 
-                let mut keccak_internal_state: [E::Fr; 25] = keccak_internal_state
-                    .into_iter()
-                    .map(|el| u64_to_fe::<E::Fr>(el))
-                    .collect::<Vec<_>>()
-                    .try_into()
-                    .unwrap();
-
-                if early_termination {
-                    assert_eq!(precompile_state, Keccak256PrecompileState::Finished);
-                    // we finished all the requests, but didn't reset the state as circuit would do
-
-                    // Even though any work of the circuit after requests are done is NOT observable
-                    // and doesn't affect the correctness, we have a strict check that simulated input + output
-                    // matches to what output circuit produced by itself based on the common input only
-                    for el in u64_words_buffer_markers.iter_mut() {
-                        *el = false;
-                    }
-                    for el in input_buffer.words.iter_mut() {
-                        *el = 0u64;
-                    }
-                    // internal state is a bit more tricky, it'll be a round over empty input
-                    let mut internal_state_over_empty_buffer = Keccak256::default();
-                    let empty_block = [0u8; KECCAK_RATE_IN_U64_WORDS * 8];
-                    use zk_evm::precompiles::keccak256::Digest;
-                    internal_state_over_empty_buffer.update(&empty_block);
-                    let empty_state_inner = zk_evm::precompiles::keccak256::transmute_state(
-                        internal_state_over_empty_buffer.clone(),
-                    );
-                    let mut keccak_internal_state_over_empty_buffer = vec![];
-                    for i in 0..5 {
-                        for j in 0..5 {
-                            let el = empty_state_inner[i + 5 * j]; // circuit and non-circuit impls have different order
-                            keccak_internal_state_over_empty_buffer.push(el);
-                        }
-                    }
-
-                    keccak_internal_state = keccak_internal_state_over_empty_buffer
-                        .into_iter()
-                        .map(|el| u64_to_fe::<E::Fr>(el))
-                        .collect::<Vec<_>>()
-                        .try_into()
-                        .unwrap();
-                }
+        if is_last_round {
+            assert!(round.writes.is_some());
+        }
+    }
 
-                let input_is_empty = is_last_request;
-                let nothing_left = is_last_round && input_is_empty;
-
-                assert_eq!(nothing_left, finished);
-
-                let completed = precompile_state == Keccak256PrecompileState::Finished;
-                let read_unaligned_words_for_round =
-                    precompile_state == Keccak256PrecompileState::RunRoundFunction;
-                let read_precompile_call =
-                    precompile_state == Keccak256PrecompileState::GetRequestFromQueue;
-
-                use sync_vm::precompiles::keccak256::*;
-
-                let hidden_fsm_output_state = KeccakPrecompileStateWitness::<E> {
-                    completed,
-                    read_unaligned_words_for_round,
-                    keccak_internal_state,
-                    read_precompile_call,
-                    timestamp_to_use_for_read: request.timestamp.0,
-                    timestamp_to_use_for_write: request.timestamp.0 + 1,
-                    u64_words_buffer: input_buffer.words,
-                    u64_words_buffer_markers,
-                    call_params: KeccakPrecompileCallParamsWitness::<E> {
-                        input_page: precompile_request.memory_page_to_read,
-                        input_offset: precompile_request.input_memory_offset,
-                        output_page: precompile_request.memory_page_to_write,
-                        output_offset: precompile_request.output_memory_offset,
-                        num_rounds: num_rounds_left as u16,
-                        _marker: std::marker::PhantomData,
-                    },
+    fn initial_fsm_state() -> Self::FsmState {
+        let mut state = KeccakPrecompileState::<E>::placeholder_witness();
+        state.read_precompile_call = true;
 
-                    _marker: std::marker::PhantomData,
-                };
+        state
+    }
 
-                use crate::encodings::log_query::log_query_into_storage_record_witness;
+    fn empty_fsm_output_state() -> Self::FsmState {
+        let mut state = KeccakPrecompileState::<E>::placeholder_witness();
+        state.completed = true;
+        state.keccak_internal_state = keccak_internal_state_over_empty_buffer::<E>();
 
-                let range = starting_request_idx..(request_idx + 1);
-                let wit: VecDeque<_> = (&simulator_witness[range])
-                    .iter()
-                    .map(|el| {
-                        let mapped = log_query_into_storage_record_witness::<E>(&el.2);
+        state
+    }
 
-                        (el.0, mapped, el.1)
-                    })
-                    .collect();
+    fn fsm_output_state(
+        state: &Self::RequestState,
+        request: &LogQuery,
+        precompile_state: PrecompileState,
+        num_rounds_left: usize,
+        early_termination: bool,
+    ) -> Self::FsmState {
+        let mut u64_words_buffer = state.input_buffer.words;
+        let mut u64_words_buffer_markers = [false; BUFFER_SIZE];
+        for i in 0..state.input_buffer.filled {
+            u64_words_buffer_markers[i] = true;
+        }
 
-                let current_reads = std::mem::replace(&mut memory_reads_per_request, vec![]);
-                let mut current_witness = std::mem::replace(&mut memory_read_witnesses, vec![]);
-                current_witness.push(current_reads);
+        let mut keccak_internal_state =
+            keccak_internal_state_for_circuit::<E>(&state.internal_state);
 
-                let mut observable_input_data = PrecompileFunctionInputData::placeholder_witness();
-                if result.len() == 0 {
-                    observable_input_data.initial_log_queue_state = log_queue_input_state.clone();
-                    observable_input_data.initial_memory_state = memory_queue_input_state.clone();
-                }
+        if early_termination {
+            // Even though any work of the circuit after requests are done is NOT observable
+            // and doesn't affect the correctness, we have a strict check that simulated input + output
+            // matches to what output circuit produced by itself based on the common input only
+            u64_words_buffer_markers = [false; BUFFER_SIZE];
+            u64_words_buffer = [0u64; BUFFER_SIZE];
+            keccak_internal_state = keccak_internal_state_over_empty_buffer::<E>();
+        }
 
-                let mut observable_output_data =
-                    PrecompileFunctionOutputData::placeholder_witness();
-                if finished {
-                    observable_output_data.final_memory_state = current_memory_queue_state.clone();
-                }
+        KeccakPrecompileStateWitness::<E> {
+            completed: precompile_state == PrecompileState::Finished,
+            read_unaligned_words_for_round: precompile_state == PrecompileState::RunRoundFunction,
+            keccak_internal_state,
+            read_precompile_call: precompile_state == PrecompileState::GetRequestFromQueue,
+            timestamp_to_use_for_read: request.timestamp.0,
+            timestamp_to_use_for_write: request.timestamp.0 + 1,
+            u64_words_buffer,
+            u64_words_buffer_markers,
+            call_params: KeccakPrecompileCallParamsWitness::<E> {
+                input_page: state.call_params.input_page,
+                input_offset: state.call_params.input_offset,
+                output_page: state.call_params.output_page,
+                output_offset: state.call_params.output_offset,
+                num_rounds: num_rounds_left as u16,
+                _marker: std::marker::PhantomData,
+            },
 
-                let witness = Keccak256RoundFunctionInstanceWitness::<E> {
-                    closed_form_input: Keccak256RoundFunctionInputOutputWitness::<E> {
-                        start_flag: result.len() == 0,
-                        completion_flag: finished,
-                        observable_input: observable_input_data,
-                        observable_output: observable_output_data,
-                        hidden_fsm_input: Keccak256RoundFunctionFSMWitness::<E> {
-                            precompile_state: hidden_fsm_input_state,
-                            log_queue_state: log_queue_input_state.clone(),
-
-                            memory_queue_state: memory_queue_input_state.clone(),
-                            _marker: std::marker::PhantomData,
-                        },
-                        hidden_fsm_output: Keccak256RoundFunctionFSMWitness::<E> {
-                            precompile_state: hidden_fsm_output_state.clone(),
-                            log_queue_state: take_queue_state_from_simulator(
-                                &artifacts.demuxed_keccak_precompile_queue_simulator,
-                            ),
-                            memory_queue_state: current_memory_queue_state.clone(),
-                            _marker: std::marker::PhantomData,
-                        },
-                        _marker_e: (),
-                        _marker: std::marker::PhantomData,
-                    },
-                    requests_queue_witness: FixedWidthEncodingGenericQueueWitness { wit: wit },
-                    memory_reads_witness: current_witness,
-                };
-
-                // make non-inclusize
-                request_ranges.push(starting_request_idx..(request_idx + 1));
-                starting_request_idx = request_idx + 1;
-
-                result.push(witness);
-
-                log_queue_input_state = take_queue_state_from_simulator(
-                    &artifacts.demuxed_keccak_precompile_queue_simulator,
-                );
-                hidden_fsm_input_state = hidden_fsm_output_state;
-                memory_queue_input_state = current_memory_queue_state.clone();
-            }
+            _marker: std::marker::PhantomData,
         }
+    }
 
-        if !memory_reads_per_request.is_empty() {
-            // we may have drained it already if it was the end of the circuit
-            memory_read_witnesses.push(memory_reads_per_request);
+    fn instance_witness(
+        parts: PrecompileInstanceWitnessParts<E, Self::FsmState>,
+    ) -> Self::InstanceWitness {
+        Keccak256RoundFunctionInstanceWitness::<E> {
+            closed_form_input: Keccak256RoundFunctionInputOutputWitness::<E> {
+                start_flag: parts.start_flag,
+                completion_flag: parts.completion_flag,
+                observable_input: parts.observable_input,
+                observable_output: parts.observable_output,
+                hidden_fsm_input: Keccak256RoundFunctionFSMWitness::<E> {
+                    precompile_state: parts.hidden_fsm_input.precompile_state,
+                    log_queue_state: parts.hidden_fsm_input.log_queue_state,
+                    memory_queue_state: parts.hidden_fsm_input.memory_queue_state,
+                    _marker: std::marker::PhantomData,
+                },
+                hidden_fsm_output: Keccak256RoundFunctionFSMWitness::<E> {
+                    precompile_state: parts.hidden_fsm_output.precompile_state,
+                    log_queue_state: parts.hidden_fsm_output.log_queue_state,
+                    memory_queue_state: parts.hidden_fsm_output.memory_queue_state,
+                    _marker: std::marker::PhantomData,
+                },
+                _marker_e: (),
+                _marker: std::marker::PhantomData,
+            },
+            requests_queue_witness: parts.requests_queue_witness,
+            memory_reads_witness: parts.memory_reads_witness,
         }
     }
+}
 
-    assert_eq!(
-        artifacts.all_memory_queries_accumulated.len(),
-        artifacts.all_memory_queue_states.len()
-    );
-    assert_eq!(
-        artifacts.all_memory_queries_accumulated.len(),
-        artifacts.memory_queue_simulator.num_items as usize
-    );
-
-    result
+pub fn keccak256_decompose_into_per_circuit_witness<
+    E: Engine,
+    R: CircuitArithmeticRoundFunction<E, 2, 3>,
+>(
+    artifacts: &mut FullBlockArtifacts<E>,
+    num_rounds_per_circuit: usize,
+    round_function: &R,
+) -> Vec<Keccak256RoundFunctionInstanceWitness<E>> {
+    decompose_precompile_into_per_circuit_witness::<E, R, Keccak256Decomposer>(
+        artifacts,
+        num_rounds_per_circuit,
+        round_function,
+    )
 }
 
 // #[cfg(test)]
@@ -3,10 +3,14 @@ use crate::bellman::Engine;
 use crate::encodings::decommittment_request::DecommittmentQueueSimulator;
 use crate::encodings::log_query::log_query_into_storage_record_witness;
 use crate::encodings::log_query::LogQueueSimulator;
+use crate::encodings::log_query::LogQueueState;
 use crate::encodings::memory_query::MemoryQueueSimulator;
 use crate::ff::Field;
 use crate::utils::biguint_from_u256;
-use crate::witness::full_block_artifact::FullBlockArtifacts;
+use crate::witness::full_block_artifact::DemuxedPrecompileQueues;
+use crate::witness::full_block_artifact::EventsArtifacts;
+use crate::witness::full_block_artifact::L1MessagesArtifacts;
+use crate::witness::full_block_artifact::RollupStorageArtifacts;
 use rayon::prelude::*;
 use std::cmp::Ordering;
 use sync_vm::circuit_structures::traits::CircuitArithmeticRoundFunction;
@@ -24,7 +28,12 @@ use zk_evm::aux_structures::*;
 
 /// Take a storage log, output logs separately for events, l1 messages, storage, etc
 pub fn compute_logs_demux<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>(
-    artifacts: &mut FullBlockArtifacts<E>,
+    original_log_queue_simulator: &LogQueueSimulator<E>,
+    original_log_queue_states: &[(u32, LogQueueState<E>)],
+    rollup_storage: &mut RollupStorageArtifacts<E>,
+    events: &mut EventsArtifacts<E>,
+    l1_messages: &mut L1MessagesArtifacts<E>,
+    precompile_queues: &mut DemuxedPrecompileQueues<E>,
     per_circuit_capacity: usize,
     round_function: &R,
 ) -> Vec<LogDemuxerCircuitInstanceWitness<E>> {
@@ -32,14 +41,13 @@ pub fn compute_logs_demux<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>
 
     // have to manually unroll, otherwise borrow checker will complain
 
-    assert!(artifacts
-        .original_log_queue_simulator
+    assert!(original_log_queue_simulator
         .witness
         .as_slices()
         .1
         .is_empty());
-    let input_queue_witness = &artifacts.original_log_queue_simulator.witness.as_slices().0;
-    let mut states_iter = artifacts.original_log_queue_states.iter();
+    let input_queue_witness = original_log_queue_simulator.witness.as_slices().0;
+    let mut states_iter = original_log_queue_states.iter();
 
     let mut results: Vec<LogDemuxerCircuitInstanceWitness<E>> = vec![];
 
@@ -49,8 +57,7 @@ pub fn compute_logs_demux<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>
 
     let mut state_idx = 0;
 
-    let full_log_queue_state =
-        take_queue_state_from_simulator(&artifacts.original_log_queue_simulator);
+    let full_log_queue_state = take_queue_state_from_simulator(original_log_queue_simulator);
 
     use zk_evm::zkevm_opcode_defs::system_params::{
         ECRECOVER_INNER_FUNCTION_PRECOMPILE_FORMAL_ADDRESS,
@@ -62,19 +69,20 @@ pub fn compute_logs_demux<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>
         EVENT_AUX_BYTE, L1_MESSAGE_AUX_BYTE, PRECOMPILE_AUX_BYTE, STORAGE_AUX_BYTE,
     };
 
-    let mut demuxed_rollup_storage_queries_it = artifacts.demuxed_rollup_storage_queries.iter();
-    let mut demuxed_event_queries_it = artifacts.demuxed_event_queries.iter();
-    let mut demuxed_to_l1_queries_it = artifacts.demuxed_to_l1_queries.iter();
+    let mut demuxed_rollup_storage_queries_it =
+        rollup_storage.demuxed_rollup_storage_queries.iter();
+    let mut demuxed_event_queries_it = events.demuxed_event_queries.iter();
+    let mut demuxed_to_l1_queries_it = l1_messages.demuxed_to_l1_queries.iter();
     let mut demuxed_keccak_precompile_queries_it =
-        artifacts.demuxed_keccak_precompile_queries.iter();
+        precompile_queues.demuxed_keccak_precompile_queries.iter();
     let mut demuxed_sha256_precompile_queries_it =
-        artifacts.demuxed_sha256_precompile_queries.iter();
-    let mut demuxed_ecrecover_queries_it = artifacts.demuxed_ecrecover_queries.iter();
+        precompile_queues.demuxed_sha256_precompile_queries.iter();
+    let mut demuxed_ecrecover_queries_it = precompile_queues.demuxed_ecrecover_queries.iter();
 
     let mut input_passthrough_data = LogDemuxerInputData::placeholder_witness();
     // we only need the state of the original input
     input_passthrough_data.initial_log_queue_state =
-        take_queue_state_from_simulator(&artifacts.original_log_queue_simulator);
+        take_queue_state_from_simulator(original_log_queue_simulator);
 
     let output_passthrough_data = LogDemuxerOutputData::placeholder_witness();
 
@@ -91,11 +99,11 @@ pub fn compute_logs_demux<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>
                     match query.shard_id {
                         0 => {
                             let item = demuxed_rollup_storage_queries_it.next().copied().unwrap();
-                            let (_old_tail, intermediate_info) = artifacts
+                            let (_old_tail, intermediate_info) = rollup_storage
                                 .demuxed_rollup_storage_queue_simulator
                                 .push_and_output_intermediate_data(item, round_function);
 
-                            artifacts
+                            rollup_storage
                                 .demuxed_rollup_storage_queue_states
                                 .push(intermediate_info);
                         }
@@ -104,19 +112,21 @@ pub fn compute_logs_demux<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>
                 }
                 L1_MESSAGE_AUX_BYTE => {
                     let item = demuxed_to_l1_queries_it.next().copied().unwrap();
-                    let (_old_tail, intermediate_info) = artifacts
+                    let (_old_tail, intermediate_info) = l1_messages
                         .demuxed_to_l1_queue_simulator
                         .push_and_output_intermediate_data(item, round_function);
 
-                    artifacts.demuxed_to_l1_queue_states.push(intermediate_info);
+                    l1_messages
+                        .demuxed_to_l1_queue_states
+                        .push(intermediate_info);
                 }
                 EVENT_AUX_BYTE => {
                     let item = demuxed_event_queries_it.next().copied().unwrap();
-                    let (_old_tail, intermediate_info) = artifacts
+                    let (_old_tail, intermediate_info) = events
                         .demuxed_events_queue_simulator
                         .push_and_output_intermediate_data(item, round_function);
 
-                    artifacts.demuxed_event_queue_states.push(intermediate_info);
+                    events.demuxed_event_queue_states.push(intermediate_info);
                 }
                 PRECOMPILE_AUX_BYTE => {
                     assert!(!query.rollback);
@@ -127,11 +137,11 @@ pub fn compute_logs_demux<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>
                                 .next()
                                 .copied()
                                 .unwrap();
-                            let (_old_tail, intermediate_info) = artifacts
+                            let (_old_tail, intermediate_info) = precompile_queues
                                 .demuxed_keccak_precompile_queue_simulator
                                 .push_and_output_intermediate_data(item, round_function);
 
-                            artifacts
+                            precompile_queues
                                 .demuxed_keccak_precompile_queue_states
                                 .push(intermediate_info);
                         }
@@ -140,21 +150,21 @@ pub fn compute_logs_demux<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>
                                 .next()
                                 .copied()
                                 .unwrap();
-                            let (_old_tail, intermediate_info) = artifacts
+                            let (_old_tail, intermediate_info) = precompile_queues
                                 .demuxed_sha256_precompile_queue_simulator
                                 .push_and_output_intermediate_data(item, round_function);
 
-                            artifacts
+                            precompile_queues
                                 .demuxed_sha256_precompile_queue_states
                                 .push(intermediate_info);
                         }
                         a if a == *ECRECOVER_INNER_FUNCTION_PRECOMPILE_FORMAL_ADDRESS => {
                             let item = demuxed_ecrecover_queries_it.next().copied().unwrap();
-                            let (_old_tail, intermediate_info) = artifacts
+                            let (_old_tail, intermediate_info) = precompile_queues
                                 .demuxed_ecrecover_queue_simulator
                                 .push_and_output_intermediate_data(item, round_function);
 
-                            artifacts
+                            precompile_queues
                                 .demuxed_ecrecover_queue_states
                                 .push(intermediate_info);
                         }
@@ -169,8 +179,7 @@ pub fn compute_logs_demux<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>
 
         // make the output
 
-        let input_witness: VecDeque<_> = artifacts
-            .original_log_queue_simulator
+        let input_witness: VecDeque<_> = original_log_queue_simulator
             .witness
             .iter()
             .skip(state_idx)
@@ -184,26 +193,28 @@ pub fn compute_logs_demux<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>
 
         state_idx += per_circuit_capacity;
 
-        let idx = std::cmp::min(artifacts.original_log_queue_states.len(), state_idx) - 1;
+        let idx = std::cmp::min(original_log_queue_states.len(), state_idx) - 1;
 
         let mut fsm_output = LogDemuxerFSMInputOutput::placeholder_witness();
         let mut initial_log_queue_state = full_log_queue_state.clone();
-        initial_log_queue_state.head_state = artifacts.original_log_queue_states[idx].1.tail;
-        initial_log_queue_state.num_items -= artifacts.original_log_queue_states[idx].1.num_items;
+        initial_log_queue_state.head_state = original_log_queue_states[idx].1.tail;
+        initial_log_queue_state.num_items -= original_log_queue_states[idx].1.num_items;
 
         fsm_output.initial_log_queue_state = initial_log_queue_state;
         fsm_output.storage_access_queue_state =
-            take_queue_state_from_simulator(&artifacts.demuxed_rollup_storage_queue_simulator);
+            take_queue_state_from_simulator(&rollup_storage.demuxed_rollup_storage_queue_simulator);
         fsm_output.events_access_queue_state =
-            take_queue_state_from_simulator(&artifacts.demuxed_events_queue_simulator);
+            take_queue_state_from_simulator(&events.demuxed_events_queue_simulator);
         fsm_output.l1messages_access_queue_state =
-            take_queue_state_from_simulator(&artifacts.demuxed_to_l1_queue_simulator);
-        fsm_output.keccak256_access_queue_state =
-            take_queue_state_from_simulator(&artifacts.demuxed_keccak_precompile_queue_simulator);
-        fsm_output.sha256_access_queue_state =
-            take_queue_state_from_simulator(&artifacts.demuxed_sha256_precompile_queue_simulator);
+            take_queue_state_from_simulator(&l1_messages.demuxed_to_l1_queue_simulator);
+        fsm_output.keccak256_access_queue_state = take_queue_state_from_simulator(
+            &precompile_queues.demuxed_keccak_precompile_queue_simulator,
+        );
+        fsm_output.sha256_access_queue_state = take_queue_state_from_simulator(
+            &precompile_queues.demuxed_sha256_precompile_queue_simulator,
+        );
         fsm_output.ecrecover_access_queue_state =
-            take_queue_state_from_simulator(&artifacts.demuxed_ecrecover_queue_simulator);
+            take_queue_state_from_simulator(&precompile_queues.demuxed_ecrecover_queue_simulator);
 
         let mut witness = LogDemuxerCircuitInstanceWitness {
             closed_form_input: ClosedFormInputWitness {
@@ -223,35 +234,37 @@ pub fn compute_logs_demux<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>
             witness
                 .closed_form_input
                 .observable_output
-                .storage_access_queue_state =
-                take_queue_state_from_simulator(&artifacts.demuxed_rollup_storage_queue_simulator);
+                .storage_access_queue_state = take_queue_state_from_simulator(
+                &rollup_storage.demuxed_rollup_storage_queue_simulator,
+            );
             witness
                 .closed_form_input
                 .observable_output
                 .events_access_queue_state =
-                take_queue_state_from_simulator(&artifacts.demuxed_events_queue_simulator);
+                take_queue_state_from_simulator(&events.demuxed_events_queue_simulator);
             witness
                 .closed_form_input
                 .observable_output
                 .l1messages_access_queue_state =
-                take_queue_state_from_simulator(&artifacts.demuxed_to_l1_queue_simulator);
+                take_queue_state_from_simulator(&l1_messages.demuxed_to_l1_queue_simulator);
             witness
                 .closed_form_input
                 .observable_output
                 .keccak256_access_queue_state = take_queue_state_from_simulator(
-                &artifacts.demuxed_keccak_precompile_queue_simulator,
+                &precompile_queues.demuxed_keccak_precompile_queue_simulator,
             );
             witness
                 .closed_form_input
                 .observable_output
                 .sha256_access_queue_state = take_queue_state_from_simulator(
-                &artifacts.demuxed_sha256_precompile_queue_simulator,
+                &precompile_queues.demuxed_sha256_precompile_queue_simulator,
             );
             witness
                 .closed_form_input
                 .observable_output
-                .ecrecover_access_queue_state =
-                take_queue_state_from_simulator(&artifacts.demuxed_ecrecover_queue_simulator);
+                .ecrecover_access_queue_state = take_queue_state_from_simulator(
+                &precompile_queues.demuxed_ecrecover_queue_simulator,
+            );
         }
 
         if is_last {
@@ -9,6 +9,7 @@ pub mod events_sort_dedup;
 pub mod get_storage_application_pubdata;
 pub mod keccak256_round_function;
 pub mod log_demux;
+pub mod precompile_decomposer;
 pub mod ram_permutation;
 pub mod sha256_round_function;
 pub mod storage_application;
@@ -0,0 +1,716 @@
+use super::*;
+use crate::biguint_from_u256;
+use crate::encodings::log_query::{LogQueueSimulator, LogQueueState};
+use crate::pairing::Engine;
+use crate::witness::full_block_artifact::FullBlockArtifacts;
+use derivative::Derivative;
+use num_bigint::BigUint;
+use sync_vm::circuit_structures::traits::CircuitArithmeticRoundFunction;
+use sync_vm::precompiles::*;
+use sync_vm::scheduler::data_access_functions::StorageLogRecord;
+use sync_vm::scheduler::queues::{
+    FixedWidthEncodingGenericQueueStateWitness, FixedWidthEncodingGenericQueueWitness,
+    FullSpongeLikeQueueStateWitness,
+};
+use sync_vm::traits::CSWitnessable;
+use zk_evm::aux_structures::{LogQuery, MemoryQuery};
+
+// we want to simulate splitting of data into many separate instances of the same circuit.
+// So we basically need to reconstruct the FSM state on input/output, and passthrough data.
+// Requests queue, memory queue and closed form inputs are handled the same way for all the precompiles,
+// and only the internal state of the precompile (e.g. hash state and buffer) is specific
+
+#[derive(Derivative)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrecompileState {
+    GetRequestFromQueue,
+    RunRoundFunction,
+    Finished,
+}
+
+// memory locations of the request, offsets are advanced while the request is processed
+#[derive(Derivative)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PrecompileCallParams {
+    pub input_page: u32,
+    pub input_offset: u32,
+    pub output_page: u32,
+    pub output_offset: u32,
+}
+
+impl PrecompileCallParams {
+    pub fn from_request(request: &LogQuery) -> Self {
+        use zk_evm::precompiles::precompile_abi_in_log;
+        let precompile_request = precompile_abi_in_log(*request);
+
+        Self {
+            input_page: precompile_request.memory_page_to_read,
+            input_offset: precompile_request.input_memory_offset,
+            output_page: precompile_request.memory_page_to_write,
+            output_offset: precompile_request.output_memory_offset,
+        }
+    }
+}
+
+// number of rounds as encoded in the request by the caller
+pub fn num_rounds_from_request(request: &LogQuery) -> usize {
+    use zk_evm::precompiles::precompile_abi_in_log;
+    precompile_abi_in_log(*request).precompile_interpreted_data as usize
+}
+
+#[derive(Derivative)]
+#[derivative(Clone(bound = "S: Clone"))]
+pub struct PrecompileFsmWitness<E: Engine, S> {
+    pub precompile_state: S,
+    pub log_queue_state: FixedWidthEncodingGenericQueueStateWitness<E>,
+    pub memory_queue_state: FullSpongeLikeQueueStateWitness<E>,
+}
+
+// everything that is needed to build a witness for the single instance of the precompile circuit
+pub struct PrecompileInstanceWitnessParts<E: Engine, S> {
+    pub start_flag: bool,
+    pub completion_flag: bool,
+    pub observable_input: <PrecompileFunctionInputData<E> as CSWitnessable<E>>::Witness,
+    pub observable_output: <PrecompileFunctionOutputData<E> as CSWitnessable<E>>::Witness,
+    pub hidden_fsm_input: PrecompileFsmWitness<E, S>,
+    pub hidden_fsm_output: PrecompileFsmWitness<E, S>,
+    pub requests_queue_witness: FixedWidthEncodingGenericQueueWitness<E, StorageLogRecord<E>, 5>,
+    pub memory_reads_witness: Vec<Vec<BigUint>>,
+}
+
+pub trait PrecompileDecomposer<E: Engine> {
+    // everything that out-of-circuit precompile recorded for a single request
+    type RequestWitness;
+    // memory accesses of a single round of the circuit
+    type RoundWitness;
+    // state that is carried between the rounds of the same request
+    type RequestState;
+    // precompile specific part of the hidden FSM state of the circuit
+    type FsmState: Clone;
+    type InstanceWitness;
+
+    fn requests_queue_simulator(artifacts: &mut FullBlockArtifacts<E>)
+        -> &mut LogQueueSimulator<E>;
+
+    // requests queue state in the hidden output of the circuit that had no requests to process
+    fn empty_output_log_queue_state(
+        artifacts: &mut FullBlockArtifacts<E>,
+    ) -> FixedWidthEncodingGenericQueueStateWitness<E> {
+        take_queue_state_from_simulator(Self::requests_queue_simulator(artifacts))
+    }
+
+    // demuxed requests, their queue states and the witness of every request
+    fn take_requests(
+        artifacts: &mut FullBlockArtifacts<E>,
+    ) -> (
+        Vec<LogQuery>,
+        Vec<LogQueueState<E>>,
+        Vec<(u32, LogQuery, Self::RequestWitness)>,
+    );
+
+    // separate copy of the precompile memory queries, that is used to check the rounds witness
+    fn memory_queries(artifacts: &mut FullBlockArtifacts<E>) -> &mut Vec<MemoryQuery>;
+
+    fn into_rounds(witness: Self::RequestWitness) -> Vec<Self::RoundWitness>;
+    fn round_reads(round: &Self::RoundWitness) -> &[MemoryQuery];
+    fn round_writes(round: &Self::RoundWitness) -> &[MemoryQuery];
+    fn num_rounds(request: &LogQuery) -> usize;
+
+    fn new_request_state(request: &LogQuery) -> Self::RequestState;
+    // called after the reads of the round are added into the memory queue, and before the writes
+    fn process_round(
+        state: &mut Self::RequestState,
+        round: &Self::RoundWitness,
+        round_idx: usize,
+        is_last_round: bool,
+    );
+
+    // circuit starts by reading a request from the queue
+    fn initial_fsm_state() -> Self::FsmState;
+    // state of the circuit that had no requests to process
+    fn empty_fsm_output_state() -> Self::FsmState;
+    // if circuit has rounds left after all the requests are processed, it continues to run
+    // over the empty input, so its state is different from the out-of-circuit one
+    fn fsm_output_state(
+        state: &Self::RequestState,
+        request: &LogQuery,
+        precompile_state: PrecompileState,
+        num_rounds_left: usize,
+        early_termination: bool,
+    ) -> Self::FsmState;
+
+    fn instance_witness(
+        parts: PrecompileInstanceWitnessParts<E, Self::FsmState>,
+    ) -> Self::InstanceWitness;
+}
+
+pub fn decompose_precompile_into_per_circuit_witness<
+    E: Engine,
+    R: CircuitArithmeticRoundFunction<E, 2, 3>,
+    D: PrecompileDecomposer<E>,
+>(
+    artifacts: &mut FullBlockArtifacts<E>,
+    num_rounds_per_circuit: usize,
+    round_function: &R,
+) -> Vec<D::InstanceWitness> {
+    decompose_precompile_into_instance_parts::<E, R, D>(
+        artifacts,
+        num_rounds_per_circuit,
+        round_function,
+    )
+    .into_iter()
+    .map(D::instance_witness)
+    .collect()
+}
+
+fn push_memory_query<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+    artifacts: &mut FullBlockArtifacts<E>,
+    query: MemoryQuery,
+    round_function: &R,
+) {
+    artifacts.all_memory_queries_accumulated.push(query);
+    let (_, intermediate_info) = artifacts
+        .memory_queue_simulator
+        .push_and_output_intermediate_data(query, round_function);
+    artifacts.all_memory_queue_states.push(intermediate_info);
+}
+
+pub fn decompose_precompile_into_instance_parts<
+    E: Engine,
+    R: CircuitArithmeticRoundFunction<E, 2, 3>,
+    D: PrecompileDecomposer<E>,
+>(
+    artifacts: &mut FullBlockArtifacts<E>,
+    num_rounds_per_circuit: usize,
+    round_function: &R,
+) -> Vec<PrecompileInstanceWitnessParts<E, D::FsmState>> {
+    assert!(num_rounds_per_circuit > 0);
+    assert_eq!(
+        artifacts.all_memory_queries_accumulated.len(),
+        artifacts.all_memory_queue_states.len()
+    );
+    assert_eq!(
+        artifacts.all_memory_queries_accumulated.len(),
+        artifacts.memory_queue_simulator.num_items as usize
+    );
+
+    let (precompile_calls, precompile_calls_queue_states, round_function_witness) =
+        D::take_requests(artifacts);
+    let round_function_witness: Vec<_> = round_function_witness
+        .into_iter()
+        .map(|(cycle, request, witness)| (cycle, request, D::into_rounds(witness)))
+        .collect();
+
+    // split into aux witness, don't mix with the memory
+    let precompile_memory_queries = D::memory_queries(artifacts);
+    for (_cycle, _query, rounds) in round_function_witness.iter() {
+        for round in rounds.iter() {
+            // we read, then write
+            precompile_memory_queries.extend_from_slice(D::round_reads(round));
+            precompile_memory_queries.extend_from_slice(D::round_writes(round));
+        }
+    }
+    let memory_queries = std::mem::replace(D::memory_queries(artifacts), vec![]);
+
+    let simulator_witness: Vec<_> = D::requests_queue_simulator(artifacts)
+        .witness
+        .clone()
+        .into();
+
+    // check basic consistency
+    assert_eq!(precompile_calls.len(), precompile_calls_queue_states.len());
+    assert_eq!(precompile_calls.len(), round_function_witness.len());
+
+    let mut result = vec![];
+
+    if precompile_calls.len() == 0 {
+        // we can not skip the circuit (at least for now), so we have to create a dummy on
+        let log_queue_input_state =
+            take_queue_state_from_simulator(D::requests_queue_simulator(artifacts));
+        let log_queue_output_state = D::empty_output_log_queue_state(artifacts);
+        let memory_queue_input_state =
+            take_sponge_like_queue_state_from_simulator(&artifacts.memory_queue_simulator);
+
+        let mut observable_input = PrecompileFunctionInputData::placeholder_witness();
+        observable_input.initial_memory_state = memory_queue_input_state.clone();
+        observable_input.initial_log_queue_state = log_queue_input_state.clone();
+
+        let mut observable_output = PrecompileFunctionOutputData::placeholder_witness();
+        observable_output.final_memory_state = memory_queue_input_state.clone();
+
+        result.push(PrecompileInstanceWitnessParts {
+            start_flag: true,
+            completion_flag: true,
+            observable_input,
+            observable_output,
+            hidden_fsm_input: PrecompileFsmWitness {
+                precompile_state: D::initial_fsm_state(),
+                log_queue_state: log_queue_input_state.clone(),
+                memory_queue_state: memory_queue_input_state.clone(),
+            },
+            hidden_fsm_output: PrecompileFsmWitness {
+                precompile_state: D::empty_fsm_output_state(),
+                log_queue_state: log_queue_output_state,
+                memory_queue_state: memory_queue_input_state,
+            },
+            requests_queue_witness: FixedWidthEncodingGenericQueueWitness {
+                wit: VecDeque::new(),
+            },
+            memory_reads_witness: vec![],
+        });
+
+        return result;
+    }
+
+    let mut round_counter = 0;
+    let num_requests = precompile_calls.len();
+
+    // convension
+    let mut log_queue_input_state =
+        take_queue_state_from_simulator(D::requests_queue_simulator(artifacts));
+    let mut hidden_fsm_input_state = D::initial_fsm_state();
+
+    let mut memory_queries_it = memory_queries.into_iter();
+
+    let mut memory_read_witnesses = vec![];
+
+    let mut precompile_state = PrecompileState::GetRequestFromQueue;
+
+    let mut starting_request_idx = 0;
+
+    let mut memory_queue_input_state =
+        take_sponge_like_queue_state_from_simulator(&artifacts.memory_queue_simulator);
+    let mut current_memory_queue_state = memory_queue_input_state.clone();
+
+    for (request_idx, ((request, _queue_transition_state), per_request_work)) in precompile_calls
+        .into_iter()
+        .zip(precompile_calls_queue_states.into_iter())
+        .zip(round_function_witness.into_iter())
+        .enumerate()
+    {
+        // request level. Each request can be broken into few rounds
+
+        let _ =
+            D::requests_queue_simulator(artifacts).pop_and_output_intermediate_data(round_function);
+
+        let mut memory_reads_per_request = vec![];
+
+        assert_eq!(precompile_state, PrecompileState::GetRequestFromQueue);
+
+        let (_cycle, _req, round_witness) = per_request_work;
+        assert_eq!(request, _req);
+
+        let num_rounds = D::num_rounds(&request);
+        assert_eq!(num_rounds, round_witness.len());
+
+        let mut num_rounds_left = num_rounds;
+
+        let is_last_request = request_idx == num_requests - 1;
+
+        let mut request_state = D::new_request_state(&request);
+
+        precompile_state = PrecompileState::RunRoundFunction;
+
+        for (round_idx, round) in round_witness.into_iter().enumerate() {
+            // we proceed the request as long as we can
+            for read in D::round_reads(&round).iter().copied() {
+                let read_query = memory_queries_it.next().unwrap();
+                assert_eq!(read, read_query);
+                assert!(!read_query.rw_flag);
+                memory_reads_per_request.push(biguint_from_u256(read_query.value));
+
+                push_memory_query(artifacts, read, round_function);
+                current_memory_queue_state =
+                    take_sponge_like_queue_state_from_simulator(&artifacts.memory_queue_simulator);
+            }
+
+            let is_last_round = round_idx == num_rounds - 1;
+
+            D::process_round(&mut request_state, &round, round_idx, is_last_round);
+
+            num_rounds_left -= 1;
+
+            for write in D::round_writes(&round).iter().copied() {
+                let write_query = memory_queries_it.next().unwrap();
+                assert_eq!(write, write_query);
+                assert!(write_query.rw_flag);
+
+                push_memory_query(artifacts, write, round_function);
+                current_memory_queue_state =
+                    take_sponge_like_queue_state_from_simulator(&artifacts.memory_queue_simulator);
+            }
+
+            if is_last_round {
+                assert_eq!(num_rounds_left, 0);
+
+                if is_last_request {
+                    precompile_state = PrecompileState::Finished;
+                } else {
+                    precompile_state = PrecompileState::GetRequestFromQueue;
+                }
+            }
+
+            round_counter += 1;
+
+            if round_counter == num_rounds_per_circuit || (is_last_request && is_last_round) {
+                let early_termination = round_counter != num_rounds_per_circuit;
+                round_counter = 0;
+
+                let finished = is_last_request && is_last_round;
+                if finished {
+                    assert!(memory_queries_it.next().is_none());
+                }
+
+                if early_termination {
+                    // we finished all the requests, but didn't reset the state as circuit would do
+                    assert_eq!(precompile_state, PrecompileState::Finished);
+                }
+
+                let hidden_fsm_output_state = D::fsm_output_state(
+                    &request_state,
+                    &request,
+                    precompile_state,
+                    num_rounds_left,
+                    early_termination,
+                );
+
+                use crate::encodings::log_query::log_query_into_storage_record_witness;
+
+                let range = starting_request_idx..(request_idx + 1);
+                let wit: VecDeque<_> = (&simulator_witness[range])
+                    .iter()
+                    .map(|el| {
+                        let mapped = log_query_into_storage_record_witness::<E>(&el.2);
+
+                        (el.0, mapped, el.1)
+                    })
+                    .collect();
+
+                let current_reads = std::mem::replace(&mut memory_reads_per_request, vec![]);
+                let mut current_witness = std::mem::replace(&mut memory_read_witnesses, vec![]);
+                current_witness.push(current_reads);
+
+                let mut observable_input = PrecompileFunctionInputData::placeholder_witness();
+                if result.len() == 0 {
+                    observable_input.initial_memory_state = memory_queue_input_state.clone();
+                    observable_input.initial_log_queue_state = log_queue_input_state.clone();
+                }
+
+                let mut observable_output = PrecompileFunctionOutputData::placeholder_witness();
+                if finished {
+                    observable_output.final_memory_state = current_memory_queue_state.clone();
+                }
+
+                let log_queue_output_state =
+                    take_queue_state_from_simulator(D::requests_queue_simulator(artifacts));
+
+                result.push(PrecompileInstanceWitnessParts {
+                    start_flag: result.len() == 0,
+                    completion_flag: finished,
+                    observable_input,
+                    observable_output,
+                    hidden_fsm_input: PrecompileFsmWitness {
+                        precompile_state: hidden_fsm_input_state,
+                        log_queue_state: log_queue_input_state,
+                        memory_queue_state: memory_queue_input_state,
+                    },
+                    hidden_fsm_output: PrecompileFsmWitness {
+                        precompile_state: hidden_fsm_output_state.clone(),
+                        log_queue_state: log_queue_output_state.clone(),
+                        memory_queue_state: current_memory_queue_state.clone(),
+                    },
+                    requests_queue_witness: FixedWidthEncodingGenericQueueWitness { wit },
+                    memory_reads_witness: current_witness,
+                });
+
+                // make non-inclusize
+                starting_request_idx = request_idx + 1;
+
+                log_queue_input_state = log_queue_output_state;
+                hidden_fsm_input_state = hidden_fsm_output_state;
+                memory_queue_input_state = current_memory_queue_state.clone();
+            }
+        }
+
+        if !memory_reads_per_request.is_empty() {
+            // we may have drained it already if it was the end of the circuit
+            memory_read_witnesses.push(memory_reads_per_request);
+        }
+    }
+
+    assert_eq!(
+        artifacts.all_memory_queries_accumulated.len(),
+        artifacts.all_memory_queue_states.len()
+    );
+    assert_eq!(
+        artifacts.all_memory_queries_accumulated.len(),
+        artifacts.memory_queue_simulator.num_items as usize
+    );
+
+    result
+}
+
+// Runs the decomposition and checks the properties that any precompile must satisfy:
+// instances form a chain over the requests and memory queues, consume every request
+// and every memory query of the precompile exactly once, and only the first (last)
+// instance has an observable input (output). Returns instances for precompile specific checks
+#[cfg(test)]
+pub(crate) fn check_precompile_decomposition<
+    E: Engine,
+    R: CircuitArithmeticRoundFunction<E, 2, 3>,
+    D: PrecompileDecomposer<E>,
+>(
+    artifacts: &mut FullBlockArtifacts<E>,
+    num_rounds_per_circuit: usize,
+    round_function: &R,
+) -> Vec<PrecompileInstanceWitnessParts<E, D::FsmState>> {
+    fn assert_log_queue_states_equal<E: Engine>(
+        a: &FixedWidthEncodingGenericQueueStateWitness<E>,
+        b: &FixedWidthEncodingGenericQueueStateWitness<E>,
+    ) {
+        assert_eq!(a.num_items, b.num_items);
+        assert_eq!(a.head_state, b.head_state);
+        assert_eq!(a.tail_state, b.tail_state);
+    }
+
+    fn assert_memory_queue_states_equal<E: Engine>(
+        a: &FullSpongeLikeQueueStateWitness<E>,
+        b: &FullSpongeLikeQueueStateWitness<E>,
+    ) {
+        assert_eq!(a.length, b.length);
+        assert_eq!(a.head, b.head);
+        assert_eq!(a.tail, b.tail);
+    }
+
+    let (requests, _, round_witnesses) = D::take_requests(&mut artifacts.clone());
+    let num_requests = requests.len();
+    let num_rounds: usize = requests.iter().map(D::num_rounds).sum();
+    let mut num_reads = 0;
+    let mut num_queries = 0;
+    for (_, _, witness) in round_witnesses.into_iter() {
+        for round in D::into_rounds(witness).iter() {
+            num_reads += D::round_reads(round).len();
+            num_queries += D::round_reads(round).len() + D::round_writes(round).len();
+        }
+    }
+
+    let initial_log_queue_state =
+        take_queue_state_from_simulator(D::requests_queue_simulator(artifacts));
+    let initial_memory_queue_state =
+        take_sponge_like_queue_state_from_simulator(&artifacts.memory_queue_simulator);
+
+    let instances = decompose_precompile_into_instance_parts::<E, R, D>(
+        artifacts,
+        num_rounds_per_circuit,
+        round_function,
+    );
+
+    let final_memory_queue_state =
+        take_sponge_like_queue_state_from_simulator(&artifacts.memory_queue_simulator);
+    assert_eq!(
+        final_memory_queue_state.length,
+        initial_memory_queue_state.length + num_queries as u32
+    );
+    assert_eq!(D::requests_queue_simulator(artifacts).num_items, 0);
+
+    let expected_num_instances = std::cmp::max(
+        (num_rounds + num_rounds_per_circuit - 1) / num_rounds_per_circuit,
+        1,
+    );
+    assert_eq!(instances.len(), expected_num_instances);
+
+    let first = instances.first().unwrap();
+    assert_log_queue_states_equal(
+        &first.observable_input.initial_log_queue_state,
+        &initial_log_queue_state,
+    );
+    assert_memory_queue_states_equal(
+        &first.observable_input.initial_memory_state,
+        &initial_memory_queue_state,
+    );
+    let last = instances.last().unwrap();
+    assert_memory_queue_states_equal(
+        &last.observable_output.final_memory_state,
+        &final_memory_queue_state,
+    );
+    assert_memory_queue_states_equal(
+        &last.hidden_fsm_output.memory_queue_state,
+        &final_memory_queue_state,
+    );
+    assert_eq!(last.hidden_fsm_output.log_queue_state.num_items, 0);
+
+    let mut total_requests = 0;
+    let mut total_reads = 0;
+    for (idx, instance) in instances.iter().enumerate() {
+        assert_eq!(instance.start_flag, idx == 0);
+        assert_eq!(instance.completion_flag, idx == instances.len() - 1);
+        if idx > 0 {
+            let previous = &instances[idx - 1];
+            assert_log_queue_states_equal(
+                &instance.hidden_fsm_input.log_queue_state,
+                &previous.hidden_fsm_output.log_queue_state,
+            );
+            assert_memory_queue_states_equal(
+                &instance.hidden_fsm_input.memory_queue_state,
+                &previous.hidden_fsm_output.memory_queue_state,
+            );
+        }
+
+        let popped_requests = instance.hidden_fsm_input.log_queue_state.num_items
+            - instance.hidden_fsm_output.log_queue_state.num_items;
+        assert_eq!(
+            instance.requests_queue_witness.wit.len(),
+            popped_requests as usize
+        );
+        total_requests += instance.requests_queue_witness.wit.len();
+        total_reads += instance
+            .memory_reads_witness
+            .iter()
+            .map(|el| el.len())
+            .sum::<usize>();
+    }
+    assert_eq!(total_requests, num_requests);
+    assert_eq!(total_reads, num_reads);
+
+    instances
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ethereum_types::{Address, U256};
+    use crate::pairing::bn256::Bn256;
+    use crate::witness::individual_circuits::ecrecover::EcrecoverDecomposer;
+    use crate::witness::individual_circuits::keccak256_round_function::Keccak256Decomposer;
+    use crate::witness::individual_circuits::sha256_round_function::Sha256Decomposer;
+    use zk_evm::aux_structures::{MemoryIndex, MemoryLocation, MemoryPage, MemoryType, Timestamp};
+    use zk_evm::precompiles::ecrecover::ECRecoverRoundWitness;
+
+    fn memory_query(timestamp: u32, index: u32, rw_flag: bool) -> MemoryQuery {
+        MemoryQuery {
+            timestamp: Timestamp(timestamp),
+            location: MemoryLocation {
+                memory_type: MemoryType::Heap,
+                page: MemoryPage(8),
+                index: MemoryIndex(index),
+            },
+            rw_flag,
+            is_pended: false,
+            value_is_pointer: false,
+            value: U256::from(index as u64 + 1),
+        }
+    }
+
+    #[test]
+    fn test_precompile_decomposition() {
+        use sync_vm::testing::create_test_artifacts_with_optimized_gate;
+        let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
+
+        // precompiles without requests still produce a single circuit
+        let mut artifacts = FullBlockArtifacts::<Bn256>::default();
+        check_precompile_decomposition::<_, _, Keccak256Decomposer>(
+            &mut artifacts,
+            4,
+            &round_function,
+        );
+        check_precompile_decomposition::<_, _, Sha256Decomposer>(
+            &mut artifacts,
+            4,
+            &round_function,
+        );
+        check_precompile_decomposition::<_, _, EcrecoverDecomposer>(
+            &mut artifacts,
+            4,
+            &round_function,
+        );
+
+        // decomposer doesn't interpret the data, so arbitrary memory queries are fine
+        let mut artifacts = FullBlockArtifacts::<Bn256>::default();
+        for request_idx in 0..5u32 {
+            let timestamp = 1024 + request_idx * 4;
+            let request = LogQuery {
+                timestamp: Timestamp(timestamp),
+                tx_number_in_block: 0,
+                aux_byte: zk_evm::zkevm_opcode_defs::system_params::PRECOMPILE_AUX_BYTE,
+                shard_id: 0,
+                address: Address::from_low_u64_be(1),
+                key: U256::from(request_idx as u64),
+                read_value: U256::zero(),
+                written_value: U256::zero(),
+                rw_flag: false,
+                rollback: false,
+                is_service: false,
+            };
+            let (_, intermediate_info) = artifacts
+                .demuxed_ecrecover_queue_simulator
+                .push_and_output_intermediate_data(request, &round_function);
+            artifacts.demuxed_ecrecover_queries.push(request);
+            artifacts
+                .demuxed_ecrecover_queue_states
+                .push(intermediate_info);
+
+            let witness = ECRecoverRoundWitness {
+                new_request: request,
+                reads: std::array::from_fn(|idx| {
+                    memory_query(timestamp, request_idx * 6 + idx as u32, false)
+                }),
+                writes: std::array::from_fn(|idx| {
+                    memory_query(timestamp + 1, request_idx * 6 + 4 + idx as u32, true)
+                }),
+            };
+            artifacts
+                .ecrecover_witnesses
+                .push((timestamp, request, witness));
+        }
+
+        let instances = check_precompile_decomposition::<_, _, EcrecoverDecomposer>(
+            &mut artifacts,
+            2,
+            &round_function,
+        );
+        assert_eq!(instances.len(), 3);
+        assert_eq!(artifacts.memory_queue_simulator.num_items, 30);
+    }
+
+    #[test]
+    fn test_empty_ecrecover_output_log_queue_state() {
+        use sync_vm::testing::create_test_artifacts_with_optimized_gate;
+        let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
+
+        // empty ecrecover circuit outputs the state of the sha256 requests queue
+        let mut artifacts = FullBlockArtifacts::<Bn256>::default();
+        let request = LogQuery {
+            timestamp: Timestamp(1024),
+            tx_number_in_block: 0,
+            aux_byte: zk_evm::zkevm_opcode_defs::system_params::PRECOMPILE_AUX_BYTE,
+            shard_id: 0,
+            address: Address::from_low_u64_be(2),
+            key: U256::zero(),
+            read_value: U256::zero(),
+            written_value: U256::zero(),
+            rw_flag: false,
+            rollback: false,
+            is_service: false,
+        };
+        artifacts
+            .demuxed_sha256_precompile_queue_simulator
+            .push(request, &round_function);
+        let sha256_queue_state =
+            take_queue_state_from_simulator(&artifacts.demuxed_sha256_precompile_queue_simulator);
+        let ecrecover_queue_state =
+            take_queue_state_from_simulator(&artifacts.demuxed_ecrecover_queue_simulator);
+
+        let instances = decompose_precompile_into_instance_parts::<_, _, EcrecoverDecomposer>(
+            &mut artifacts,
+            2,
+            &round_function,
+        );
+        assert_eq!(instances.len(), 1);
+        let input_state = &instances[0].hidden_fsm_input.log_queue_state;
+        assert_eq!(input_state.tail_state, ecrecover_queue_state.tail_state);
+        let output_state = &instances[0].hidden_fsm_output.log_queue_state;
+        assert_eq!(output_state.num_items, sha256_queue_state.num_items);
+        assert_eq!(output_state.head_state, sha256_queue_state.head_state);
+        assert_eq!(output_state.tail_state, sha256_queue_state.tail_state);
+    }
+}
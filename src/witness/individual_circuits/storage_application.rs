@@ -2,9 +2,8 @@ use super::*;
 use crate::biguint_from_u256;
 use crate::ff::{Field, PrimeField};
 use crate::pairing::Engine;
-use crate::witness::full_block_artifact::FullBlockArtifacts;
+use crate::witness::full_block_artifact::RollupStorageArtifacts;
 use crate::witness::tree::*;
-use blake2::Blake2s256;
 use derivative::Derivative;
 use num_bigint::BigUint;
 use sync_vm::circuit_structures::traits::CircuitArithmeticRoundFunction;
@@ -18,9 +17,10 @@ use zk_evm::precompiles::keccak256::BUFFER_SIZE;
 pub fn decompose_into_storage_application_witnesses<
     E: Engine,
     R: CircuitArithmeticRoundFunction<E, 2, 3>,
+    H: BinaryHasher<32>,
 >(
-    artifacts: &mut FullBlockArtifacts<E>,
-    tree: &mut impl BinarySparseStorageTree<256, 32, 32, 8, 32, Blake2s256, ZkSyncStorageLeaf>,
+    artifacts: &mut RollupStorageArtifacts<E>,
+    tree: &mut impl BinarySparseStorageTree<256, 32, 32, 8, 32, H, ZkSyncStorageLeaf>,
     round_function: &R,
     num_rounds_per_circuit: usize,
 ) -> Vec<StorageApplicationCircuitInstanceWitness<E>> {
@@ -179,7 +179,7 @@ pub fn decompose_into_storage_application_witnesses<
                 // assert_eq!(current_root, tree.root());
                 // we can use independent implementation here to check
                 assert!(
-                    ZKSyncTestingTree::verify_inclusion(&current_root, &read_query),
+                    verify_leaf_query::<256, 32, 8, H, _>(&current_root, &read_query),
                     "failed to verify inclusion of read query during write operation over log query {:?}",
                     &el
                 );
@@ -198,7 +198,7 @@ pub fn decompose_into_storage_application_witnesses<
                 current_root = tree.root();
                 assert!(tree.verify_inclusion_proxy(&tree.root(), &write_query));
                 assert!(
-                    ZKSyncTestingTree::verify_inclusion(&current_root, &write_query),
+                    verify_leaf_query::<256, 32, 8, H, _>(&current_root, &write_query),
                     "failed to verify inclusion of write query during write operation over log query {:?}",
                     &el
                 );
@@ -245,7 +245,7 @@ pub fn decompose_into_storage_application_witnesses<
 
                 // we can use independent implementation here to check
                 assert!(
-                    ZKSyncTestingTree::verify_inclusion(&current_root, &read_query),
+                    verify_leaf_query::<256, 32, 8, H, _>(&current_root, &read_query),
                     "failed to verify inclusion of query during read operation over log query {:?}",
                     &el
                 );
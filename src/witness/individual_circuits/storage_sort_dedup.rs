@@ -7,7 +7,7 @@ use crate::encodings::memory_query::MemoryQueueSimulator;
 use crate::encodings::OutOfCircuitFixedLengthEncodable;
 use crate::ff::Field;
 use crate::utils::biguint_from_u256;
-use crate::witness::full_block_artifact::FullBlockArtifacts;
+use crate::witness::full_block_artifact::RollupStorageArtifacts;
 use num_bigint::BigUint;
 use rayon::prelude::*;
 use std::cmp::Ordering;
@@ -26,7 +26,7 @@ use zk_evm::aux_structures::MemoryQuery;
 use zk_evm::aux_structures::*;
 
 pub fn compute_storage_dedup_and_sort<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>(
-    artifacts: &mut FullBlockArtifacts<E>,
+    artifacts: &mut RollupStorageArtifacts<E>,
     per_circuit_capacity: usize,
     round_function: &R,
 ) -> Vec<StorageDeduplicatorInstanceWitness<E>> {
@@ -0,0 +1,393 @@
+use super::block_header::*;
+use super::block_proof_bundle::{fe_to_be_bytes, VerificationKeysCommittments};
+use super::*;
+use crate::bellman::pairing::bn256::{Fq, G1Affine};
+use crate::bellman::plonk::better_better_cs::cs::Circuit;
+use crate::bellman::plonk::better_better_cs::proof::Proof;
+use crate::bellman::{CurveAffine, PrimeField, PrimeFieldRepr};
+use crate::ethereum_types::U256;
+use derivative::*;
+use sync_vm::testing::{Bn256, Fr};
+
+// everything that L1 submitter needs to verify the scheduler proof by PLONK verifier contract.
+// Both parts are flat arrays of uint256
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct SchedulerProofCalldata {
+    pub public_inputs: Vec<U256>,
+    pub serialized_proof: Vec<U256>,
+}
+
+pub fn fe_to_u256<F: PrimeField>(value: F) -> U256 {
+    let mut buffer = vec![];
+    value.into_repr().write_be(&mut buffer).unwrap();
+
+    U256::from_big_endian(&buffer)
+}
+
+fn g1_to_u256_pair(point: &G1Affine) -> (U256, U256) {
+    // point at infinity is encoded as (0, 0) as in Ethereum precompiles
+    if point.is_zero() {
+        return (U256::zero(), U256::zero());
+    }
+
+    let (x, y) = point.into_xy_unchecked();
+
+    (fe_to_u256::<Fq>(x), fe_to_u256::<Fq>(y))
+}
+
+// layout follows the order in which verifier contract reads the proof
+pub fn serialize_proof_for_l1<C: Circuit<Bn256>>(
+    proof: &Proof<Bn256, C>,
+) -> SchedulerProofCalldata {
+    let public_inputs: Vec<_> = proof
+        .inputs
+        .iter()
+        .map(|el| fe_to_u256::<Fr>(*el))
+        .collect();
+
+    let mut serialized_proof = vec![];
+    fn push_point(dst: &mut Vec<U256>, point: &G1Affine) {
+        let (x, y) = g1_to_u256_pair(point);
+        dst.push(x);
+        dst.push(y);
+    }
+
+    for el in proof.state_polys_commitments.iter() {
+        push_point(&mut serialized_proof, el);
+    }
+    push_point(
+        &mut serialized_proof,
+        &proof.copy_permutation_grand_product_commitment,
+    );
+    push_point(
+        &mut serialized_proof,
+        proof
+            .lookup_s_poly_commitment
+            .as_ref()
+            .expect("proof must use lookups"),
+    );
+    push_point(
+        &mut serialized_proof,
+        proof
+            .lookup_grand_product_commitment
+            .as_ref()
+            .expect("proof must use lookups"),
+    );
+    for el in proof.quotient_poly_parts_commitments.iter() {
+        push_point(&mut serialized_proof, el);
+    }
+
+    for el in proof.state_polys_openings_at_z.iter() {
+        serialized_proof.push(fe_to_u256::<Fr>(*el));
+    }
+    for (_, _, el) in proof.state_polys_openings_at_dilations.iter() {
+        serialized_proof.push(fe_to_u256::<Fr>(*el));
+    }
+    assert!(
+        proof.gate_setup_openings_at_z.is_empty(),
+        "verifier does not expect openings of gate setup"
+    );
+    for (_, el) in proof.gate_selectors_openings_at_z.iter() {
+        serialized_proof.push(fe_to_u256::<Fr>(*el));
+    }
+    for el in proof.copy_permutation_polys_openings_at_z.iter() {
+        serialized_proof.push(fe_to_u256::<Fr>(*el));
+    }
+    serialized_proof.push(fe_to_u256::<Fr>(
+        proof.copy_permutation_grand_product_opening_at_z_omega,
+    ));
+
+    for el in [
+        proof.lookup_s_poly_opening_at_z_omega,
+        proof.lookup_grand_product_opening_at_z_omega,
+        proof.lookup_t_poly_opening_at_z,
+        proof.lookup_t_poly_opening_at_z_omega,
+        proof.lookup_selector_poly_opening_at_z,
+        proof.lookup_table_type_poly_opening_at_z,
+    ] {
+        let el = el.expect("proof must use lookups");
+        serialized_proof.push(fe_to_u256::<Fr>(el));
+    }
+
+    serialized_proof.push(fe_to_u256::<Fr>(proof.quotient_poly_opening_at_z));
+    serialized_proof.push(fe_to_u256::<Fr>(proof.linearization_poly_opening_at_z));
+
+    push_point(&mut serialized_proof, &proof.opening_proof_at_z);
+    push_point(&mut serialized_proof, &proof.opening_proof_at_z_omega);
+
+    SchedulerProofCalldata {
+        public_inputs,
+        serialized_proof,
+    }
+}
+
+fn u256_to_be_bytes(value: &U256) -> [u8; 32] {
+    let mut result = [0u8; 32];
+    value.to_big_endian(&mut result);
+
+    result
+}
+
+fn be_bytes_to_u256(bytes: &[u8]) -> U256 {
+    assert_eq!(bytes.len(), 32);
+    U256::from_big_endian(bytes)
+}
+
+impl SchedulerProofCalldata {
+    // ABI encoding of (uint256[] public_inputs, uint256[] proof)
+    pub fn abi_encode(&self) -> Vec<u8> {
+        let mut result = vec![];
+        // two offsets in the head
+        let first_offset = 64usize;
+        let second_offset = first_offset + 32 + 32 * self.public_inputs.len();
+        result.extend(u256_to_be_bytes(&U256::from(first_offset)));
+        result.extend(u256_to_be_bytes(&U256::from(second_offset)));
+
+        for arr in [&self.public_inputs, &self.serialized_proof] {
+            result.extend(u256_to_be_bytes(&U256::from(arr.len())));
+            for el in arr.iter() {
+                result.extend(u256_to_be_bytes(el));
+            }
+        }
+
+        result
+    }
+
+    pub fn abi_decode(encoding: &[u8]) -> Result<Self, CalldataDecodingError> {
+        if encoding.len() % 32 != 0 {
+            return Err(CalldataDecodingError::NotWordAligned);
+        }
+        let words: Vec<U256> = encoding.chunks(32).map(be_bytes_to_u256).collect();
+        if words.len() < 2 {
+            return Err(CalldataDecodingError::MissingHead);
+        }
+
+        let read_array = |offset: U256| -> Result<Vec<U256>, CalldataDecodingError> {
+            // anything that doesn't fit into usize points outside of the encoding anyway
+            if offset > U256::from(usize::MAX) {
+                return Err(CalldataDecodingError::OffsetOutOfBounds);
+            }
+            let offset = offset.as_usize();
+            if offset % 32 != 0 {
+                return Err(CalldataDecodingError::UnalignedOffset);
+            }
+            let start = offset / 32;
+            let len = *words
+                .get(start)
+                .ok_or(CalldataDecodingError::OffsetOutOfBounds)?;
+            if len > U256::from(words.len()) {
+                return Err(CalldataDecodingError::EncodingTooShort);
+            }
+            let len = len.as_usize();
+            let end = start
+                .checked_add(1)
+                .and_then(|el| el.checked_add(len))
+                .ok_or(CalldataDecodingError::EncodingTooShort)?;
+            let array = words
+                .get((start + 1)..end)
+                .ok_or(CalldataDecodingError::EncodingTooShort)?;
+
+            Ok(array.to_vec())
+        };
+
+        Ok(Self {
+            public_inputs: read_array(words[0])?,
+            serialized_proof: read_array(words[1])?,
+        })
+    }
+}
+
+#[derive(Derivative)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalldataDecodingError {
+    NotWordAligned,
+    MissingHead,
+    UnalignedOffset,
+    OffsetOutOfBounds,
+    EncodingTooShort,
+}
+
+// preimage of the formal block hash, that is checked by L1 against the stored block data
+pub fn block_commitment_preimage(header: &BlockContentHeader) -> Vec<u8> {
+    let block_data_hash = header.block_data.hash();
+    let block_meta_hash = header.block_meta.hash();
+    let auxilary_output_hash = header.auxilary_output.hash();
+
+    let mut result = vec![];
+    result.extend_from_slice(&block_data_hash);
+    result.extend_from_slice(&block_meta_hash);
+    result.extend_from_slice(&auxilary_output_hash);
+
+    result
+}
+
+// hashes of recursive verification keys in the same form as those are used in `block_proof_input`
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecursiveVerificationKeysHashes {
+    pub recursion_node_verification_key_hash: [u8; 32],
+    pub recursion_leaf_verification_key_hash: [u8; 32],
+    pub all_different_circuits_keys_hash: [u8; 32],
+}
+
+impl RecursiveVerificationKeysHashes {
+    pub fn from_committments(committments: &VerificationKeysCommittments) -> Self {
+        Self {
+            recursion_node_verification_key_hash: fe_to_be_bytes(
+                committments.node_aggregation_vk_committment,
+            ),
+            recursion_leaf_verification_key_hash: fe_to_be_bytes(
+                committments.leaf_aggregation_vk_committment,
+            ),
+            all_different_circuits_keys_hash: fe_to_be_bytes(committments.leaf_vks_committment),
+        }
+    }
+
+    // ABI encoding of (bytes32, bytes32, bytes32)
+    pub fn abi_encode(&self) -> Vec<u8> {
+        let mut result = vec![];
+        result.extend_from_slice(&self.recursion_node_verification_key_hash);
+        result.extend_from_slice(&self.recursion_leaf_verification_key_hash);
+        result.extend_from_slice(&self.all_different_circuits_keys_hash);
+
+        result
+    }
+}
+
+#[test]
+fn test_calldata_round_trip() {
+    use crate::utils::u256_to_fe;
+    use crate::witness::recursive_aggregation::get_paddings;
+    use sha3::{Digest, Keccak256};
+
+    let header = BlockContentHeader {
+        block_data: BlockPassthroughData {
+            per_shard_states: [
+                PerShardState {
+                    enumeration_counter: 123,
+                    state_root: [1u8; 32],
+                },
+                PerShardState {
+                    enumeration_counter: 0,
+                    state_root: [0u8; 32],
+                },
+            ],
+        },
+        block_meta: BlockMetaParameters {
+            zkporter_is_available: false,
+            bootloader_code_hash: [2u8; 32],
+            default_aa_code_hash: [3u8; 32],
+        },
+        auxilary_output: BlockAuxilaryOutput {
+            l1_messages_root: [4u8; 32],
+            l1_messages_linear_hash: [5u8; 32],
+            rollup_initital_writes_pubdata_hash: [6u8; 32],
+            rollup_repeated_writes_pubdata_hash: [7u8; 32],
+        },
+    };
+
+    let preimage = block_commitment_preimage(&header);
+    let (this_block_formal_hash, _) = header.into_formal_block_hash();
+    assert_eq!(
+        Keccak256::digest(&preimage).as_slice(),
+        &this_block_formal_hash
+    );
+
+    let vk_hashes = RecursiveVerificationKeysHashes {
+        recursion_node_verification_key_hash: [8u8; 32],
+        recursion_leaf_verification_key_hash: [9u8; 32],
+        all_different_circuits_keys_hash: [10u8; 32],
+    };
+    let encoded_vk_hashes = vk_hashes.abi_encode();
+    assert_eq!(encoded_vk_hashes.len(), 96);
+
+    let aggregation_result = [[11u8; 32], [12u8; 32], [13u8; 32], [14u8; 32]];
+    let expected_input = block_proof_input(
+        [0u8; 32],
+        this_block_formal_hash,
+        vk_hashes.recursion_node_verification_key_hash,
+        vk_hashes.recursion_leaf_verification_key_hash,
+        vk_hashes.all_different_circuits_keys_hash,
+        aggregation_result,
+    );
+
+    // any proof works for encoding purposes
+    let (_, [mut proof, _]) = get_paddings();
+    proof.inputs = vec![u256_to_fe::<Fr>(expected_input)];
+
+    let calldata = serialize_proof_for_l1(&proof);
+    assert_eq!(calldata.public_inputs, vec![expected_input]);
+
+    let encoding = calldata.abi_encode();
+    let decoded = SchedulerProofCalldata::abi_decode(&encoding).unwrap();
+    assert_eq!(decoded, calldata);
+    assert_eq!(u256_to_fe::<Fr>(decoded.public_inputs[0]), proof.inputs[0]);
+
+    let (x, y) = g1_to_u256_pair(&proof.opening_proof_at_z_omega);
+    let len = decoded.serialized_proof.len();
+    assert_eq!(decoded.serialized_proof[len - 2], x);
+    assert_eq!(decoded.serialized_proof[len - 1], y);
+}
+
+#[test]
+fn test_calldata_decoding_of_malformed_input() {
+    fn word(value: usize) -> [u8; 32] {
+        u256_to_be_bytes(&U256::from(value))
+    }
+
+    assert_eq!(
+        SchedulerProofCalldata::abi_decode(&[0u8; 33]),
+        Err(CalldataDecodingError::NotWordAligned)
+    );
+    assert_eq!(
+        SchedulerProofCalldata::abi_decode(&[0u8; 32]),
+        Err(CalldataDecodingError::MissingHead)
+    );
+
+    let mut encoding = vec![];
+    encoding.extend(word(65));
+    encoding.extend(word(64));
+    encoding.extend(word(0));
+    assert_eq!(
+        SchedulerProofCalldata::abi_decode(&encoding),
+        Err(CalldataDecodingError::UnalignedOffset)
+    );
+
+    let mut encoding = vec![];
+    encoding.extend(word(64));
+    encoding.extend(u256_to_be_bytes(&U256::max_value()));
+    encoding.extend(word(0));
+    assert_eq!(
+        SchedulerProofCalldata::abi_decode(&encoding),
+        Err(CalldataDecodingError::OffsetOutOfBounds)
+    );
+
+    let mut encoding = vec![];
+    encoding.extend(word(64));
+    encoding.extend(word(96));
+    encoding.extend(word(0));
+    assert_eq!(
+        SchedulerProofCalldata::abi_decode(&encoding),
+        Err(CalldataDecodingError::OffsetOutOfBounds)
+    );
+
+    // array length that overflows on addition
+    let mut encoding = vec![];
+    encoding.extend(word(64));
+    encoding.extend(word(64));
+    encoding.extend(word(usize::MAX));
+    assert_eq!(
+        SchedulerProofCalldata::abi_decode(&encoding),
+        Err(CalldataDecodingError::EncodingTooShort)
+    );
+
+    let mut encoding = vec![];
+    encoding.extend(word(64));
+    encoding.extend(word(64));
+    encoding.extend(word(1));
+    assert_eq!(
+        SchedulerProofCalldata::abi_decode(&encoding),
+        Err(CalldataDecodingError::EncodingTooShort)
+    );
+}
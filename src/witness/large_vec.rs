@@ -0,0 +1,357 @@
+// Storage for the largest intermediate vectors of the block processing. Elements are kept
+// in a plain vector until its size reaches a threshold, and after that in a memory mapped
+// temporary file, so that the OS can page them out instead of keeping them resident
+
+use crate::encodings::memory_query::MemoryQueueState;
+use crate::pairing::Engine;
+use memmap2::MmapMut;
+use std::fs::{File, OpenOptions};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use zk_evm::aux_structures::MemoryQuery;
+
+pub const DEFAULT_SPILL_THRESHOLD_BYTES: usize = 1 << 30;
+
+// only makes names of the spill files unique
+static SPILL_FILES_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Element type that can be kept in a memory mapped file.
+///
+/// # Safety
+///
+/// Elements are moved into the mapped file byte by byte, so they must not own resources or
+/// point into other memory. Only the written part of the file is ever viewed as elements,
+/// so types with invalid bit patterns are fine
+pub unsafe trait SpillableElement: Copy + 'static {}
+
+unsafe impl SpillableElement for MemoryQuery {}
+unsafe impl SpillableElement for (u32, MemoryQuery) {}
+unsafe impl<E: Engine> SpillableElement for MemoryQueueState<E> {}
+unsafe impl<E: Engine> SpillableElement for (u32, bool, MemoryQueueState<E>) {}
+
+// The file is private and removed on drop
+struct MappedStorage<T: SpillableElement> {
+    path: PathBuf,
+    file: File,
+    map: MmapMut,
+    len: usize,
+    capacity: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: SpillableElement> MappedStorage<T> {
+    fn with_capacity(capacity: usize) -> Self {
+        assert!(std::mem::size_of::<T>() > 0);
+        let capacity = std::cmp::max(capacity, 1);
+
+        let path = std::env::temp_dir().join(format!(
+            "zkevm_test_harness_{}_{}.bin",
+            std::process::id(),
+            SPILL_FILES_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .expect("must create a spill file");
+        file.set_len((capacity * std::mem::size_of::<T>()) as u64)
+            .expect("must resize a spill file");
+        let map = unsafe { MmapMut::map_mut(&file) }.expect("must map a spill file");
+
+        Self {
+            path,
+            file,
+            map,
+            len: 0,
+            capacity,
+            _marker: PhantomData,
+        }
+    }
+
+    fn from_slice(values: &[T]) -> Self {
+        let mut result = Self::with_capacity(values.len() * 2);
+        unsafe {
+            std::ptr::copy_nonoverlapping(values.as_ptr(), result.as_mut_ptr(), values.len());
+        }
+        result.len = values.len();
+
+        result
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        self.map.as_mut_ptr() as *mut T
+    }
+
+    fn grow(&mut self) {
+        let new_capacity = self.capacity * 2;
+        self.file
+            .set_len((new_capacity * std::mem::size_of::<T>()) as u64)
+            .expect("must resize a spill file");
+        self.map = unsafe { MmapMut::map_mut(&self.file) }.expect("must map a spill file");
+        self.capacity = new_capacity;
+    }
+
+    fn push(&mut self, value: T) {
+        if self.len == self.capacity {
+            self.grow();
+        }
+        unsafe { self.as_mut_ptr().add(self.len).write(value) };
+        self.len += 1;
+    }
+
+    fn as_slice(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.map.as_ptr() as *const T, self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+    }
+}
+
+impl<T: SpillableElement> Drop for MappedStorage<T> {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+enum LargeVecStorage<T: SpillableElement> {
+    InMemory(Vec<T>),
+    Mapped(MappedStorage<T>),
+}
+
+pub struct LargeVec<T: SpillableElement> {
+    storage: LargeVecStorage<T>,
+    spill_threshold_bytes: usize,
+}
+
+impl<T: SpillableElement> LargeVec<T> {
+    pub fn new() -> Self {
+        Self::with_spill_threshold(DEFAULT_SPILL_THRESHOLD_BYTES)
+    }
+
+    pub fn with_spill_threshold(spill_threshold_bytes: usize) -> Self {
+        Self::from_vec_with_spill_threshold(vec![], spill_threshold_bytes)
+    }
+
+    pub fn from_vec_with_spill_threshold(values: Vec<T>, spill_threshold_bytes: usize) -> Self {
+        let mut result = Self {
+            storage: LargeVecStorage::InMemory(values),
+            spill_threshold_bytes,
+        };
+        result.spill_if_necessary();
+
+        result
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.storage, LargeVecStorage::Mapped(..))
+    }
+
+    pub fn push(&mut self, value: T) {
+        match &mut self.storage {
+            LargeVecStorage::InMemory(values) => {
+                values.push(value);
+                self.spill_if_necessary();
+            }
+            LargeVecStorage::Mapped(values) => values.push(value),
+        }
+    }
+
+    // releases the memory or the file, unlike `Vec::clear`
+    pub fn clear(&mut self) {
+        self.storage = LargeVecStorage::InMemory(vec![]);
+    }
+
+    fn spill_if_necessary(&mut self) {
+        let element_size = std::mem::size_of::<T>();
+        if element_size == 0 {
+            return;
+        }
+        if let LargeVecStorage::InMemory(values) = &self.storage {
+            if values.len() * element_size > self.spill_threshold_bytes {
+                self.storage = LargeVecStorage::Mapped(MappedStorage::from_slice(values));
+            }
+        }
+    }
+}
+
+impl<T: SpillableElement> Deref for LargeVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match &self.storage {
+            LargeVecStorage::InMemory(values) => values,
+            LargeVecStorage::Mapped(values) => values.as_slice(),
+        }
+    }
+}
+
+impl<T: SpillableElement> DerefMut for LargeVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        match &mut self.storage {
+            LargeVecStorage::InMemory(values) => values,
+            LargeVecStorage::Mapped(values) => values.as_mut_slice(),
+        }
+    }
+}
+
+impl<T: SpillableElement> Default for LargeVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: SpillableElement> Clone for LargeVec<T> {
+    fn clone(&self) -> Self {
+        let storage = match &self.storage {
+            LargeVecStorage::InMemory(values) => LargeVecStorage::InMemory(values.clone()),
+            LargeVecStorage::Mapped(values) => {
+                LargeVecStorage::Mapped(MappedStorage::from_slice(values.as_slice()))
+            }
+        };
+
+        Self {
+            storage,
+            spill_threshold_bytes: self.spill_threshold_bytes,
+        }
+    }
+}
+
+impl<T: SpillableElement + std::fmt::Debug> std::fmt::Debug for LargeVec<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: SpillableElement> Extend<T> for LargeVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for el in iter {
+            self.push(el);
+        }
+    }
+}
+
+impl<'a, T: SpillableElement> Extend<&'a T> for LargeVec<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+impl<T: SpillableElement> FromIterator<T> for LargeVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut result = Self::new();
+        result.extend(iter);
+
+        result
+    }
+}
+
+impl<T: SpillableElement> From<Vec<T>> for LargeVec<T> {
+    fn from(values: Vec<T>) -> Self {
+        Self::from_vec_with_spill_threshold(values, DEFAULT_SPILL_THRESHOLD_BYTES)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    unsafe impl SpillableElement for (u32, u64) {}
+
+    #[test]
+    fn test_large_vec_spills_to_disk() {
+        let mut values = LargeVec::<(u32, u64)>::with_spill_threshold(64);
+        for idx in 0..4 {
+            values.push((idx, idx as u64 * 3));
+        }
+        assert!(!values.is_spilled());
+
+        values.extend((4..1000).map(|idx| (idx, idx as u64 * 3)));
+        assert!(values.is_spilled());
+        assert_eq!(values.len(), 1000);
+        assert_eq!(values[999], (999, 2997));
+
+        let mut copy = values.clone();
+        assert!(copy.is_spilled());
+        copy.sort_by(|a, b| b.cmp(a));
+        assert_eq!(copy.first(), Some(&(999, 2997)));
+        assert_eq!(values.first(), Some(&(0, 0)));
+
+        let expected: Vec<_> = (0..1000).map(|idx| (idx, idx as u64 * 3)).collect();
+        assert_eq!(&values[..], &expected[..]);
+
+        values.clear();
+        assert!(!values.is_spilled());
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn test_spilled_artifacts_produce_same_circuits() {
+        use crate::ethereum_types::U256;
+        use crate::pairing::bn256::Bn256;
+        use crate::tests::run_manually::{
+            generate_block_for_extended_state,
+            generate_block_for_extended_state_with_spill_threshold,
+        };
+        use crate::witness::full_block_artifact::FullBlockArtifacts;
+        use zk_evm::aux_structures::{
+            MemoryIndex, MemoryLocation, MemoryPage, MemoryType, Timestamp,
+        };
+        use zkevm_assembly::Assembly;
+
+        let asm = r#"
+            .text
+            .file	"Test_26"
+            .rodata.cst32
+            .p2align	5
+            .text
+            .globl	__entry
+        __entry:
+        .main:
+            add 10000, r0, r1
+            add 1000, r0, r10
+            sstore r1, r10
+            sload r1, r2
+            event.first r1, r10
+            to_l1.first r0, r1
+            ret.ok r0
+        "#;
+
+        let mut assembly = Assembly::try_from(asm.to_owned()).unwrap();
+        let bytecode = assembly.compile_to_bytecode().unwrap();
+
+        let (circuits, inputs, _, _) =
+            generate_block_for_extended_state(bytecode.clone(), vec![], 50, false);
+
+        // every memory related vector spills right after the first element
+        let mut artifacts = FullBlockArtifacts::<Bn256>::with_spill_threshold(0);
+        artifacts.all_memory_queries_accumulated.push(MemoryQuery {
+            timestamp: Timestamp(0),
+            location: MemoryLocation {
+                memory_type: MemoryType::Heap,
+                page: MemoryPage(0),
+                index: MemoryIndex(0),
+            },
+            rw_flag: false,
+            is_pended: false,
+            value: U256::zero(),
+            value_is_pointer: false,
+        });
+        assert!(artifacts.all_memory_queries_accumulated.is_spilled());
+
+        let (spilled_circuits, spilled_inputs, _, _) =
+            generate_block_for_extended_state_with_spill_threshold(bytecode, vec![], 50, false, 0);
+
+        assert_eq!(
+            inputs.into_flattened_set(),
+            spilled_inputs.into_flattened_set()
+        );
+        assert_eq!(
+            bincode::serialize(&circuits).unwrap(),
+            bincode::serialize(&spilled_circuits).unwrap()
+        );
+    }
+}
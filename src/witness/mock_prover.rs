@@ -0,0 +1,314 @@
+use super::*;
+use crate::abstract_zksync_circuit::concrete_circuits::{
+    ZkSyncCircuit, ZkSyncProof, ZkSyncVerificationKey,
+};
+use crate::bellman::plonk::better_better_cs::proof::Proof;
+use crate::bellman::plonk::better_better_cs::setup::VerificationKey;
+use crate::witness::oracle::VmWitnessOracle;
+use sync_vm::recursion::node_aggregation::ZkSyncParametricCircuit;
+use sync_vm::testing::{Bn256, Fr};
+
+// Prover that does not need a trusted setup and does not create real proofs. Basic circuits are checked
+// for satisfiability, and "proof" is a padding proof with a public input replaced, so it will never pass
+// a real verification. Such proofs are only accepted by `prepare_*_with_verification` functions called with
+// `ProofsVerification::Mock`, that use `mock_aggregations` instead of real aggregation results. Use `mock_aggregations`
+// as padding aggregations for node level too.
+//
+// Note that block proof bundle made from those proofs will not pass `verify_block_proof_bundle`,
+// use `verify_block_proof_bundle_with_verification` with `ProofsVerification::Mock` instead
+pub struct MockProver {
+    padding_vk: VerificationKey<Bn256, ZkSyncParametricCircuit<Bn256>>,
+    padding_proof: Proof<Bn256, ZkSyncParametricCircuit<Bn256>>,
+}
+
+impl Default for MockProver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockProver {
+    pub fn new() -> Self {
+        use crate::witness::recursive_aggregation::get_paddings;
+
+        let (padding_vk, padding_proofs) = get_paddings();
+        let [padding_proof, _] = padding_proofs;
+
+        Self {
+            padding_vk,
+            padding_proof,
+        }
+    }
+
+    // placeholder proof that carries circuit type and public input
+    pub fn create_proof(&self, circuit_type: u8, public_input: Fr) -> ZkSyncProof<Bn256> {
+        let mut proof: Proof<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>> =
+            unsafe { std::mem::transmute(self.padding_proof.clone()) }; // only transmute marker
+        proof.inputs = vec![public_input];
+
+        ZkSyncProof::from_proof_and_numeric_type(circuit_type, proof)
+    }
+
+    // placeholder verification key. Keys are different for different circuit types,
+    // so those can be used to form committments to the set of keys
+    pub fn create_verification_key(&self, circuit_type: u8) -> ZkSyncVerificationKey<Bn256> {
+        use crate::bellman::{CurveAffine, CurveProjective, PrimeField};
+
+        let mut vk: VerificationKey<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>> =
+            unsafe { std::mem::transmute(self.padding_vk.clone()) }; // only transmute marker
+
+        let scalar = Fr::from_str(&(circuit_type as u64 + 1).to_string()).unwrap();
+        vk.gate_setup_commitments[0] = vk.gate_setup_commitments[0].mul(scalar).into_affine();
+
+        ZkSyncVerificationKey::from_verification_key_and_numeric_type(circuit_type, vk)
+    }
+
+    // placeholder verification keys for all circuit types
+    pub fn create_verification_keys(&self) -> Vec<ZkSyncVerificationKey<Bn256>> {
+        use sync_vm::scheduler::CircuitType;
+
+        let all_circuit_types = [
+            CircuitType::Scheduler as u8,
+            CircuitType::IntermidiateNode as u8,
+            CircuitType::Leaf as u8,
+            CircuitType::VM as u8,
+            CircuitType::DecommitmentsFilter as u8,
+            CircuitType::Decommiter as u8,
+            CircuitType::LogDemultiplexer as u8,
+            CircuitType::KeccakPrecompile as u8,
+            CircuitType::Sha256Precompile as u8,
+            CircuitType::EcrecoverPrecompile as u8,
+            CircuitType::RamValidation as u8,
+            CircuitType::StorageFilter as u8,
+            CircuitType::StorageApplicator as u8,
+            CircuitType::StorageFreshWritesHasher as u8,
+            CircuitType::StorageRepeatedWritesHasher as u8,
+            CircuitType::EventsRevertsFilter as u8,
+            CircuitType::L1MessagesRevertsFilter as u8,
+            CircuitType::L1MessagesHasher as u8,
+            CircuitType::L1MessagesMerkelization as u8,
+        ];
+
+        all_circuit_types
+            .iter()
+            .map(|el| self.create_verification_key(*el))
+            .collect()
+    }
+
+    // marks circuit as proven. Basic circuits must be satisfied. Aggregation circuits
+    // and scheduler are not synthesized and must have an expected public input set
+    pub fn prove(
+        &self,
+        circuit: ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>,
+    ) -> ZkSyncProof<Bn256> {
+        use crate::bellman::plonk::better_better_cs::cs::PlonkCsWidth4WithNextStepAndCustomGatesParams;
+
+        let circuit_type = circuit.numeric_circuit_type();
+        let descr = circuit.short_description();
+        let public_input = match circuit {
+            ZkSyncCircuit::LeafAggregation(inner) => inner
+                .expected_public_input
+                .expect("leaf aggregation circuit must have expected public input"),
+            ZkSyncCircuit::NodeAggregation(inner) => inner
+                .expected_public_input
+                .expect("node aggregation circuit must have expected public input"),
+            ZkSyncCircuit::Scheduler(inner) => inner
+                .expected_public_input
+                .expect("scheduler circuit must have expected public input"),
+            circuit => {
+                let (is_satisfied, public_input) = circuit_testing::check_if_satisfied::<
+                    Bn256,
+                    _,
+                    PlonkCsWidth4WithNextStepAndCustomGatesParams,
+                >(circuit)
+                .unwrap();
+                assert!(is_satisfied, "circuit {} is not satisfied", descr);
+
+                public_input
+            }
+        };
+
+        self.create_proof(circuit_type, public_input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::witness::block_proof_bundle::{
+        find_verification_key, leaf_aggregation_public_input, node_aggregation_public_input,
+        VerificationKeysCommittments,
+    };
+    use crate::witness::recursion_plan::*;
+    use crate::witness::recursive_aggregation::{mock_aggregations, ProofsVerification};
+    use crate::witness::utils::take_queue_state_from_simulator;
+    use sync_vm::scheduler::CircuitType;
+    use zkevm_assembly::Assembly;
+
+    #[test]
+    fn test_mock_proving_of_full_block() {
+        let asm = r#"
+            .text
+            .file	"Test_26"
+            .rodata.cst32
+            .p2align	5
+            .text
+            .globl	__entry
+        __entry:
+        .main:
+            add 10000, r0, r1
+            add 1000, r0, r10
+            sstore r1, r10
+            sload r1, r2
+            event.first r1, r10
+            to_l1.first r0, r1
+            ret.ok r0
+        "#;
+
+        let mut assembly = Assembly::try_from(asm.to_owned()).unwrap();
+        let bytecode = assembly.compile_to_bytecode().unwrap();
+        let (basic_block_circuits, basic_block_circuits_inputs, scheduler_witness, _) =
+            crate::tests::run_manually::generate_block_for_extended_state(
+                bytecode,
+                vec![],
+                50,
+                false,
+            );
+
+        let prover = MockProver::default();
+
+        let verification_keys = prover.create_verification_keys();
+        let committments = VerificationKeysCommittments::from_verification_keys(&verification_keys);
+
+        // basic circuits
+        let basic_proofs: Vec<_> = basic_block_circuits
+            .clone()
+            .into_flattened_set()
+            .into_iter()
+            .map(|el| prover.prove(el))
+            .collect();
+        for (proof, expected) in basic_proofs
+            .iter()
+            .zip(basic_block_circuits_inputs.clone().into_flattened_set())
+        {
+            assert_eq!(proof.as_proof().inputs[0], expected);
+        }
+
+        let plan = RecursionPlan::new(basic_proofs.len(), 4, 2, 4 * (1 << 10));
+        let basic_vks: Vec<_> = basic_proofs
+            .iter()
+            .map(|el| {
+                find_verification_key(&verification_keys, el.numeric_circuit_type())
+                    .clone()
+                    .into_verification_key()
+            })
+            .collect();
+
+        // leafs
+        let (mut previous_sequence, leaf_outputs, leaf_circuits) =
+            prepare_leaf_aggregations_for_plan(
+                &plan,
+                basic_block_circuits,
+                basic_block_circuits_inputs,
+                basic_proofs.into_iter().map(|el| el.into_proof()).collect(),
+                basic_vks,
+                committments.leaf_vks_committments_set.clone(),
+                committments.leaf_vks_committment,
+                committments.g2_points,
+                ProofsVerification::Mock,
+            );
+
+        let mut previous_level_proofs = vec![];
+        for ((circuit, subqueue), output) in leaf_circuits
+            .into_iter()
+            .zip(previous_sequence.iter())
+            .zip(leaf_outputs.iter())
+        {
+            let proof = prover.prove(circuit);
+            assert_eq!(
+                proof.as_proof().inputs[0],
+                leaf_aggregation_public_input(
+                    take_queue_state_from_simulator(subqueue),
+                    committments.leaf_vks_committment,
+                    output.clone(),
+                )
+            );
+            previous_level_proofs.push(proof.into_proof());
+        }
+
+        // nodes
+        let mut previous_level_leafs_aggregations = leaf_outputs;
+        let mut previous_level_node_aggregations = vec![];
+        for depth in 0..plan.num_node_layers() as u32 {
+            let previous_level_circuit_type = if depth == 0 {
+                CircuitType::Leaf
+            } else {
+                CircuitType::IntermidiateNode
+            };
+
+            let (merged, node_outputs, node_circuits) = prepare_node_aggregations_for_plan(
+                &plan,
+                depth,
+                std::mem::take(&mut previous_level_proofs),
+                find_verification_key(&verification_keys, previous_level_circuit_type as u8)
+                    .clone()
+                    .into_verification_key(),
+                std::mem::take(&mut previous_level_leafs_aggregations),
+                std::mem::take(&mut previous_level_node_aggregations),
+                previous_sequence,
+                mock_aggregations(plan.splitting_factor_for_nodes),
+                committments.leaf_vks_committment,
+                committments.node_aggregation_vk_committment,
+                committments.leaf_aggregation_vk_committment,
+                committments.g2_points,
+                ProofsVerification::Mock,
+            );
+
+            for ((circuit, subqueue), output) in node_circuits
+                .into_iter()
+                .zip(merged.iter())
+                .zip(node_outputs.iter())
+            {
+                let proof = prover.prove(circuit);
+                assert_eq!(
+                    proof.as_proof().inputs[0],
+                    node_aggregation_public_input(
+                        take_queue_state_from_simulator(subqueue),
+                        committments.leaf_vks_committment,
+                        committments.node_aggregation_vk_committment,
+                        committments.leaf_aggregation_vk_committment,
+                        output.clone(),
+                    )
+                );
+                previous_level_proofs.push(proof.into_proof());
+            }
+
+            previous_sequence = merged;
+            previous_level_node_aggregations = node_outputs;
+        }
+
+        // scheduler
+        assert_eq!(previous_level_proofs.len(), 1);
+        assert_eq!(previous_level_node_aggregations.len(), 1);
+
+        let (circuit, _block_application_witness) = prepare_scheduler_circuit_for_plan(
+            &plan,
+            scheduler_witness,
+            previous_level_proofs.pop().unwrap(),
+            find_verification_key(&verification_keys, CircuitType::IntermidiateNode as u8)
+                .clone()
+                .into_verification_key(),
+            previous_level_node_aggregations.pop().unwrap(),
+            committments.leaf_vks_committment,
+            committments.node_aggregation_vk_committment,
+            committments.leaf_aggregation_vk_committment,
+            [0u8; 32],
+            [0u8; 32],
+            committments.g2_points,
+            ProofsVerification::Mock,
+        );
+
+        let proof = prover.prove(circuit);
+        assert_eq!(proof.numeric_circuit_type(), CircuitType::Scheduler as u8);
+    }
+}
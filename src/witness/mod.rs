@@ -1,15 +1,29 @@
 use super::*;
 
+pub mod artifacts_summary;
 pub mod block_header;
+pub mod block_proof_bundle;
+pub mod call_tree;
 pub mod callstack_handler;
+pub mod cycle_trace;
 pub mod full_block_artifact;
 pub mod individual_circuits;
+pub mod l1_calldata;
+pub mod large_vec;
+pub mod mock_prover;
 pub mod oracle;
+pub mod oracle_codec;
+pub mod oracle_validator;
 pub mod postprocessing;
+pub mod proving_jobs;
+pub mod recursion_plan;
 pub mod recursive_aggregation;
 pub mod sort_storage_access;
+pub mod state_reconstruction;
+pub mod storage_access_report;
 pub mod tracer;
 pub mod tree;
+pub mod tx_attribution;
 pub mod utils;
 pub mod vk_set_generator;
 pub mod vm_snapshot;
@@ -12,6 +12,7 @@ use crate::ethereum_types::U256;
 use crate::ff::Field;
 use crate::toolset::GeometryConfig;
 use crate::u160_from_address;
+use crate::witness::large_vec::{LargeVec, DEFAULT_SPILL_THRESHOLD_BYTES};
 use crate::witness::tracer::{QueryMarker, WitnessTracer};
 use derivative::Derivative;
 use num_bigint::BigUint;
@@ -193,6 +194,44 @@ pub fn create_artifacts_from_tracer<E: Engine, R: CircuitArithmeticRoundFunction
 ) -> (
     Vec<VmInstanceWitness<E, VmWitnessOracle<E>>>,
     FullBlockArtifacts<E>,
+) {
+    create_artifacts_from_tracer_with_processing(
+        tracer,
+        round_function,
+        geometry,
+        entry_point_decommittment_query,
+        tree,
+        num_non_deterministic_heap_queries,
+        DEFAULT_SPILL_THRESHOLD_BYTES,
+        |artifacts, round_function, geometry, tree, num_non_deterministic_heap_queries| {
+            artifacts.process(
+                round_function,
+                geometry,
+                tree,
+                num_non_deterministic_heap_queries,
+            )
+        },
+    )
+}
+
+// same as `create_artifacts_from_tracer`, but artifacts are processed by the given function,
+// e.g. to run the stages one by one, and memory related vectors use the given spill threshold
+pub(crate) fn create_artifacts_from_tracer_with_processing<
+    E: Engine,
+    R: CircuitArithmeticRoundFunction<E, 2, 3>,
+    T: BinarySparseStorageTree<256, 32, 32, 8, 32, Blake2s256, ZkSyncStorageLeaf>,
+>(
+    tracer: WitnessTracer,
+    round_function: &R,
+    geometry: &GeometryConfig,
+    entry_point_decommittment_query: (DecommittmentQuery, Vec<U256>),
+    tree: &mut T,
+    num_non_deterministic_heap_queries: usize,
+    spill_threshold_bytes: usize,
+    process: impl FnOnce(&mut FullBlockArtifacts<E>, &R, &GeometryConfig, &mut T, usize),
+) -> (
+    Vec<VmInstanceWitness<E, VmWitnessOracle<E>>>,
+    FullBlockArtifacts<E>,
 ) {
     let WitnessTracer {
         memory_queries,
@@ -205,6 +244,7 @@ pub fn create_artifacts_from_tracer<E: Engine, R: CircuitArithmeticRoundFunction
         monotonic_query_counter: _,
         callstack_with_aux_data,
         vm_snapshots,
+        tx_number_changes,
         ..
     } = tracer;
 
@@ -943,8 +983,11 @@ pub fn create_artifacts_from_tracer<E: Engine, R: CircuitArithmeticRoundFunction
     // each history record contains an information on what was the stack state between points
     // when it potentially came into and out of scope
 
-    let mut artifacts = FullBlockArtifacts::<E>::default();
-    artifacts.vm_memory_queries_accumulated = vm_memory_queries_accumulated;
+    let mut artifacts = FullBlockArtifacts::<E>::with_spill_threshold(spill_threshold_bytes);
+    artifacts.vm_memory_queries_accumulated = LargeVec::from_vec_with_spill_threshold(
+        vm_memory_queries_accumulated,
+        spill_threshold_bytes,
+    );
     artifacts.all_decommittment_queries = decommittment_queries;
     artifacts.keccak_round_function_witnesses = keccak_round_function_witnesses;
     artifacts.sha256_round_function_witnesses = sha256_round_function_witnesses;
@@ -961,10 +1004,15 @@ pub fn create_artifacts_from_tracer<E: Engine, R: CircuitArithmeticRoundFunction
     artifacts.demuxed_keccak_precompile_queries = demuxed_keccak_precompile_queries;
     artifacts.demuxed_sha256_precompile_queries = demuxed_sha256_precompile_queries;
     artifacts.demuxed_ecrecover_queries = demuxed_ecrecover_queries;
+    artifacts.tx_cycle_ranges = crate::witness::tx_attribution::tx_cycle_ranges(
+        &tx_number_changes,
+        vm_snapshots.last().unwrap().at_cycle,
+    );
 
     tracing::debug!("Processing artifacts queue");
 
-    artifacts.process(
+    process(
+        &mut artifacts,
         round_function,
         geometry,
         tree,
@@ -0,0 +1,909 @@
+// Compact binary form of the main VM witness oracle. Compared to the plain serde form
+// it delta-encodes cycles and timestamps, interns addresses and memory pages, drops the
+// callstack sponge states that can be recomputed with the round function, and can
+// optionally compress the result in independent blocks
+
+use crate::encodings::callstack_entry::{CallstackSimulatorState, ExtendedCallstackEntry};
+use crate::encodings::OutOfCircuitFixedLengthEncodable;
+use crate::ethereum_types::{Address, U256};
+use crate::ff::{PrimeField, PrimeFieldRepr};
+use crate::pairing::Engine;
+use crate::witness::oracle::VmWitnessOracle;
+use std::collections::{HashMap, VecDeque};
+use sync_vm::circuit_structures::traits::CircuitArithmeticRoundFunction;
+use sync_vm::vm::vm_cycle::input::VmCircuitWitness;
+use zk_evm::aux_structures::{
+    DecommittmentQuery, LogQuery, MemoryIndex, MemoryLocation, MemoryPage, MemoryQuery, Timestamp,
+};
+use zk_evm::vm_state::CallStackEntry;
+
+const COMPACT_WITNESS_MAGIC: [u8; 4] = *b"zkvw";
+const COMPACT_WITNESS_VERSION: u8 = 1;
+
+const PAYLOAD_KIND_ORACLE: u8 = 0;
+const PAYLOAD_KIND_VM_CIRCUIT_WITNESS: u8 = 1;
+
+const FLAG_COMPRESSED: u8 = 1;
+
+pub const DEFAULT_COMPRESSION_BLOCK_SIZE: usize = 1 << 20;
+
+fn pack_flags(flags: &[bool]) -> u8 {
+    assert!(flags.len() <= 8);
+    flags
+        .iter()
+        .enumerate()
+        .fold(0u8, |acc, (idx, flag)| acc | ((*flag as u8) << idx))
+}
+
+fn unpack_flags<const N: usize>(byte: u8) -> [bool; N] {
+    assert!(N <= 8);
+    std::array::from_fn(|idx| byte & (1 << idx) != 0)
+}
+
+struct CompactWriter {
+    buffer: Vec<u8>,
+    addresses: HashMap<Address, u32>,
+    pages: HashMap<u32, u32>,
+}
+
+impl CompactWriter {
+    fn new() -> Self {
+        Self {
+            buffer: vec![],
+            addresses: HashMap::new(),
+            pages: HashMap::new(),
+        }
+    }
+
+    fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    fn write_varint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.buffer.push(byte);
+                break;
+            }
+            self.buffer.push(byte | 0x80);
+        }
+    }
+
+    // zigzag encoded difference, so occasional decreases are still cheap
+    fn write_delta(&mut self, value: u32, previous: u32) {
+        let delta = value as i64 - previous as i64;
+        self.write_varint(((delta << 1) ^ (delta >> 63)) as u64);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_varint(bytes.len() as u64);
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    // big endian without leading zeroes
+    fn write_u256(&mut self, value: &U256) {
+        let mut bytes = [0u8; 32];
+        value.to_big_endian(&mut bytes);
+        let num_zeroes = bytes.iter().take_while(|el| **el == 0).count();
+        self.write_u8((32 - num_zeroes) as u8);
+        self.buffer.extend_from_slice(&bytes[num_zeroes..]);
+    }
+
+    fn write_fe<F: PrimeField>(&mut self, value: &F) {
+        for limb in value.into_repr().as_ref().iter() {
+            self.buffer.extend_from_slice(&limb.to_le_bytes());
+        }
+    }
+
+    fn write_address(&mut self, address: &Address) {
+        if let Some(idx) = self.addresses.get(address).copied() {
+            self.write_varint(idx as u64);
+        } else {
+            let idx = self.addresses.len() as u32;
+            self.addresses.insert(*address, idx);
+            self.write_varint(idx as u64);
+            self.buffer.extend_from_slice(address.as_bytes());
+        }
+    }
+
+    fn write_page(&mut self, page: MemoryPage) {
+        if let Some(idx) = self.pages.get(&page.0).copied() {
+            self.write_varint(idx as u64);
+        } else {
+            let idx = self.pages.len() as u32;
+            self.pages.insert(page.0, idx);
+            self.write_varint(idx as u64);
+            self.write_varint(page.0 as u64);
+        }
+    }
+
+    // unit-only enums are encoded by bincode as a variant index
+    fn write_enum_tag<T: serde::Serialize>(&mut self, value: &T) {
+        let encoding = bincode::serialize(value).expect("must serialize enum");
+        let tag = u32::from_le_bytes(encoding.try_into().expect("must be a unit-only enum"));
+        self.write_varint(tag as u64);
+    }
+
+    fn write_serde<T: serde::Serialize>(&mut self, value: &T) {
+        let encoding = bincode::serialize(value).expect("must serialize");
+        self.write_bytes(&encoding);
+    }
+
+    fn write_memory_query(&mut self, query: &MemoryQuery, previous_timestamp: &mut u32) {
+        self.write_delta(query.timestamp.0, *previous_timestamp);
+        *previous_timestamp = query.timestamp.0;
+        self.write_enum_tag(&query.location.memory_type);
+        self.write_page(query.location.page);
+        self.write_varint(query.location.index.0 as u64);
+        self.write_u256(&query.value);
+        self.write_u8(pack_flags(&[
+            query.rw_flag,
+            query.value_is_pointer,
+            query.is_pended,
+        ]));
+    }
+
+    fn write_log_query(&mut self, query: &LogQuery, previous_timestamp: &mut u32) {
+        self.write_delta(query.timestamp.0, *previous_timestamp);
+        *previous_timestamp = query.timestamp.0;
+        self.write_varint(query.tx_number_in_block as u64);
+        self.write_u8(query.aux_byte);
+        self.write_u8(query.shard_id);
+        self.write_address(&query.address);
+        self.write_u256(&query.key);
+        self.write_u256(&query.read_value);
+        self.write_u256(&query.written_value);
+        self.write_u8(pack_flags(&[
+            query.rw_flag,
+            query.rollback,
+            query.is_service,
+        ]));
+    }
+
+    fn write_decommittment_query(
+        &mut self,
+        query: &DecommittmentQuery,
+        previous_timestamp: &mut u32,
+    ) {
+        self.write_delta(query.timestamp.0, *previous_timestamp);
+        *previous_timestamp = query.timestamp.0;
+        self.write_u256(&query.hash);
+        self.write_page(query.memory_page);
+        self.write_varint(query.decommitted_length as u64);
+        self.write_u8(pack_flags(&[query.is_fresh]));
+    }
+
+    fn write_callstack_entry(&mut self, entry: &CallStackEntry) {
+        self.write_address(&entry.this_address);
+        self.write_address(&entry.msg_sender);
+        self.write_address(&entry.code_address);
+        self.write_page(entry.base_memory_page);
+        self.write_page(entry.code_page);
+        self.write_varint(entry.sp as u64);
+        self.write_varint(entry.pc as u64);
+        self.write_varint(entry.exception_handler_location as u64);
+        self.write_varint(entry.ergs_remaining as u64);
+        self.write_u8(entry.this_shard_id);
+        self.write_u8(entry.caller_shard_id);
+        self.write_u8(entry.code_shard_id);
+        self.write_u8(pack_flags(&[entry.is_static, entry.is_local_frame]));
+        self.write_u256(&U256::from(entry.context_u128_value));
+        self.write_varint(entry.heap_bound as u64);
+        self.write_varint(entry.aux_heap_bound as u64);
+    }
+
+    fn write_queue<T>(
+        &mut self,
+        queue: &VecDeque<(u32, T)>,
+        mut write_item: impl FnMut(&mut Self, &T),
+    ) {
+        self.write_varint(queue.len() as u64);
+        let mut previous_cycle = 0;
+        for (cycle, item) in queue.iter() {
+            self.write_delta(*cycle, previous_cycle);
+            previous_cycle = *cycle;
+            write_item(self, item);
+        }
+    }
+}
+
+struct CompactReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    addresses: Vec<Address>,
+    pages: Vec<u32>,
+}
+
+impl<'a> CompactReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            position: 0,
+            addresses: vec![],
+            pages: vec![],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    fn read_slice(&mut self, len: usize) -> &'a [u8] {
+        let end = self.position + len;
+        assert!(end <= self.bytes.len(), "unexpected end of compact witness");
+        let result = &self.bytes[self.position..end];
+        self.position = end;
+
+        result
+    }
+
+    fn read_u8(&mut self) -> u8 {
+        self.read_slice(1)[0]
+    }
+
+    fn read_varint(&mut self) -> u64 {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            assert!(shift < 64, "varint is too long");
+            let byte = self.read_u8();
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+
+        result
+    }
+
+    fn read_delta(&mut self, previous: u32) -> u32 {
+        let encoded = self.read_varint();
+        let delta = ((encoded >> 1) as i64) ^ -((encoded & 1) as i64);
+        let value = previous as i64 + delta;
+        assert!(value >= 0 && value <= u32::MAX as i64, "invalid delta");
+
+        value as u32
+    }
+
+    fn read_bytes(&mut self) -> &'a [u8] {
+        let len = self.read_varint() as usize;
+        self.read_slice(len)
+    }
+
+    fn read_u256(&mut self) -> U256 {
+        let len = self.read_u8() as usize;
+        assert!(len <= 32);
+        U256::from_big_endian(self.read_slice(len))
+    }
+
+    fn read_fe<F: PrimeField>(&mut self) -> F {
+        let mut repr = F::zero().into_repr();
+        for limb in repr.as_mut().iter_mut() {
+            *limb = u64::from_le_bytes(self.read_slice(8).try_into().unwrap());
+        }
+
+        F::from_repr(repr).expect("must be a valid field element")
+    }
+
+    fn read_address(&mut self) -> Address {
+        let idx = self.read_varint() as usize;
+        if idx == self.addresses.len() {
+            let address = Address::from_slice(self.read_slice(20));
+            self.addresses.push(address);
+        }
+
+        *self.addresses.get(idx).expect("unknown address index")
+    }
+
+    fn read_page(&mut self) -> MemoryPage {
+        let idx = self.read_varint() as usize;
+        if idx == self.pages.len() {
+            let page = self.read_varint() as u32;
+            self.pages.push(page);
+        }
+
+        MemoryPage(*self.pages.get(idx).expect("unknown page index"))
+    }
+
+    fn read_enum_tag<T: serde::de::DeserializeOwned>(&mut self) -> T {
+        let tag = self.read_varint() as u32;
+        bincode::deserialize(&tag.to_le_bytes()).expect("must be a valid enum tag")
+    }
+
+    fn read_serde<T: serde::de::DeserializeOwned>(&mut self) -> T {
+        bincode::deserialize(self.read_bytes()).expect("must deserialize")
+    }
+
+    fn read_memory_query(&mut self, previous_timestamp: &mut u32) -> MemoryQuery {
+        let timestamp = self.read_delta(*previous_timestamp);
+        *previous_timestamp = timestamp;
+        let memory_type = self.read_enum_tag();
+        let page = self.read_page();
+        let index = self.read_varint() as u32;
+        let value = self.read_u256();
+        let [rw_flag, value_is_pointer, is_pended] = unpack_flags(self.read_u8());
+
+        MemoryQuery {
+            timestamp: Timestamp(timestamp),
+            location: MemoryLocation {
+                memory_type,
+                page,
+                index: MemoryIndex(index),
+            },
+            value,
+            value_is_pointer,
+            rw_flag,
+            is_pended,
+        }
+    }
+
+    fn read_log_query(&mut self, previous_timestamp: &mut u32) -> LogQuery {
+        let timestamp = self.read_delta(*previous_timestamp);
+        *previous_timestamp = timestamp;
+        let tx_number_in_block = self.read_varint() as u16;
+        let aux_byte = self.read_u8();
+        let shard_id = self.read_u8();
+        let address = self.read_address();
+        let key = self.read_u256();
+        let read_value = self.read_u256();
+        let written_value = self.read_u256();
+        let [rw_flag, rollback, is_service] = unpack_flags(self.read_u8());
+
+        LogQuery {
+            timestamp: Timestamp(timestamp),
+            tx_number_in_block,
+            aux_byte,
+            shard_id,
+            address,
+            key,
+            read_value,
+            written_value,
+            rw_flag,
+            rollback,
+            is_service,
+        }
+    }
+
+    fn read_decommittment_query(&mut self, previous_timestamp: &mut u32) -> DecommittmentQuery {
+        let timestamp = self.read_delta(*previous_timestamp);
+        *previous_timestamp = timestamp;
+        let hash = self.read_u256();
+        let memory_page = self.read_page();
+        let decommitted_length = self.read_varint() as u16;
+        let [is_fresh] = unpack_flags(self.read_u8());
+
+        DecommittmentQuery {
+            hash,
+            timestamp: Timestamp(timestamp),
+            memory_page,
+            decommitted_length,
+            is_fresh,
+        }
+    }
+
+    fn read_callstack_entry(&mut self) -> CallStackEntry {
+        let this_address = self.read_address();
+        let msg_sender = self.read_address();
+        let code_address = self.read_address();
+        let base_memory_page = self.read_page();
+        let code_page = self.read_page();
+        let sp = self.read_varint() as u16;
+        let pc = self.read_varint() as u16;
+        let exception_handler_location = self.read_varint() as u16;
+        let ergs_remaining = self.read_varint() as u32;
+        let this_shard_id = self.read_u8();
+        let caller_shard_id = self.read_u8();
+        let code_shard_id = self.read_u8();
+        let [is_static, is_local_frame] = unpack_flags(self.read_u8());
+        let context_u128_value = self.read_u256().low_u128();
+        let heap_bound = self.read_varint() as u32;
+        let aux_heap_bound = self.read_varint() as u32;
+
+        CallStackEntry {
+            this_address,
+            msg_sender,
+            code_address,
+            base_memory_page,
+            code_page,
+            sp,
+            pc,
+            exception_handler_location,
+            ergs_remaining,
+            this_shard_id,
+            caller_shard_id,
+            code_shard_id,
+            is_static,
+            is_local_frame,
+            context_u128_value,
+            heap_bound,
+            aux_heap_bound,
+        }
+    }
+
+    fn read_queue<T>(&mut self, mut read_item: impl FnMut(&mut Self) -> T) -> VecDeque<(u32, T)> {
+        let len = self.read_varint() as usize;
+        let mut result = VecDeque::with_capacity(len);
+        let mut previous_cycle = 0;
+        for _ in 0..len {
+            let cycle = self.read_delta(previous_cycle);
+            previous_cycle = cycle;
+            result.push_back((cycle, read_item(self)));
+        }
+
+        result
+    }
+}
+
+// Follows the callstack sponge the same way as the callstack simulator does. The oracle
+// of a single VM instance starts with a non-empty callstack, so states that were saved
+// before the instance began are not known and have to be stored explicitly
+struct CallstackSpongeTracker<E: Engine> {
+    current_state: Option<[E::Fr; 3]>,
+    states_before_pushes: Vec<[E::Fr; 3]>,
+}
+
+impl<E: Engine> CallstackSpongeTracker<E> {
+    fn new() -> Self {
+        Self {
+            current_state: None,
+            states_before_pushes: vec![],
+        }
+    }
+
+    fn expected_new_state<R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+        &self,
+        is_push: bool,
+        previous_state: [E::Fr; 3],
+        encoding: &[E::Fr; 6],
+        round_function: &R,
+    ) -> Option<[E::Fr; 3]> {
+        if is_push {
+            let states = round_function.simulate_absorb_multiple_rounds(previous_state, encoding);
+            states.last().map(|el| el.1)
+        } else {
+            self.states_before_pushes.last().copied()
+        }
+    }
+
+    fn apply(&mut self, is_push: bool, previous_state: [E::Fr; 3], new_state: [E::Fr; 3]) {
+        if is_push {
+            self.states_before_pushes.push(previous_state);
+        } else {
+            let _ = self.states_before_pushes.pop();
+        }
+        self.current_state = Some(new_state);
+    }
+}
+
+fn write_callstack_values<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+    writer: &mut CompactWriter,
+    values: &VecDeque<(u32, (ExtendedCallstackEntry<E>, CallstackSimulatorState<E>))>,
+    round_function: &R,
+) {
+    let mut tracker = CallstackSpongeTracker::<E>::new();
+    writer.write_queue(values, |writer, (entry, state)| {
+        writer.write_callstack_entry(&entry.callstack_entry);
+        writer.write_fe(&entry.rollback_queue_head);
+        writer.write_fe(&entry.rollback_queue_tail);
+        writer.write_varint(entry.rollback_queue_segment_length as u64);
+
+        let encoding = entry.encoding_witness();
+        let previous_is_known = tracker.current_state == Some(state.previous_state);
+        let new_is_known = tracker.expected_new_state(
+            state.is_push,
+            state.previous_state,
+            &encoding,
+            round_function,
+        ) == Some(state.new_state);
+
+        writer.write_u8(pack_flags(&[
+            state.is_push,
+            previous_is_known,
+            new_is_known,
+        ]));
+        writer.write_varint(state.depth as u64);
+        if !previous_is_known {
+            for el in state.previous_state.iter() {
+                writer.write_fe(el);
+            }
+        }
+        if !new_is_known {
+            for el in state.new_state.iter() {
+                writer.write_fe(el);
+            }
+        }
+
+        tracker.apply(state.is_push, state.previous_state, state.new_state);
+    });
+}
+
+fn read_callstack_values<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+    reader: &mut CompactReader,
+    round_function: &R,
+) -> VecDeque<(u32, (ExtendedCallstackEntry<E>, CallstackSimulatorState<E>))> {
+    let mut tracker = CallstackSpongeTracker::<E>::new();
+    reader.read_queue(|reader| {
+        let callstack_entry = reader.read_callstack_entry();
+        let rollback_queue_head = reader.read_fe();
+        let rollback_queue_tail = reader.read_fe();
+        let rollback_queue_segment_length = reader.read_varint() as u32;
+        let entry = ExtendedCallstackEntry::<E> {
+            callstack_entry,
+            rollback_queue_head,
+            rollback_queue_tail,
+            rollback_queue_segment_length,
+        };
+
+        let encoding = entry.encoding_witness();
+        let [is_push, previous_is_known, new_is_known] = unpack_flags(reader.read_u8());
+        let depth = reader.read_varint() as u32;
+        let previous_state = if previous_is_known {
+            tracker
+                .current_state
+                .expect("previous callstack state must be known")
+        } else {
+            std::array::from_fn(|_| reader.read_fe())
+        };
+        let new_state = if new_is_known {
+            tracker
+                .expected_new_state(is_push, previous_state, &encoding, round_function)
+                .expect("new callstack state must be known")
+        } else {
+            std::array::from_fn(|_| reader.read_fe())
+        };
+        tracker.apply(is_push, previous_state, new_state);
+
+        // the same intermediate states as the simulator outputs
+        let absorbed_into = if is_push { previous_state } else { new_state };
+        let round_function_execution_pairs = round_function
+            .simulate_absorb_multiple_rounds(absorbed_into, &encoding)
+            .try_into()
+            .unwrap();
+
+        let state = CallstackSimulatorState::<E> {
+            is_push,
+            previous_state,
+            new_state,
+            depth,
+            round_function_execution_pairs,
+        };
+
+        (entry, state)
+    })
+}
+
+fn write_oracle<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+    writer: &mut CompactWriter,
+    oracle: &VmWitnessOracle<E>,
+    round_function: &R,
+) {
+    let mut previous_timestamp = 0;
+    writer.write_queue(&oracle.memory_read_witness, |writer, query| {
+        writer.write_memory_query(query, &mut previous_timestamp)
+    });
+
+    writer.write_u8(oracle.memory_write_witness.is_some() as u8);
+    if let Some(memory_write_witness) = oracle.memory_write_witness.as_ref() {
+        let mut previous_timestamp = 0;
+        writer.write_queue(memory_write_witness, |writer, query| {
+            writer.write_memory_query(query, &mut previous_timestamp)
+        });
+    }
+
+    writer.write_queue(&oracle.rollback_queue_head_segments, |writer, el| {
+        writer.write_fe(el)
+    });
+
+    let mut previous_timestamp = 0;
+    writer.write_queue(&oracle.decommittment_requests_witness, |writer, query| {
+        writer.write_decommittment_query(query, &mut previous_timestamp)
+    });
+
+    writer.write_queue(
+        &oracle.rollback_queue_initial_tails_for_new_frames,
+        |writer, el| writer.write_fe(el),
+    );
+
+    let mut previous_timestamp = 0;
+    writer.write_queue(&oracle.storage_queries, |writer, query| {
+        writer.write_log_query(query, &mut previous_timestamp)
+    });
+
+    writer.write_varint(oracle.storage_refund_queries.len() as u64);
+    let mut previous_cycle = 0;
+    let mut previous_timestamp = 0;
+    for (cycle, query, refund) in oracle.storage_refund_queries.iter() {
+        writer.write_delta(*cycle, previous_cycle);
+        previous_cycle = *cycle;
+        writer.write_log_query(query, &mut previous_timestamp);
+        writer.write_varint(*refund as u64);
+    }
+
+    writer.write_queue(&oracle.callstack_new_frames_witnesses, |writer, entry| {
+        writer.write_callstack_entry(entry)
+    });
+
+    write_callstack_values(writer, &oracle.callstack_values_witnesses, round_function);
+}
+
+fn read_oracle<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+    reader: &mut CompactReader,
+    round_function: &R,
+) -> VmWitnessOracle<E> {
+    let mut previous_timestamp = 0;
+    let memory_read_witness =
+        reader.read_queue(|reader| reader.read_memory_query(&mut previous_timestamp));
+
+    let memory_write_witness = match reader.read_u8() {
+        0 => None,
+        1 => {
+            let mut previous_timestamp = 0;
+            Some(reader.read_queue(|reader| reader.read_memory_query(&mut previous_timestamp)))
+        }
+        _ => panic!("invalid marker for memory write witness"),
+    };
+
+    let rollback_queue_head_segments = reader.read_queue(|reader| reader.read_fe());
+
+    let mut previous_timestamp = 0;
+    let decommittment_requests_witness =
+        reader.read_queue(|reader| reader.read_decommittment_query(&mut previous_timestamp));
+
+    let rollback_queue_initial_tails_for_new_frames = reader.read_queue(|reader| reader.read_fe());
+
+    let mut previous_timestamp = 0;
+    let storage_queries =
+        reader.read_queue(|reader| reader.read_log_query(&mut previous_timestamp));
+
+    let len = reader.read_varint() as usize;
+    let mut storage_refund_queries = VecDeque::with_capacity(len);
+    let mut previous_cycle = 0;
+    let mut previous_timestamp = 0;
+    for _ in 0..len {
+        let cycle = reader.read_delta(previous_cycle);
+        previous_cycle = cycle;
+        let query = reader.read_log_query(&mut previous_timestamp);
+        let refund = reader.read_varint() as u32;
+        storage_refund_queries.push_back((cycle, query, refund));
+    }
+
+    let callstack_new_frames_witnesses = reader.read_queue(|reader| reader.read_callstack_entry());
+
+    let callstack_values_witnesses = read_callstack_values(reader, round_function);
+
+    VmWitnessOracle {
+        memory_read_witness,
+        memory_write_witness,
+        rollback_queue_head_segments,
+        decommittment_requests_witness,
+        rollback_queue_initial_tails_for_new_frames,
+        storage_queries,
+        storage_refund_queries,
+        callstack_new_frames_witnesses,
+        callstack_values_witnesses,
+    }
+}
+
+fn seal(payload_kind: u8, body: Vec<u8>, compression_block_size: Option<usize>) -> Vec<u8> {
+    let mut result = COMPACT_WITNESS_MAGIC.to_vec();
+    result.push(COMPACT_WITNESS_VERSION);
+    result.push(payload_kind);
+
+    match compression_block_size {
+        None => {
+            result.push(0);
+            result.extend(body);
+        }
+        Some(block_size) => {
+            assert!(block_size > 0);
+            result.push(FLAG_COMPRESSED);
+            let mut writer = CompactWriter::new();
+            writer.write_varint(body.len() as u64);
+            for block in body.chunks(block_size) {
+                writer.write_varint(block.len() as u64);
+                writer.write_bytes(&lz4_flex::block::compress(block));
+            }
+            result.extend(writer.buffer);
+        }
+    }
+
+    result
+}
+
+fn unseal(payload_kind: u8, bytes: &[u8]) -> Vec<u8> {
+    let mut reader = CompactReader::new(bytes);
+    assert_eq!(
+        reader.read_slice(4),
+        &COMPACT_WITNESS_MAGIC[..],
+        "not a compact witness"
+    );
+    assert_eq!(
+        reader.read_u8(),
+        COMPACT_WITNESS_VERSION,
+        "unsupported compact witness version"
+    );
+    assert_eq!(reader.read_u8(), payload_kind, "unexpected payload kind");
+
+    let flags = reader.read_u8();
+    if flags & FLAG_COMPRESSED == 0 {
+        return bytes[reader.position..].to_vec();
+    }
+
+    let total_len = reader.read_varint() as usize;
+    let mut body = Vec::with_capacity(total_len);
+    while body.len() < total_len {
+        let block_len = reader.read_varint() as usize;
+        let block = lz4_flex::block::decompress(reader.read_bytes(), block_len)
+            .expect("must decompress the block");
+        assert_eq!(block.len(), block_len);
+        body.extend(block);
+    }
+    assert_eq!(body.len(), total_len);
+    assert!(reader.is_empty(), "trailing bytes after the last block");
+
+    body
+}
+
+pub fn encode_vm_witness_oracle<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+    oracle: &VmWitnessOracle<E>,
+    round_function: &R,
+    compression_block_size: Option<usize>,
+) -> Vec<u8> {
+    let mut writer = CompactWriter::new();
+    write_oracle(&mut writer, oracle, round_function);
+
+    seal(PAYLOAD_KIND_ORACLE, writer.buffer, compression_block_size)
+}
+
+pub fn decode_vm_witness_oracle<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+    bytes: &[u8],
+    round_function: &R,
+) -> VmWitnessOracle<E> {
+    let body = unseal(PAYLOAD_KIND_ORACLE, bytes);
+    let mut reader = CompactReader::new(&body);
+    let oracle = read_oracle(&mut reader, round_function);
+    assert!(reader.is_empty(), "trailing bytes after the oracle");
+
+    oracle
+}
+
+// closed form input is small compared to the oracle, so it's kept in the serde form
+pub fn encode_vm_circuit_witness<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+    witness: &VmCircuitWitness<E, VmWitnessOracle<E>>,
+    round_function: &R,
+    compression_block_size: Option<usize>,
+) -> Vec<u8> {
+    let mut writer = CompactWriter::new();
+    writer.write_serde(&witness.closed_form_input);
+    write_oracle(&mut writer, &witness.witness_oracle, round_function);
+
+    seal(
+        PAYLOAD_KIND_VM_CIRCUIT_WITNESS,
+        writer.buffer,
+        compression_block_size,
+    )
+}
+
+pub fn decode_vm_circuit_witness<E: Engine, R: CircuitArithmeticRoundFunction<E, 2, 3>>(
+    bytes: &[u8],
+    round_function: &R,
+) -> VmCircuitWitness<E, VmWitnessOracle<E>> {
+    let body = unseal(PAYLOAD_KIND_VM_CIRCUIT_WITNESS, bytes);
+    let mut reader = CompactReader::new(&body);
+    let closed_form_input = reader.read_serde();
+    let witness_oracle = read_oracle(&mut reader, round_function);
+    assert!(reader.is_empty(), "trailing bytes after the witness");
+
+    VmCircuitWitness {
+        closed_form_input,
+        witness_oracle,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bellman::bn256::{Bn256, Fr};
+    use crate::encodings::callstack_entry::CallstackSimulator;
+    use crate::entry_point::initial_out_of_circuit_context;
+    use crate::ff::Field;
+    use sync_vm::testing::create_test_artifacts_with_optimized_gate;
+    use zk_evm::aux_structures::MemoryType;
+
+    // serde form skips the round function intermediates, so compare them separately
+    fn assert_same_oracles<E: Engine>(a: &VmWitnessOracle<E>, b: &VmWitnessOracle<E>) {
+        assert_eq!(
+            bincode::serialize(a).unwrap(),
+            bincode::serialize(b).unwrap()
+        );
+        for ((_, (_, a)), (_, (_, b))) in a
+            .callstack_values_witnesses
+            .iter()
+            .zip(b.callstack_values_witnesses.iter())
+        {
+            assert_eq!(
+                a.round_function_execution_pairs,
+                b.round_function_execution_pairs
+            );
+        }
+    }
+
+    #[test]
+    fn test_compact_oracle_roundtrip() {
+        let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
+
+        let mut oracle = VmWitnessOracle::<Bn256>::default();
+        for (cycle, idx) in [(3u32, 0u32), (3, 1), (5, 0), (9, 31)] {
+            let query = MemoryQuery {
+                timestamp: Timestamp(cycle * 4),
+                location: MemoryLocation {
+                    memory_type: MemoryType::Heap,
+                    page: MemoryPage(8 + idx % 2),
+                    index: MemoryIndex(idx),
+                },
+                value: U256::from(u64::MAX) * U256::from(idx),
+                value_is_pointer: idx == 1,
+                rw_flag: false,
+                is_pended: false,
+            };
+            oracle.memory_read_witness.push_back((cycle, query));
+        }
+        oracle.rollback_queue_head_segments = VecDeque::from([(4, Fr::one()), (2, Fr::zero())]);
+
+        let entry = |ergs| {
+            let mut entry = initial_out_of_circuit_context(
+                0,
+                ergs,
+                Address::from_low_u64_be(0x8001),
+                Address::zero(),
+                Address::from_low_u64_be(0x8001),
+            );
+            entry.context_u128_value = u128::MAX - ergs as u128;
+            ExtendedCallstackEntry::<Bn256> {
+                callstack_entry: entry,
+                rollback_queue_head: Fr::one(),
+                rollback_queue_tail: Fr::one(),
+                rollback_queue_segment_length: ergs,
+            }
+        };
+        // an instance starting with the non-empty callstack
+        let mut simulator = CallstackSimulator::<Bn256>::empty();
+        simulator.push(entry(1), &round_function);
+        let mut values = VecDeque::new();
+        values.push_back((
+            10,
+            simulator.push_and_output_intermediate_data(entry(2), &round_function),
+        ));
+        values.push_back((
+            11,
+            simulator
+                .pop_and_output_intermediate_data(&round_function)
+                .1,
+        ));
+        values.push_back((
+            12,
+            simulator
+                .pop_and_output_intermediate_data(&round_function)
+                .1,
+        ));
+        oracle.callstack_values_witnesses = values
+            .into_iter()
+            .zip([entry(2), entry(2), entry(1)])
+            .map(|((cycle, state), entry)| (cycle, (entry, state)))
+            .collect();
+        oracle.callstack_new_frames_witnesses = VecDeque::from([(10, entry(2).callstack_entry)]);
+
+        for compression in [None, Some(64)] {
+            let encoded = encode_vm_witness_oracle(&oracle, &round_function, compression);
+            let decoded = decode_vm_witness_oracle::<Bn256, _>(&encoded, &round_function);
+            assert_same_oracles(&oracle, &decoded);
+        }
+
+        let uncompressed = encode_vm_witness_oracle(&oracle, &round_function, None);
+        let full_size = bincode::serialize(&oracle).unwrap().len();
+        assert!(uncompressed.len() < full_size);
+    }
+}
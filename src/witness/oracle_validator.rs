@@ -0,0 +1,773 @@
+use crate::bellman::plonk::better_better_cs::cs::{
+    PlonkCsWidth4WithNextStepAndCustomGatesParams, TrivialAssembly,
+};
+use crate::bellman::plonk::better_better_cs::gates::selector_optimized_with_d_next::SelectorOptimizedWidth4MainGateWithDNext;
+use crate::entry_point::{create_in_circuit_global_context, run_vm_instance_with_oracle};
+use crate::ethereum_types::U256;
+use crate::ff::Field;
+use crate::franklin_crypto::plonk::circuit::allocated_num::Num;
+use crate::franklin_crypto::plonk::circuit::boolean::Boolean;
+use crate::witness::oracle::{VmInstanceWitness, VmWitnessOracle};
+use derivative::Derivative;
+use num_bigint::BigUint;
+use std::collections::VecDeque;
+use sync_vm::circuit_structures::traits::CircuitArithmeticRoundFunction;
+use sync_vm::franklin_crypto::bellman::pairing::Engine;
+use sync_vm::scheduler::data_access_functions::StorageLogRecord;
+use sync_vm::scheduler::queues::{DecommitQuery, DecommitQueryWitness};
+use sync_vm::traits::CSWitnessable;
+use sync_vm::vm::primitives::*;
+use sync_vm::vm::vm_cycle::memory::MemoryLocation;
+use sync_vm::vm::vm_cycle::memory_view::write_query::MemoryWriteQuery;
+use sync_vm::vm::vm_cycle::witness_oracle::{MemoryWitness, WitnessOracle};
+use sync_vm::vm::vm_state::saved_contract_context::{
+    ExecutionContextRecord, ExecutionContextRecordWitness,
+};
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OracleQueue {
+    MemoryReads,
+    MemoryWrites,
+    RollbackQueueHeads,
+    RollbackQueueTailsForNewFrames,
+    DecommittmentRequests,
+    StorageQueries,
+    StorageRefunds,
+    CallstackNewFrames,
+    CallstackValues,
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OracleConsumptionIssueKind {
+    // circuit requested an entry, but the queue is empty
+    Exhausted,
+    // entry at the front of the queue was produced at the different cycle
+    WrongCycle { entry_cycle: u32 },
+    // entry was not consumed until the end of the instance
+    Leftover { entry_cycle: u32 },
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OracleConsumptionIssue {
+    pub queue: OracleQueue,
+    // cycle at which the circuit has requested the entry, or the last cycle of the instance for leftovers
+    pub cycle: u32,
+    pub kind: OracleConsumptionIssueKind,
+}
+
+impl std::fmt::Display for OracleConsumptionIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            OracleConsumptionIssueKind::Exhausted => {
+                write!(
+                    f,
+                    "{:?} queue is exhausted at cycle {}",
+                    self.queue, self.cycle
+                )
+            }
+            OracleConsumptionIssueKind::WrongCycle { entry_cycle } => write!(
+                f,
+                "{:?} entry of cycle {} is requested at cycle {}",
+                self.queue, entry_cycle, self.cycle
+            ),
+            OracleConsumptionIssueKind::Leftover { entry_cycle } => write!(
+                f,
+                "{:?} entry of cycle {} is left after the last cycle {}",
+                self.queue, entry_cycle, self.cycle
+            ),
+        }
+    }
+}
+
+// Wraps the oracle and records how it's consumed instead of failing on the first
+// problem. Replay driver sets the current cycle before every cycle of the circuit.
+// Entries of the current cycle are passed to the inner oracle, so problems with the values
+// themselves are still caught by its assertions. Otherwise a placeholder is returned
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone(bound = ""), Default(bound = ""))]
+#[serde(bound = "")]
+pub struct ValidatingWitnessOracle<E: Engine> {
+    pub inner: VmWitnessOracle<E>,
+    pub current_cycle: u32,
+    pub issues: Vec<OracleConsumptionIssue>,
+}
+
+// entries of the past cycles can never be consumed anymore, so they are reported and dropped.
+// Returns if the front entry belongs to the current cycle and can be consumed
+fn check_front<T>(
+    issues: &mut Vec<OracleConsumptionIssue>,
+    queue: OracleQueue,
+    current_cycle: u32,
+    entries: &mut VecDeque<T>,
+    entry_cycle: impl Fn(&T) -> u32,
+) -> bool {
+    let mut report = |kind| {
+        issues.push(OracleConsumptionIssue {
+            queue,
+            cycle: current_cycle,
+            kind,
+        })
+    };
+
+    loop {
+        match entries.front().map(&entry_cycle) {
+            None => {
+                report(OracleConsumptionIssueKind::Exhausted);
+                return false;
+            }
+            Some(cycle) if cycle < current_cycle => {
+                report(OracleConsumptionIssueKind::WrongCycle { entry_cycle: cycle });
+                let _ = entries.pop_front();
+            }
+            Some(cycle) if cycle > current_cycle => {
+                report(OracleConsumptionIssueKind::WrongCycle { entry_cycle: cycle });
+                return false;
+            }
+            Some(_) => return true,
+        }
+    }
+}
+
+fn is_set(flag: &Boolean) -> bool {
+    flag.get_value().unwrap_or(false)
+}
+
+impl<E: Engine> ValidatingWitnessOracle<E> {
+    pub fn new(inner: VmWitnessOracle<E>) -> Self {
+        Self {
+            inner,
+            current_cycle: 0,
+            issues: vec![],
+        }
+    }
+
+    fn check_queue<T>(
+        &mut self,
+        queue: OracleQueue,
+        entries: impl FnOnce(&mut VmWitnessOracle<E>) -> &mut VecDeque<(u32, T)>,
+    ) -> bool {
+        check_front(
+            &mut self.issues,
+            queue,
+            self.current_cycle,
+            entries(&mut self.inner),
+            |el| el.0,
+        )
+    }
+
+    fn add_leftovers<T>(
+        issues: &mut Vec<OracleConsumptionIssue>,
+        queue: OracleQueue,
+        last_cycle: u32,
+        leftovers: &VecDeque<(u32, T)>,
+    ) {
+        issues.extend(
+            leftovers
+                .iter()
+                .map(|(entry_cycle, _)| OracleConsumptionIssue {
+                    queue,
+                    cycle: last_cycle,
+                    kind: OracleConsumptionIssueKind::Leftover {
+                        entry_cycle: *entry_cycle,
+                    },
+                }),
+        );
+    }
+
+    // all the issues, including entries that were never consumed
+    pub fn finish(self) -> Vec<OracleConsumptionIssue> {
+        let Self {
+            inner,
+            current_cycle,
+            mut issues,
+        } = self;
+
+        Self::add_leftovers(
+            &mut issues,
+            OracleQueue::MemoryReads,
+            current_cycle,
+            &inner.memory_read_witness,
+        );
+        if let Some(memory_write_witness) = inner.memory_write_witness.as_ref() {
+            Self::add_leftovers(
+                &mut issues,
+                OracleQueue::MemoryWrites,
+                current_cycle,
+                memory_write_witness,
+            );
+        }
+        Self::add_leftovers(
+            &mut issues,
+            OracleQueue::RollbackQueueHeads,
+            current_cycle,
+            &inner.rollback_queue_head_segments,
+        );
+        Self::add_leftovers(
+            &mut issues,
+            OracleQueue::RollbackQueueTailsForNewFrames,
+            current_cycle,
+            &inner.rollback_queue_initial_tails_for_new_frames,
+        );
+        Self::add_leftovers(
+            &mut issues,
+            OracleQueue::DecommittmentRequests,
+            current_cycle,
+            &inner.decommittment_requests_witness,
+        );
+        Self::add_leftovers(
+            &mut issues,
+            OracleQueue::StorageQueries,
+            current_cycle,
+            &inner.storage_queries,
+        );
+        let refund_cycles: VecDeque<_> = inner
+            .storage_refund_queries
+            .iter()
+            .map(|el| (el.0, ()))
+            .collect();
+        Self::add_leftovers(
+            &mut issues,
+            OracleQueue::StorageRefunds,
+            current_cycle,
+            &refund_cycles,
+        );
+        Self::add_leftovers(
+            &mut issues,
+            OracleQueue::CallstackNewFrames,
+            current_cycle,
+            &inner.callstack_new_frames_witnesses,
+        );
+        Self::add_leftovers(
+            &mut issues,
+            OracleQueue::CallstackValues,
+            current_cycle,
+            &inner.callstack_values_witnesses,
+        );
+
+        issues
+    }
+}
+
+impl<E: Engine> WitnessOracle<E> for ValidatingWitnessOracle<E> {
+    fn get_memory_witness_for_read(
+        &mut self,
+        timestamp: UInt32<E>,
+        key: &MemoryLocation<E>,
+        execute: &Boolean,
+    ) -> Option<MemoryWitness> {
+        if is_set(execute)
+            && !self.check_queue(OracleQueue::MemoryReads, |el| &mut el.memory_read_witness)
+        {
+            return Some(MemoryWitness {
+                value: BigUint::from(0u64),
+                is_ptr: false,
+            });
+        }
+
+        self.inner
+            .get_memory_witness_for_read(timestamp, key, execute)
+    }
+
+    fn push_memory_witness(&mut self, memory_query: &MemoryWriteQuery<E>, execute: &Boolean) {
+        if is_set(execute)
+            && self.inner.memory_write_witness.is_some()
+            && !self.check_queue(OracleQueue::MemoryWrites, |el| {
+                el.memory_write_witness.as_mut().unwrap()
+            })
+        {
+            return;
+        }
+
+        self.inner.push_memory_witness(memory_query, execute)
+    }
+
+    fn get_storage_read_witness(
+        &mut self,
+        record: &StorageLogRecord<E>,
+        needs_read_witness: &Boolean,
+        execute: &Boolean,
+    ) -> Option<BigUint> {
+        if is_set(execute)
+            && is_set(needs_read_witness)
+            && !self.check_queue(OracleQueue::StorageQueries, |el| &mut el.storage_queries)
+        {
+            return Some(BigUint::from(0u64));
+        }
+
+        self.inner
+            .get_storage_read_witness(record, needs_read_witness, execute)
+    }
+
+    fn get_refunds(
+        &mut self,
+        record: &StorageLogRecord<E>,
+        is_write: &Boolean,
+        execute: &Boolean,
+    ) -> Option<u32> {
+        if is_set(execute)
+            && is_set(is_write)
+            && !check_front(
+                &mut self.issues,
+                OracleQueue::StorageRefunds,
+                self.current_cycle,
+                &mut self.inner.storage_refund_queries,
+                |el| el.0,
+            )
+        {
+            return Some(0u32);
+        }
+
+        self.inner.get_refunds(record, is_write, execute)
+    }
+
+    fn push_storage_witness(
+        &mut self,
+        record: &StorageLogRecord<E>,
+        is_write: &Boolean,
+        execute: &Boolean,
+    ) {
+        self.inner.push_storage_witness(record, is_write, execute)
+    }
+
+    fn get_rollback_queue_witness(
+        &mut self,
+        key: &StorageLogRecord<E>,
+        execute: &Boolean,
+    ) -> Option<E::Fr> {
+        if is_set(execute)
+            && !self.check_queue(OracleQueue::RollbackQueueHeads, |el| {
+                &mut el.rollback_queue_head_segments
+            })
+        {
+            return Some(E::Fr::zero());
+        }
+
+        self.inner.get_rollback_queue_witness(key, execute)
+    }
+
+    fn get_rollback_queue_tail_witness_for_call(
+        &mut self,
+        timestamp: UInt32<E>,
+        execute: &Boolean,
+    ) -> Option<E::Fr> {
+        if is_set(execute)
+            && !self.check_queue(OracleQueue::RollbackQueueTailsForNewFrames, |el| {
+                &mut el.rollback_queue_initial_tails_for_new_frames
+            })
+        {
+            return Some(E::Fr::zero());
+        }
+
+        self.inner
+            .get_rollback_queue_tail_witness_for_call(timestamp, execute)
+    }
+
+    fn report_new_callstack_frame(
+        &mut self,
+        new_callstack: &ExecutionContextRecord<E>,
+        new_depth: UInt32<E>,
+        is_call: &Boolean,
+        execute: &Boolean,
+    ) {
+        if is_set(execute)
+            && is_set(is_call)
+            && !self.check_queue(OracleQueue::CallstackNewFrames, |el| {
+                &mut el.callstack_new_frames_witnesses
+            })
+        {
+            return;
+        }
+
+        self.inner
+            .report_new_callstack_frame(new_callstack, new_depth, is_call, execute)
+    }
+
+    fn push_callstack_witness(
+        &mut self,
+        current_record: &ExecutionContextRecord<E>,
+        current_depth: &UInt32<E>,
+        execute: &Boolean,
+    ) {
+        if is_set(execute)
+            && !self.check_queue(OracleQueue::CallstackValues, |el| {
+                &mut el.callstack_values_witnesses
+            })
+        {
+            return;
+        }
+
+        self.inner
+            .push_callstack_witness(current_record, current_depth, execute)
+    }
+
+    fn get_callstack_witness(
+        &mut self,
+        execute: &Boolean,
+        depth: &UInt32<E>,
+    ) -> (Option<ExecutionContextRecordWitness<E>>, Option<[E::Fr; 3]>) {
+        if is_set(execute)
+            && !self.check_queue(OracleQueue::CallstackValues, |el| {
+                &mut el.callstack_values_witnesses
+            })
+        {
+            return (
+                Some(ExecutionContextRecord::placeholder_witness()),
+                Some([E::Fr::zero(); 3]),
+            );
+        }
+
+        self.inner.get_callstack_witness(execute, depth)
+    }
+
+    fn get_decommittment_request_witness(
+        &mut self,
+        request: &DecommitQuery<E>,
+        execute: &Boolean,
+    ) -> Option<DecommitQueryWitness<E>> {
+        if is_set(execute)
+            && !self.check_queue(OracleQueue::DecommittmentRequests, |el| {
+                &mut el.decommittment_requests_witness
+            })
+        {
+            return Some(DecommitQuery::placeholder_witness());
+        }
+
+        self.inner
+            .get_decommittment_request_witness(request, execute)
+    }
+
+    fn at_completion(self) {
+        let issues = self.finish();
+        if !issues.is_empty() {
+            let report: Vec<_> = issues.iter().map(|el| el.to_string()).collect();
+            panic!("oracle is consumed incorrectly:\n{}", report.join("\n"));
+        }
+    }
+}
+
+// Replays the cycles of the VM instance in the witness-only constraint system, so the oracle is
+// consumed exactly as by the main VM circuit, and returns all the consumption issues found
+pub fn validate_oracle_consumption<
+    E: Engine,
+    R: CircuitArithmeticRoundFunction<E, 2, 3, StateElement = Num<E>>,
+>(
+    round_function: &R,
+    zk_porter_is_available: bool,
+    default_aa_code_hash: U256,
+    instance: VmInstanceWitness<E, VmWitnessOracle<E>>,
+) -> Vec<OracleConsumptionIssue> {
+    use sync_vm::vm::vm_cycle::add_all_tables;
+
+    let VmInstanceWitness {
+        initial_state,
+        witness_oracle,
+        auxilary_initial_parameters,
+        cycles_range,
+        final_state,
+        auxilary_final_parameters,
+    } = instance;
+    let instance = VmInstanceWitness {
+        initial_state,
+        witness_oracle: ValidatingWitnessOracle::new(witness_oracle),
+        auxilary_initial_parameters,
+        cycles_range,
+        final_state,
+        auxilary_final_parameters,
+    };
+
+    let mut cs = TrivialAssembly::<
+        E,
+        PlonkCsWidth4WithNextStepAndCustomGatesParams,
+        SelectorOptimizedWidth4MainGateWithDNext,
+    >::new();
+    add_all_tables(&mut cs).unwrap();
+    let in_circuit_global_context =
+        create_in_circuit_global_context::<E>(zk_porter_is_available, default_aa_code_hash);
+
+    let (_, oracle) = run_vm_instance_with_oracle(
+        &mut cs,
+        round_function,
+        &in_circuit_global_context,
+        instance,
+        |oracle, cycle| oracle.current_cycle = cycle,
+    );
+
+    oracle.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bellman::bn256::{Bn256, Fr};
+    use zk_evm::aux_structures::*;
+
+    fn memory_query() -> MemoryQuery {
+        MemoryQuery {
+            timestamp: Timestamp(0),
+            location: zk_evm::aux_structures::MemoryLocation {
+                memory_type: MemoryType::Heap,
+                page: MemoryPage(0),
+                index: MemoryIndex(0),
+            },
+            rw_flag: false,
+            is_pended: false,
+            value: U256::zero(),
+            value_is_pointer: false,
+        }
+    }
+
+    // runs the code out of circuit in the same way as `run`, but stops at the VM instances
+    fn create_vm_instances(asm: &str) -> Vec<VmInstanceWitness<Bn256, VmWitnessOracle<Bn256>>> {
+        use crate::entry_point::create_out_of_circuit_global_context;
+        use crate::ethereum_types::Address;
+        use crate::geometry_config::get_geometry_config;
+        use crate::toolset::{create_out_of_circuit_vm, create_tools, GeometryConfig};
+        use crate::witness::oracle::create_artifacts_from_tracer;
+        use crate::witness::tree::ZKSyncTestingTree;
+        use crate::witness::vm_snapshot::VmSnapshot;
+        use sync_vm::testing::create_test_artifacts_with_optimized_gate;
+        use zk_evm::reference_impls::memory::SimpleMemory;
+        use zk_evm::testing::storage::InMemoryStorage;
+        use zk_evm::utils::{bytecode_to_code_hash, contract_bytecode_to_words};
+        use zk_evm::witness_trace::VmWitnessTracer;
+        use zk_evm::GenericNoopTracer;
+        use zkevm_assembly::Assembly;
+
+        let mut assembly = Assembly::try_from(asm.to_owned()).unwrap();
+        let bytecode = assembly.compile_to_bytecode().unwrap();
+        let code_hash = U256::from_big_endian(&bytecode_to_code_hash(&bytecode).unwrap());
+
+        let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
+        let geometry = GeometryConfig {
+            cycles_per_vm_snapshot: 4,
+            ..get_geometry_config()
+        };
+        let mut tools = create_tools(
+            InMemoryStorage::new(),
+            SimpleMemory::new_without_preallocations(),
+            &geometry,
+        );
+        tools
+            .decommittment_processor
+            .populate(vec![(code_hash, contract_bytecode_to_words(&bytecode))]);
+        let query = DecommittmentQuery {
+            hash: code_hash,
+            timestamp: Timestamp(sync_vm::scheduler::SCHEDULER_TIMESTAMP),
+            memory_page: MemoryPage(zk_evm::zkevm_opcode_defs::BOOTLOADER_CODE_PAGE),
+            decommitted_length: bytecode.len() as u16,
+            is_fresh: true,
+        };
+        let (query, witness) =
+            tools
+                .decommittment_processor
+                .decommit_into_memory(0, query, &mut tools.memory);
+        let witness = witness.unwrap();
+        tools
+            .witness_tracer
+            .add_decommittment(0, query, witness.clone());
+
+        let block_properties = create_out_of_circuit_global_context(false, U256::zero());
+        let entry_point_address = Address::from_low_u64_be(0x8001);
+        let mut vm = create_out_of_circuit_vm(
+            &mut tools,
+            &block_properties,
+            Address::zero(),
+            entry_point_address,
+        );
+        let mut tracer = GenericNoopTracer::<_>::new();
+        let mut snapshots_len = None;
+        for _ in 0..64 {
+            if vm.execution_has_ended() && !vm.is_any_pending() {
+                match snapshots_len {
+                    None => snapshots_len = Some(vm.witness_tracer.vm_snapshots.len()),
+                    Some(len) if len != vm.witness_tracer.vm_snapshots.len() => break,
+                    Some(_) => {}
+                }
+            }
+            vm.cycle(&mut tracer);
+        }
+        assert!(vm.execution_has_ended());
+        let local_state = vm.local_state.clone();
+        drop(vm);
+
+        // the final snapshot is taken in the same way as in `run`
+        if snapshots_len.is_none() {
+            let at_cycle = tools.witness_tracer.current_cycle_counter;
+            tools.witness_tracer.vm_snapshots.push(VmSnapshot {
+                local_state,
+                at_cycle,
+            });
+        }
+
+        let mut tree = ZKSyncTestingTree::empty();
+        let (instances, _) = create_artifacts_from_tracer(
+            tools.witness_tracer,
+            &round_function,
+            &geometry,
+            (query, witness),
+            &mut tree,
+            0,
+        );
+
+        instances
+    }
+
+    fn validate(
+        instance: VmInstanceWitness<Bn256, VmWitnessOracle<Bn256>>,
+    ) -> Vec<OracleConsumptionIssue> {
+        use sync_vm::testing::create_test_artifacts_with_optimized_gate;
+
+        let (_, round_function, _) = create_test_artifacts_with_optimized_gate();
+        validate_oracle_consumption(&round_function, false, U256::zero(), instance)
+    }
+
+    const HEAP_READ_ASM: &str = r#"
+        .text
+        .file	"Test_26"
+        .rodata.cst32
+        .p2align	5
+        .text
+        .globl	__entry
+    __entry:
+    .main:
+        add 64, r0, r1
+        ld.1 r1, r2
+        add 1, r2, r3
+        add 2, r3, r4
+        add 3, r4, r5
+        ret.ok r0
+    "#;
+
+    #[test]
+    fn test_oracle_consumption_checks() {
+        let mut inner = VmWitnessOracle::<Bn256>::default();
+        inner.memory_read_witness = VecDeque::from([(10, memory_query()), (12, memory_query())]);
+        inner.rollback_queue_head_segments = VecDeque::from([(9, Fr::zero()), (11, Fr::zero())]);
+        let mut oracle = ValidatingWitnessOracle::new(inner);
+
+        oracle.current_cycle = 10;
+        assert!(oracle.check_queue(OracleQueue::MemoryReads, |el| &mut el.memory_read_witness));
+        let _ = oracle.inner.memory_read_witness.pop_front();
+        // entry of the future cycle is not consumed
+        oracle.current_cycle = 11;
+        assert!(!oracle.check_queue(OracleQueue::MemoryReads, |el| &mut el.memory_read_witness));
+        assert_eq!(oracle.inner.memory_read_witness.len(), 1);
+        // entry of the past cycle is dropped
+        assert!(oracle.check_queue(OracleQueue::RollbackQueueHeads, |el| {
+            &mut el.rollback_queue_head_segments
+        }));
+        assert_eq!(oracle.inner.rollback_queue_head_segments.len(), 1);
+        oracle.current_cycle = 13;
+        assert!(!oracle.check_queue(OracleQueue::MemoryReads, |el| &mut el.memory_read_witness));
+        assert!(oracle.inner.memory_read_witness.is_empty());
+
+        let issues = oracle.finish();
+        assert_eq!(
+            issues,
+            vec![
+                OracleConsumptionIssue {
+                    queue: OracleQueue::MemoryReads,
+                    cycle: 11,
+                    kind: OracleConsumptionIssueKind::WrongCycle { entry_cycle: 12 },
+                },
+                OracleConsumptionIssue {
+                    queue: OracleQueue::RollbackQueueHeads,
+                    cycle: 11,
+                    kind: OracleConsumptionIssueKind::WrongCycle { entry_cycle: 9 },
+                },
+                OracleConsumptionIssue {
+                    queue: OracleQueue::MemoryReads,
+                    cycle: 13,
+                    kind: OracleConsumptionIssueKind::WrongCycle { entry_cycle: 12 },
+                },
+                OracleConsumptionIssue {
+                    queue: OracleQueue::MemoryReads,
+                    cycle: 13,
+                    kind: OracleConsumptionIssueKind::Exhausted,
+                },
+                OracleConsumptionIssue {
+                    queue: OracleQueue::RollbackQueueHeads,
+                    cycle: 13,
+                    kind: OracleConsumptionIssueKind::Leftover { entry_cycle: 11 },
+                },
+            ]
+        );
+        assert_eq!(
+            issues[3].to_string(),
+            "MemoryReads queue is exhausted at cycle 13"
+        );
+    }
+
+    #[test]
+    fn test_real_oracle_is_consumed_correctly() {
+        let instances = create_vm_instances(HEAP_READ_ASM);
+        assert!(instances.len() > 1);
+        for instance in instances.into_iter() {
+            let cycles_range = instance.cycles_range.clone();
+            let issues = validate(instance);
+            assert!(
+                issues.is_empty(),
+                "instance for cycles {:?} has issues {:?}",
+                cycles_range,
+                issues
+            );
+        }
+    }
+
+    #[test]
+    fn test_missing_and_extra_oracle_entries_are_reported() {
+        let mut instances = create_vm_instances(HEAP_READ_ASM);
+
+        // the only read of the cycle, so no other read can take its place. The value is zero,
+        // so the placeholder keeps the circuit on the same path
+        let (instance_idx, entry_idx) = instances
+            .iter()
+            .enumerate()
+            .find_map(|(instance_idx, instance)| {
+                let reads = &instance.witness_oracle.memory_read_witness;
+                reads
+                    .iter()
+                    .position(|(cycle, query)| {
+                        matches!(
+                            query.location.memory_type,
+                            MemoryType::Heap | MemoryType::AuxHeap
+                        ) && query.value.is_zero()
+                            && reads.iter().filter(|el| el.0 == *cycle).count() == 1
+                    })
+                    .map(|entry_idx| (instance_idx, entry_idx))
+            })
+            .expect("heap read must be in the witness");
+
+        let mut instance = instances.swap_remove(instance_idx);
+        let reads = &mut instance.witness_oracle.memory_read_witness;
+        let (removed_cycle, _) = reads.remove(entry_idx).unwrap();
+        let next_entry = reads.get(entry_idx).map(|el| el.0);
+        let extra_cycle = instance.cycles_range.end + 5;
+        reads.push_back((extra_cycle, memory_query()));
+
+        let issues = validate(instance);
+        let missing_kind = match next_entry {
+            Some(entry_cycle) => OracleConsumptionIssueKind::WrongCycle { entry_cycle },
+            None => OracleConsumptionIssueKind::Exhausted,
+        };
+        assert_eq!(issues.len(), 2, "unexpected issues {:?}", issues);
+        assert_eq!(
+            issues[0],
+            OracleConsumptionIssue {
+                queue: OracleQueue::MemoryReads,
+                cycle: removed_cycle,
+                kind: missing_kind,
+            }
+        );
+        assert_eq!(issues[1].queue, OracleQueue::MemoryReads);
+        assert_eq!(
+            issues[1].kind,
+            OracleConsumptionIssueKind::Leftover {
+                entry_cycle: extra_cycle
+            }
+        );
+    }
+}
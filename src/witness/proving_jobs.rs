@@ -0,0 +1,820 @@
+use std::collections::{HashMap, HashSet};
+
+use super::block_header::BlockContentHeader;
+use super::block_proof_bundle::*;
+use super::recursion_plan::*;
+use super::recursive_aggregation::ProofsVerification;
+use super::*;
+use crate::abstract_zksync_circuit::concrete_circuits::{
+    ZkSyncCircuit, ZkSyncProof, ZkSyncVerificationKey,
+};
+use crate::encodings::recursion_request::RecursionQueueSimulator;
+use crate::witness::full_block_artifact::{BlockBasicCircuits, BlockBasicCircuitsPublicInputs};
+use crate::witness::oracle::VmWitnessOracle;
+use crate::witness::utils::take_queue_state_from_simulator;
+use derivative::*;
+use sync_vm::recursion::leaf_aggregation::LeafAggregationOutputDataWitness;
+use sync_vm::recursion::node_aggregation::NodeAggregationOutputDataWitness;
+use sync_vm::recursion::recursion_tree::NUM_LIMBS;
+use sync_vm::scheduler::{BlockApplicationWitness, CircuitType, SchedulerCircuitInstanceWitness};
+use sync_vm::testing::{Bn256, Fr};
+
+/// Stable identifier of a proving job within a block
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ProvingJobId {
+    // index in the flattened set of basic circuits
+    Basic(usize),
+    // index in the leaf layer
+    Leaf(usize),
+    // depth and index in the layer
+    Node(u32, usize),
+    Scheduler,
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug)]
+pub struct ProvingJob {
+    pub id: ProvingJobId,
+    pub circuit_type: u8,
+    // jobs which proofs are aggregated by this one
+    pub dependencies: Vec<ProvingJobId>,
+    // known in advance for basic circuits, and becomes known for aggregation circuits
+    // once the previous layer is proven and the circuit is produced
+    pub expected_public_input: Option<Fr>,
+}
+
+/// Jobs graph for a single block, in topological order
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug)]
+pub struct ProvingJobGraph {
+    pub plan: RecursionPlan,
+    pub jobs: Vec<ProvingJob>,
+}
+
+impl ProvingJobGraph {
+    pub fn new(
+        plan: RecursionPlan,
+        basic_circuits_types: Vec<u8>,
+        basic_circuits_inputs: BlockBasicCircuitsPublicInputs<Bn256>,
+    ) -> Self {
+        let flattened_inputs = basic_circuits_inputs.into_flattened_set();
+        assert_eq!(basic_circuits_types.len(), plan.num_basic_circuits);
+        assert_eq!(flattened_inputs.len(), plan.num_basic_circuits);
+
+        let mut jobs = vec![];
+
+        for (idx, (circuit_type, public_input)) in basic_circuits_types
+            .into_iter()
+            .zip(flattened_inputs.into_iter())
+            .enumerate()
+        {
+            jobs.push(ProvingJob {
+                id: ProvingJobId::Basic(idx),
+                circuit_type,
+                dependencies: vec![],
+                expected_public_input: Some(public_input),
+            });
+        }
+
+        let mut previous_layer: Vec<_> = (0..plan.num_basic_circuits)
+            .map(|idx| ProvingJobId::Basic(idx))
+            .collect();
+
+        let leaf_ids: Vec<_> = (0..plan.leaf_layer.num_circuits)
+            .map(|idx| ProvingJobId::Leaf(idx))
+            .collect();
+        for (id, dependencies) in leaf_ids
+            .iter()
+            .zip(previous_layer.chunks(plan.splitting_factor_for_leafs))
+        {
+            jobs.push(ProvingJob {
+                id: *id,
+                circuit_type: CircuitType::Leaf as u8,
+                dependencies: dependencies.to_vec(),
+                expected_public_input: None,
+            });
+        }
+        previous_layer = leaf_ids;
+
+        for (depth, layer) in plan.node_layers.iter().enumerate() {
+            let node_ids: Vec<_> = (0..layer.num_circuits)
+                .map(|idx| ProvingJobId::Node(depth as u32, idx))
+                .collect();
+            for (id, dependencies) in node_ids
+                .iter()
+                .zip(previous_layer.chunks(plan.splitting_factor_for_nodes))
+            {
+                jobs.push(ProvingJob {
+                    id: *id,
+                    circuit_type: CircuitType::IntermidiateNode as u8,
+                    dependencies: dependencies.to_vec(),
+                    expected_public_input: None,
+                });
+            }
+            previous_layer = node_ids;
+        }
+
+        assert_eq!(previous_layer.len(), 1);
+
+        jobs.push(ProvingJob {
+            id: ProvingJobId::Scheduler,
+            circuit_type: CircuitType::Scheduler as u8,
+            dependencies: previous_layer,
+            expected_public_input: None,
+        });
+
+        assert_eq!(jobs.len(), plan.total_num_circuits());
+
+        Self { plan, jobs }
+    }
+
+    pub fn job(&self, id: ProvingJobId) -> &ProvingJob {
+        self.jobs
+            .iter()
+            .find(|el| el.id == id)
+            .unwrap_or_else(|| panic!("unknown job {:?}", id))
+    }
+
+    fn job_mut(&mut self, id: ProvingJobId) -> &mut ProvingJob {
+        self.jobs
+            .iter_mut()
+            .find(|el| el.id == id)
+            .unwrap_or_else(|| panic!("unknown job {:?}", id))
+    }
+}
+
+#[derive(Derivative)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq)]
+enum ExecutorStage {
+    Basic,
+    Leafs,
+    Nodes(u32),
+    Scheduler,
+    Done,
+}
+
+#[derive(Derivative)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub enum SubmitProofError {
+    UnknownJob(ProvingJobId),
+    // circuit of the job was not returned by `take_ready_circuits` yet
+    NotProduced(ProvingJobId),
+    AlreadySubmitted(ProvingJobId),
+    WrongCircuitType { expected: u8, received: u8 },
+    WrongNumberOfInputs(usize),
+    MismatchingInput { expected: Fr, received: Fr },
+    InvalidProof,
+}
+
+/// Drives the recursion for a single block: accepts proofs by job ID and produces
+/// circuits of the next layer once the layer it depends on is fully proven.
+/// Proofs are verified once, when those are submitted, unless `verification` says otherwise
+pub struct ProvingJobsExecutor {
+    graph: ProvingJobGraph,
+    stage: ExecutorStage,
+    verification: ProofsVerification,
+    emitted: HashSet<ProvingJobId>,
+    basic_block_circuits: Option<BlockBasicCircuits<Bn256>>,
+    basic_block_circuits_inputs: BlockBasicCircuitsPublicInputs<Bn256>,
+    incomplete_scheduler_witness: Option<SchedulerCircuitInstanceWitness<Bn256>>,
+    verification_keys: Vec<ZkSyncVerificationKey<Bn256>>,
+    committments: VerificationKeysCommittments,
+    padding_aggregations: Vec<(
+        [Fr; NUM_LIMBS],
+        [Fr; NUM_LIMBS],
+        [Fr; NUM_LIMBS],
+        [Fr; NUM_LIMBS],
+    )>,
+    previous_aux_hash: [u8; 32],
+    previous_meta_hash: [u8; 32],
+    // to compute the expected public input of the scheduler
+    previous_block_formal_hash: [u8; 32],
+    block_header: BlockContentHeader,
+    proofs: HashMap<ProvingJobId, ZkSyncProof<Bn256>>,
+    previous_sequence: Vec<RecursionQueueSimulator<Bn256>>,
+    leaf_aggregation_outputs: Vec<LeafAggregationOutputDataWitness<Bn256>>,
+    // per depth
+    node_aggregation_outputs: Vec<Vec<NodeAggregationOutputDataWitness<Bn256>>>,
+    block_application_witness: Option<BlockApplicationWitness<Bn256>>,
+}
+
+impl ProvingJobsExecutor {
+    pub fn new(
+        plan: RecursionPlan,
+        basic_block_circuits: BlockBasicCircuits<Bn256>,
+        basic_block_circuits_inputs: BlockBasicCircuitsPublicInputs<Bn256>,
+        incomplete_scheduler_witness: SchedulerCircuitInstanceWitness<Bn256>,
+        verification_keys: Vec<ZkSyncVerificationKey<Bn256>>, // one per circuit type
+        padding_aggregations: Vec<(
+            [Fr; NUM_LIMBS],
+            [Fr; NUM_LIMBS],
+            [Fr; NUM_LIMBS],
+            [Fr; NUM_LIMBS],
+        )>,
+        previous_aux_hash: [u8; 32],
+        previous_meta_hash: [u8; 32],
+        previous_block_formal_hash: [u8; 32],
+        block_header: BlockContentHeader,
+        verification: ProofsVerification,
+    ) -> Self {
+        let basic_circuits_types: Vec<_> = basic_block_circuits
+            .clone()
+            .into_flattened_set()
+            .iter()
+            .map(|el| el.numeric_circuit_type())
+            .collect();
+        let graph = ProvingJobGraph::new(
+            plan,
+            basic_circuits_types,
+            basic_block_circuits_inputs.clone(),
+        );
+        for job in graph.jobs.iter() {
+            assert!(
+                verification_keys
+                    .iter()
+                    .any(|el| el.numeric_circuit_type() == job.circuit_type),
+                "no verification key for circuit type {}",
+                job.circuit_type
+            );
+        }
+        let committments = VerificationKeysCommittments::from_verification_keys(&verification_keys);
+
+        Self {
+            graph,
+            stage: ExecutorStage::Basic,
+            verification,
+            emitted: HashSet::new(),
+            basic_block_circuits: Some(basic_block_circuits),
+            basic_block_circuits_inputs,
+            incomplete_scheduler_witness: Some(incomplete_scheduler_witness),
+            verification_keys,
+            committments,
+            padding_aggregations,
+            previous_aux_hash,
+            previous_meta_hash,
+            previous_block_formal_hash,
+            block_header,
+            proofs: HashMap::new(),
+            previous_sequence: vec![],
+            leaf_aggregation_outputs: vec![],
+            node_aggregation_outputs: vec![],
+            block_application_witness: None,
+        }
+    }
+
+    pub fn graph(&self) -> &ProvingJobGraph {
+        &self.graph
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.stage == ExecutorStage::Done
+    }
+
+    pub fn proof(&self, id: ProvingJobId) -> Option<&ZkSyncProof<Bn256>> {
+        self.proofs.get(&id)
+    }
+
+    // available once the scheduler circuit is produced
+    pub fn block_application_witness(&self) -> Option<&BlockApplicationWitness<Bn256>> {
+        self.block_application_witness.as_ref()
+    }
+
+    // available once the scheduler is proven
+    pub fn into_block_proof_bundle(self) -> Option<BlockProofBundle> {
+        if !self.is_complete() {
+            return None;
+        }
+
+        let plan = self.graph.plan;
+        let mut proofs = self.proofs;
+        let mut take_layer = |ids: Vec<ProvingJobId>| -> Vec<IndexedProof> {
+            ids.into_iter()
+                .enumerate()
+                .map(|(index, id)| {
+                    let proof = proofs.remove(&id).unwrap();
+                    IndexedProof {
+                        circuit_type: proof.numeric_circuit_type(),
+                        index,
+                        proof,
+                    }
+                })
+                .collect()
+        };
+
+        let basic_circuits_proofs = take_layer(
+            (0..plan.num_basic_circuits)
+                .map(|idx| ProvingJobId::Basic(idx))
+                .collect(),
+        );
+        let leaf_aggregation_proofs = take_layer(
+            (0..plan.leaf_layer.num_circuits)
+                .map(|idx| ProvingJobId::Leaf(idx))
+                .collect(),
+        );
+        let node_aggregation_proofs = plan
+            .node_layers
+            .iter()
+            .enumerate()
+            .map(|(depth, layer)| {
+                take_layer(
+                    (0..layer.num_circuits)
+                        .map(|idx| ProvingJobId::Node(depth as u32, idx))
+                        .collect(),
+                )
+            })
+            .collect();
+        let scheduler_proof = take_layer(vec![ProvingJobId::Scheduler]).pop().unwrap();
+
+        let aggregation_result =
+            aggregation_result_into_bytes(&self.node_aggregation_outputs.last().unwrap()[0]);
+
+        Some(BlockProofBundle {
+            splitting_factor_for_leafs: plan.splitting_factor_for_leafs,
+            splitting_factor_for_nodes: plan.splitting_factor_for_nodes,
+            basic_circuits_inputs: self.basic_block_circuits_inputs,
+            basic_circuits_proofs,
+            leaf_aggregation_proofs,
+            leaf_aggregation_outputs: self.leaf_aggregation_outputs,
+            node_aggregation_proofs,
+            node_aggregation_outputs: self.node_aggregation_outputs,
+            scheduler_proof,
+            aggregation_result,
+            previous_block_formal_hash: self.previous_block_formal_hash,
+            block_header: self.block_header,
+        })
+    }
+
+    pub fn submit_proof(
+        &mut self,
+        id: ProvingJobId,
+        proof: ZkSyncProof<Bn256>,
+    ) -> Result<(), SubmitProofError> {
+        let job = match self.graph.jobs.iter().find(|el| el.id == id) {
+            Some(job) => job,
+            None => return Err(SubmitProofError::UnknownJob(id)),
+        };
+        if !self.emitted.contains(&id) {
+            return Err(SubmitProofError::NotProduced(id));
+        }
+        if self.proofs.contains_key(&id) {
+            return Err(SubmitProofError::AlreadySubmitted(id));
+        }
+        if job.circuit_type != proof.numeric_circuit_type() {
+            return Err(SubmitProofError::WrongCircuitType {
+                expected: job.circuit_type,
+                received: proof.numeric_circuit_type(),
+            });
+        }
+        let inputs = &proof.as_proof().inputs;
+        if inputs.len() != 1 {
+            return Err(SubmitProofError::WrongNumberOfInputs(inputs.len()));
+        }
+        if let Some(expected_public_input) = job.expected_public_input {
+            if inputs[0] != expected_public_input {
+                return Err(SubmitProofError::MismatchingInput {
+                    expected: expected_public_input,
+                    received: inputs[0],
+                });
+            }
+        }
+
+        if self.verification == ProofsVerification::Verify {
+            let vk = find_verification_key(&self.verification_keys, job.circuit_type);
+            if !vk.verify_proof(&proof) {
+                return Err(SubmitProofError::InvalidProof);
+            }
+        }
+
+        self.proofs.insert(id, proof);
+
+        if id == ProvingJobId::Scheduler {
+            self.stage = ExecutorStage::Done;
+        }
+
+        Ok(())
+    }
+
+    // proofs are verified on submission, so aggregation doesn't need to do it again
+    fn aggregation_verification(&self) -> ProofsVerification {
+        match self.verification {
+            ProofsVerification::Verify => ProofsVerification::AlreadyVerified,
+            verification => verification,
+        }
+    }
+
+    fn all_proven(&self, ids: impl Iterator<Item = ProvingJobId>) -> bool {
+        let mut all_proven = true;
+        for id in ids {
+            all_proven &= self.proofs.contains_key(&id);
+        }
+
+        all_proven
+    }
+
+    fn take_proofs(&self, ids: impl Iterator<Item = ProvingJobId>) -> Vec<ZkSyncProof<Bn256>> {
+        ids.map(|id| self.proofs.get(&id).unwrap().clone())
+            .collect()
+    }
+
+    // returns circuits which dependencies are satisfied and that were not returned before
+    pub fn take_ready_circuits(
+        &mut self,
+    ) -> Vec<(ProvingJobId, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>)> {
+        let plan = self.graph.plan.clone();
+
+        match self.stage {
+            ExecutorStage::Basic => {
+                if self.emitted.is_empty() {
+                    let circuits = self
+                        .basic_block_circuits
+                        .as_ref()
+                        .unwrap()
+                        .clone()
+                        .into_flattened_set();
+
+                    let result: Vec<_> = circuits
+                        .into_iter()
+                        .enumerate()
+                        .map(|(idx, el)| (ProvingJobId::Basic(idx), el))
+                        .collect();
+                    self.emitted.extend(result.iter().map(|el| el.0));
+
+                    return result;
+                }
+
+                if !self
+                    .all_proven((0..plan.num_basic_circuits).map(|idx| ProvingJobId::Basic(idx)))
+                {
+                    return vec![];
+                }
+
+                let proofs: Vec<_> = self
+                    .take_proofs((0..plan.num_basic_circuits).map(|idx| ProvingJobId::Basic(idx)));
+                let verification_keys: Vec<_> = proofs
+                    .iter()
+                    .map(|el| {
+                        find_verification_key(&self.verification_keys, el.numeric_circuit_type())
+                            .clone()
+                            .into_verification_key()
+                    })
+                    .collect();
+                let proofs: Vec<_> = proofs.into_iter().map(|el| el.into_proof()).collect();
+
+                let (subqueues, aggregation_outputs, circuits) = prepare_leaf_aggregations_for_plan(
+                    &plan,
+                    self.basic_block_circuits.take().unwrap(),
+                    self.basic_block_circuits_inputs.clone(),
+                    proofs,
+                    verification_keys,
+                    self.committments.leaf_vks_committments_set.clone(),
+                    self.committments.leaf_vks_committment,
+                    self.committments.g2_points,
+                    self.aggregation_verification(),
+                );
+
+                let mut result = vec![];
+                for (idx, ((circuit, subqueue), output)) in circuits
+                    .into_iter()
+                    .zip(subqueues.iter())
+                    .zip(aggregation_outputs.iter())
+                    .enumerate()
+                {
+                    let id = ProvingJobId::Leaf(idx);
+                    self.graph.job_mut(id).expected_public_input =
+                        Some(leaf_aggregation_public_input(
+                            take_queue_state_from_simulator(subqueue),
+                            self.committments.leaf_vks_committment,
+                            output.clone(),
+                        ));
+                    self.emitted.insert(id);
+                    result.push((id, circuit));
+                }
+
+                self.previous_sequence = subqueues;
+                self.leaf_aggregation_outputs = aggregation_outputs;
+                self.stage = ExecutorStage::Leafs;
+
+                result
+            }
+            ExecutorStage::Leafs | ExecutorStage::Nodes(..) => {
+                let (depth, previous_ids): (u32, Vec<_>) = match self.stage {
+                    ExecutorStage::Leafs => (
+                        0,
+                        (0..plan.leaf_layer.num_circuits)
+                            .map(|idx| ProvingJobId::Leaf(idx))
+                            .collect(),
+                    ),
+                    ExecutorStage::Nodes(depth) => (
+                        depth + 1,
+                        (0..plan.node_layers[depth as usize].num_circuits)
+                            .map(|idx| ProvingJobId::Node(depth, idx))
+                            .collect(),
+                    ),
+                    _ => unreachable!(),
+                };
+
+                if !self.all_proven(previous_ids.iter().copied()) {
+                    return vec![];
+                }
+
+                let previous_level_proofs: Vec<_> = self
+                    .take_proofs(previous_ids.iter().copied())
+                    .into_iter()
+                    .map(|el| el.into_proof())
+                    .collect();
+
+                if depth as usize == plan.node_layers.len() {
+                    // final node is proven, so we can create a scheduler
+                    assert_eq!(previous_level_proofs.len(), 1);
+                    let final_node_aggregation = self.node_aggregation_outputs.last().unwrap();
+                    assert_eq!(final_node_aggregation.len(), 1);
+                    let final_node_aggregation = final_node_aggregation[0].clone();
+
+                    let node_vk = find_verification_key(
+                        &self.verification_keys,
+                        CircuitType::IntermidiateNode as u8,
+                    )
+                    .clone()
+                    .into_verification_key();
+
+                    let (circuit, block_application_witness) = prepare_scheduler_circuit_for_plan(
+                        &plan,
+                        self.incomplete_scheduler_witness.take().unwrap(),
+                        previous_level_proofs.into_iter().next().unwrap(),
+                        node_vk,
+                        final_node_aggregation.clone(),
+                        self.committments.leaf_vks_committment,
+                        self.committments.node_aggregation_vk_committment,
+                        self.committments.leaf_aggregation_vk_committment,
+                        self.previous_aux_hash,
+                        self.previous_meta_hash,
+                        self.committments.g2_points,
+                        self.aggregation_verification(),
+                    );
+
+                    let expected_public_input = scheduler_public_input(
+                        self.previous_block_formal_hash,
+                        self.block_header,
+                        self.committments.leaf_vks_committment,
+                        self.committments.node_aggregation_vk_committment,
+                        self.committments.leaf_aggregation_vk_committment,
+                        aggregation_result_into_bytes(&final_node_aggregation),
+                    );
+                    self.graph
+                        .job_mut(ProvingJobId::Scheduler)
+                        .expected_public_input = Some(expected_public_input);
+                    self.block_application_witness = Some(block_application_witness);
+                    self.stage = ExecutorStage::Scheduler;
+                    self.emitted.insert(ProvingJobId::Scheduler);
+
+                    return vec![(ProvingJobId::Scheduler, circuit)];
+                }
+
+                let previous_level_circuit_type = if depth == 0 {
+                    CircuitType::Leaf as u8
+                } else {
+                    CircuitType::IntermidiateNode as u8
+                };
+                let previous_level_vk =
+                    find_verification_key(&self.verification_keys, previous_level_circuit_type)
+                        .clone()
+                        .into_verification_key();
+
+                // outputs of all the layers are kept for the block proof bundle
+                let (previous_level_leafs_aggregations, previous_level_node_aggregations) =
+                    if depth == 0 {
+                        (self.leaf_aggregation_outputs.clone(), vec![])
+                    } else {
+                        (vec![], self.node_aggregation_outputs.last().unwrap().clone())
+                    };
+                let previous_sequence = std::mem::replace(&mut self.previous_sequence, vec![]);
+
+                let (merged, aggregation_outputs, circuits) = prepare_node_aggregations_for_plan(
+                    &plan,
+                    depth,
+                    previous_level_proofs,
+                    previous_level_vk,
+                    previous_level_leafs_aggregations,
+                    previous_level_node_aggregations,
+                    previous_sequence,
+                    self.padding_aggregations.clone(),
+                    self.committments.leaf_vks_committment,
+                    self.committments.node_aggregation_vk_committment,
+                    self.committments.leaf_aggregation_vk_committment,
+                    self.committments.g2_points,
+                    self.aggregation_verification(),
+                );
+
+                let mut result = vec![];
+                for (idx, ((circuit, subqueue), output)) in circuits
+                    .into_iter()
+                    .zip(merged.iter())
+                    .zip(aggregation_outputs.iter())
+                    .enumerate()
+                {
+                    let id = ProvingJobId::Node(depth, idx);
+                    self.graph.job_mut(id).expected_public_input =
+                        Some(node_aggregation_public_input(
+                            take_queue_state_from_simulator(subqueue),
+                            self.committments.leaf_vks_committment,
+                            self.committments.node_aggregation_vk_committment,
+                            self.committments.leaf_aggregation_vk_committment,
+                            output.clone(),
+                        ));
+                    self.emitted.insert(id);
+                    result.push((id, circuit));
+                }
+
+                self.previous_sequence = merged;
+                self.node_aggregation_outputs.push(aggregation_outputs);
+                self.stage = ExecutorStage::Nodes(depth);
+
+                result
+            }
+            ExecutorStage::Scheduler | ExecutorStage::Done => vec![],
+        }
+    }
+}
+
+#[test]
+fn test_proving_job_graph_shape() {
+    use crate::ff::Field;
+
+    let inputs = BlockBasicCircuitsPublicInputs::<Bn256> {
+        main_vm_circuits: vec![Fr::zero(); 5],
+        code_decommittments_sorter_circuits: vec![Fr::zero()],
+        code_decommitter_circuits: vec![Fr::zero()],
+        log_demux_circuits: vec![Fr::zero()],
+        keccak_precompile_circuits: vec![Fr::zero()],
+        sha256_precompile_circuits: vec![Fr::zero()],
+        ecrecover_precompile_circuits: vec![Fr::zero()],
+        ram_permutation_circuits: vec![Fr::zero(); 2],
+        storage_sorter_circuits: vec![Fr::zero()],
+        storage_application_circuits: vec![Fr::zero()],
+        initial_writes_hasher_circuit: Fr::zero(),
+        repeated_writes_hasher_circuit: Fr::zero(),
+        events_sorter_circuits: vec![Fr::zero()],
+        l1_messages_sorter_circuits: vec![Fr::zero()],
+        l1_messages_pubdata_hasher_circuit: Fr::zero(),
+        l1_messages_merklizer_circuit: Fr::zero(),
+    };
+    let num_basic_circuits = inputs.clone().into_flattened_set().len();
+    assert_eq!(num_basic_circuits, 21);
+
+    let plan = RecursionPlan::new(num_basic_circuits, 4, 2, 4 * 2 * 2 * 2);
+    let graph = ProvingJobGraph::new(
+        plan,
+        vec![CircuitType::VM as u8; num_basic_circuits],
+        inputs,
+    );
+
+    assert_eq!(graph.job(ProvingJobId::Leaf(4)).dependencies.len(), 4);
+    assert_eq!(
+        graph.job(ProvingJobId::Node(0, 2)).dependencies,
+        vec![ProvingJobId::Leaf(4), ProvingJobId::Leaf(5)]
+    );
+    assert_eq!(
+        graph.job(ProvingJobId::Scheduler).dependencies,
+        vec![ProvingJobId::Node(2, 0)]
+    );
+
+    let serialized = serde_json::to_string(&graph).unwrap();
+    let deserialized: ProvingJobGraph = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(deserialized.jobs.len(), graph.jobs.len());
+}
+
+// executor for a small block, to be proven with `MockProver`
+#[cfg(test)]
+pub(crate) fn create_test_block_executor() -> (ProvingJobsExecutor, super::mock_prover::MockProver)
+{
+    use super::block_header::{BlockPassthroughData, PerShardState};
+    use super::mock_prover::MockProver;
+    use super::recursive_aggregation::mock_aggregations;
+    use zkevm_assembly::Assembly;
+
+    let asm = r#"
+        .text
+        .file	"Test_26"
+        .rodata.cst32
+        .p2align	5
+        .text
+        .globl	__entry
+    __entry:
+    .main:
+        add 10000, r0, r1
+        add 1000, r0, r10
+        sstore r1, r10
+        sload r1, r2
+        event.first r1, r10
+        to_l1.first r0, r1
+        ret.ok r0
+    "#;
+
+    let mut assembly = Assembly::try_from(asm.to_owned()).unwrap();
+    let bytecode = assembly.compile_to_bytecode().unwrap();
+    let (basic_block_circuits, basic_block_circuits_inputs, scheduler_witness, block_header) =
+        crate::tests::run_manually::generate_block_for_extended_state(bytecode, vec![], 50, false);
+
+    // previous block has zero meta and auxilary output hashes
+    let previous_block_data = BlockPassthroughData {
+        per_shard_states: std::array::from_fn(|idx| {
+            let state = &scheduler_witness.prev_block_data.per_shard_states[idx];
+            PerShardState {
+                enumeration_counter: state.enumeration_counter,
+                state_root: state.state_root.inner,
+            }
+        }),
+    };
+    let previous_block_formal_hash = BlockContentHeader::formal_block_hash_from_partial_hashes(
+        previous_block_data.hash(),
+        [0u8; 32],
+        [0u8; 32],
+    );
+
+    let num_basic_circuits = basic_block_circuits_inputs
+        .clone()
+        .into_flattened_set()
+        .len();
+    let plan = RecursionPlan::new(num_basic_circuits, 4, 2, 4 * (1 << 10));
+    let padding_aggregations = mock_aggregations(plan.splitting_factor_for_nodes);
+    let prover = MockProver::default();
+
+    let executor = ProvingJobsExecutor::new(
+        plan,
+        basic_block_circuits,
+        basic_block_circuits_inputs,
+        scheduler_witness,
+        prover.create_verification_keys(),
+        padding_aggregations,
+        [0u8; 32],
+        [0u8; 32],
+        previous_block_formal_hash,
+        block_header,
+        ProofsVerification::Mock,
+    );
+
+    (executor, prover)
+}
+
+#[test]
+fn test_proving_jobs_executor_with_mock_prover() {
+    use crate::ff::Field;
+
+    let (mut executor, prover) = create_test_block_executor();
+    let first_job = executor.graph().job(ProvingJobId::Basic(0)).clone();
+    let expected_public_input = first_job.expected_public_input.unwrap();
+
+    let proof = prover.create_proof(first_job.circuit_type, expected_public_input);
+    assert_eq!(
+        executor.submit_proof(ProvingJobId::Basic(0), proof.clone()),
+        Err(SubmitProofError::NotProduced(ProvingJobId::Basic(0)))
+    );
+    assert_eq!(
+        executor.submit_proof(ProvingJobId::Leaf(1000), proof.clone()),
+        Err(SubmitProofError::UnknownJob(ProvingJobId::Leaf(1000)))
+    );
+
+    let mut ready_circuits = executor.take_ready_circuits();
+    assert_eq!(
+        ready_circuits.len(),
+        executor.graph().plan.num_basic_circuits
+    );
+
+    let wrong_input_proof = prover.create_proof(first_job.circuit_type, Fr::zero());
+    assert_eq!(
+        executor.submit_proof(ProvingJobId::Basic(0), wrong_input_proof),
+        Err(SubmitProofError::MismatchingInput {
+            expected: expected_public_input,
+            received: Fr::zero(),
+        })
+    );
+    let wrong_type_proof = prover.create_proof(CircuitType::Scheduler as u8, expected_public_input);
+    assert_eq!(
+        executor.submit_proof(ProvingJobId::Basic(0), wrong_type_proof),
+        Err(SubmitProofError::WrongCircuitType {
+            expected: first_job.circuit_type,
+            received: CircuitType::Scheduler as u8,
+        })
+    );
+
+    // every layer is produced once the previous one is proven, up to the scheduler
+    let mut num_proven_jobs = 0;
+    while !executor.is_complete() {
+        assert!(!ready_circuits.is_empty());
+        for (id, circuit) in ready_circuits.into_iter() {
+            executor.submit_proof(id, prover.prove(circuit)).unwrap();
+            num_proven_jobs += 1;
+        }
+        ready_circuits = executor.take_ready_circuits();
+    }
+
+    assert!(ready_circuits.is_empty());
+    assert_eq!(num_proven_jobs, executor.graph().plan.total_num_circuits());
+    assert!(executor.proof(ProvingJobId::Scheduler).is_some());
+    assert!(executor.block_application_witness().is_some());
+    assert_eq!(
+        executor.submit_proof(ProvingJobId::Basic(0), proof),
+        Err(SubmitProofError::AlreadySubmitted(ProvingJobId::Basic(0)))
+    );
+}
@@ -0,0 +1,311 @@
+use super::recursive_aggregation::*;
+use super::*;
+use crate::abstract_zksync_circuit::concrete_circuits::ZkSyncCircuit;
+use crate::bellman::plonk::better_better_cs::proof::Proof;
+use crate::bellman::plonk::better_better_cs::setup::VerificationKey;
+use crate::encodings::recursion_request::RecursionQueueSimulator;
+use crate::witness::full_block_artifact::{BlockBasicCircuits, BlockBasicCircuitsPublicInputs};
+use crate::witness::oracle::VmWitnessOracle;
+use derivative::*;
+use sync_vm::recursion::leaf_aggregation::LeafAggregationOutputDataWitness;
+use sync_vm::recursion::node_aggregation::NodeAggregationOutputDataWitness;
+use sync_vm::recursion::recursion_tree::NUM_LIMBS;
+use sync_vm::scheduler::{BlockApplicationWitness, SchedulerCircuitInstanceWitness};
+use sync_vm::testing::{Bn256, Fr};
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RecursionLayerPlan {
+    // number of circuits at this layer
+    pub num_circuits: usize,
+    // number of proofs from the previous layer that are aggregated
+    pub num_aggregated_proofs: usize,
+    // number of padding proofs used to fill the last circuit of the layer
+    pub num_padding_proofs: usize,
+}
+
+// shape of the recursion tree for a block: basic circuits are split into leaf aggregations,
+// then node aggregations are applied until a single proof remains, and it's verified by the scheduler
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RecursionPlan {
+    pub num_basic_circuits: usize,
+    pub splitting_factor_for_leafs: usize,
+    pub splitting_factor_for_nodes: usize,
+    // is a maximum number of circuits to scheduler. Should be in a form of splitting_per_leafs * splitting_per_node^K
+    pub scheduler_upper_bound: u32,
+    // K from the expression above
+    pub max_node_depth: u32,
+    pub leaf_layer: RecursionLayerPlan,
+    // node layers, starting from depth 0
+    pub node_layers: Vec<RecursionLayerPlan>,
+}
+
+fn layer_for(num_aggregated_proofs: usize, splitting_factor: usize) -> RecursionLayerPlan {
+    let num_circuits = (num_aggregated_proofs + splitting_factor - 1) / splitting_factor;
+
+    RecursionLayerPlan {
+        num_circuits,
+        num_aggregated_proofs,
+        num_padding_proofs: num_circuits * splitting_factor - num_aggregated_proofs,
+    }
+}
+
+impl RecursionPlan {
+    pub fn new(
+        num_basic_circuits: usize,
+        splitting_factor_for_leafs: usize,
+        splitting_factor_for_nodes: usize,
+        scheduler_upper_bound: u32,
+    ) -> Self {
+        assert!(
+            num_basic_circuits > 0,
+            "there must be at least one basic circuit"
+        );
+        assert!(splitting_factor_for_leafs > 0);
+        assert!(
+            splitting_factor_for_nodes > 1,
+            "node aggregation must reduce the number of proofs"
+        );
+
+        // upper bound must be in a form of splitting_per_leafs * splitting_per_node^K
+        let scheduler_upper_bound_as_usize = scheduler_upper_bound as usize;
+        assert!(
+            scheduler_upper_bound_as_usize % splitting_factor_for_leafs == 0,
+            "scheduler upper bound {} is not divisible by leaf splitting factor {}",
+            scheduler_upper_bound,
+            splitting_factor_for_leafs
+        );
+        let mut max_leafs = scheduler_upper_bound_as_usize / splitting_factor_for_leafs;
+        let mut max_node_depth = 0u32;
+        while max_leafs > 1 {
+            assert!(
+                max_leafs % splitting_factor_for_nodes == 0,
+                "scheduler upper bound {} is not in a form of {} * {}^K",
+                scheduler_upper_bound,
+                splitting_factor_for_leafs,
+                splitting_factor_for_nodes
+            );
+            max_leafs /= splitting_factor_for_nodes;
+            max_node_depth += 1;
+        }
+        assert_eq!(max_leafs, 1);
+
+        assert!(
+            num_basic_circuits <= scheduler_upper_bound_as_usize,
+            "block has {} basic circuits, but scheduler can only take {}",
+            num_basic_circuits,
+            scheduler_upper_bound
+        );
+
+        let leaf_layer = layer_for(num_basic_circuits, splitting_factor_for_leafs);
+
+        // there is always at least one node layer, even over a single leaf
+        let mut node_layers = vec![];
+        let mut num_previous_level_proofs = leaf_layer.num_circuits;
+        loop {
+            let layer = layer_for(num_previous_level_proofs, splitting_factor_for_nodes);
+            num_previous_level_proofs = layer.num_circuits;
+            node_layers.push(layer);
+
+            if num_previous_level_proofs == 1 {
+                break;
+            }
+        }
+
+        assert!(node_layers.len() <= std::cmp::max(max_node_depth, 1) as usize);
+
+        Self {
+            num_basic_circuits,
+            splitting_factor_for_leafs,
+            splitting_factor_for_nodes,
+            scheduler_upper_bound,
+            max_node_depth,
+            leaf_layer,
+            node_layers,
+        }
+    }
+
+    pub fn num_leaf_circuits(&self) -> usize {
+        self.leaf_layer.num_circuits
+    }
+
+    pub fn num_node_layers(&self) -> usize {
+        self.node_layers.len()
+    }
+
+    pub fn num_node_circuits_at_depth(&self, depth: u32) -> usize {
+        self.node_layers[depth as usize].num_circuits
+    }
+
+    // total number of circuits to prove including the scheduler
+    pub fn total_num_circuits(&self) -> usize {
+        let num_nodes: usize = self.node_layers.iter().map(|el| el.num_circuits).sum();
+
+        self.num_basic_circuits + self.leaf_layer.num_circuits + num_nodes + 1
+    }
+
+    pub fn total_num_padding_proofs(&self) -> usize {
+        let num_nodes_paddings: usize = self
+            .node_layers
+            .iter()
+            .map(|el| el.num_padding_proofs)
+            .sum();
+
+        self.leaf_layer.num_padding_proofs + num_nodes_paddings
+    }
+}
+
+// same as `prepare_leaf_aggregations_with_verification`, but takes parameters from the plan
+// and checks the output shape
+pub fn prepare_leaf_aggregations_for_plan(
+    plan: &RecursionPlan,
+    basic_block_circuits: BlockBasicCircuits<Bn256>,
+    basic_block_circuits_inputs: BlockBasicCircuitsPublicInputs<Bn256>,
+    individual_proofs: Vec<Proof<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>>,
+    verification_keys: Vec<VerificationKey<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>>,
+    leaf_vks_committments_set: Vec<Fr>,
+    leaf_vks_committment: Fr,
+    g2_points: [bellman::pairing::bn256::G2Affine; 2],
+    verification: ProofsVerification,
+) -> (
+    Vec<RecursionQueueSimulator<Bn256>>,
+    Vec<LeafAggregationOutputDataWitness<Bn256>>,
+    Vec<ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>,
+) {
+    assert_eq!(individual_proofs.len(), plan.num_basic_circuits);
+
+    let (subqueues, aggregation_outputs, circuits) = prepare_leaf_aggregations_with_verification(
+        basic_block_circuits,
+        basic_block_circuits_inputs,
+        individual_proofs,
+        verification_keys,
+        plan.splitting_factor_for_leafs,
+        leaf_vks_committments_set,
+        leaf_vks_committment,
+        g2_points,
+        verification,
+    );
+
+    assert_eq!(circuits.len(), plan.leaf_layer.num_circuits);
+
+    (subqueues, aggregation_outputs, circuits)
+}
+
+// same as `prepare_node_aggregations_with_verification`, but takes parameters from the plan
+// and checks the output shape
+pub fn prepare_node_aggregations_for_plan(
+    plan: &RecursionPlan,
+    depth: u32,
+    previous_level_proofs: Vec<Proof<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>>,
+    previous_level_vk: VerificationKey<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>,
+    previous_level_leafs_aggregations: Vec<LeafAggregationOutputDataWitness<Bn256>>,
+    previous_level_node_aggregations: Vec<NodeAggregationOutputDataWitness<Bn256>>,
+    previous_sequence: Vec<RecursionQueueSimulator<Bn256>>,
+    padding_aggregations: Vec<(
+        [Fr; NUM_LIMBS],
+        [Fr; NUM_LIMBS],
+        [Fr; NUM_LIMBS],
+        [Fr; NUM_LIMBS],
+    )>,
+    leaf_vks_committment: Fr,
+    node_aggregation_vk_committment: Fr,
+    leaf_aggregation_vk_committment: Fr,
+    g2_points: [bellman::pairing::bn256::G2Affine; 2],
+    verification: ProofsVerification,
+) -> (
+    Vec<RecursionQueueSimulator<Bn256>>,
+    Vec<NodeAggregationOutputDataWitness<Bn256>>,
+    Vec<ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>,
+) {
+    let layer = plan.node_layers[depth as usize];
+    assert_eq!(previous_level_proofs.len(), layer.num_aggregated_proofs);
+
+    let (merged, aggregation_outputs, circuits) = prepare_node_aggregations_with_verification(
+        previous_level_proofs,
+        previous_level_vk,
+        depth == 0,
+        depth,
+        previous_level_leafs_aggregations,
+        previous_level_node_aggregations,
+        previous_sequence,
+        plan.splitting_factor_for_leafs,
+        plan.splitting_factor_for_nodes,
+        padding_aggregations,
+        leaf_vks_committment,
+        node_aggregation_vk_committment,
+        leaf_aggregation_vk_committment,
+        g2_points,
+        verification,
+    );
+
+    assert_eq!(circuits.len(), layer.num_circuits);
+
+    (merged, aggregation_outputs, circuits)
+}
+
+// same as `prepare_scheduler_circuit_with_verification`, but takes the upper bound from the plan
+pub fn prepare_scheduler_circuit_for_plan(
+    plan: &RecursionPlan,
+    incomplete_scheduler_witness: SchedulerCircuitInstanceWitness<Bn256>,
+    node_final_proof_level_proofs: Proof<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>,
+    node_aggregation_vk: VerificationKey<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>,
+    final_node_aggregations: NodeAggregationOutputDataWitness<Bn256>,
+    leaf_vks_committment: Fr,
+    node_aggregation_vk_committment: Fr,
+    leaf_aggregation_vk_committment: Fr,
+    previous_aux_hash: [u8; 32],
+    previous_meta_hash: [u8; 32],
+    g2_points: [bellman::pairing::bn256::G2Affine; 2],
+    verification: ProofsVerification,
+) -> (
+    ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>,
+    BlockApplicationWitness<Bn256>,
+) {
+    prepare_scheduler_circuit_with_verification(
+        incomplete_scheduler_witness,
+        node_final_proof_level_proofs,
+        node_aggregation_vk,
+        final_node_aggregations,
+        leaf_vks_committment,
+        node_aggregation_vk_committment,
+        leaf_aggregation_vk_committment,
+        previous_aux_hash,
+        previous_meta_hash,
+        plan.scheduler_upper_bound,
+        g2_points,
+        verification,
+    )
+}
+
+#[test]
+fn test_recursion_plan_shape() {
+    let plan = RecursionPlan::new(97, 50, 48, 50 * 48);
+    assert_eq!(plan.max_node_depth, 1);
+    assert_eq!(plan.leaf_layer.num_circuits, 2);
+    assert_eq!(plan.leaf_layer.num_padding_proofs, 3);
+    assert_eq!(plan.node_layers.len(), 1);
+    assert_eq!(plan.node_layers[0].num_circuits, 1);
+    assert_eq!(plan.node_layers[0].num_padding_proofs, 46);
+    assert_eq!(plan.total_num_circuits(), 97 + 2 + 1 + 1);
+
+    let plan = RecursionPlan::new(1, 4, 2, 4 * 2 * 2 * 2);
+    assert_eq!(plan.max_node_depth, 3);
+    assert_eq!(plan.leaf_layer.num_circuits, 1);
+    assert_eq!(plan.node_layers.len(), 1);
+
+    let plan = RecursionPlan::new(17, 4, 2, 4 * 2 * 2 * 2);
+    assert_eq!(plan.leaf_layer.num_circuits, 5);
+    let per_layer: Vec<_> = plan.node_layers.iter().map(|el| el.num_circuits).collect();
+    assert_eq!(per_layer, vec![3, 2, 1]);
+
+    let serialized = serde_json::to_string(&plan).unwrap();
+    let deserialized: RecursionPlan = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(plan, deserialized);
+}
+
+#[test]
+#[should_panic]
+fn test_recursion_plan_invalid_upper_bound() {
+    let _ = RecursionPlan::new(10, 4, 3, 4 * 3 * 2);
+}
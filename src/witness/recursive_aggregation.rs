@@ -3,6 +3,7 @@ use std::collections::{HashSet, VecDeque};
 use super::full_block_artifact::{BlockBasicCircuits, BlockBasicCircuitsPublicInputs};
 use super::*;
 use crate::abstract_zksync_circuit::concrete_circuits::{ZkSyncCircuit, ZkSyncVerificationKey};
+use crate::bellman::kate_commitment::{Crs, CrsForMonomialForm};
 use crate::bellman::plonk::better_better_cs::proof::Proof;
 use crate::bellman::plonk::better_better_cs::setup::VerificationKey;
 use crate::bellman::Engine;
@@ -28,10 +29,70 @@ pub struct AggregationResult<E: Engine> {
     pub pairing_with_x_y_limbs: [E::Fr; NUM_LIMBS],
 }
 
-// we need two unequal proofs and verification keys for internal procedure
+pub const PADDING_PROOFS_DIR_ENV: &str = "ZKSYNC_PADDING_PROOFS_DIR";
+pub const PADDING_VK_FILE_NAME: &str = "padding_vk.json";
+pub const PADDING_PROOF_FILE_NAMES: [&str; 2] = ["padding_proof_1.json", "padding_proof_2.json"];
+
+// public inputs of padding proofs are derived from those, so proofs are different
+pub const PADDING_CIRCUIT_WITNESSES: [u64; 2] = [1234567, 7654321];
+pub const PADDING_CIRCUIT_NUM_SQUARINGS: usize = 16;
+
+fn check_paddings(
+    padding_vk: VerificationKey<Bn256, ZkSyncParametricCircuit<Bn256>>,
+    padding_proof_1: Proof<Bn256, ZkSyncParametricCircuit<Bn256>>,
+    padding_proof_2: Proof<Bn256, ZkSyncParametricCircuit<Bn256>>,
+) -> (
+    VerificationKey<Bn256, ZkSyncParametricCircuit<Bn256>>,
+    [Proof<Bn256, ZkSyncParametricCircuit<Bn256>>; 2],
+) {
+    assert!(padding_proof_1.opening_proof_at_z != padding_proof_2.opening_proof_at_z);
+    assert!(padding_proof_1.opening_proof_at_z_omega != padding_proof_2.opening_proof_at_z_omega);
+
+    (padding_vk, [padding_proof_1, padding_proof_2])
+}
+
+// we need two unequal proofs and verification keys for internal procedure.
+// Those are taken from the directory set by ZKSYNC_PADDING_PROOFS_DIR if it's present,
+// otherwise embedded ones from `src/padding_proofs` are used. Use `padding_proofs_generator` to create new ones
 pub fn get_paddings() -> (
     VerificationKey<Bn256, ZkSyncParametricCircuit<Bn256>>,
     [Proof<Bn256, ZkSyncParametricCircuit<Bn256>>; 2],
+) {
+    if let Ok(dir) = std::env::var(PADDING_PROOFS_DIR_ENV) {
+        return get_paddings_from_dir(dir);
+    }
+
+    get_embedded_paddings()
+}
+
+pub fn get_paddings_from_dir<P: AsRef<std::path::Path>>(
+    dir: P,
+) -> (
+    VerificationKey<Bn256, ZkSyncParametricCircuit<Bn256>>,
+    [Proof<Bn256, ZkSyncParametricCircuit<Bn256>>; 2],
+) {
+    fn read_from_file<T: serde::de::DeserializeOwned>(path: std::path::PathBuf) -> T {
+        let file = std::fs::File::open(&path).unwrap_or_else(|_| {
+            panic!(
+                "padding file {} is not found, use `padding_proofs_generator` to create it",
+                path.display()
+            )
+        });
+        serde_json::from_reader(file).unwrap()
+    }
+
+    let dir = dir.as_ref();
+
+    let padding_vk = read_from_file(dir.join(PADDING_VK_FILE_NAME));
+    let padding_proof_1 = read_from_file(dir.join(PADDING_PROOF_FILE_NAMES[0]));
+    let padding_proof_2 = read_from_file(dir.join(PADDING_PROOF_FILE_NAMES[1]));
+
+    check_paddings(padding_vk, padding_proof_1, padding_proof_2)
+}
+
+pub fn get_embedded_paddings() -> (
+    VerificationKey<Bn256, ZkSyncParametricCircuit<Bn256>>,
+    [Proof<Bn256, ZkSyncParametricCircuit<Bn256>>; 2],
 ) {
     let padding_vk_1 = include_bytes!("../padding_proofs/padding_vk.json");
     let padding_vk_1: VerificationKey<Bn256, ZkSyncParametricCircuit<Bn256>> =
@@ -45,10 +106,109 @@ pub fn get_paddings() -> (
     let padding_proof_2: Proof<Bn256, ZkSyncParametricCircuit<Bn256>> =
         serde_json::from_slice(padding_proof_2).unwrap();
 
-    assert!(padding_proof_1.opening_proof_at_z != padding_proof_2.opening_proof_at_z);
-    assert!(padding_proof_1.opening_proof_at_z_omega != padding_proof_2.opening_proof_at_z_omega);
+    check_paddings(padding_vk_1, padding_proof_1, padding_proof_2)
+}
+
+// deterministically creates padding VK and two different valid padding proofs
+// for a given CRS. CRS should be large enough to fit all the tables
+pub fn generate_paddings(
+    crs: &Crs<Bn256, CrsForMonomialForm>,
+) -> (
+    VerificationKey<Bn256, ZkSyncParametricCircuit<Bn256>>,
+    [Proof<Bn256, ZkSyncParametricCircuit<Bn256>>; 2],
+) {
+    use crate::abstract_zksync_circuit::concrete_circuits::PaddingCircuit;
+    use crate::bellman::plonk::better_better_cs::cs::{
+        Circuit, PlonkCsWidth4WithNextStepAndCustomGatesParams, TrivialAssembly,
+    };
+    use crate::bellman::worker::Worker;
+    use sync_vm::franklin_crypto::bellman::plonk::better_better_cs::gates::selector_optimized_with_d_next::SelectorOptimizedWidth4MainGateWithDNext;
+    use sync_vm::glue::traits::GenericHasher;
+    use sync_vm::recursion::RescueTranscriptForRecursion;
+
+    let worker = Worker::new();
+    let sponge_params = bn254_rescue_params();
+    let rns_params = get_prefered_rns_params();
+    let transcript_params = (&sponge_params, &rns_params);
+    let round_function = GenericHasher::new_from_params(&sponge_params);
+
+    let mut padding_vk = None;
+    let mut padding_proofs = vec![];
+
+    for witness in PADDING_CIRCUIT_WITNESSES.into_iter() {
+        let circuit = PaddingCircuit::<Bn256>::new(
+            Some(witness),
+            PADDING_CIRCUIT_NUM_SQUARINGS,
+            round_function.clone(),
+            None,
+        );
+
+        let mut assembly = TrivialAssembly::<
+            Bn256,
+            PlonkCsWidth4WithNextStepAndCustomGatesParams,
+            SelectorOptimizedWidth4MainGateWithDNext,
+        >::new();
+        circuit
+            .synthesize(&mut assembly)
+            .expect("must synthesize padding circuit");
+        assert!(assembly.is_satisfied());
+        assembly.finalize();
+
+        let setup = assembly
+            .create_setup::<PaddingCircuit<Bn256>>(&worker)
+            .expect("must create setup");
+        let vk = VerificationKey::from_setup(&setup, &worker, crs).expect("must create VK");
+        let proof = assembly
+            .create_proof::<PaddingCircuit<Bn256>, RescueTranscriptForRecursion<'_>>(
+                &worker,
+                &setup,
+                crs,
+                Some(transcript_params),
+            )
+            .expect("must create proof");
 
-    (padding_vk_1, [padding_proof_1, padding_proof_2])
+        let is_valid = crate::bellman::plonk::better_better_cs::verifier::verify::<
+            Bn256,
+            _,
+            RescueTranscriptForRecursion<'_>,
+        >(&vk, &proof, Some(transcript_params))
+        .expect("must try to verify a proof");
+        assert!(is_valid, "padding proof and VK must be valid");
+
+        // only transmute marker
+        let vk: VerificationKey<Bn256, ZkSyncParametricCircuit<Bn256>> =
+            unsafe { std::mem::transmute(vk) };
+        let proof: Proof<Bn256, ZkSyncParametricCircuit<Bn256>> =
+            unsafe { std::mem::transmute(proof) };
+
+        if padding_vk.is_none() {
+            padding_vk = Some(vk);
+        }
+        padding_proofs.push(proof);
+    }
+
+    let padding_proof_2 = padding_proofs.pop().unwrap();
+    let padding_proof_1 = padding_proofs.pop().unwrap();
+
+    check_paddings(padding_vk.unwrap(), padding_proof_1, padding_proof_2)
+}
+
+// writes padding VK and proofs in the format expected by `get_paddings_from_dir`
+pub fn write_paddings<P: AsRef<std::path::Path>>(
+    dir: P,
+    padding_vk: &VerificationKey<Bn256, ZkSyncParametricCircuit<Bn256>>,
+    padding_proofs: &[Proof<Bn256, ZkSyncParametricCircuit<Bn256>>; 2],
+) {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir).unwrap();
+
+    let file = std::fs::File::create(dir.join(PADDING_VK_FILE_NAME)).unwrap();
+    serde_json::to_writer(file, padding_vk).unwrap();
+
+    for (file_name, proof) in PADDING_PROOF_FILE_NAMES.iter().zip(padding_proofs.iter()) {
+        let file = std::fs::File::create(dir.join(file_name)).unwrap();
+        serde_json::to_writer(file, proof).unwrap();
+    }
 }
 
 pub fn get_filled_paddings(
@@ -80,14 +240,46 @@ pub fn padding_aggregations(
     [Fr; NUM_LIMBS],
     [Fr; NUM_LIMBS],
 )> {
-    use crate::franklin_crypto::plonk::circuit::bigint::split_into_limbs;
-    let rns_params = get_prefered_rns_params();
-
     let crs_mons = circuit_testing::get_trusted_setup::<Bn256>(1 << 26);
     let mut p1 = crs_mons.g1_bases[1];
     use sync_vm::franklin_crypto::bellman::CurveAffine;
     p1.negate();
-    let mut p2 = crs_mons.g1_bases[0];
+    let p2 = crs_mons.g1_bases[0];
+
+    aggregations_from_points(p1, p2, num_elements)
+}
+
+// same as `padding_aggregations`, but does not need a trusted setup. Points do NOT
+// satisfy the pairing check, so those can only be used along with mock proofs
+pub fn mock_aggregations(
+    num_elements: usize,
+) -> Vec<(
+    [Fr; NUM_LIMBS],
+    [Fr; NUM_LIMBS],
+    [Fr; NUM_LIMBS],
+    [Fr; NUM_LIMBS],
+)> {
+    use sync_vm::franklin_crypto::bellman::CurveAffine;
+    let mut p1 = bellman::pairing::bn256::G1Affine::one();
+    p1.negate();
+    let p2 = bellman::pairing::bn256::G1Affine::one();
+
+    aggregations_from_points(p1, p2, num_elements)
+}
+
+fn aggregations_from_points(
+    mut p1: bellman::pairing::bn256::G1Affine,
+    mut p2: bellman::pairing::bn256::G1Affine,
+    num_elements: usize,
+) -> Vec<(
+    [Fr; NUM_LIMBS],
+    [Fr; NUM_LIMBS],
+    [Fr; NUM_LIMBS],
+    [Fr; NUM_LIMBS],
+)> {
+    use crate::franklin_crypto::plonk::circuit::bigint::split_into_limbs;
+    use sync_vm::franklin_crypto::bellman::CurveAffine;
+    let rns_params = get_prefered_rns_params();
 
     let mut all_aggregations = vec![];
 
@@ -136,6 +328,18 @@ pub fn padding_aggregations(
     all_aggregations
 }
 
+// how `prepare_*` functions treat the proofs they aggregate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProofsVerification {
+    // proofs are verified before aggregation
+    Verify,
+    // proofs were verified by the caller, e.g. when those were submitted
+    AlreadyVerified,
+    // placeholder proofs from `MockProver`, those are never valid. Aggregation results are
+    // replaced by `mock_aggregations` and circuits get expected public inputs instead
+    Mock,
+}
+
 use sync_vm::recursion::node_aggregation::ZkSyncParametricCircuit;
 
 // helper function. Erases type internally
@@ -280,6 +484,51 @@ pub fn prepare_leaf_aggregations(
             VmWitnessOracle<sync_vm::testing::Bn256>,
         >,
     >,
+) {
+    prepare_leaf_aggregations_with_verification(
+        basic_block_circuits,
+        basic_block_circuits_inputs,
+        individual_proofs,
+        verification_keys,
+        splitting_factor,
+        leaf_vks_committments_set,
+        leaf_vks_committment,
+        g2_points,
+        ProofsVerification::Verify,
+    )
+}
+
+// same as `prepare_leaf_aggregations`, but proofs are treated according to `verification`
+pub fn prepare_leaf_aggregations_with_verification(
+    basic_block_circuits: BlockBasicCircuits<Bn256>,
+    basic_block_circuits_inputs: BlockBasicCircuitsPublicInputs<Bn256>,
+    individual_proofs: Vec<Proof<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>>,
+    verification_keys: Vec<VerificationKey<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>>,
+    splitting_factor: usize,
+    leaf_vks_committments_set: Vec<Fr>,
+    leaf_vks_committment: Fr,
+    g2_points: [bellman::pairing::bn256::G2Affine; 2],
+    verification: ProofsVerification,
+) -> (
+    Vec<
+        crate::encodings::QueueSimulator<
+            sync_vm::testing::Bn256,
+            crate::encodings::recursion_request::RecursionRequest<sync_vm::testing::Bn256>,
+            2,
+            2,
+        >,
+    >,
+    Vec<
+        sync_vm::recursion::leaf_aggregation::LeafAggregationOutputDataWitness<
+            sync_vm::testing::Bn256,
+        >,
+    >,
+    Vec<
+        crate::abstract_zksync_circuit::concrete_circuits::ZkSyncCircuit<
+            sync_vm::testing::Bn256,
+            VmWitnessOracle<sync_vm::testing::Bn256>,
+        >,
+    >,
 ) {
     // basic sanity tests
     let flattened_expected_inputs = basic_block_circuits_inputs.into_flattened_set();
@@ -294,6 +543,8 @@ pub fn prepare_leaf_aggregations(
     let rns_params = get_prefered_rns_params();
     let transcript_params = (&sponge_params, &rns_params);
 
+    let is_mock = verification == ProofsVerification::Mock;
+
     for (idx, ((proof, vk), expected_public_input)) in individual_proofs
         .iter()
         .zip(verification_keys.iter())
@@ -304,6 +555,11 @@ pub fn prepare_leaf_aggregations(
             mismatched_inputs.insert(idx);
         }
 
+        // inputs are still checked for proofs that are not verified here
+        if verification != ProofsVerification::Verify {
+            continue;
+        }
+
         let is_valid = crate::bellman::plonk::better_better_cs::verifier::verify::<
             Bn256,
             _,
@@ -423,6 +679,12 @@ pub fn prepare_leaf_aggregations(
     let mut aggregation_outputs = vec![];
     let mut leaf_circuits = vec![];
 
+    let mock_leaf_aggregations = if is_mock {
+        mock_aggregations(leaf_layer_requests.len())
+    } else {
+        vec![]
+    };
+
     for (idx, (subset, circuits)) in leaf_layer_requests
         .into_iter()
         .zip(leaf_layer_flattened_set.into_iter())
@@ -510,31 +772,58 @@ pub fn prepare_leaf_aggregations(
 
         drop(this_aggregation_subqueue);
 
-        // we use the circuit itself to output some witness
-        use sync_vm::testing::create_test_artifacts_with_optimized_gate;
-        let (mut cs, _, _) = create_test_artifacts_with_optimized_gate();
-        let (_aggregated_public_input, output_data) =
-            aggregate_at_leaf_level_entry_point::<_, _, _, _, _, true>(
-                &mut cs,
-                Some(wit.clone()),
-                &round_function,
-                (
-                    splitting_factor,
-                    rns_params.clone(),
-                    aggregation_params.clone(),
-                    padding_vk_committment,
-                    padding_vk_encoding.clone(),
-                    padding_public_inputs.clone(),
-                    padding_proofs.clone(),
-                    Some(g2_points.clone()),
-                ),
-            )
-            .unwrap();
+        let result_observable_output = if is_mock {
+            // mock proofs can not be aggregated, so we use dummy points instead
+            let (pair_with_generator_x, pair_with_generator_y, pair_with_x_x, pair_with_x_y) =
+                mock_leaf_aggregations[idx];
 
-        let result_observable_output = output_data.create_witness().unwrap();
+            LeafAggregationOutputDataWitness {
+                pair_with_generator_x,
+                pair_with_generator_y,
+                pair_with_x_x,
+                pair_with_x_y,
+                _marker: std::marker::PhantomData,
+            }
+        } else {
+            // we use the circuit itself to output some witness
+            use sync_vm::testing::create_test_artifacts_with_optimized_gate;
+            let (mut cs, _, _) = create_test_artifacts_with_optimized_gate();
+            let (_aggregated_public_input, output_data) =
+                aggregate_at_leaf_level_entry_point::<_, _, _, _, _, true>(
+                    &mut cs,
+                    Some(wit.clone()),
+                    &round_function,
+                    (
+                        splitting_factor,
+                        rns_params.clone(),
+                        aggregation_params.clone(),
+                        padding_vk_committment,
+                        padding_vk_encoding.clone(),
+                        padding_public_inputs.clone(),
+                        padding_proofs.clone(),
+                        Some(g2_points.clone()),
+                    ),
+                )
+                .unwrap();
+
+            output_data.create_witness().unwrap()
+        };
 
         wit.closed_form_input.observable_output = result_observable_output.clone();
 
+        // mock prover can not synthesize the circuit, so it relies on the expected input
+        let expected_public_input = if is_mock {
+            Some(
+                crate::witness::block_proof_bundle::leaf_aggregation_public_input(
+                    take_queue_state_from_simulator(&leaf_layer_subqueues[idx]),
+                    leaf_vks_committment,
+                    result_observable_output.clone(),
+                ),
+            )
+        } else {
+            None
+        };
+
         aggregation_outputs.push(result_observable_output);
 
         use crate::abstract_zksync_circuit::concrete_circuits::LeafAggregationCircuit;
@@ -552,7 +841,7 @@ pub fn prepare_leaf_aggregations(
                 Some(g2_points.clone()),
             ),
             round_function.clone(),
-            None,
+            expected_public_input,
         );
 
         let circuit = ZkSyncCircuit::<Bn256, VmWitnessOracle<Bn256>>::LeafAggregation(circuit);
@@ -618,6 +907,71 @@ pub fn prepare_node_aggregations(
             VmWitnessOracle<sync_vm::testing::Bn256>,
         >,
     >,
+) {
+    prepare_node_aggregations_with_verification(
+        previous_level_proofs,
+        previous_level_vk,
+        previous_level_are_leafs,
+        depth,
+        previous_level_leafs_aggregations,
+        previous_level_node_aggregations,
+        previous_sequence,
+        splitting_factor_for_leafs,
+        splitting_factor_for_nodes,
+        padding_aggregations,
+        leaf_vks_committment,
+        node_aggregation_vk_committment,
+        leaf_aggregation_vk_committment,
+        g2_points,
+        ProofsVerification::Verify,
+    )
+}
+
+// same as `prepare_node_aggregations`, but proofs are treated according to `verification`
+pub fn prepare_node_aggregations_with_verification(
+    previous_level_proofs: Vec<Proof<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>>,
+    previous_level_vk: VerificationKey<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>,
+    previous_level_are_leafs: bool,
+    depth: u32,
+    previous_level_leafs_aggregations: Vec<LeafAggregationOutputDataWitness<Bn256>>,
+    previous_level_node_aggregations: Vec<NodeAggregationOutputDataWitness<Bn256>>,
+    previous_sequence: Vec<
+        crate::encodings::QueueSimulator<
+            sync_vm::testing::Bn256,
+            crate::encodings::recursion_request::RecursionRequest<sync_vm::testing::Bn256>,
+            2,
+            2,
+        >,
+    >,
+    splitting_factor_for_leafs: usize,
+    splitting_factor_for_nodes: usize,
+    padding_aggregations: Vec<(
+        [Fr; NUM_LIMBS],
+        [Fr; NUM_LIMBS],
+        [Fr; NUM_LIMBS],
+        [Fr; NUM_LIMBS],
+    )>,
+    leaf_vks_committment: Fr,
+    node_aggregation_vk_committment: Fr,
+    leaf_aggregation_vk_committment: Fr,
+    g2_points: [bellman::pairing::bn256::G2Affine; 2],
+    verification: ProofsVerification,
+) -> (
+    Vec<
+        crate::encodings::QueueSimulator<
+            sync_vm::testing::Bn256,
+            crate::encodings::recursion_request::RecursionRequest<sync_vm::testing::Bn256>,
+            2,
+            2,
+        >,
+    >,
+    Vec<NodeAggregationOutputDataWitness<sync_vm::testing::Bn256>>,
+    Vec<
+        crate::abstract_zksync_circuit::concrete_circuits::ZkSyncCircuit<
+            sync_vm::testing::Bn256,
+            VmWitnessOracle<sync_vm::testing::Bn256>,
+        >,
+    >,
 ) {
     if depth == 0 {
         assert!(previous_level_are_leafs);
@@ -653,7 +1007,13 @@ pub fn prepare_node_aggregations(
 
     let mut invalid_proofs = HashSet::new();
 
+    let is_mock = verification == ProofsVerification::Mock;
+
     for (idx, proof) in previous_level_proofs.iter().enumerate() {
+        if verification != ProofsVerification::Verify {
+            continue;
+        }
+
         let is_valid = crate::bellman::plonk::better_better_cs::verifier::verify::<
             Bn256,
             _,
@@ -747,7 +1107,13 @@ pub fn prepare_node_aggregations(
 
     let mut circuit_to_aggregate_index = 0;
 
-    for (_idx, subset) in merged.iter().cloned().enumerate() {
+    let mock_node_aggregations = if is_mock {
+        mock_aggregations(merged.len())
+    } else {
+        vec![]
+    };
+
+    for (idx, subset) in merged.iter().cloned().enumerate() {
         let queue_wit: VecDeque<_> = subset
             .witness
             .iter()
@@ -818,38 +1184,67 @@ pub fn prepare_node_aggregations(
             circuit_to_aggregate_index += 1;
         }
 
-        use sync_vm::recursion::node_aggregation::aggregate_at_node_level_entry_point;
-        use sync_vm::testing::create_test_artifacts_with_optimized_gate;
-
-        let (mut cs, _, _) = create_test_artifacts_with_optimized_gate();
-        let (
-            _aggregated_public_input,
-            _leaf_aggregation_output_data,
-            _node_aggregation_output_data,
-            output_data,
-        ) = aggregate_at_node_level_entry_point::<_, _, _, _, _, true>(
-            &mut cs,
-            Some(wit.clone()),
-            &round_function,
-            (
-                splitting_factor_for_nodes,
-                splitting_factor_for_leafs,
-                rns_params.clone(),
-                aggregation_params.clone(),
-                padding_vk_committment,
-                padding_vk_encoding.clone(),
-                padding_public_inputs.clone(),
-                padding_proofs.clone(),
-                padding_aggregations.clone(),
-                Some(g2_points.clone()),
-            ),
-        )
-        .unwrap();
+        let result_observable_output = if is_mock {
+            // mock proofs can not be aggregated, so we use dummy points instead
+            let (pair_with_generator_x, pair_with_generator_y, pair_with_x_x, pair_with_x_y) =
+                mock_node_aggregations[idx];
 
-        let result_observable_output = output_data.create_witness().unwrap();
+            NodeAggregationOutputDataWitness {
+                pair_with_generator_x,
+                pair_with_generator_y,
+                pair_with_x_x,
+                pair_with_x_y,
+                _marker: std::marker::PhantomData,
+            }
+        } else {
+            use sync_vm::recursion::node_aggregation::aggregate_at_node_level_entry_point;
+            use sync_vm::testing::create_test_artifacts_with_optimized_gate;
+
+            let (mut cs, _, _) = create_test_artifacts_with_optimized_gate();
+            let (
+                _aggregated_public_input,
+                _leaf_aggregation_output_data,
+                _node_aggregation_output_data,
+                output_data,
+            ) = aggregate_at_node_level_entry_point::<_, _, _, _, _, true>(
+                &mut cs,
+                Some(wit.clone()),
+                &round_function,
+                (
+                    splitting_factor_for_nodes,
+                    splitting_factor_for_leafs,
+                    rns_params.clone(),
+                    aggregation_params.clone(),
+                    padding_vk_committment,
+                    padding_vk_encoding.clone(),
+                    padding_public_inputs.clone(),
+                    padding_proofs.clone(),
+                    padding_aggregations.clone(),
+                    Some(g2_points.clone()),
+                ),
+            )
+            .unwrap();
+
+            output_data.create_witness().unwrap()
+        };
 
         wit.closed_form_input.observable_output = result_observable_output.clone();
 
+        // mock prover can not synthesize the circuit, so it relies on the expected input
+        let expected_public_input = if is_mock {
+            Some(
+                crate::witness::block_proof_bundle::node_aggregation_public_input(
+                    take_queue_state_from_simulator(&subset),
+                    leaf_vks_committment,
+                    node_aggregation_vk_committment,
+                    leaf_aggregation_vk_committment,
+                    result_observable_output.clone(),
+                ),
+            )
+        } else {
+            None
+        };
+
         aggregation_outputs.push(result_observable_output);
 
         let circuit = NodeAggregationCircuit::new(
@@ -867,7 +1262,7 @@ pub fn prepare_node_aggregations(
                 Some(g2_points.clone()),
             ),
             round_function.clone(),
-            None,
+            expected_public_input,
         );
 
         let circuit = ZkSyncCircuit::<Bn256, VmWitnessOracle<Bn256>>::NodeAggregation(circuit);
@@ -900,6 +1295,43 @@ pub fn prepare_scheduler_circuit(
         VmWitnessOracle<sync_vm::testing::Bn256>,
     >,
     BlockApplicationWitness<Bn256>,
+) {
+    prepare_scheduler_circuit_with_verification(
+        incomplete_scheduler_witness,
+        node_final_proof_level_proofs,
+        node_aggregation_vk,
+        final_node_aggregations,
+        leaf_vks_committment,
+        node_aggregation_vk_committment,
+        leaf_aggregation_vk_committment,
+        previous_aux_hash,
+        previous_meta_hash,
+        scheduler_upper_bound,
+        g2_points,
+        ProofsVerification::Verify,
+    )
+}
+
+// same as `prepare_scheduler_circuit`, but the final node proof is treated according to `verification`
+pub fn prepare_scheduler_circuit_with_verification(
+    incomplete_scheduler_witness: SchedulerCircuitInstanceWitness<Bn256>,
+    node_final_proof_level_proofs: Proof<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>,
+    node_aggregation_vk: VerificationKey<Bn256, ZkSyncCircuit<Bn256, VmWitnessOracle<Bn256>>>,
+    final_node_aggregations: NodeAggregationOutputDataWitness<Bn256>,
+    leaf_vks_committment: Fr,
+    node_aggregation_vk_committment: Fr,
+    leaf_aggregation_vk_committment: Fr,
+    previous_aux_hash: [u8; 32],
+    previous_meta_hash: [u8; 32],
+    scheduler_upper_bound: u32,
+    g2_points: [bellman::pairing::bn256::G2Affine; 2],
+    verification: ProofsVerification,
+) -> (
+    crate::abstract_zksync_circuit::concrete_circuits::ZkSyncCircuit<
+        sync_vm::testing::Bn256,
+        VmWitnessOracle<sync_vm::testing::Bn256>,
+    >,
+    BlockApplicationWitness<Bn256>,
 ) {
     let rns_params = get_prefered_rns_params();
     use crate::encodings::QueueSimulator;
@@ -912,6 +1344,10 @@ pub fn prepare_scheduler_circuit(
 
     let mut scheduler_witness = incomplete_scheduler_witness;
 
+    // final node proof from `MockProver` is not valid and is aggregated over mock points,
+    // so the resulting pairing can not be checked
+    let is_mock = verification == ProofsVerification::Mock;
+
     let node_final_proof_level_proofs = erase_proof_type(node_final_proof_level_proofs);
     let node_aggregation_vk = erase_vk_type(node_aggregation_vk);
 
@@ -1012,7 +1448,7 @@ pub fn prepare_scheduler_circuit(
     }) as Box<dyn FnOnce(BlockApplicationWitness<Bn256>) -> ()>;
 
     let (mut cs, _, _) = create_test_artifacts_with_optimized_gate();
-    let _ = scheduler_function(
+    let public_input = scheduler_function(
         &mut cs,
         Some(scheduler_witness.clone()),
         Some(reporting_function),
@@ -1023,13 +1459,25 @@ pub fn prepare_scheduler_circuit(
             aggregation_params.clone(),
             padding_vk_encoding,
             padding_proofs[0].clone(),
-            Some(g2_points.clone()),
+            if is_mock {
+                None
+            } else {
+                Some(g2_points.clone())
+            },
         ),
-    );
+    )
+    .unwrap();
 
     // now we can unwrap and get the values we want
     let final_aggregation_result = report.lock().unwrap().take().unwrap();
 
+    // mock prover can not synthesize the circuit, so it relies on the expected input
+    let expected_public_input = if is_mock {
+        Some(public_input.get_value().unwrap())
+    } else {
+        None
+    };
+
     let circuit = SchedulerCircuit::new(
         Some(scheduler_witness),
         (
@@ -1041,7 +1489,7 @@ pub fn prepare_scheduler_circuit(
             Some(g2_points.clone()),
         ),
         round_function.clone(),
-        None,
+        expected_public_input,
     );
 
     let circuit = ZkSyncCircuit::<Bn256, VmWitnessOracle<Bn256>>::Scheduler(circuit);
@@ -0,0 +1,215 @@
+use super::*;
+use crate::encodings::initial_storage_write::InitialStorageWrite;
+use crate::encodings::repeated_storage_write::RepeatedStorageWrite;
+use crate::pairing::Engine;
+use crate::utils::bytes_to_u128_le;
+use crate::witness::full_block_artifact::BlockBasicCircuits;
+use crate::witness::individual_circuits::data_hasher_and_merklizer::serialize_pubdata;
+use crate::witness::tree::{BinaryHasher, EnumeratedBinaryLeaf, InMemoryStorageTree};
+
+// Everything that is needed to follow the rollup state from the published data only:
+// pubdata byte strings (as hashed by the initial and repeated writes pubdata hashers)
+// are parsed back into writes, and those are applied to the tree of the previous block
+
+fn parse_pubdata<const SERIALIZATION_WIDTH: usize>(
+    pubdata: &[u8],
+) -> Vec<[u8; SERIALIZATION_WIDTH]> {
+    assert!(
+        pubdata.len() >= 4,
+        "pubdata must start with number of items"
+    );
+    let (num_items, items) = pubdata.split_at(4);
+    let num_items = u32::from_be_bytes(num_items.try_into().unwrap()) as usize;
+    assert_eq!(
+        items.len(),
+        num_items * SERIALIZATION_WIDTH,
+        "pubdata length doesn't match the number of items"
+    );
+
+    items
+        .chunks(SERIALIZATION_WIDTH)
+        .map(|el| el.try_into().unwrap())
+        .collect()
+}
+
+pub fn parse_initial_writes_pubdata(pubdata: &[u8]) -> Vec<InitialStorageWrite> {
+    parse_pubdata::<64>(pubdata)
+        .into_iter()
+        .map(|el| InitialStorageWrite {
+            key: el[0..32].try_into().unwrap(),
+            value: el[32..64].try_into().unwrap(),
+        })
+        .collect()
+}
+
+pub fn parse_repeated_writes_pubdata(pubdata: &[u8]) -> Vec<RepeatedStorageWrite> {
+    parse_pubdata::<40>(pubdata)
+        .into_iter()
+        .map(|el| RepeatedStorageWrite {
+            index: u64::from_be_bytes(el[0..8].try_into().unwrap()),
+            value: el[8..40].try_into().unwrap(),
+        })
+        .collect()
+}
+
+// applies writes of the block to the state of the previous one, and returns new root
+// and enumeration counter. Initial writes are enumerated in the order of pubdata
+pub fn apply_storage_pubdata<
+    const DEPTH: usize,
+    const LEAF_METADATA_WIDTH: usize,
+    H: BinaryHasher<32>,
+    L: EnumeratedBinaryLeaf<32>,
+>(
+    tree: &mut InMemoryStorageTree<DEPTH, 32, LEAF_METADATA_WIDTH, H, L>,
+    initial_writes: &[InitialStorageWrite],
+    repeated_writes: &[RepeatedStorageWrite],
+) -> ([u8; 32], u64) {
+    let mut indexes = Vec::with_capacity(initial_writes.len() + repeated_writes.len());
+    let mut leafs = Vec::with_capacity(initial_writes.len() + repeated_writes.len());
+    for el in repeated_writes.iter() {
+        let (key, _) = tree
+            .leaf_by_enumeration_index(el.index)
+            .unwrap_or_else(|| panic!("repeated write to unknown enumeration index {}", el.index));
+        indexes.push(key);
+        leafs.push(L::from_value(el.value));
+    }
+    for el in initial_writes.iter() {
+        assert!(
+            !tree.leafs.contains_key(&el.key),
+            "initial write to existing key {}",
+            hex::encode(&el.key)
+        );
+        indexes.push(el.key);
+        leafs.push(L::from_value(el.value));
+    }
+
+    let queries = tree.insert_many_leafs_batched(&indexes, leafs);
+    for (query, is_initial) in queries.iter().zip(
+        std::iter::repeat(false)
+            .take(repeated_writes.len())
+            .chain(std::iter::repeat(true)),
+    ) {
+        assert_eq!(
+            query.first_write,
+            is_initial,
+            "key {} is written more than once",
+            hex::encode(&query.index)
+        );
+    }
+
+    (tree.root, tree.next_enumeration_index)
+}
+
+// pubdata byte strings in the same form as hashed by the pubdata hasher circuits of the block
+pub fn storage_pubdata_from_circuits<E: Engine>(
+    basic_circuits: &BlockBasicCircuits<E>,
+) -> (Vec<u8>, Vec<u8>) {
+    let initial_writes: Vec<_> = basic_circuits
+        .initial_writes_hasher_circuit
+        .clone_witness()
+        .unwrap()
+        .input_queue_witness
+        .wit
+        .iter()
+        .map(|(_, el, _)| InitialStorageWrite {
+            key: el.key,
+            value: el.value,
+        })
+        .collect();
+
+    let repeated_writes: Vec<_> = basic_circuits
+        .repeated_writes_hasher_circuit
+        .clone_witness()
+        .unwrap()
+        .input_queue_witness
+        .wit
+        .iter()
+        .map(|(_, el, _)| RepeatedStorageWrite {
+            index: el.index,
+            value: el.value,
+        })
+        .collect();
+
+    (
+        serialize_pubdata(initial_writes.iter()),
+        serialize_pubdata(repeated_writes.iter()),
+    )
+}
+
+// reconstructs the state after the block from it's pubdata and the state of the previous block,
+// and checks that it's the same as the output of the storage application
+pub fn reconstruct_block_state<
+    E: Engine,
+    const DEPTH: usize,
+    const LEAF_METADATA_WIDTH: usize,
+    H: BinaryHasher<32>,
+    L: EnumeratedBinaryLeaf<32>,
+>(
+    previous_block_tree: &mut InMemoryStorageTree<DEPTH, 32, LEAF_METADATA_WIDTH, H, L>,
+    basic_circuits: &BlockBasicCircuits<E>,
+) -> ([u8; 32], u64) {
+    let (initial_writes_pubdata, repeated_writes_pubdata) =
+        storage_pubdata_from_circuits(basic_circuits);
+    let initial_writes = parse_initial_writes_pubdata(&initial_writes_pubdata);
+    let repeated_writes = parse_repeated_writes_pubdata(&repeated_writes_pubdata);
+
+    let (root, next_enumeration_index) =
+        apply_storage_pubdata(previous_block_tree, &initial_writes, &repeated_writes);
+
+    let storage_application_output = basic_circuits
+        .storage_application_circuits
+        .last()
+        .unwrap()
+        .clone_witness()
+        .unwrap()
+        .closed_form_input
+        .observable_output;
+
+    assert_eq!(
+        bytes_to_u128_le::<32, 2>(&root),
+        storage_application_output.final_root,
+        "reconstructed root {} doesn't match the storage application output",
+        hex::encode(&root)
+    );
+    assert_eq!(
+        next_enumeration_index, storage_application_output.final_next_enumeration_counter,
+        "reconstructed enumeration counter doesn't match the storage application output"
+    );
+
+    (root, next_enumeration_index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::witness::tree::{BinarySparseStorageTree, ZKSyncTestingTree, ZkSyncStorageLeaf};
+
+    #[test]
+    fn test_apply_parsed_pubdata() {
+        let mut tree = ZKSyncTestingTree::empty();
+        for i in 0..10u8 {
+            let _ = tree.insert_leaf(&[i; 32], ZkSyncStorageLeaf::from_value([i; 32]));
+        }
+        let mut expected = ZKSyncTestingTree::from_dump(&tree.dump());
+        let _ = expected.insert_leaf(&[2u8; 32], ZkSyncStorageLeaf::from_value([100u8; 32]));
+        let _ = expected.insert_leaf(&[50u8; 32], ZkSyncStorageLeaf::from_value([101u8; 32]));
+        let _ = expected.insert_leaf(&[40u8; 32], ZkSyncStorageLeaf::from_value([102u8; 32]));
+        let _ = expected.insert_leaf(&[7u8; 32], ZkSyncStorageLeaf::from_value([103u8; 32]));
+
+        let diff = tree.state_diff(&expected);
+        let initial_writes_pubdata = serialize_pubdata(diff.initial_writes.iter());
+        let repeated_writes_pubdata = serialize_pubdata(diff.repeated_writes.iter());
+        assert_eq!(initial_writes_pubdata.len(), 4 + 2 * 64);
+        assert_eq!(repeated_writes_pubdata.len(), 4 + 2 * 40);
+
+        let initial_writes = parse_initial_writes_pubdata(&initial_writes_pubdata);
+        let repeated_writes = parse_repeated_writes_pubdata(&repeated_writes_pubdata);
+        assert_eq!(initial_writes, diff.initial_writes);
+        assert_eq!(repeated_writes, diff.repeated_writes);
+
+        let (root, next_enumeration_index) =
+            apply_storage_pubdata(&mut tree, &initial_writes, &repeated_writes);
+        assert_eq!(root, expected.root());
+        assert_eq!(next_enumeration_index, expected.next_enumeration_index());
+    }
+}
@@ -0,0 +1,317 @@
+use crate::pairing::Engine;
+use crate::witness::call_tree::{format_address, format_u256};
+use crate::witness::full_block_artifact::FullBlockArtifacts;
+use crate::witness::tree::{
+    BinaryHasher, BinarySparseStorageTree, EnumeratedBinaryLeaf, ZkSyncStorageLeaf,
+};
+use derivative::Derivative;
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use zk_evm::aux_structures::LogQuery;
+use zk_evm::ethereum_types::{Address, U256};
+
+// serialization widths of the items hashed by the pubdata hashers
+pub const INITIAL_WRITE_PUBDATA_BYTES: usize = 64;
+pub const REPEATED_WRITE_PUBDATA_BYTES: usize = 40;
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FinalWriteKind {
+    Initial,
+    Repeated,
+}
+
+impl FinalWriteKind {
+    pub fn pubdata_bytes(&self) -> usize {
+        match self {
+            FinalWriteKind::Initial => INITIAL_WRITE_PUBDATA_BYTES,
+            FinalWriteKind::Repeated => REPEATED_WRITE_PUBDATA_BYTES,
+        }
+    }
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct StorageSlotAccessStats {
+    pub address: String,
+    pub key: String,
+    pub reads: usize,
+    // writes are counted even if they were rolled back later
+    pub writes: usize,
+    pub rolled_back_writes: usize,
+    // values before and after the block, if the slot ended up in the deduplicated queue
+    pub initial_value: Option<String>,
+    pub final_value: Option<String>,
+    pub protective_read: bool,
+    pub final_write: Option<FinalWriteKind>,
+    pub num_refunds: usize,
+    pub total_refund: u64,
+    pub pubdata_bytes: usize,
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct ContractStorageAccessStats {
+    pub address: String,
+    pub reads: usize,
+    pub writes: usize,
+    pub rolled_back_writes: usize,
+    pub initial_writes: usize,
+    pub repeated_writes: usize,
+    pub total_refund: u64,
+    pub pubdata_bytes: usize,
+    // ordered by key
+    pub slots: Vec<StorageSlotAccessStats>,
+}
+
+// Aggregated storage accesses of the block in the rollup shard. Contracts are ordered by the
+// pubdata they are responsible for, most expensive first
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct StorageAccessReport {
+    pub total_reads: usize,
+    pub total_writes: usize,
+    pub total_rolled_back_writes: usize,
+    pub total_refund: u64,
+    pub total_pubdata_bytes: usize,
+    pub contracts: Vec<ContractStorageAccessStats>,
+}
+
+impl StorageSlotAccessStats {
+    fn new(address: &Address, key: &U256) -> Self {
+        Self {
+            address: format_address(address),
+            key: format_u256(key),
+            reads: 0,
+            writes: 0,
+            rolled_back_writes: 0,
+            initial_value: None,
+            final_value: None,
+            protective_read: false,
+            final_write: None,
+            num_refunds: 0,
+            total_refund: 0,
+            pubdata_bytes: 0,
+        }
+    }
+}
+
+impl ContractStorageAccessStats {
+    fn from_slots(address: &Address, slots: Vec<StorageSlotAccessStats>) -> Self {
+        let mut result = Self {
+            address: format_address(address),
+            reads: 0,
+            writes: 0,
+            rolled_back_writes: 0,
+            initial_writes: 0,
+            repeated_writes: 0,
+            total_refund: 0,
+            pubdata_bytes: 0,
+            slots: vec![],
+        };
+        for el in slots.iter() {
+            result.reads += el.reads;
+            result.writes += el.writes;
+            result.rolled_back_writes += el.rolled_back_writes;
+            match el.final_write {
+                Some(FinalWriteKind::Initial) => result.initial_writes += 1,
+                Some(FinalWriteKind::Repeated) => result.repeated_writes += 1,
+                None => {}
+            }
+            result.total_refund += el.total_refund;
+            result.pubdata_bytes += el.pubdata_bytes;
+        }
+        result.slots = slots;
+
+        result
+    }
+}
+
+type SlotsByContract = BTreeMap<Address, BTreeMap<U256, StorageSlotAccessStats>>;
+
+fn slot_stats<'a>(
+    slots: &'a mut SlotsByContract,
+    address: &Address,
+    key: &U256,
+) -> &'a mut StorageSlotAccessStats {
+    slots
+        .entry(*address)
+        .or_default()
+        .entry(*key)
+        .or_insert_with(|| StorageSlotAccessStats::new(address, key))
+}
+
+impl StorageAccessReport {
+    // `previous_block_tree` is the state before the block, so the final writes can be split
+    // into initial and repeated ones the same way as for the pubdata hashers. Refunds are
+    // the ones recorded by the witness tracer
+    pub fn from_block_artifacts<E: Engine, H: BinaryHasher<32>>(
+        artifacts: &FullBlockArtifacts<E>,
+        refunds_logs: &[(u32, LogQuery, u32)],
+        previous_block_tree: &impl BinarySparseStorageTree<256, 32, 32, 8, 32, H, ZkSyncStorageLeaf>,
+    ) -> Self {
+        assert!(artifacts.is_processed, "artifacts must be processed");
+
+        let mut slots = BTreeMap::new();
+
+        for el in artifacts.demuxed_rollup_storage_queries.iter() {
+            let stats = slot_stats(&mut slots, &el.address, &el.key);
+            match (el.rw_flag, el.rollback) {
+                (false, _) => stats.reads += 1,
+                (true, false) => stats.writes += 1,
+                (true, true) => stats.rolled_back_writes += 1,
+            }
+        }
+
+        for (_cycle, query, refund) in refunds_logs.iter() {
+            if query.shard_id != 0 {
+                continue;
+            }
+            let stats = slot_stats(&mut slots, &query.address, &query.key);
+            stats.num_refunds += 1;
+            stats.total_refund += *refund as u64;
+        }
+
+        let net_writes: Vec<_> = artifacts
+            .deduplicated_rollup_storage_queries
+            .iter()
+            .filter(|el| el.rw_flag)
+            .collect();
+        let net_write_keys: Vec<_> = net_writes
+            .iter()
+            .map(|el| el.derive_final_address())
+            .collect();
+        let (_, first_writes, _) = previous_block_tree.filter_renumerate(
+            net_write_keys.iter(),
+            net_writes.iter().map(|_| ZkSyncStorageLeaf::empty()),
+        );
+        let initial_keys: HashSet<_> = first_writes.into_iter().map(|(key, _)| key).collect();
+
+        for el in artifacts.deduplicated_rollup_storage_queries.iter() {
+            let stats = slot_stats(&mut slots, &el.address, &el.key);
+            stats.initial_value = Some(format_u256(&el.read_value));
+            stats.final_value = Some(format_u256(&el.written_value));
+            if el.rw_flag {
+                let kind = if initial_keys.contains(&el.derive_final_address()) {
+                    FinalWriteKind::Initial
+                } else {
+                    FinalWriteKind::Repeated
+                };
+                stats.final_write = Some(kind);
+                stats.pubdata_bytes = kind.pubdata_bytes();
+            } else {
+                stats.protective_read = true;
+            }
+        }
+
+        let mut contracts: Vec<_> = slots
+            .into_iter()
+            .map(|(address, slots)| {
+                ContractStorageAccessStats::from_slots(&address, slots.into_values().collect())
+            })
+            .collect();
+        // stable, so equally expensive ones remain ordered by address
+        contracts.sort_by(|a, b| b.pubdata_bytes.cmp(&a.pubdata_bytes));
+
+        Self {
+            total_reads: contracts.iter().map(|el| el.reads).sum(),
+            total_writes: contracts.iter().map(|el| el.writes).sum(),
+            total_rolled_back_writes: contracts.iter().map(|el| el.rolled_back_writes).sum(),
+            total_refund: contracts.iter().map(|el| el.total_refund).sum(),
+            total_pubdata_bytes: contracts.iter().map(|el| el.pubdata_bytes).sum(),
+            contracts,
+        }
+    }
+
+    pub fn write_json<W: Write>(&self, dst: W) {
+        serde_json::to_writer_pretty(dst, self).expect("must serialize the storage access report");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bellman::bn256::Bn256;
+    use crate::witness::sort_storage_access::sort_storage_access_queries;
+    use crate::witness::tree::ZKSyncTestingTree;
+    use zk_evm::aux_structures::Timestamp;
+    use zk_evm::zkevm_opcode_defs::system_params::STORAGE_AUX_BYTE;
+
+    fn storage_query(
+        address: u64,
+        key: u64,
+        read_value: u64,
+        written_value: u64,
+        rw_flag: bool,
+        rollback: bool,
+    ) -> LogQuery {
+        LogQuery {
+            timestamp: Timestamp(0),
+            tx_number_in_block: 0,
+            aux_byte: STORAGE_AUX_BYTE,
+            shard_id: 0,
+            address: Address::from_low_u64_be(address),
+            key: U256::from(key),
+            read_value: U256::from(read_value),
+            written_value: U256::from(written_value),
+            rw_flag,
+            rollback,
+            is_service: false,
+        }
+    }
+
+    #[test]
+    fn test_storage_access_report() {
+        let mut tree = ZKSyncTestingTree::empty();
+        let existing = storage_query(0x10000, 1, 0, 0, false, false).derive_final_address();
+        let _ = tree.insert_leaf(&existing, ZkSyncStorageLeaf::from_value([0u8; 32]));
+
+        let queries = vec![
+            // repeated write of existing slot
+            storage_query(0x10000, 1, 0, 0, false, false),
+            storage_query(0x10000, 1, 0, 5, true, false),
+            // initial write
+            storage_query(0x10000, 2, 0, 7, true, false),
+            // written and rolled back, but read before
+            storage_query(0x20000, 1, 0, 0, false, false),
+            storage_query(0x20000, 1, 0, 3, true, false),
+            storage_query(0x20000, 1, 0, 3, true, true),
+        ];
+
+        let mut artifacts = FullBlockArtifacts::<Bn256>::default();
+        artifacts.is_processed = true;
+        artifacts.demuxed_rollup_storage_queries = queries.clone();
+        let (_, deduplicated) = sort_storage_access_queries(queries.iter());
+        artifacts.deduplicated_rollup_storage_queries = deduplicated;
+        let refunds_logs = vec![(10, queries[1], 100), (20, queries[2], 0)];
+
+        let report = StorageAccessReport::from_block_artifacts(&artifacts, &refunds_logs, &tree);
+        assert_eq!(report.total_reads, 2);
+        assert_eq!(report.total_writes, 3);
+        assert_eq!(report.total_rolled_back_writes, 1);
+        assert_eq!(report.total_refund, 100);
+        assert_eq!(
+            report.total_pubdata_bytes,
+            INITIAL_WRITE_PUBDATA_BYTES + REPEATED_WRITE_PUBDATA_BYTES
+        );
+
+        let contract = &report.contracts[0];
+        assert_eq!(
+            contract.address,
+            format_address(&Address::from_low_u64_be(0x10000))
+        );
+        assert_eq!(contract.initial_writes, 1);
+        assert_eq!(contract.repeated_writes, 1);
+        assert_eq!(
+            contract.slots[0].final_write,
+            Some(FinalWriteKind::Repeated)
+        );
+        assert_eq!(contract.slots[0].num_refunds, 1);
+        assert_eq!(contract.slots[1].final_write, Some(FinalWriteKind::Initial));
+
+        let rolled_back = &report.contracts[1].slots[0];
+        assert_eq!(rolled_back.final_write, None);
+        assert!(rolled_back.protective_read);
+        assert_eq!(rolled_back.pubdata_bytes, 0);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::witness::callstack_handler::CallstackWithAuxData;
+use crate::witness::cycle_trace::CycleTraceSink;
 use zk_evm::abstractions::PrecompileCyclesWitness;
 use zk_evm::aux_structures::LogQuery;
 use zk_evm::aux_structures::*;
@@ -83,6 +84,10 @@ pub struct WitnessTracer {
     pub callstack_with_aux_data: CallstackWithAuxData,
     pub sponge_busy_range: HashSet<usize>,
     pub vm_snapshots: Vec<VmSnapshot>,
+    // cycles at which the transaction number in block has changed, and the new number
+    pub tx_number_changes: Vec<(u32, u16)>,
+    // optional per-cycle trace, no-op by default
+    pub cycle_trace_sink: CycleTraceSink,
     // we need to properly preserve the information about logs. Not just flattening them into something,
     // but also keep the markers on when new frame has started and has finished, and the final frame execution
     // result, so we can properly substitute hash chain results in there for non-determinism
@@ -143,6 +148,8 @@ impl WitnessTracer {
             callstack_with_aux_data: CallstackWithAuxData::empty(),
             sponge_busy_range: HashSet::with_capacity(8),
             vm_snapshots: vec![],
+            tx_number_changes: vec![],
+            cycle_trace_sink: CycleTraceSink::default(),
         }
     }
 }
@@ -274,11 +281,26 @@ impl VmWitnessTracer<8, EncodingModeProduction> for WitnessTracer {
             }
         }
 
+        self.callstack_with_aux_data.observe_cycle(
+            current_state.monotonic_cycle_counter,
+            current_state.callstack.current.ergs_remaining,
+            current_state.pending_exception,
+        );
+        if self.tx_number_changes.last().map(|el| el.1) != Some(current_state.tx_number_in_block) {
+            self.tx_number_changes.push((
+                current_state.monotonic_cycle_counter,
+                current_state.tx_number_in_block,
+            ));
+        }
+        self.cycle_trace_sink.begin_cycle(current_state);
+
         // monotonic counter always increases
         self.current_cycle_counter += 1;
     }
 
-    fn end_execution_cycle(&mut self, _current_state: &VmLocalState) {
+    fn end_execution_cycle(&mut self, current_state: &VmLocalState) {
+        self.cycle_trace_sink.end_cycle(current_state);
+
         // dbg!(&self.sponge_busy_range);
         if !self.sponge_busy_range.is_empty() {
             for i in 0..NUM_SPONGES {
@@ -310,6 +332,8 @@ impl VmWitnessTracer<8, EncodingModeProduction> for WitnessTracer {
     }
 
     fn add_memory_query(&mut self, monotonic_cycle_counter: u32, memory_query: MemoryQuery) {
+        self.cycle_trace_sink
+            .add_memory_query(monotonic_cycle_counter, &memory_query);
         self.memory_queries
             .push((monotonic_cycle_counter, memory_query));
     }
@@ -326,6 +350,8 @@ impl VmWitnessTracer<8, EncodingModeProduction> for WitnessTracer {
     }
 
     fn add_log_query(&mut self, monotonic_cycle_counter: u32, log_query: LogQuery) {
+        self.cycle_trace_sink
+            .add_log_query(monotonic_cycle_counter, &log_query);
         // log both reads and writes
         if log_query.aux_byte == STORAGE_AUX_BYTE {
             self.storage_queries
@@ -342,6 +368,8 @@ impl VmWitnessTracer<8, EncodingModeProduction> for WitnessTracer {
         decommittment_query: DecommittmentQuery,
         mem_witness: Vec<U256>,
     ) {
+        self.cycle_trace_sink
+            .add_decommittment(monotonic_cycle_counter, &decommittment_query);
         // this will literally form the queue of decommittment queries, one to one
         self.decommittment_queries.push((
             monotonic_cycle_counter,
@@ -0,0 +1,519 @@
+use super::*;
+use std::collections::LinkedList;
+
+// subtrees with less insertions than this are hashed in the current thread
+pub const PARALLEL_SUBTREE_THRESHOLD: usize = 64;
+
+// Batch insertion produces exactly the same state and the same per-leaf queries
+// as inserting leafs one by one, but every node is touched only once per batch.
+//
+// Insertions are identified by their sequence number in the batch. Every node of the tree is
+// a function of time, and the merkle path of insertion `seq` consists of values of
+// neighbour nodes right before `seq` (after every insertion with smaller sequence number).
+// We walk the tree from the root, splitting insertions into the left and right subtrees
+// (that effectively sorts them by index), and for every node we only compute values at the
+// points of time that are requested by the parent: either to form the parent's own value at
+// those points, or to serve as a path element for insertions into the neighbour subtree.
+// Independent subtrees are processed in parallel
+struct BatchContext<'a, const DEPTH: usize, const INDEX_BYTES: usize> {
+    layers: &'a [HashMap<[u8; INDEX_BYTES], [u8; 32]>; DEPTH],
+    empty_hashes: &'a [[u8; 32]; DEPTH],
+    indexes: &'a [[u8; INDEX_BYTES]],
+    leaf_hashes: &'a [[u8; 32]],
+}
+
+struct SubtreeUpdate {
+    final_hash: [u8; 32],
+    // values before every requested point of time, in the same order as requested
+    observed: Vec<[u8; 32]>,
+    // (level, index, hash) of every updated node at the end of the batch
+    nodes: LinkedList<Vec<(usize, usize, [u8; 32])>>,
+    // (seq, level, hash) of path elements
+    path_elements: LinkedList<Vec<(usize, usize, [u8; 32])>>,
+}
+
+impl<'a, const DEPTH: usize, const INDEX_BYTES: usize> BatchContext<'a, DEPTH, INDEX_BYTES> {
+    fn stored_node(&self, level: usize, index: &[u8; INDEX_BYTES]) -> [u8; 32] {
+        let mut index = *index;
+        for bit in 0..level {
+            let word_idx = bit / 8;
+            let bit_idx = bit % 8;
+            index[word_idx] = index[word_idx] & (!(1 << bit_idx));
+        }
+
+        if let Some(node_hash) = self.layers[level].get(&index) {
+            *node_hash
+        } else {
+            self.empty_hashes[level]
+        }
+    }
+
+    // `seqs` are insertions into the subtree of the node at `level` in increasing order,
+    // and `points` are requested points of time in increasing order. Those are insertions
+    // into this subtree except the first one, as other values are known to the caller
+    fn update_subtree<H: BinaryHasher<32>>(
+        &self,
+        level: usize,
+        seqs: &[usize],
+        points: &[usize],
+    ) -> SubtreeUpdate {
+        debug_assert!(!seqs.is_empty());
+        let representative_index = self.indexes[seqs[0]];
+
+        if level == 0 {
+            // all insertions are into the same leaf
+            let observed = points
+                .iter()
+                .map(|p| {
+                    let pos = seqs.partition_point(|s| s < p);
+                    debug_assert!(pos > 0);
+                    self.leaf_hashes[seqs[pos - 1]]
+                })
+                .collect();
+            let final_hash = self.leaf_hashes[*seqs.last().unwrap()];
+
+            let mut nodes = LinkedList::new();
+            nodes.push_back(vec![(0, seqs[0], final_hash)]);
+
+            return SubtreeUpdate {
+                final_hash,
+                observed,
+                nodes,
+                path_elements: LinkedList::new(),
+            };
+        }
+
+        if seqs.len() == 1 {
+            // nothing is observed, so it's the same as sequential insertion
+            let seq = seqs[0];
+            let mut nodes = Vec::with_capacity(level);
+            let mut path_elements = Vec::with_capacity(level);
+            let mut current_hash = self.leaf_hashes[seq];
+            for child_level in 0..level {
+                nodes.push((child_level, seq, current_hash));
+                let pair_idx = create_neighbour_index(&representative_index, child_level);
+                let pair_node_hash = self.stored_node(child_level, &pair_idx);
+                path_elements.push((seq, child_level, pair_node_hash));
+
+                current_hash = if is_right_side_node(&representative_index, child_level) {
+                    H::node_hash(child_level, &pair_node_hash, &current_hash)
+                } else {
+                    H::node_hash(child_level, &current_hash, &pair_node_hash)
+                };
+            }
+            if level < DEPTH {
+                nodes.push((level, seq, current_hash));
+            }
+
+            return SubtreeUpdate {
+                final_hash: current_hash,
+                observed: vec![],
+                nodes: LinkedList::from([nodes]),
+                path_elements: LinkedList::from([path_elements]),
+            };
+        }
+
+        let child_level = level - 1;
+        let (right_seqs, left_seqs): (Vec<usize>, Vec<usize>) = seqs
+            .iter()
+            .partition(|s| is_right_side_node(&self.indexes[**s], child_level));
+
+        let (left, right) = match (left_seqs.is_empty(), right_seqs.is_empty()) {
+            (false, false) => {
+                let left_points = points_for_child(&left_seqs, points, &right_seqs);
+                let right_points = points_for_child(&right_seqs, points, &left_seqs);
+
+                let (left, right) = if seqs.len() >= PARALLEL_SUBTREE_THRESHOLD {
+                    rayon::join(
+                        || self.update_subtree::<H>(child_level, &left_seqs, &left_points),
+                        || self.update_subtree::<H>(child_level, &right_seqs, &right_points),
+                    )
+                } else {
+                    (
+                        self.update_subtree::<H>(child_level, &left_seqs, &left_points),
+                        self.update_subtree::<H>(child_level, &right_seqs, &right_points),
+                    )
+                };
+
+                (
+                    ChildHistory::Updated {
+                        seqs: &left_seqs,
+                        initial: self.stored_node(child_level, &self.indexes[left_seqs[0]]),
+                        points: left_points,
+                        update: left,
+                    },
+                    ChildHistory::Updated {
+                        seqs: &right_seqs,
+                        initial: self.stored_node(child_level, &self.indexes[right_seqs[0]]),
+                        points: right_points,
+                        update: right,
+                    },
+                )
+            }
+            (false, true) => {
+                let neighbour_index = create_neighbour_index(&representative_index, child_level);
+                let update = self.update_subtree::<H>(child_level, seqs, points);
+
+                (
+                    ChildHistory::Updated {
+                        seqs,
+                        initial: self.stored_node(child_level, &representative_index),
+                        points: points.to_vec(),
+                        update,
+                    },
+                    ChildHistory::Untouched(self.stored_node(child_level, &neighbour_index)),
+                )
+            }
+            (true, false) => {
+                let neighbour_index = create_neighbour_index(&representative_index, child_level);
+                let update = self.update_subtree::<H>(child_level, seqs, points);
+
+                (
+                    ChildHistory::Untouched(self.stored_node(child_level, &neighbour_index)),
+                    ChildHistory::Updated {
+                        seqs,
+                        initial: self.stored_node(child_level, &representative_index),
+                        points: points.to_vec(),
+                        update,
+                    },
+                )
+            }
+            (true, true) => unreachable!(),
+        };
+
+        let observed = points
+            .iter()
+            .map(|p| H::node_hash(child_level, &left.value_before(*p), &right.value_before(*p)))
+            .collect();
+        let final_hash = H::node_hash(child_level, &left.final_hash(), &right.final_hash());
+
+        // path element of every insertion on this level is the other child
+        let mut this_level_path_elements = Vec::with_capacity(seqs.len());
+        for s in left_seqs.iter() {
+            this_level_path_elements.push((*s, child_level, right.value_before(*s)));
+        }
+        for s in right_seqs.iter() {
+            this_level_path_elements.push((*s, child_level, left.value_before(*s)));
+        }
+
+        let mut nodes = LinkedList::new();
+        let mut path_elements = LinkedList::new();
+        for child in [left, right] {
+            if let ChildHistory::Updated { update, .. } = child {
+                let mut update = update;
+                nodes.append(&mut update.nodes);
+                path_elements.append(&mut update.path_elements);
+            }
+        }
+        if level < DEPTH {
+            nodes.push_back(vec![(level, seqs[0], final_hash)]);
+        }
+        path_elements.push_back(this_level_path_elements);
+
+        SubtreeUpdate {
+            final_hash,
+            observed,
+            nodes,
+            path_elements,
+        }
+    }
+}
+
+enum ChildHistory<'b> {
+    Untouched([u8; 32]),
+    Updated {
+        seqs: &'b [usize],
+        initial: [u8; 32],
+        points: Vec<usize>,
+        update: SubtreeUpdate,
+    },
+}
+
+impl<'b> ChildHistory<'b> {
+    fn value_before(&self, point: usize) -> [u8; 32] {
+        match self {
+            ChildHistory::Untouched(value) => *value,
+            ChildHistory::Updated {
+                seqs,
+                initial,
+                points,
+                update,
+            } => {
+                // value only changes at the insertions into this subtree
+                let num_applied = seqs.partition_point(|s| *s < point);
+                if num_applied == 0 {
+                    *initial
+                } else if num_applied == seqs.len() {
+                    update.final_hash
+                } else {
+                    let pos = points.binary_search(&seqs[num_applied]).unwrap();
+                    update.observed[pos]
+                }
+            }
+        }
+    }
+
+    fn final_hash(&self) -> [u8; 32] {
+        match self {
+            ChildHistory::Untouched(value) => *value,
+            ChildHistory::Updated { update, .. } => update.final_hash,
+        }
+    }
+}
+
+// points of time at which the child must be known to the parent: parent's own points and
+// insertions into the neighbour. Every point is replaced by the next insertion into the child,
+// as the child doesn't change in between, and points with trivially known values are skipped
+fn points_for_child(
+    seqs: &[usize],
+    parent_points: &[usize],
+    neighbour_seqs: &[usize],
+) -> Vec<usize> {
+    let mut result = vec![];
+    let mut a = parent_points.iter().peekable();
+    let mut b = neighbour_seqs.iter().peekable();
+    loop {
+        let next = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) => {
+                if x <= y {
+                    a.next()
+                } else {
+                    b.next()
+                }
+            }
+            (Some(_), None) => a.next(),
+            (None, Some(_)) => b.next(),
+            (None, None) => break,
+        };
+        let num_applied = seqs.partition_point(|s| s < next.unwrap());
+        if num_applied == 0 || num_applied == seqs.len() {
+            continue;
+        }
+        let point = seqs[num_applied];
+        if result.last() != Some(&point) {
+            result.push(point);
+        }
+    }
+
+    result
+}
+
+impl<
+        const DEPTH: usize,
+        const INDEX_BYTES: usize,
+        const LEAF_METADATA_WIDTH: usize,
+        H: BinaryHasher<32>,
+        L: EnumeratedBinaryLeaf<32>,
+    > InMemoryStorageTree<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>
+{
+    // same as sequential `insert_leaf` for every pair, but hashes every updated node only once
+    // (in addition to the path elements that are observed by later insertions), and does it in parallel
+    pub fn insert_many_leafs_batched(
+        &mut self,
+        indexes: &[[u8; INDEX_BYTES]],
+        leafs: Vec<L>,
+    ) -> Vec<LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L>> {
+        use rayon::prelude::*;
+
+        assert_eq!(indexes.len(), leafs.len());
+        if indexes.is_empty() {
+            return vec![];
+        }
+
+        // enumerate in the order of insertion, same as `insert_leaf`
+        let mut inserted_leafs = Vec::with_capacity(leafs.len());
+        let mut first_writes = Vec::with_capacity(leafs.len());
+        for (index, leaf) in indexes.iter().zip(leafs.into_iter()) {
+            let mut first_write = false;
+            if let Some(existing_leaf) = self.leafs.get_mut(index) {
+                existing_leaf.set_value(leaf.value());
+            } else {
+                let mut leaf = leaf;
+                first_write = true;
+                leaf.set_index(self.next_enumeration_index);
+                self.leafs.insert(*index, leaf);
+                self.key_index.insert(index, self.next_enumeration_index);
+                self.next_enumeration_index += 1;
+            }
+
+            inserted_leafs.push(self.leafs.get(index).cloned().unwrap());
+            first_writes.push(first_write);
+        }
+
+        let leaf_encodings: Vec<Vec<u8>> = inserted_leafs
+            .iter()
+            .map(|leaf| {
+                let mut leaf_bytes = vec![0u8; LEAF_METADATA_WIDTH + 32];
+                leaf_bytes[LEAF_METADATA_WIDTH..].copy_from_slice(leaf.value());
+                let leaf_index_bytes = leaf.current_index().to_be_bytes();
+                leaf_bytes[(LEAF_METADATA_WIDTH - 8)..LEAF_METADATA_WIDTH]
+                    .copy_from_slice(&leaf_index_bytes);
+
+                leaf_bytes
+            })
+            .collect();
+        let leaf_hashes: Vec<[u8; 32]> = leaf_encodings
+            .par_iter()
+            .map(|el| H::leaf_hash(el))
+            .collect();
+
+        let context = BatchContext::<DEPTH, INDEX_BYTES> {
+            layers: &self.layers,
+            empty_hashes: &self.empty_hashes,
+            indexes,
+            leaf_hashes: &leaf_hashes,
+        };
+        let all_seqs: Vec<usize> = (0..indexes.len()).collect();
+        let update = context.update_subtree::<H>(DEPTH, &all_seqs, &[]);
+
+        self.root = update.final_hash;
+        for chunk in update.nodes.into_iter() {
+            for (level, seq, hash) in chunk.into_iter() {
+                self.insert_path_element(level, indexes[seq], hash);
+            }
+        }
+
+        let mut paths: Vec<Box<[[u8; 32]; DEPTH]>> = (0..indexes.len())
+            .map(|_| Box::new([[0u8; 32]; DEPTH]))
+            .collect();
+        for chunk in update.path_elements.into_iter() {
+            for (seq, level, hash) in chunk.into_iter() {
+                paths[seq][level] = hash;
+            }
+        }
+
+        let mut result = Vec::with_capacity(indexes.len());
+        for (((index, leaf), first_write), path) in indexes
+            .iter()
+            .zip(inserted_leafs.into_iter())
+            .zip(first_writes.into_iter())
+            .zip(paths.into_iter())
+        {
+            result.push(LeafQuery {
+                leaf,
+                first_write,
+                index: *index,
+                merkle_path: path,
+            });
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn random_leafs(
+        num_leafs: usize,
+        num_distinct_keys: usize,
+        seed: u64,
+    ) -> (Vec<[u8; 32]>, Vec<ZkSyncStorageLeaf>) {
+        use crate::sha3::{Digest, Keccak256};
+
+        let mut indexes = vec![];
+        let mut leafs = vec![];
+        for i in 0..num_leafs {
+            let mut key_preimage = seed.to_be_bytes().to_vec();
+            key_preimage.extend(((i % num_distinct_keys) as u64).to_be_bytes());
+            let mut index = [0u8; 32];
+            index.copy_from_slice(Keccak256::digest(&key_preimage).as_slice());
+
+            let mut value_preimage = key_preimage.clone();
+            value_preimage.extend((i as u64).to_be_bytes());
+            let mut value = [0u8; 32];
+            value.copy_from_slice(Keccak256::digest(&value_preimage).as_slice());
+
+            indexes.push(index);
+            leafs.push(ZkSyncStorageLeaf::from_value(value));
+        }
+
+        (indexes, leafs)
+    }
+
+    fn assert_same_queries(
+        a: &[LeafQuery<256, 32, 32, 32, ZkSyncStorageLeaf>],
+        b: &[LeafQuery<256, 32, 32, 32, ZkSyncStorageLeaf>],
+    ) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert_eq!(a.index, b.index);
+            assert_eq!(a.first_write, b.first_write);
+            assert_eq!(a.leaf.index, b.leaf.index);
+            assert_eq!(a.leaf.value, b.leaf.value);
+            assert_eq!(a.merkle_path, b.merkle_path);
+        }
+    }
+
+    fn insert_sequentially_and_in_batch(
+        sequential: &mut ZKSyncTestingTree,
+        batched: &mut ZKSyncTestingTree,
+        indexes: &[[u8; 32]],
+        leafs: Vec<ZkSyncStorageLeaf>,
+    ) {
+        let mut sequential_queries = vec![];
+        let mut intermediate_roots = vec![];
+        for (index, leaf) in indexes.iter().zip(leafs.iter()) {
+            sequential_queries.push(sequential.insert_leaf(index, *leaf));
+            intermediate_roots.push(sequential.root);
+        }
+
+        let batched_queries = batched.insert_many_leafs_batched(indexes, leafs);
+        assert_same_queries(&sequential_queries, &batched_queries);
+        // every query is a valid witness for the root right after it
+        for (query, root) in batched_queries.iter().zip(intermediate_roots.iter()) {
+            assert!(ZKSyncTestingTree::verify_inclusion(root, query));
+        }
+
+        assert_eq!(sequential.root, batched.root);
+        assert_eq!(
+            sequential.next_enumeration_index,
+            batched.next_enumeration_index
+        );
+        for level in 0..256 {
+            assert_eq!(sequential.layers[level], batched.layers[level]);
+        }
+    }
+
+    #[test]
+    fn test_batched_insert_equivalence() {
+        let mut sequential = ZKSyncTestingTree::empty();
+        let mut batched = ZKSyncTestingTree::empty();
+
+        // empty tree, some keys are written more than once
+        let (indexes, leafs) = random_leafs(300, 200, 0);
+        insert_sequentially_and_in_batch(&mut sequential, &mut batched, &indexes, leafs);
+
+        // updates of existing leafs mixed with new ones
+        let (mut indexes, mut leafs) = random_leafs(100, 100, 0);
+        let (new_indexes, new_leafs) = random_leafs(100, 50, 1);
+        indexes.extend(new_indexes);
+        leafs.extend(new_leafs);
+        insert_sequentially_and_in_batch(&mut sequential, &mut batched, &indexes, leafs);
+
+        // single leaf and empty batch
+        let (indexes, leafs) = random_leafs(1, 1, 2);
+        insert_sequentially_and_in_batch(&mut sequential, &mut batched, &indexes, leafs);
+        insert_sequentially_and_in_batch(&mut sequential, &mut batched, &[], vec![]);
+    }
+
+    #[test]
+    fn test_batched_insert_neighbour_leafs() {
+        // leafs that share the longest possible paths
+        let mut sequential = ZKSyncTestingTree::empty();
+        let mut batched = ZKSyncTestingTree::empty();
+
+        let mut indexes = vec![];
+        let mut leafs = vec![];
+        for i in 0..40u8 {
+            let mut index = [0u8; 32];
+            index[0] = i % 8;
+            index[31] = i % 3;
+            indexes.push(index);
+            leafs.push(ZkSyncStorageLeaf::from_value([i; 32]));
+        }
+
+        insert_sequentially_and_in_batch(&mut sequential, &mut batched, &indexes, leafs);
+    }
+}
@@ -0,0 +1,306 @@
+use super::proofs::{deserialize_fixed_bytes, leaf_hash, normalize_index, serialize_fixed_bytes};
+use super::*;
+use crate::encodings::initial_storage_write::InitialStorageWrite;
+use crate::encodings::repeated_storage_write::RepeatedStorageWrite;
+use std::io::{Read, Write};
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StorageTreeDumpLeaf<const INDEX_BYTES: usize> {
+    #[serde(serialize_with = "serialize_fixed_bytes")]
+    #[serde(deserialize_with = "deserialize_fixed_bytes")]
+    pub index: [u8; INDEX_BYTES],
+    pub enumeration_index: u64,
+    pub value: [u8; 32],
+}
+
+// All the leafs of the tree, sorted by enumeration index. Root is only used to check
+// the consistency of the dump on import
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+pub struct StorageTreeDump<const INDEX_BYTES: usize> {
+    pub root: [u8; 32],
+    pub next_enumeration_index: u64,
+    pub leafs: Vec<StorageTreeDumpLeaf<INDEX_BYTES>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageTreeDumpFormat {
+    Json,
+    Binary,
+}
+
+impl<const INDEX_BYTES: usize> StorageTreeDump<INDEX_BYTES> {
+    pub fn write_to<W: Write>(&self, dst: W, format: StorageTreeDumpFormat) {
+        match format {
+            StorageTreeDumpFormat::Json => {
+                serde_json::to_writer_pretty(dst, self).expect("must serialize the tree dump")
+            }
+            StorageTreeDumpFormat::Binary => {
+                bincode::serialize_into(dst, self).expect("must serialize the tree dump")
+            }
+        }
+    }
+
+    pub fn read_from<R: Read>(src: R, format: StorageTreeDumpFormat) -> Self {
+        match format {
+            StorageTreeDumpFormat::Json => {
+                serde_json::from_reader(src).expect("must deserialize the tree dump")
+            }
+            StorageTreeDumpFormat::Binary => {
+                bincode::deserialize_from(src).expect("must deserialize the tree dump")
+            }
+        }
+    }
+}
+
+// Difference between two states of the tree in the form of storage application pubdata.
+// Initial writes are ordered by enumeration index, that is the order in which those were
+// published. Order of repeated writes in the pubdata depends on the order of storage
+// queries that is not known to the tree, so those are also ordered by enumeration index
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq, Default)]
+pub struct StateDiff {
+    pub initial_writes: Vec<InitialStorageWrite>,
+    pub repeated_writes: Vec<RepeatedStorageWrite>,
+}
+
+// `changes` are (key, previous leaf if any, new leaf)
+pub(crate) fn state_diff_from_changes<L: EnumeratedBinaryLeaf<32>>(
+    changes: impl Iterator<Item = ([u8; 32], Option<L>, L)>,
+) -> StateDiff {
+    let mut initial_writes = vec![];
+    let mut repeated_writes = vec![];
+    for (key, previous, current) in changes {
+        match previous {
+            None => {
+                initial_writes.push((current.current_index(), key, *current.value()));
+            }
+            Some(previous) => {
+                assert_eq!(
+                    previous.current_index(),
+                    current.current_index(),
+                    "enumeration index of key {} has changed",
+                    hex::encode(&key)
+                );
+                if previous.value() != current.value() {
+                    repeated_writes.push((current.current_index(), *current.value()));
+                }
+            }
+        }
+    }
+
+    initial_writes.sort_by_key(|el| el.0);
+    repeated_writes.sort_by_key(|el| el.0);
+
+    StateDiff {
+        initial_writes: initial_writes
+            .into_iter()
+            .map(|(_, key, value)| InitialStorageWrite { key, value })
+            .collect(),
+        repeated_writes: repeated_writes
+            .into_iter()
+            .map(|(index, value)| RepeatedStorageWrite { index, value })
+            .collect(),
+    }
+}
+
+impl<
+        const DEPTH: usize,
+        const INDEX_BYTES: usize,
+        const LEAF_METADATA_WIDTH: usize,
+        H: BinaryHasher<32>,
+        L: EnumeratedBinaryLeaf<32>,
+    > InMemoryStorageTree<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>
+{
+    pub fn dump(&self) -> StorageTreeDump<INDEX_BYTES> {
+        let mut leafs: Vec<_> = self
+            .leafs
+            .iter()
+            .map(|(index, leaf)| StorageTreeDumpLeaf {
+                index: *index,
+                enumeration_index: leaf.current_index(),
+                value: *leaf.value(),
+            })
+            .collect();
+        leafs.sort_by_key(|el| el.enumeration_index);
+
+        StorageTreeDump {
+            root: self.root,
+            next_enumeration_index: self.next_enumeration_index,
+            leafs,
+        }
+    }
+
+    // rebuilds the tree from the leafs and checks that the root is the same as in the dump
+    pub fn from_dump(dump: &StorageTreeDump<INDEX_BYTES>) -> Self {
+        let mut tree = Self::new();
+        let mut used_enumeration_indexes = std::collections::HashSet::new();
+        for el in dump.leafs.iter() {
+            assert!(
+                el.enumeration_index != L::empty_index()
+                    && el.enumeration_index < dump.next_enumeration_index,
+                "invalid enumeration index {} for key {}",
+                el.enumeration_index,
+                hex::encode(&el.index)
+            );
+            assert!(
+                used_enumeration_indexes.insert(el.enumeration_index),
+                "duplicate enumeration index {}",
+                el.enumeration_index
+            );
+
+            let mut leaf = L::from_value(el.value);
+            leaf.set_index(el.enumeration_index);
+            let existing = tree.leafs.insert(el.index, leaf);
+            assert!(
+                existing.is_none(),
+                "duplicate key {}",
+                hex::encode(&el.index)
+            );
+            tree.key_index.insert(&el.index, el.enumeration_index);
+        }
+        tree.next_enumeration_index = dump.next_enumeration_index;
+        tree.recompute_nodes();
+
+        assert_eq!(
+            tree.root,
+            dump.root,
+            "root of the imported tree {} does not match the one in the dump {}",
+            hex::encode(&tree.root),
+            hex::encode(&dump.root)
+        );
+
+        tree
+    }
+
+    // recomputes all the nodes from the leafs level by level
+    fn recompute_nodes(&mut self) {
+        let mut current_level: HashMap<[u8; INDEX_BYTES], [u8; 32]> = self
+            .leafs
+            .iter()
+            .map(|(index, leaf)| (*index, leaf_hash::<LEAF_METADATA_WIDTH, H, L>(leaf)))
+            .collect();
+
+        for level in 0..DEPTH {
+            let mut next_level = HashMap::with_capacity(current_level.len());
+            for (index, node_hash) in current_level.iter() {
+                let parent_index = normalize_index(index, level + 1);
+                if next_level.contains_key(&parent_index) {
+                    continue;
+                }
+                let neighbour_index = create_neighbour_index(index, level);
+                let pair_node_hash = current_level
+                    .get(&neighbour_index)
+                    .unwrap_or(&self.empty_hashes[level]);
+
+                let (l, r) = if is_right_side_node(index, level) {
+                    (pair_node_hash, node_hash)
+                } else {
+                    (node_hash, pair_node_hash)
+                };
+                next_level.insert(parent_index, H::node_hash(level, l, r));
+            }
+
+            self.layers[level] = current_level;
+            current_level = next_level;
+        }
+
+        if let Some(root) = current_level.values().next() {
+            self.root = *root;
+        } else {
+            self.root = Self::new().root;
+        }
+    }
+}
+
+impl<
+        const DEPTH: usize,
+        const LEAF_METADATA_WIDTH: usize,
+        H: BinaryHasher<32>,
+        L: EnumeratedBinaryLeaf<32>,
+    > InMemoryStorageTree<DEPTH, 32, LEAF_METADATA_WIDTH, H, L>
+{
+    // writes that transform this tree into the `newer` one
+    pub fn state_diff(&self, newer: &Self) -> StateDiff {
+        assert!(self.next_enumeration_index <= newer.next_enumeration_index);
+        for index in self.leafs.keys() {
+            assert!(
+                newer.leafs.contains_key(index),
+                "key {} is missing in the newer tree",
+                hex::encode(index)
+            );
+        }
+
+        state_diff_from_changes(
+            newer
+                .leafs
+                .iter()
+                .map(|(index, leaf)| (*index, self.leafs.get(index).cloned(), leaf.clone())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tree_dump_and_state_diff() {
+        let mut tree = ZKSyncTestingTree::empty();
+        for i in 0..20u8 {
+            let _ = tree.insert_leaf(&[i; 32], ZkSyncStorageLeaf::from_value([i + 1; 32]));
+        }
+
+        for format in [StorageTreeDumpFormat::Json, StorageTreeDumpFormat::Binary] {
+            let mut buffer = vec![];
+            tree.dump().write_to(&mut buffer, format);
+            let dump = StorageTreeDump::<32>::read_from(&buffer[..], format);
+            assert_eq!(dump, tree.dump());
+
+            let imported = ZKSyncTestingTree::from_dump(&dump);
+            assert_eq!(imported.root, tree.root);
+            assert_eq!(imported.next_enumeration_index, tree.next_enumeration_index);
+            for level in 0..256 {
+                assert_eq!(imported.layers[level], tree.layers[level]);
+            }
+        }
+
+        let mut newer = ZKSyncTestingTree::from_dump(&tree.dump());
+        // rewrite with the same value is not a diff
+        let _ = newer.insert_leaf(&[3u8; 32], ZkSyncStorageLeaf::from_value([4u8; 32]));
+        let _ = newer.insert_leaf(&[200u8; 32], ZkSyncStorageLeaf::from_value([1u8; 32]));
+        let _ = newer.insert_leaf(&[7u8; 32], ZkSyncStorageLeaf::from_value([0u8; 32]));
+        let _ = newer.insert_leaf(&[100u8; 32], ZkSyncStorageLeaf::from_value([2u8; 32]));
+        let _ = newer.insert_leaf(&[2u8; 32], ZkSyncStorageLeaf::from_value([5u8; 32]));
+
+        let diff = tree.state_diff(&newer);
+        assert_eq!(
+            diff.initial_writes,
+            vec![
+                InitialStorageWrite {
+                    key: [200u8; 32],
+                    value: [1u8; 32]
+                },
+                InitialStorageWrite {
+                    key: [100u8; 32],
+                    value: [2u8; 32]
+                },
+            ]
+        );
+        assert_eq!(
+            diff.repeated_writes,
+            vec![
+                RepeatedStorageWrite {
+                    index: 3,
+                    value: [5u8; 32]
+                },
+                RepeatedStorageWrite {
+                    index: 8,
+                    value: [0u8; 32]
+                },
+            ]
+        );
+        assert_eq!(newer.state_diff(&newer), StateDiff::default());
+    }
+}
@@ -0,0 +1,187 @@
+use super::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::{Bound, RangeBounds};
+
+// Secondary index over the occupied leafs of the tree: enumeration index -> key, and keys
+// in the sorted order. Keys are never removed from the tree, except by rolling back to the
+// earlier state, and those are exactly the keys enumerated after that state
+#[derive(Derivative)]
+#[derivative(Clone, Debug, Default(bound = ""))]
+pub struct LeafKeyIndex<const INDEX_BYTES: usize> {
+    keys_by_enumeration_index: BTreeMap<u64, [u8; INDEX_BYTES]>,
+    sorted_keys: BTreeSet<[u8; INDEX_BYTES]>,
+}
+
+// range of keys that start with the prefix
+pub fn prefix_range<const INDEX_BYTES: usize>(
+    prefix: &[u8],
+) -> (Bound<[u8; INDEX_BYTES]>, Bound<[u8; INDEX_BYTES]>) {
+    assert!(
+        prefix.len() <= INDEX_BYTES,
+        "prefix of {} bytes is longer than the key",
+        prefix.len()
+    );
+    let mut start = [0u8; INDEX_BYTES];
+    start[..prefix.len()].copy_from_slice(prefix);
+    let mut end = [0xffu8; INDEX_BYTES];
+    end[..prefix.len()].copy_from_slice(prefix);
+
+    (Bound::Included(start), Bound::Included(end))
+}
+
+impl<const INDEX_BYTES: usize> LeafKeyIndex<INDEX_BYTES> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // should be called for every write, it's a no-op for the keys that are already indexed
+    pub fn insert(&mut self, key: &[u8; INDEX_BYTES], enumeration_index: u64) {
+        if self.sorted_keys.insert(*key) {
+            let existing = self
+                .keys_by_enumeration_index
+                .insert(enumeration_index, *key);
+            assert!(
+                existing.is_none(),
+                "enumeration index {} is already used by key {}",
+                enumeration_index,
+                hex::encode(&existing.unwrap())
+            );
+        }
+    }
+
+    // removes keys that were enumerated at or after `next_enumeration_index`
+    pub fn truncate(&mut self, next_enumeration_index: u64) {
+        let removed = self
+            .keys_by_enumeration_index
+            .split_off(&next_enumeration_index);
+        for key in removed.values() {
+            self.sorted_keys.remove(key);
+        }
+    }
+
+    pub fn key_by_enumeration_index(&self, enumeration_index: u64) -> Option<&[u8; INDEX_BYTES]> {
+        self.keys_by_enumeration_index.get(&enumeration_index)
+    }
+
+    pub fn keys_in_range(
+        &self,
+        range: impl RangeBounds<[u8; INDEX_BYTES]>,
+    ) -> std::collections::btree_set::Range<'_, [u8; INDEX_BYTES]> {
+        self.sorted_keys.range(range)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sorted_keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sorted_keys.is_empty()
+    }
+}
+
+impl<
+        const DEPTH: usize,
+        const INDEX_BYTES: usize,
+        const LEAF_METADATA_WIDTH: usize,
+        H: BinaryHasher<32>,
+        L: EnumeratedBinaryLeaf<32>,
+    > InMemoryStorageTree<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>
+{
+    pub fn leaf_by_enumeration_index(
+        &self,
+        enumeration_index: u64,
+    ) -> Option<([u8; INDEX_BYTES], L)> {
+        let key = self.key_index.key_by_enumeration_index(enumeration_index)?;
+        let leaf = self.leafs.get(key).cloned().unwrap();
+        debug_assert_eq!(leaf.current_index(), enumeration_index);
+
+        Some((*key, leaf))
+    }
+
+    // occupied leafs with keys in the range, ordered by key
+    pub fn leafs_in_range(
+        &self,
+        range: impl RangeBounds<[u8; INDEX_BYTES]>,
+    ) -> Vec<([u8; INDEX_BYTES], L)> {
+        self.key_index
+            .keys_in_range(range)
+            .map(|key| (*key, self.leafs.get(key).cloned().unwrap()))
+            .collect()
+    }
+
+    pub fn num_occupied_leafs(&self) -> usize {
+        debug_assert_eq!(self.key_index.len(), self.leafs.len());
+        self.key_index.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_leaf_key_index() {
+        let mut tree = ZKSyncTestingTree::empty();
+        let mut batched = ZKSyncTestingTree::empty();
+        let mut versioned = ZKSyncVersionedTree::empty();
+        let mut indexes = vec![];
+        let mut leafs = vec![];
+        for i in (0..40u8).rev() {
+            let mut index = [i; 32];
+            index[0] = i % 4;
+            let leaf = ZkSyncStorageLeaf::from_value([i; 32]);
+            let _ = tree.insert_leaf(&index, leaf);
+            let _ = versioned.insert_leaf(&index, leaf);
+            indexes.push(index);
+            leafs.push(leaf);
+        }
+        let _ = batched.insert_many_leafs(&indexes, leafs);
+        let _ = tree.insert_leaf(&indexes[5], ZkSyncStorageLeaf::from_value([0xff; 32]));
+        let imported = ZKSyncTestingTree::from_dump(&tree.dump());
+
+        assert_eq!(tree.num_occupied_leafs(), 40);
+        assert_eq!(BinarySparseStorageTree::num_occupied_leafs(&versioned), 40);
+        assert_eq!(imported.num_occupied_leafs(), 40);
+        assert_eq!(batched.num_occupied_leafs(), 40);
+
+        let (key, leaf) = tree.leaf_by_enumeration_index(6).unwrap();
+        assert_eq!(key, indexes[5]);
+        assert_eq!(leaf.value(), &[0xff; 32]);
+        assert_eq!(imported.leaf_by_enumeration_index(6), Some((key, leaf)));
+        assert_eq!(
+            batched.leaf_by_enumeration_index(40).unwrap().0,
+            indexes[39]
+        );
+        assert!(tree.leaf_by_enumeration_index(0).is_none());
+        assert!(tree.leaf_by_enumeration_index(41).is_none());
+
+        let with_prefix = tree.leafs_with_prefix(&[2]);
+        assert_eq!(with_prefix.len(), 10);
+        assert!(with_prefix.windows(2).all(|el| el[0].0 < el[1].0));
+        assert!(with_prefix.iter().all(|(key, _)| key[0] == 2));
+        assert_eq!(imported.leafs_with_prefix(&[2]), with_prefix);
+
+        let in_range = tree.leafs_in_range([1u8; 32]..[3u8; 32]);
+        assert_eq!(in_range.len(), 20);
+        assert_eq!(
+            BinarySparseStorageTree::leafs_in_range(&versioned, [1u8; 32]..[3u8; 32])
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>(),
+            in_range.iter().map(|(key, _)| *key).collect::<Vec<_>>()
+        );
+
+        // rolled back keys are removed from the index
+        let version = versioned.commit_version();
+        let _ = versioned.insert_leaf(&[0xffu8; 32], ZkSyncStorageLeaf::from_value([1; 32]));
+        assert_eq!(
+            BinarySparseStorageTree::leaf_by_enumeration_index(&versioned, 41)
+                .unwrap()
+                .0,
+            [0xffu8; 32]
+        );
+        versioned.rollback_to(version);
+        assert!(BinarySparseStorageTree::leaf_by_enumeration_index(&versioned, 41).is_none());
+        assert_eq!(BinarySparseStorageTree::num_occupied_leafs(&versioned), 40);
+    }
+}
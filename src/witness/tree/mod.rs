@@ -1,5 +1,22 @@
 use crate::witness::utils::take_queue_state_from_simulator;
 use crate::witness::utils::transform_queue_witness;
+
+pub mod batch;
+pub mod dump;
+pub mod key_index;
+pub mod persistent;
+pub mod proofs;
+pub mod rescue_hasher;
+pub mod versioned;
+pub use self::dump::{StateDiff, StorageTreeDump, StorageTreeDumpFormat};
+pub use self::key_index::LeafKeyIndex;
+pub use self::persistent::{PersistentStorageTree, ZKSyncPersistentTree};
+pub use self::proofs::{
+    verify_compact_leaf_proof, verify_leaf_query, verify_multi_proof, CompactLeafProof, MultiProof,
+};
+pub use self::rescue_hasher::{RescueHasher, ZkSyncRescueTestingTree};
+pub use self::versioned::{VersionedStorageTree, ZKSyncVersionedTree};
+
 pub trait EnumeratedBinaryLeaf<const LEAF_DATA_WIDTH: usize>: Clone + std::hash::Hash {
     fn empty() -> Self;
     fn empty_index() -> u64 {
@@ -26,6 +43,11 @@ pub trait BinaryHasher<const HASH_OUTPUT_WIDTH: usize>: Clone + Send + Sync {
     fn leaf_hash(leaf: &[u8]) -> [u8; HASH_OUTPUT_WIDTH];
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(bound(
+    serialize = "L: serde::Serialize",
+    deserialize = "L: serde::de::DeserializeOwned"
+))]
 pub struct LeafQuery<
     const DEPTH: usize,
     const INDEX_BYTES: usize,
@@ -35,7 +57,11 @@ pub struct LeafQuery<
 > {
     pub leaf: L,
     pub first_write: bool,
+    #[serde(serialize_with = "self::proofs::serialize_fixed_bytes")]
+    #[serde(deserialize_with = "self::proofs::deserialize_fixed_bytes")]
     pub index: [u8; INDEX_BYTES],
+    #[serde(serialize_with = "self::proofs::serialize_merkle_path")]
+    #[serde(deserialize_with = "self::proofs::deserialize_merkle_path")]
     pub merkle_path: Box<[[u8; HASH_OUTPUT_WIDTH]; DEPTH]>, // too large
 }
 
@@ -93,6 +119,17 @@ pub trait BinarySparseStorageTree<
     ) -> bool {
         Self::verify_inclusion(root, query)
     }
+    // key and leaf that were enumerated with such index, e.g. to resolve repeated writes
+    fn leaf_by_enumeration_index(&self, enumeration_index: u64) -> Option<([u8; INDEX_BYTES], L)>;
+    // occupied leafs with keys in the range, ordered by key
+    fn leafs_in_range(
+        &self,
+        range: impl std::ops::RangeBounds<[u8; INDEX_BYTES]>,
+    ) -> Vec<([u8; INDEX_BYTES], L)>;
+    fn leafs_with_prefix(&self, prefix: &[u8]) -> Vec<([u8; INDEX_BYTES], L)> {
+        self.leafs_in_range(self::key_index::prefix_range(prefix))
+    }
+    fn num_occupied_leafs(&self) -> usize;
 }
 
 pub type ZKSyncTestingTree = InMemoryStorageTree<256, 32, 8, Blake2s256, ZkSyncStorageLeaf>;
@@ -112,7 +149,9 @@ pub struct InMemoryStorageTree<
     pub empty_hashes: Box<[[u8; 32]; DEPTH]>,
     pub root: [u8; 32],
     pub layers: [HashMap<[u8; INDEX_BYTES], [u8; 32]>; DEPTH],
+    // every new key should also be added into the key index
     pub leafs: HashMap<[u8; INDEX_BYTES], L>,
+    pub key_index: LeafKeyIndex<INDEX_BYTES>,
 }
 
 fn create_neighbour_index<const N: usize>(index: &[u8; N], depth: usize) -> [u8; N] {
@@ -181,6 +220,7 @@ impl<
             root,
             layers: layers,
             leafs: HashMap::new(),
+            key_index: LeafKeyIndex::new(),
         }
     }
 
@@ -235,29 +275,7 @@ impl<
     }
 
     fn verify_inclusion(root: &[u8; 32], query: &LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L>) -> bool {
-        let mut leaf_bytes = vec![0u8; LEAF_METADATA_WIDTH + 32]; // can make a scratch space somewhere later on
-        leaf_bytes[LEAF_METADATA_WIDTH..].copy_from_slice(query.leaf.value());
-
-        let leaf_index_bytes = query.leaf.current_index().to_be_bytes();
-        leaf_bytes[(LEAF_METADATA_WIDTH - 8)..LEAF_METADATA_WIDTH]
-            .copy_from_slice(&leaf_index_bytes);
-
-        let leaf_hash = H::leaf_hash(&leaf_bytes);
-
-        let mut current_hash = leaf_hash;
-        for level in 0..DEPTH {
-            let (l, r) = if is_right_side_node(&query.index, level) {
-                (&query.merkle_path[level], &current_hash)
-            } else {
-                (&current_hash, &query.merkle_path[level])
-            };
-
-            let this_level_hash = H::node_hash(level, l, r);
-
-            current_hash = this_level_hash;
-        }
-
-        root == &current_hash
+        verify_leaf_query::<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>(root, query)
     }
 
     // fn filter_renumerate(&self, indexes: &[[u8; INDEX_BYTES]], leafs: &[L]) -> (u64, Vec<L>, Vec<L>) {
@@ -305,6 +323,7 @@ impl<
             first_write = true;
             leaf.set_index(self.next_enumeration_index);
             self.leafs.insert(*index, leaf);
+            self.key_index.insert(index, self.next_enumeration_index);
             self.next_enumeration_index += 1;
         }
 
@@ -380,6 +399,13 @@ impl<
     ) -> LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L> {
         Self::insert_leaf(self, index, leaf)
     }
+    fn insert_many_leafs(
+        &mut self,
+        indexes: &[[u8; INDEX_BYTES]],
+        leafs: Vec<L>,
+    ) -> Vec<LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L>> {
+        Self::insert_many_leafs_batched(self, indexes, leafs)
+    }
     // fn filter_renumerate(&self, indexes: &[[u8; INDEX_BYTES]], leafs: &[L]) -> (u64, Vec<L>, Vec<L>) {
     fn filter_renumerate<'a>(
         &self,
@@ -391,6 +417,18 @@ impl<
     fn verify_inclusion(root: &[u8; 32], query: &LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L>) -> bool {
         Self::verify_inclusion(root, query)
     }
+    fn leaf_by_enumeration_index(&self, enumeration_index: u64) -> Option<([u8; INDEX_BYTES], L)> {
+        Self::leaf_by_enumeration_index(self, enumeration_index)
+    }
+    fn leafs_in_range(
+        &self,
+        range: impl std::ops::RangeBounds<[u8; INDEX_BYTES]>,
+    ) -> Vec<([u8; INDEX_BYTES], L)> {
+        Self::leafs_in_range(self, range)
+    }
+    fn num_occupied_leafs(&self) -> usize {
+        Self::num_occupied_leafs(self)
+    }
 }
 
 use crate::blake2::{Blake2s256, Digest};
@@ -445,8 +483,8 @@ impl BinaryHasher<32> for Keccak256 {
 
 use derivative::Derivative;
 
-#[derive(Derivative)]
-#[derivative(Clone, Copy, Hash, Debug)]
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Copy, Hash, Debug, PartialEq, Eq)]
 pub struct ZkSyncStorageLeaf {
     pub index: u64,
     pub value: [u8; 32],
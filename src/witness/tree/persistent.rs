@@ -0,0 +1,645 @@
+use super::proofs::normalize_index;
+use super::*;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const TREE_LOG_FILE_NAME: &str = "tree.log";
+pub const DEFAULT_COMPACTION_THRESHOLD: usize = 1 << 22;
+
+pub type ZKSyncPersistentTree = PersistentStorageTree<256, 32, 8, Blake2s256, ZkSyncStorageLeaf>;
+
+// we do not use const generic arrays here, so records are serializable for any index width
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug)]
+enum LogRecord {
+    Leaf {
+        index: Vec<u8>,
+        enumeration_index: u64,
+        value: [u8; 32],
+    },
+    Node {
+        level: u16,
+        index: Vec<u8>,
+        hash: [u8; 32],
+    },
+    // everything before this record forms a consistent state of the tree with such root
+    Commit {
+        root: [u8; 32],
+        next_enumeration_index: u64,
+    },
+}
+
+// Tree that keeps the full state in memory (as `InMemoryStorageTree`), and also appends every
+// update to the node log in the directory. `commit_block` closes a consistent state of the tree
+// (e.g. at the end of the block), so the tree can be reopened at the latest or any recorded root.
+// Updates after the last commit are lost on reopen. The log is periodically compacted into
+// a single snapshot of the current state, so roots recorded before the compaction are forgotten
+pub struct PersistentStorageTree<
+    const DEPTH: usize,
+    const INDEX_BYTES: usize,
+    const LEAF_METADATA_WIDTH: usize,
+    H: BinaryHasher<32>,
+    L: EnumeratedBinaryLeaf<32>,
+> {
+    pub inner: InMemoryStorageTree<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>,
+    pub directory: PathBuf,
+    pub compaction_threshold: usize,
+    // not present if the tree is opened at a past root
+    log: Option<std::io::BufWriter<std::fs::File>>,
+    records_since_compaction: usize,
+}
+
+fn write_record<W: Write>(dst: &mut W, record: &LogRecord) {
+    bincode::serialize_into(dst, record).expect("must write a record into the tree log");
+}
+
+impl<
+        const DEPTH: usize,
+        const INDEX_BYTES: usize,
+        const LEAF_METADATA_WIDTH: usize,
+        H: BinaryHasher<32>,
+        L: EnumeratedBinaryLeaf<32>,
+    > PersistentStorageTree<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>
+{
+    // opens the tree at the latest committed state, or creates an empty one
+    pub fn open<P: AsRef<Path>>(directory: P) -> Self {
+        let directory = directory.as_ref().to_path_buf();
+        std::fs::create_dir_all(&directory).expect("must create a directory for the tree");
+
+        let (inner, committed_length, num_records) = Self::replay_log(&directory, None);
+        // updates that were not committed can not be continued
+        Self::truncate_log(&directory, committed_length);
+
+        Self {
+            inner,
+            directory: directory.clone(),
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
+            log: Some(Self::open_log_for_append(&directory)),
+            records_since_compaction: num_records,
+        }
+    }
+
+    // opens the tree at the state with a given root. History after it is kept, so such tree
+    // is read-only. Use `rollback_to_root` to continue from this state
+    pub fn open_at_root<P: AsRef<Path>>(directory: P, root: [u8; 32]) -> Self {
+        let directory = directory.as_ref().to_path_buf();
+        let (inner, _, num_records) = Self::replay_log(&directory, Some(root));
+
+        Self {
+            inner,
+            directory,
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
+            log: None,
+            records_since_compaction: num_records,
+        }
+    }
+
+    // drops everything that was committed after the state with a given root from the log,
+    // and continues from this state
+    pub fn rollback_to_root(&mut self, root: [u8; 32]) {
+        if let Some(log) = self.log.as_mut() {
+            log.flush().expect("must flush the tree log");
+        }
+        self.log = None;
+
+        let (inner, committed_length, num_records) = Self::replay_log(&self.directory, Some(root));
+        Self::truncate_log(&self.directory, committed_length);
+
+        self.inner = inner;
+        self.log = Some(Self::open_log_for_append(&self.directory));
+        self.records_since_compaction = num_records;
+    }
+
+    // returns the state at the given root (or at the latest commit), the length of the log
+    // up to the commit of this state, and the number of records in it
+    fn replay_log(
+        directory: &Path,
+        at_root: Option<[u8; 32]>,
+    ) -> (
+        InMemoryStorageTree<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>,
+        u64,
+        usize,
+    ) {
+        let log_path = directory.join(TREE_LOG_FILE_NAME);
+
+        let mut content = vec![];
+        if log_path.exists() {
+            let mut file = std::fs::File::open(&log_path).unwrap();
+            file.read_to_end(&mut content).unwrap();
+        }
+
+        let mut inner = InMemoryStorageTree::<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>::new();
+
+        // replay the log. An incomplete record at the end (if process was killed in the middle of the write),
+        // or records that are not followed by a commit are ignored
+        let mut cursor = std::io::Cursor::new(&content[..]);
+        let mut pending = vec![];
+        let mut num_records = 0;
+        let mut committed_length = 0u64;
+        let mut found_root = at_root.is_none() || at_root == Some(inner.root);
+
+        while !(at_root.is_some() && found_root) {
+            let record: LogRecord = match bincode::deserialize_from(&mut cursor) {
+                Ok(record) => record,
+                Err(_) => break,
+            };
+
+            match record {
+                LogRecord::Commit {
+                    root,
+                    next_enumeration_index,
+                } => {
+                    num_records += pending.len() + 1;
+                    for el in pending.drain(..) {
+                        Self::apply_record(&mut inner, el);
+                    }
+                    inner.root = root;
+                    inner.next_enumeration_index = next_enumeration_index;
+                    committed_length = cursor.position();
+
+                    if at_root == Some(root) {
+                        found_root = true;
+                    }
+                }
+                el => {
+                    pending.push(el);
+                }
+            }
+        }
+
+        assert!(
+            found_root,
+            "root 0x{} was not found in the tree log",
+            hex::encode(&at_root.unwrap())
+        );
+
+        (inner, committed_length, num_records)
+    }
+
+    // cuts everything after the given length. Prefix is copied into a new file that replaces the log,
+    // so the log is never left half-truncated
+    fn truncate_log(directory: &Path, length: u64) {
+        let log_path = directory.join(TREE_LOG_FILE_NAME);
+        let current_length = match std::fs::metadata(&log_path) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return,
+        };
+        if current_length == length {
+            return;
+        }
+
+        let mut content = vec![];
+        let mut file = std::fs::File::open(&log_path).unwrap();
+        file.read_to_end(&mut content).unwrap();
+
+        let tmp_path = directory.join(format!("{}.tmp", TREE_LOG_FILE_NAME));
+        {
+            let mut file = std::fs::File::create(&tmp_path).unwrap();
+            file.write_all(&content[..length as usize]).unwrap();
+            file.sync_all().unwrap();
+        }
+        std::fs::rename(&tmp_path, &log_path).expect("must replace the tree log");
+    }
+
+    fn open_log_for_append(directory: &Path) -> std::io::BufWriter<std::fs::File> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(directory.join(TREE_LOG_FILE_NAME))
+            .expect("must open the tree log");
+
+        std::io::BufWriter::new(file)
+    }
+
+    fn log(&mut self) -> &mut std::io::BufWriter<std::fs::File> {
+        self.log
+            .as_mut()
+            .expect("tree is opened at a past root, use `rollback_to_root` to modify it")
+    }
+
+    fn apply_record(
+        tree: &mut InMemoryStorageTree<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>,
+        record: LogRecord,
+    ) {
+        match record {
+            LogRecord::Leaf {
+                index,
+                enumeration_index,
+                value,
+            } => {
+                let index: [u8; INDEX_BYTES] = index.try_into().expect("invalid index width");
+                let mut leaf = L::from_value(value);
+                leaf.set_index(enumeration_index);
+                tree.leafs.insert(index, leaf);
+                tree.key_index.insert(&index, enumeration_index);
+            }
+            LogRecord::Node { level, index, hash } => {
+                let index: [u8; INDEX_BYTES] = index.try_into().expect("invalid index width");
+                // same normalization as on the lookup, even if the record was written without it
+                tree.insert_path_element(level as usize, index, hash);
+            }
+            LogRecord::Commit { .. } => {
+                unreachable!()
+            }
+        }
+    }
+
+    // closes the current state of the tree, so it's restored on reopen
+    pub fn commit_block(&mut self) {
+        let record = LogRecord::Commit {
+            root: self.inner.root,
+            next_enumeration_index: self.inner.next_enumeration_index,
+        };
+        let log = self.log();
+        write_record(log, &record);
+        log.flush().expect("must flush the tree log");
+        self.records_since_compaction += 1;
+
+        if self.records_since_compaction >= self.compaction_threshold {
+            self.compact();
+        }
+    }
+
+    // rewrites the log as a single snapshot of the current state. Updates that were not committed
+    // yet become committed
+    pub fn compact(&mut self) {
+        // makes sure that the tree is writable
+        self.log().flush().expect("must flush the tree log");
+
+        let log_path = self.directory.join(TREE_LOG_FILE_NAME);
+        let tmp_path = self.directory.join(format!("{}.tmp", TREE_LOG_FILE_NAME));
+
+        let mut num_records = 0;
+        {
+            let file = std::fs::File::create(&tmp_path).unwrap();
+            let mut dst = std::io::BufWriter::new(file);
+            for (index, leaf) in self.inner.leafs.iter() {
+                let record = LogRecord::Leaf {
+                    index: index.to_vec(),
+                    enumeration_index: leaf.current_index(),
+                    value: *leaf.value(),
+                };
+                write_record(&mut dst, &record);
+                num_records += 1;
+            }
+            for (level, layer) in self.inner.layers.iter().enumerate() {
+                for (index, hash) in layer.iter() {
+                    let record = LogRecord::Node {
+                        level: level as u16,
+                        index: index.to_vec(),
+                        hash: *hash,
+                    };
+                    write_record(&mut dst, &record);
+                    num_records += 1;
+                }
+            }
+            write_record(
+                &mut dst,
+                &LogRecord::Commit {
+                    root: self.inner.root,
+                    next_enumeration_index: self.inner.next_enumeration_index,
+                },
+            );
+            num_records += 1;
+
+            let file = dst.into_inner().unwrap();
+            file.sync_all().unwrap();
+        }
+
+        std::fs::rename(&tmp_path, &log_path).expect("must replace the tree log");
+
+        self.log = Some(Self::open_log_for_append(&self.directory));
+        self.records_since_compaction = num_records;
+    }
+
+    pub fn insert_leaf(
+        &mut self,
+        index: &[u8; INDEX_BYTES],
+        leaf: L,
+    ) -> LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L> {
+        assert!(
+            self.log.is_some(),
+            "tree is opened at a past root, use `rollback_to_root` to modify it"
+        );
+        let query = self.inner.insert_leaf(index, leaf);
+
+        let log = self.log.as_mut().unwrap();
+        write_record(
+            log,
+            &LogRecord::Leaf {
+                index: index.to_vec(),
+                enumeration_index: query.leaf.current_index(),
+                value: *query.leaf.value(),
+            },
+        );
+        // nodes on the path of the leaf are the only ones that changed
+        for level in 0..DEPTH {
+            let hash = *self.inner.get_path_element(level, *index);
+            write_record(
+                log,
+                &LogRecord::Node {
+                    level: level as u16,
+                    index: normalize_index(index, level).to_vec(),
+                    hash,
+                },
+            );
+        }
+        self.records_since_compaction += 1 + DEPTH;
+
+        query
+    }
+}
+
+fn unique_temporary_directory() -> PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+
+    std::env::temp_dir().join(format!(
+        "zksync_persistent_tree_{}_{}_{}",
+        std::process::id(),
+        nanos,
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+impl<
+        const DEPTH: usize,
+        const INDEX_BYTES: usize,
+        const LEAF_METADATA_WIDTH: usize,
+        H: BinaryHasher<32>,
+        L: EnumeratedBinaryLeaf<32>,
+    > BinarySparseStorageTree<DEPTH, INDEX_BYTES, 32, LEAF_METADATA_WIDTH, 32, H, L>
+    for PersistentStorageTree<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>
+{
+    // creates a tree in a new temporary directory. Use `open` to choose the location
+    fn empty() -> Self {
+        Self::open(unique_temporary_directory())
+    }
+    fn next_enumeration_index(&self) -> u64 {
+        self.inner.next_enumeration_index
+    }
+    fn set_next_enumeration_index(&mut self, value: u64) {
+        self.inner.next_enumeration_index = value;
+    }
+    fn root(&self) -> [u8; 32] {
+        self.inner.root
+    }
+    fn get_leaf(&mut self, index: &[u8; INDEX_BYTES]) -> LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L> {
+        self.inner.get_leaf(index)
+    }
+    fn insert_leaf(
+        &mut self,
+        index: &[u8; INDEX_BYTES],
+        leaf: L,
+    ) -> LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L> {
+        Self::insert_leaf(self, index, leaf)
+    }
+    fn filter_renumerate<'a>(
+        &self,
+        indexes: impl Iterator<Item = &'a [u8; INDEX_BYTES]>,
+        leafs: impl Iterator<Item = L>,
+    ) -> (u64, Vec<([u8; INDEX_BYTES], L)>, Vec<L>) {
+        self.inner.filter_renumerate(indexes, leafs)
+    }
+    fn verify_inclusion(root: &[u8; 32], query: &LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L>) -> bool {
+        InMemoryStorageTree::<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>::verify_inclusion(
+            root, query,
+        )
+    }
+    fn leaf_by_enumeration_index(&self, enumeration_index: u64) -> Option<([u8; INDEX_BYTES], L)> {
+        self.inner.leaf_by_enumeration_index(enumeration_index)
+    }
+    fn leafs_in_range(
+        &self,
+        range: impl std::ops::RangeBounds<[u8; INDEX_BYTES]>,
+    ) -> Vec<([u8; INDEX_BYTES], L)> {
+        self.inner.leafs_in_range(range)
+    }
+    fn num_occupied_leafs(&self) -> usize {
+        self.inner.num_occupied_leafs()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reopen_persistent_tree() {
+        let directory = unique_temporary_directory();
+
+        let mut tree = ZKSyncPersistentTree::open(&directory);
+        let mut reference = ZKSyncTestingTree::empty();
+        for i in 0..8u8 {
+            let index = [i; 32];
+            let leaf = ZkSyncStorageLeaf::from_value([i + 1; 32]);
+            tree.insert_leaf(&index, leaf);
+            reference.insert_leaf(&index, leaf);
+            assert_eq!(tree.root(), reference.root());
+        }
+        // overwrite
+        tree.insert_leaf(&[0u8; 32], ZkSyncStorageLeaf::from_value([42u8; 32]));
+        tree.commit_block();
+        let final_root = tree.root();
+        let final_enumeration_index = tree.next_enumeration_index();
+
+        // not committed, so lost on reopen
+        tree.insert_leaf(&[50u8; 32], ZkSyncStorageLeaf::from_value([1u8; 32]));
+        drop(tree);
+
+        let mut tree = ZKSyncPersistentTree::open(&directory);
+        assert_eq!(tree.root(), final_root);
+        assert_eq!(tree.next_enumeration_index(), final_enumeration_index);
+        let query = tree.get_leaf(&[0u8; 32]);
+        assert_eq!(query.leaf.value(), &[42u8; 32]);
+        assert!(ZKSyncPersistentTree::verify_inclusion(&final_root, &query));
+
+        // compaction does not change the state
+        tree.compact();
+        drop(tree);
+        let tree = ZKSyncPersistentTree::open(&directory);
+        assert_eq!(tree.root(), final_root);
+        drop(tree);
+
+        // roots before compaction are lost, so make a new history
+        let mut tree = ZKSyncPersistentTree::open(&directory);
+        tree.insert_leaf(&[100u8; 32], ZkSyncStorageLeaf::from_value([1u8; 32]));
+        tree.commit_block();
+        tree.insert_leaf(&[101u8; 32], ZkSyncStorageLeaf::from_value([2u8; 32]));
+        tree.commit_block();
+        let latest_root = tree.root();
+        drop(tree);
+
+        let mut tree = ZKSyncPersistentTree::open_at_root(&directory, final_root);
+        assert_eq!(tree.root(), final_root);
+        let query = tree.get_leaf(&[100u8; 32]);
+        assert_eq!(query.leaf.value(), &[0u8; 32]);
+        drop(tree);
+
+        // history after the root is kept
+        let mut tree = ZKSyncPersistentTree::open(&directory);
+        assert_eq!(tree.root(), latest_root);
+
+        // until it's explicitly dropped
+        tree.rollback_to_root(final_root);
+        assert_eq!(tree.root(), final_root);
+        drop(tree);
+        let tree = ZKSyncPersistentTree::open(&directory);
+        assert_eq!(tree.root(), final_root);
+        drop(tree);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_tree_opened_at_root_is_read_only() {
+        let directory = unique_temporary_directory();
+
+        let mut tree = ZKSyncPersistentTree::open(&directory);
+        let empty_root = tree.root();
+        tree.insert_leaf(&[1u8; 32], ZkSyncStorageLeaf::from_value([1u8; 32]));
+        tree.commit_block();
+        drop(tree);
+
+        let mut tree = ZKSyncPersistentTree::open_at_root(&directory, empty_root);
+        tree.insert_leaf(&[2u8; 32], ZkSyncStorageLeaf::from_value([2u8; 32]));
+    }
+
+    #[test]
+    fn test_insert_after_reopen_without_compaction() {
+        let directory = unique_temporary_directory();
+
+        let mut tree = ZKSyncPersistentTree::open(&directory);
+        let mut reference = ZKSyncTestingTree::empty();
+        for i in 0..8u8 {
+            let mut index = [0u8; 32];
+            index[0] = i;
+            index[31] = i;
+            let leaf = ZkSyncStorageLeaf::from_value([i + 1; 32]);
+            tree.insert_leaf(&index, leaf);
+            reference.insert_leaf(&index, leaf);
+        }
+        tree.commit_block();
+        let intermediate_root = tree.root();
+        let mut intermediate_reference = ZKSyncTestingTree::from_dump(&reference.dump());
+        drop(tree);
+
+        // siblings of new leafs were written by the previous session
+        let mut tree = ZKSyncPersistentTree::open(&directory);
+        assert_eq!(tree.root(), reference.root());
+        for i in 8..16u8 {
+            let mut index = [0u8; 32];
+            index[0] = i;
+            index[31] = i;
+            let leaf = ZkSyncStorageLeaf::from_value([i + 1; 32]);
+            tree.insert_leaf(&index, leaf);
+            reference.insert_leaf(&index, leaf);
+            assert_eq!(tree.root(), reference.root());
+        }
+        tree.commit_block();
+        drop(tree);
+
+        let mut tree = ZKSyncPersistentTree::open(&directory);
+        assert_eq!(tree.root(), reference.root());
+        let mut index = [0u8; 32];
+        index[0] = 3;
+        index[31] = 3;
+        let query = tree.get_leaf(&index);
+        assert_eq!(query.leaf.value(), &[4u8; 32]);
+        assert!(ZKSyncPersistentTree::verify_inclusion(
+            &reference.root(),
+            &query
+        ));
+
+        // same after rolling back to the earlier root
+        tree.rollback_to_root(intermediate_root);
+        let index = [200u8; 32];
+        let leaf = ZkSyncStorageLeaf::from_value([7u8; 32]);
+        tree.insert_leaf(&index, leaf);
+        tree.commit_block();
+        intermediate_reference.insert_leaf(&index, leaf);
+        assert_eq!(tree.root(), intermediate_reference.root());
+        assert!(!directory
+            .join(format!("{}.tmp", TREE_LOG_FILE_NAME))
+            .exists());
+        drop(tree);
+
+        let tree = ZKSyncPersistentTree::open(&directory);
+        assert_eq!(tree.root(), intermediate_reference.root());
+        drop(tree);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_persistent_tree_in_block_processing() {
+        use crate::tests::run_manually::{
+            generate_block_for_extended_state, generate_block_for_extended_state_with_tree,
+        };
+        use zkevm_assembly::Assembly;
+
+        let asm = r#"
+            .text
+            .file	"Test_26"
+            .rodata.cst32
+            .p2align	5
+            .text
+            .globl	__entry
+        __entry:
+        .main:
+            add 10000, r0, r1
+            add 1000, r0, r10
+            sstore r1, r10
+            sload r1, r2
+            event.first r1, r10
+            to_l1.first r0, r1
+            ret.ok r0
+        "#;
+
+        let mut assembly = Assembly::try_from(asm.to_owned()).unwrap();
+        let bytecode = assembly.compile_to_bytecode().unwrap();
+
+        let (circuits, inputs, _, header) =
+            generate_block_for_extended_state(bytecode.clone(), vec![], 50, false);
+
+        // storage application is done over the persistent tree
+        let directory = unique_temporary_directory();
+        let mut tree = ZKSyncPersistentTree::open(&directory);
+        let (persistent_circuits, persistent_inputs, _, persistent_header) =
+            generate_block_for_extended_state_with_tree(bytecode, vec![], 50, &mut tree);
+        tree.commit_block();
+        let final_root = tree.root();
+        let final_enumeration_index = tree.next_enumeration_index();
+        drop(tree);
+
+        assert_eq!(
+            inputs.into_flattened_set(),
+            persistent_inputs.into_flattened_set()
+        );
+        assert_eq!(
+            bincode::serialize(&circuits).unwrap(),
+            bincode::serialize(&persistent_circuits).unwrap()
+        );
+        assert_eq!(
+            header.block_data.per_shard_states[0].state_root,
+            persistent_header.block_data.per_shard_states[0].state_root
+        );
+        assert_eq!(
+            persistent_header.block_data.per_shard_states[0].state_root,
+            final_root
+        );
+
+        let tree = ZKSyncPersistentTree::open(&directory);
+        assert_eq!(tree.root(), final_root);
+        assert_eq!(tree.next_enumeration_index(), final_enumeration_index);
+        drop(tree);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,635 @@
+use super::*;
+use std::collections::BTreeMap;
+
+// serde only supports arrays of limited length, so fixed width byte strings and paths are serialized
+// as sequences of variable length and checked on deserialization
+pub(crate) fn serialize_fixed_bytes<S, const N: usize>(
+    value: &[u8; N],
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    use serde::Serialize;
+
+    value.as_slice().serialize(serializer)
+}
+
+pub(crate) fn deserialize_fixed_bytes<'de, D, const N: usize>(
+    deserializer: D,
+) -> Result<[u8; N], D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    use serde::Deserialize;
+
+    let bytes = Vec::<u8>::deserialize(deserializer)?;
+    bytes_to_array(&bytes).ok_or_else(|| D::Error::custom("invalid length of byte string"))
+}
+
+pub(crate) fn serialize_fixed_bytes_vec<S, const N: usize>(
+    value: &Vec<[u8; N]>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_seq(value.iter().map(|el| el.as_slice()))
+}
+
+pub(crate) fn deserialize_fixed_bytes_vec<'de, D, const N: usize>(
+    deserializer: D,
+) -> Result<Vec<[u8; N]>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    use serde::Deserialize;
+
+    let elements = Vec::<Vec<u8>>::deserialize(deserializer)?;
+    elements
+        .iter()
+        .map(|el| {
+            bytes_to_array(el).ok_or_else(|| D::Error::custom("invalid length of byte string"))
+        })
+        .collect()
+}
+
+pub(crate) fn serialize_merkle_path<S, const N: usize, const DEPTH: usize>(
+    path: &Box<[[u8; N]; DEPTH]>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_seq(path.iter().map(|el| el.as_slice()))
+}
+
+pub(crate) fn deserialize_merkle_path<'de, D, const N: usize, const DEPTH: usize>(
+    deserializer: D,
+) -> Result<Box<[[u8; N]; DEPTH]>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let elements = deserialize_fixed_bytes_vec::<D, N>(deserializer)?;
+    if elements.len() != DEPTH {
+        return Err(D::Error::custom("invalid length of merkle path"));
+    }
+    let mut path = Box::new([[0u8; N]; DEPTH]);
+    path.copy_from_slice(&elements);
+
+    Ok(path)
+}
+
+fn bytes_to_array<const N: usize>(bytes: &[u8]) -> Option<[u8; N]> {
+    bytes.try_into().ok()
+}
+
+// hashes of empty subtrees at every level, same as stored in `InMemoryStorageTree`
+pub fn empty_subtree_hashes<
+    const DEPTH: usize,
+    const LEAF_METADATA_WIDTH: usize,
+    H: BinaryHasher<32>,
+    L: EnumeratedBinaryLeaf<32>,
+>() -> Box<[[u8; 32]; DEPTH]> {
+    let mut empty_leaf = vec![0u8; LEAF_METADATA_WIDTH + 32];
+    empty_leaf[LEAF_METADATA_WIDTH..].copy_from_slice(L::empty().value());
+
+    let mut empty_hashes = Box::<[[u8; 32]; DEPTH]>::new([[0u8; 32]; DEPTH]);
+    empty_hashes[0] = H::leaf_hash(&empty_leaf);
+    for level in 1..DEPTH {
+        empty_hashes[level] =
+            H::node_hash(level, &empty_hashes[level - 1], &empty_hashes[level - 1]);
+    }
+
+    empty_hashes
+}
+
+pub fn leaf_hash<
+    const LEAF_METADATA_WIDTH: usize,
+    H: BinaryHasher<32>,
+    L: EnumeratedBinaryLeaf<32>,
+>(
+    leaf: &L,
+) -> [u8; 32] {
+    let mut leaf_bytes = vec![0u8; LEAF_METADATA_WIDTH + 32];
+    leaf_bytes[LEAF_METADATA_WIDTH..].copy_from_slice(leaf.value());
+
+    let leaf_index_bytes = leaf.current_index().to_be_bytes();
+    leaf_bytes[(LEAF_METADATA_WIDTH - 8)..LEAF_METADATA_WIDTH].copy_from_slice(&leaf_index_bytes);
+
+    H::leaf_hash(&leaf_bytes)
+}
+
+// same as `BinarySparseStorageTree::verify_inclusion`, but does not require a tree type
+pub fn verify_leaf_query<
+    const DEPTH: usize,
+    const INDEX_BYTES: usize,
+    const LEAF_METADATA_WIDTH: usize,
+    H: BinaryHasher<32>,
+    L: EnumeratedBinaryLeaf<32>,
+>(
+    root: &[u8; 32],
+    query: &LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L>,
+) -> bool {
+    let mut current_hash = leaf_hash::<LEAF_METADATA_WIDTH, H, L>(&query.leaf);
+    for level in 0..DEPTH {
+        let (l, r) = if is_right_side_node(&query.index, level) {
+            (&query.merkle_path[level], &current_hash)
+        } else {
+            (&current_hash, &query.merkle_path[level])
+        };
+
+        current_hash = H::node_hash(level, l, r);
+    }
+
+    root == &current_hash
+}
+
+fn set_bit(bitmask: &mut [u8], bit: usize) {
+    bitmask[bit / 8] |= 1u8 << (bit % 8);
+}
+
+fn get_bit(bitmask: &[u8], bit: usize) -> bool {
+    bitmask[bit / 8] & (1u8 << (bit % 8)) != 0
+}
+
+// Leaf query where path elements that are hashes of empty subtrees are omitted. Sparse tree
+// is mostly empty at the lower levels, so only few tens of path elements are usually present
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+#[serde(bound(
+    serialize = "L: serde::Serialize",
+    deserialize = "L: serde::de::DeserializeOwned"
+))]
+pub struct CompactLeafProof<const INDEX_BYTES: usize, L: EnumeratedBinaryLeaf<32>> {
+    pub leaf: L,
+    pub first_write: bool,
+    #[serde(serialize_with = "serialize_fixed_bytes")]
+    #[serde(deserialize_with = "deserialize_fixed_bytes")]
+    pub index: [u8; INDEX_BYTES],
+    // bit per level, set if path element is an empty subtree hash
+    pub empty_siblings_bitmask: Vec<u8>,
+    // non-empty path elements from the bottom to the top
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl<const INDEX_BYTES: usize, L: EnumeratedBinaryLeaf<32>> CompactLeafProof<INDEX_BYTES, L> {
+    pub fn from_leaf_query<const DEPTH: usize>(
+        query: &LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L>,
+        empty_hashes: &[[u8; 32]; DEPTH],
+    ) -> Self {
+        let mut empty_siblings_bitmask = vec![0u8; (DEPTH + 7) / 8];
+        let mut siblings = vec![];
+        for level in 0..DEPTH {
+            if query.merkle_path[level] == empty_hashes[level] {
+                set_bit(&mut empty_siblings_bitmask, level);
+            } else {
+                siblings.push(query.merkle_path[level]);
+            }
+        }
+
+        Self {
+            leaf: query.leaf.clone(),
+            first_write: query.first_write,
+            index: query.index,
+            empty_siblings_bitmask,
+            siblings,
+        }
+    }
+
+    // None if the bitmask does not match the number of path elements
+    pub fn into_leaf_query<const DEPTH: usize>(
+        &self,
+        empty_hashes: &[[u8; 32]; DEPTH],
+    ) -> Option<LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L>> {
+        if self.empty_siblings_bitmask.len() != (DEPTH + 7) / 8 {
+            return None;
+        }
+        let mut siblings = self.siblings.iter();
+        let mut merkle_path = Box::new([[0u8; 32]; DEPTH]);
+        for level in 0..DEPTH {
+            merkle_path[level] = if get_bit(&self.empty_siblings_bitmask, level) {
+                empty_hashes[level]
+            } else {
+                *siblings.next()?
+            };
+        }
+        if siblings.next().is_some() {
+            return None;
+        }
+
+        Some(LeafQuery {
+            leaf: self.leaf.clone(),
+            first_write: self.first_write,
+            index: self.index,
+            merkle_path,
+        })
+    }
+
+    // index || enumeration index (8 bytes BE) || value || first write (1 byte) || bitmask || siblings
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(
+            INDEX_BYTES + 8 + 32 + 1 + self.empty_siblings_bitmask.len() + 32 * self.siblings.len(),
+        );
+        result.extend_from_slice(&self.index);
+        result.extend_from_slice(&self.leaf.current_index().to_be_bytes());
+        result.extend_from_slice(self.leaf.value());
+        result.push(self.first_write as u8);
+        result.extend_from_slice(&self.empty_siblings_bitmask);
+        for el in self.siblings.iter() {
+            result.extend_from_slice(el);
+        }
+
+        result
+    }
+
+    // None if the encoding is truncated or inconsistent
+    pub fn from_bytes<const DEPTH: usize>(bytes: &[u8]) -> Option<Self> {
+        let bitmask_len = (DEPTH + 7) / 8;
+        let header_len = INDEX_BYTES + 8 + 32 + 1 + bitmask_len;
+        if bytes.len() < header_len || (bytes.len() - header_len) % 32 != 0 {
+            return None;
+        }
+
+        let (index, rest) = bytes.split_at(INDEX_BYTES);
+        let (enumeration_index, rest) = rest.split_at(8);
+        let (value, rest) = rest.split_at(32);
+        let (first_write, rest) = rest.split_at(1);
+        let (bitmask, rest) = rest.split_at(bitmask_len);
+
+        if first_write[0] > 1 {
+            return None;
+        }
+        let num_empty = (0..DEPTH).filter(|level| get_bit(bitmask, *level)).count();
+        if rest.len() / 32 != DEPTH - num_empty {
+            return None;
+        }
+
+        let mut leaf = L::from_value(value.try_into().unwrap());
+        leaf.set_index(u64::from_be_bytes(enumeration_index.try_into().unwrap()));
+
+        Some(Self {
+            leaf,
+            first_write: first_write[0] == 1,
+            index: index.try_into().unwrap(),
+            empty_siblings_bitmask: bitmask.to_vec(),
+            siblings: rest.chunks(32).map(|el| el.try_into().unwrap()).collect(),
+        })
+    }
+}
+
+pub fn verify_compact_leaf_proof<
+    const DEPTH: usize,
+    const INDEX_BYTES: usize,
+    const LEAF_METADATA_WIDTH: usize,
+    H: BinaryHasher<32>,
+    L: EnumeratedBinaryLeaf<32>,
+>(
+    root: &[u8; 32],
+    proof: &CompactLeafProof<INDEX_BYTES, L>,
+    empty_hashes: &[[u8; 32]; DEPTH],
+) -> bool {
+    match proof.into_leaf_query(empty_hashes) {
+        Some(query) => {
+            verify_leaf_query::<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>(root, &query)
+        }
+        None => false,
+    }
+}
+
+pub(crate) fn normalize_index<const N: usize>(index: &[u8; N], level: usize) -> [u8; N] {
+    let mut index = *index;
+    for bit in 0..level {
+        let word_idx = bit / 8;
+        let bit_idx = bit % 8;
+        index[word_idx] = index[word_idx] & (!(1 << bit_idx));
+    }
+
+    index
+}
+
+// Proof of inclusion of many leafs into the same root. Path elements that can be computed from
+// other proven leafs are not included, and empty subtree hashes are omitted as in `CompactLeafProof`.
+// Path elements are listed level by level from the bottom, and in the order of node indexes within a level
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Eq)]
+#[serde(bound(
+    serialize = "L: serde::Serialize",
+    deserialize = "L: serde::de::DeserializeOwned"
+))]
+pub struct MultiProof<const INDEX_BYTES: usize, L: EnumeratedBinaryLeaf<32>> {
+    // unique and sorted
+    #[serde(serialize_with = "serialize_fixed_bytes_vec")]
+    #[serde(deserialize_with = "deserialize_fixed_bytes_vec")]
+    pub indexes: Vec<[u8; INDEX_BYTES]>,
+    pub leafs: Vec<L>,
+    // bit per path element in the order of traversal, set if it is an empty subtree hash
+    pub empty_siblings_bitmask: Vec<u8>,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl<const INDEX_BYTES: usize, L: EnumeratedBinaryLeaf<32>> MultiProof<INDEX_BYTES, L> {
+    // all queries must be made against the same state of the tree (e.g. by `get_leaf`)
+    pub fn from_leaf_queries<const DEPTH: usize>(
+        queries: &[LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L>],
+        empty_hashes: &[[u8; 32]; DEPTH],
+    ) -> Self {
+        assert!(!queries.is_empty());
+        // node index -> any query that is below this node
+        let mut nodes = BTreeMap::new();
+        for (i, query) in queries.iter().enumerate() {
+            if let Some(existing) = nodes.insert(query.index, i) {
+                let existing: &LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L> = &queries[existing];
+                assert!(
+                    existing.leaf.value() == query.leaf.value()
+                        && existing.leaf.current_index() == query.leaf.current_index(),
+                    "different leafs for the same index"
+                );
+            }
+        }
+
+        let indexes: Vec<_> = nodes.keys().copied().collect();
+        let leafs: Vec<_> = nodes.values().map(|i| queries[*i].leaf.clone()).collect();
+
+        let mut num_siblings = 0;
+        let mut empty_siblings = vec![];
+        let mut siblings = vec![];
+        for level in 0..DEPTH {
+            let mut parents = BTreeMap::new();
+            for (index, query_idx) in nodes.iter() {
+                let parent_index = normalize_index(index, level + 1);
+                if parents.contains_key(&parent_index) {
+                    continue;
+                }
+                parents.insert(parent_index, *query_idx);
+
+                let neighbour_index = create_neighbour_index(index, level);
+                if nodes.contains_key(&neighbour_index) {
+                    continue;
+                }
+                let sibling = queries[*query_idx].merkle_path[level];
+                if sibling == empty_hashes[level] {
+                    empty_siblings.push(num_siblings);
+                } else {
+                    siblings.push(sibling);
+                }
+                num_siblings += 1;
+            }
+            nodes = parents;
+        }
+
+        let mut empty_siblings_bitmask = vec![0u8; (num_siblings + 7) / 8];
+        for el in empty_siblings.into_iter() {
+            set_bit(&mut empty_siblings_bitmask, el);
+        }
+
+        Self {
+            indexes,
+            leafs,
+            empty_siblings_bitmask,
+            siblings,
+        }
+    }
+}
+
+pub fn verify_multi_proof<
+    const DEPTH: usize,
+    const INDEX_BYTES: usize,
+    const LEAF_METADATA_WIDTH: usize,
+    H: BinaryHasher<32>,
+    L: EnumeratedBinaryLeaf<32>,
+>(
+    root: &[u8; 32],
+    proof: &MultiProof<INDEX_BYTES, L>,
+    empty_hashes: &[[u8; 32]; DEPTH],
+) -> bool {
+    if proof.indexes.is_empty() || proof.indexes.len() != proof.leafs.len() {
+        return false;
+    }
+
+    let mut nodes = BTreeMap::new();
+    for (index, leaf) in proof.indexes.iter().zip(proof.leafs.iter()) {
+        let leaf_hash = leaf_hash::<LEAF_METADATA_WIDTH, H, L>(leaf);
+        if nodes.insert(*index, leaf_hash).is_some() {
+            return false;
+        }
+    }
+
+    let mut sibling_idx = 0;
+    let mut siblings = proof.siblings.iter();
+    for level in 0..DEPTH {
+        let mut parents = BTreeMap::new();
+        for (index, node_hash) in nodes.iter() {
+            let parent_index = normalize_index(index, level + 1);
+            if parents.contains_key(&parent_index) {
+                continue;
+            }
+
+            let neighbour_index = create_neighbour_index(index, level);
+            let sibling = if let Some(neighbour_hash) = nodes.get(&neighbour_index) {
+                *neighbour_hash
+            } else {
+                if sibling_idx >= proof.empty_siblings_bitmask.len() * 8 {
+                    return false;
+                }
+                let is_empty = get_bit(&proof.empty_siblings_bitmask, sibling_idx);
+                sibling_idx += 1;
+                if is_empty {
+                    empty_hashes[level]
+                } else if let Some(sibling) = siblings.next() {
+                    *sibling
+                } else {
+                    return false;
+                }
+            };
+
+            let (l, r) = if is_right_side_node(index, level) {
+                (&sibling, node_hash)
+            } else {
+                (node_hash, &sibling)
+            };
+            parents.insert(parent_index, H::node_hash(level, l, r));
+        }
+        nodes = parents;
+    }
+
+    if siblings.next().is_some() || (sibling_idx + 7) / 8 != proof.empty_siblings_bitmask.len() {
+        return false;
+    }
+    assert_eq!(nodes.len(), 1);
+
+    nodes.values().next().unwrap() == root
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_proofs_serialization_and_verification() {
+        let mut tree = ZKSyncTestingTree::empty();
+        let mut indexes = vec![];
+        for i in 0..64u8 {
+            let mut index = [0u8; 32];
+            index[0] = i;
+            index[31] = i.wrapping_mul(37);
+            let _ = tree.insert_leaf(&index, ZkSyncStorageLeaf::from_value([i + 1; 32]));
+            indexes.push(index);
+        }
+        let root = tree.root;
+        let empty_hashes = tree.empty_hashes.clone();
+        assert_eq!(
+            empty_hashes,
+            empty_subtree_hashes::<256, 8, Blake2s256, ZkSyncStorageLeaf>()
+        );
+
+        // proofs for existing and empty leafs
+        let mut queried_indexes = indexes[..10].to_vec();
+        queried_indexes.push([0xffu8; 32]);
+        let queries: Vec<_> = queried_indexes
+            .iter()
+            .map(|el| ZKSyncTestingTree::get_leaf(&tree, el))
+            .collect();
+
+        for query in queries.iter() {
+            assert!(verify_leaf_query::<256, 32, 8, Blake2s256, _>(&root, query));
+
+            let encoded = serde_json::to_string(query).unwrap();
+            let decoded: LeafQuery<256, 32, 32, 32, ZkSyncStorageLeaf> =
+                serde_json::from_str(&encoded).unwrap();
+            assert_eq!(decoded.merkle_path, query.merkle_path);
+            assert_eq!(decoded.index, query.index);
+            assert!(verify_leaf_query::<256, 32, 8, Blake2s256, _>(
+                &root, &decoded
+            ));
+
+            let compact = CompactLeafProof::from_leaf_query(query, &empty_hashes);
+            assert!(compact.siblings.len() < 32);
+            let bytes = compact.to_bytes();
+            let decoded =
+                CompactLeafProof::<32, ZkSyncStorageLeaf>::from_bytes::<256>(&bytes).unwrap();
+            assert_eq!(decoded, compact);
+            let encoded = bincode::serialize(&compact).unwrap();
+            let decoded: CompactLeafProof<32, ZkSyncStorageLeaf> =
+                bincode::deserialize(&encoded).unwrap();
+            assert_eq!(decoded, compact);
+            assert!(verify_compact_leaf_proof::<256, 32, 8, Blake2s256, _>(
+                &root,
+                &decoded,
+                &empty_hashes
+            ));
+        }
+
+        let multi_proof = MultiProof::from_leaf_queries(&queries, &empty_hashes);
+        let total_siblings: usize = queries
+            .iter()
+            .map(|el| {
+                CompactLeafProof::from_leaf_query(el, &empty_hashes)
+                    .siblings
+                    .len()
+            })
+            .sum();
+        assert!(multi_proof.siblings.len() < total_siblings);
+        let encoded = serde_json::to_string(&multi_proof).unwrap();
+        let decoded: MultiProof<32, ZkSyncStorageLeaf> = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded, multi_proof);
+        assert!(verify_multi_proof::<256, 32, 8, Blake2s256, _>(
+            &root,
+            &decoded,
+            &empty_hashes
+        ));
+
+        let mut tampered = multi_proof.clone();
+        tampered.leafs[3].value[0] ^= 1;
+        assert!(!verify_multi_proof::<256, 32, 8, Blake2s256, _>(
+            &root,
+            &tampered,
+            &empty_hashes
+        ));
+        let mut tampered = multi_proof.clone();
+        tampered.siblings.pop();
+        assert!(!verify_multi_proof::<256, 32, 8, Blake2s256, _>(
+            &root,
+            &tampered,
+            &empty_hashes
+        ));
+    }
+
+    #[test]
+    fn test_malformed_compact_proofs() {
+        let mut tree = ZKSyncTestingTree::empty();
+        for i in 0..16u8 {
+            let mut index = [0u8; 32];
+            index[0] = i;
+            let _ = tree.insert_leaf(&index, ZkSyncStorageLeaf::from_value([i + 1; 32]));
+        }
+        let root = tree.root;
+        let empty_hashes = tree.empty_hashes.clone();
+
+        let query = ZKSyncTestingTree::get_leaf(&tree, &[0u8; 32]);
+        let compact = CompactLeafProof::from_leaf_query(&query, &empty_hashes);
+        assert!(!compact.siblings.is_empty());
+        let bytes = compact.to_bytes();
+
+        // truncated encodings
+        for len in [
+            0,
+            10,
+            bytes.len() - 32 * compact.siblings.len(),
+            bytes.len() - 1,
+        ] {
+            assert!(
+                CompactLeafProof::<32, ZkSyncStorageLeaf>::from_bytes::<256>(&bytes[..len])
+                    .is_none()
+            );
+        }
+        // missing sibling
+        let truncated = &bytes[..(bytes.len() - 32)];
+        assert!(CompactLeafProof::<32, ZkSyncStorageLeaf>::from_bytes::<256>(truncated).is_none());
+        // invalid first write flag
+        let mut invalid = bytes.clone();
+        invalid[32 + 8 + 32] = 2;
+        assert!(CompactLeafProof::<32, ZkSyncStorageLeaf>::from_bytes::<256>(&invalid).is_none());
+        // bitmask claims that a non-empty sibling is empty
+        let mut invalid = bytes.clone();
+        let first_non_empty_level = (0..256)
+            .find(|level| query.merkle_path[*level] != empty_hashes[*level])
+            .unwrap();
+        invalid[32 + 8 + 32 + 1 + first_non_empty_level / 8] |= 1 << (first_non_empty_level % 8);
+        assert!(CompactLeafProof::<32, ZkSyncStorageLeaf>::from_bytes::<256>(&invalid).is_none());
+
+        // same inconsistencies in the decoded form
+        let mut inconsistent = compact.clone();
+        inconsistent.siblings.pop();
+        assert!(inconsistent.into_leaf_query(&empty_hashes).is_none());
+        assert!(!verify_compact_leaf_proof::<256, 32, 8, Blake2s256, _>(
+            &root,
+            &inconsistent,
+            &empty_hashes
+        ));
+        let mut inconsistent = compact.clone();
+        inconsistent.siblings.push([0u8; 32]);
+        assert!(!verify_compact_leaf_proof::<256, 32, 8, Blake2s256, _>(
+            &root,
+            &inconsistent,
+            &empty_hashes
+        ));
+        let mut inconsistent = compact.clone();
+        inconsistent.empty_siblings_bitmask.pop();
+        assert!(!verify_compact_leaf_proof::<256, 32, 8, Blake2s256, _>(
+            &root,
+            &inconsistent,
+            &empty_hashes
+        ));
+
+        assert!(verify_compact_leaf_proof::<256, 32, 8, Blake2s256, _>(
+            &root,
+            &compact,
+            &empty_hashes
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+use super::*;
+use crate::bellman::bn256::{Bn256, Fr};
+use crate::ff::{PrimeField, PrimeFieldRepr};
+use std::sync::OnceLock;
+use sync_vm::circuit_structures::traits::CircuitArithmeticRoundFunction;
+use sync_vm::glue::traits::GenericHasher;
+use sync_vm::rescue_poseidon::RescueParams;
+
+pub type StorageTreeRoundFunction = GenericHasher<Bn256, RescueParams<Bn256, 2, 3>, 2, 3>;
+
+// number of bytes of the leaf that are packed into a single field element
+pub const RESCUE_LEAF_CHUNK_BYTES: usize = 31;
+
+static ROUND_FUNCTION: OnceLock<StorageTreeRoundFunction> = OnceLock::new();
+
+pub(crate) fn storage_tree_round_function() -> &'static StorageTreeRoundFunction {
+    ROUND_FUNCTION.get_or_init(|| {
+        let params = sync_vm::utils::bn254_rescue_params();
+        GenericHasher::new_from_params(&params)
+    })
+}
+
+// Algebraic hasher for the storage tree that uses the same Rescue round function and parameters
+// as the queues. Every node is a single field element in the little endian form, so hashing
+// of the node is a single absorption of two elements
+#[derive(Derivative)]
+#[derivative(Clone, Copy, Debug, Default)]
+pub struct RescueHasher;
+
+pub fn fr_to_bytes(value: &Fr) -> [u8; 32] {
+    let mut result = [0u8; 32];
+    value
+        .into_repr()
+        .write_le(&mut result[..])
+        .expect("must write field element");
+
+    result
+}
+
+pub fn fr_from_bytes(bytes: &[u8; 32]) -> Fr {
+    let mut repr = <Fr as PrimeField>::Repr::default();
+    repr.read_le(&bytes[..])
+        .expect("must read field element representation");
+
+    Fr::from_repr(repr).unwrap_or_else(|_| panic!("{} is not a field element", hex::encode(bytes)))
+}
+
+fn hash_field_elements(input: &[Fr]) -> [u8; 32] {
+    let round_function = storage_tree_round_function();
+    let states =
+        round_function.simulate_absorb_multiple_rounds_into_empty_with_specialization(input);
+    let commitment =
+        StorageTreeRoundFunction::simulate_state_into_commitment(states.last().unwrap().1);
+
+    fr_to_bytes(&commitment)
+}
+
+impl BinaryHasher<32> for RescueHasher {
+    fn new() -> Self {
+        Self
+    }
+    fn node_hash(_depth: usize, left_node: &[u8; 32], right_node: &[u8; 32]) -> [u8; 32] {
+        hash_field_elements(&[fr_from_bytes(left_node), fr_from_bytes(right_node)])
+    }
+    fn leaf_hash(leaf: &[u8]) -> [u8; 32] {
+        let input: Vec<_> = leaf
+            .chunks(RESCUE_LEAF_CHUNK_BYTES)
+            .map(|chunk| {
+                let mut buffer = [0u8; 32];
+                buffer[..chunk.len()].copy_from_slice(chunk);
+                fr_from_bytes(&buffer)
+            })
+            .collect();
+
+        hash_field_elements(&input)
+    }
+}
+
+pub type ZkSyncRescueTestingTree = InMemoryStorageTree<256, 32, 8, RescueHasher, ZkSyncStorageLeaf>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rescue_hasher_tree() {
+        let leaf = ZkSyncStorageLeaf::from_value([0xffu8; 32]);
+        let mut leaf_encoding = [0u8; 40];
+        leaf_encoding[..8].copy_from_slice(&1u64.to_be_bytes());
+        leaf_encoding[8..].copy_from_slice(leaf.value());
+        let leaf_hash = RescueHasher::leaf_hash(&leaf_encoding);
+        assert_eq!(fr_to_bytes(&fr_from_bytes(&leaf_hash)), leaf_hash);
+
+        let mut tree = ZkSyncRescueTestingTree::empty();
+        let mut blake_tree = ZKSyncTestingTree::empty();
+        for i in 0..16u8 {
+            let query = tree.insert_leaf(&[i; 32], ZkSyncStorageLeaf::from_value([i; 32]));
+            assert!(ZkSyncRescueTestingTree::verify_inclusion(
+                &tree.root, &query
+            ));
+            let _ = blake_tree.insert_leaf(&[i; 32], ZkSyncStorageLeaf::from_value([i; 32]));
+        }
+        assert_ne!(tree.root, blake_tree.root);
+
+        let query = tree.get_leaf(&[3u8; 32]);
+        assert!(ZkSyncRescueTestingTree::verify_inclusion(
+            &tree.root, &query
+        ));
+        assert_eq!(query.leaf.current_index(), 4);
+
+        let imported = ZkSyncRescueTestingTree::from_dump(&tree.dump());
+        assert_eq!(imported.root, tree.root);
+    }
+}
@@ -0,0 +1,496 @@
+use super::proofs::normalize_index;
+use super::*;
+use std::collections::BTreeMap;
+
+pub type ZKSyncVersionedTree = VersionedStorageTree<256, 32, 8, Blake2s256, ZkSyncStorageLeaf>;
+
+#[derive(Derivative)]
+#[derivative(Clone, Copy, Debug)]
+pub struct TreeVersion {
+    pub root: [u8; 32],
+    pub next_enumeration_index: u64,
+}
+
+// Tree that keeps the history of every node and leaf. Node storage is copy-on-write: an update
+// does not overwrite the previous value of the node, but appends a new value tagged by the version
+// that is being written. `commit_version` closes the current version (e.g. at the end of the block),
+// and every committed version can be queried, rolled back to, or pruned.
+// Version 0 is an empty tree
+pub struct VersionedStorageTree<
+    const DEPTH: usize,
+    const INDEX_BYTES: usize,
+    const LEAF_METADATA_WIDTH: usize,
+    H: BinaryHasher<32>,
+    L: EnumeratedBinaryLeaf<32>,
+> {
+    pub hasher: H,
+    pub empty_hashes: Box<[[u8; 32]; DEPTH]>,
+    pub next_enumeration_index: u64,
+    pub root: [u8; 32],
+    // version that is being written now, it's always larger than any committed one
+    pub current_version: u64,
+    pub committed_versions: BTreeMap<u64, TreeVersion>,
+    // histories are sorted by version
+    layers: Vec<HashMap<[u8; INDEX_BYTES], Vec<(u64, [u8; 32])>>>,
+    leafs: HashMap<[u8; INDEX_BYTES], Vec<(u64, L)>>,
+    // keys of the current version
+    key_index: LeafKeyIndex<INDEX_BYTES>,
+}
+
+fn value_at_version<T>(history: &[(u64, T)], version: u64) -> Option<&T> {
+    let pos = history.partition_point(|(v, _)| *v <= version);
+    if pos == 0 {
+        None
+    } else {
+        Some(&history[pos - 1].1)
+    }
+}
+
+fn write_at_version<T>(history: &mut Vec<(u64, T)>, version: u64, value: T) {
+    if let Some((last_version, last_value)) = history.last_mut() {
+        debug_assert!(*last_version <= version);
+        if *last_version == version {
+            *last_value = value;
+            return;
+        }
+    }
+    history.push((version, value));
+}
+
+fn truncate_after_version<T>(history: &mut Vec<(u64, T)>, version: u64) {
+    let pos = history.partition_point(|(v, _)| *v <= version);
+    history.truncate(pos);
+}
+
+fn prune_before_version<T>(history: &mut Vec<(u64, T)>, version: u64) {
+    // keep the latest value that is visible at `version`
+    let pos = history.partition_point(|(v, _)| *v <= version);
+    if pos > 1 {
+        history.drain(..(pos - 1));
+    }
+}
+
+impl<
+        const DEPTH: usize,
+        const INDEX_BYTES: usize,
+        const LEAF_METADATA_WIDTH: usize,
+        H: BinaryHasher<32>,
+        L: EnumeratedBinaryLeaf<32>,
+    > VersionedStorageTree<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>
+{
+    pub fn new() -> Self {
+        // empty hashes and root are the same as for in-memory tree
+        let InMemoryStorageTree::<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L> {
+            hasher,
+            next_enumeration_index,
+            empty_hashes,
+            root,
+            ..
+        } = InMemoryStorageTree::new();
+
+        let mut committed_versions = BTreeMap::new();
+        committed_versions.insert(
+            0,
+            TreeVersion {
+                root,
+                next_enumeration_index,
+            },
+        );
+
+        Self {
+            hasher,
+            empty_hashes,
+            next_enumeration_index,
+            root,
+            current_version: 1,
+            committed_versions,
+            layers: vec![HashMap::new(); DEPTH],
+            leafs: HashMap::new(),
+            key_index: LeafKeyIndex::new(),
+        }
+    }
+
+    fn get_path_element_at(
+        &self,
+        version: u64,
+        level: usize,
+        index: [u8; INDEX_BYTES],
+    ) -> [u8; 32] {
+        let index = normalize_index(&index, level);
+        self.layers[level]
+            .get(&index)
+            .and_then(|el| value_at_version(el, version))
+            .copied()
+            .unwrap_or(self.empty_hashes[level])
+    }
+
+    fn get_leaf_value_at(&self, version: u64, index: &[u8; INDEX_BYTES]) -> Option<L> {
+        self.leafs
+            .get(index)
+            .and_then(|el| value_at_version(el, version))
+            .cloned()
+    }
+
+    fn query_at(
+        &self,
+        version: u64,
+        index: &[u8; INDEX_BYTES],
+    ) -> LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L> {
+        let leaf = self
+            .get_leaf_value_at(version, index)
+            .unwrap_or_else(L::empty);
+
+        let mut path: Box<[[u8; 32]; DEPTH]> = Box::new([[0u8; 32]; DEPTH]);
+        for level in 0..DEPTH {
+            let pair_idx = create_neighbour_index(index, level);
+            path[level] = self.get_path_element_at(version, level, pair_idx);
+        }
+
+        LeafQuery {
+            leaf,
+            first_write: false,
+            index: *index,
+            merkle_path: path,
+        }
+    }
+
+    fn assert_committed(&self, version: u64) -> &TreeVersion {
+        self.committed_versions
+            .get(&version)
+            .unwrap_or_else(|| panic!("version {} is not committed or was pruned", version))
+    }
+
+    // closes the version that is being written, and returns it's ID
+    pub fn commit_version(&mut self) -> u64 {
+        let version = self.current_version;
+        self.committed_versions.insert(
+            version,
+            TreeVersion {
+                root: self.root,
+                next_enumeration_index: self.next_enumeration_index,
+            },
+        );
+        self.current_version += 1;
+
+        version
+    }
+
+    pub fn latest_committed_version(&self) -> u64 {
+        *self.committed_versions.keys().last().unwrap()
+    }
+
+    pub fn root_at(&self, version: u64) -> [u8; 32] {
+        self.assert_committed(version).root
+    }
+
+    pub fn next_enumeration_index_at(&self, version: u64) -> u64 {
+        self.assert_committed(version).next_enumeration_index
+    }
+
+    pub fn get_leaf_at(
+        &self,
+        version: u64,
+        index: &[u8; INDEX_BYTES],
+    ) -> LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L> {
+        let _ = self.assert_committed(version);
+        self.query_at(version, index)
+    }
+
+    // drops all the versions after the given one, as well as uncommitted changes
+    pub fn rollback_to(&mut self, version: u64) {
+        let TreeVersion {
+            root,
+            next_enumeration_index,
+        } = *self.assert_committed(version);
+
+        for layer in self.layers.iter_mut() {
+            layer.retain(|_, history| {
+                truncate_after_version(history, version);
+                !history.is_empty()
+            });
+        }
+        self.leafs.retain(|_, history| {
+            truncate_after_version(history, version);
+            !history.is_empty()
+        });
+
+        self.key_index.truncate(next_enumeration_index);
+
+        let _ = self.committed_versions.split_off(&(version + 1));
+        self.root = root;
+        self.next_enumeration_index = next_enumeration_index;
+        self.current_version = version + 1;
+    }
+
+    // removes history of all the versions before the given one. Those can not be queried
+    // or rolled back to anymore
+    pub fn prune(&mut self, oldest_version_to_keep: u64) {
+        let _ = self.assert_committed(oldest_version_to_keep);
+
+        for layer in self.layers.iter_mut() {
+            for history in layer.values_mut() {
+                prune_before_version(history, oldest_version_to_keep);
+            }
+        }
+        for history in self.leafs.values_mut() {
+            prune_before_version(history, oldest_version_to_keep);
+        }
+
+        self.committed_versions = self.committed_versions.split_off(&oldest_version_to_keep);
+    }
+
+    fn filter_renumerate<'a>(
+        &self,
+        mut indexes: impl Iterator<Item = &'a [u8; INDEX_BYTES]>,
+        mut leafs: impl Iterator<Item = L>,
+    ) -> (u64, Vec<([u8; INDEX_BYTES], L)>, Vec<L>) {
+        let mut first_writes = vec![];
+        let mut updates = vec![];
+        let mut next_index = self.next_enumeration_index;
+        for (idx, leaf) in (&mut indexes).zip(&mut leafs) {
+            let mut leaf = leaf;
+            if let Some(existing) = self.get_leaf_value_at(self.current_version, idx) {
+                leaf.set_index(existing.current_index());
+                updates.push(leaf);
+            } else {
+                leaf.set_index(next_index);
+                next_index += 1;
+                first_writes.push((*idx, leaf));
+            }
+        }
+
+        assert!(indexes.next().is_none());
+        assert!(leafs.next().is_none());
+
+        (next_index, first_writes, updates)
+    }
+
+    fn insert_leaf(
+        &mut self,
+        index: &[u8; INDEX_BYTES],
+        leaf: L,
+    ) -> LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L> {
+        let version = self.current_version;
+
+        let mut first_write = false;
+        let leaf = if let Some(mut existing_leaf) = self.get_leaf_value_at(version, index) {
+            existing_leaf.set_value(leaf.value());
+            existing_leaf
+        } else {
+            // enumerate
+            let mut leaf = leaf;
+            first_write = true;
+            leaf.set_index(self.next_enumeration_index);
+            self.key_index.insert(index, self.next_enumeration_index);
+            self.next_enumeration_index += 1;
+            leaf
+        };
+        write_at_version(self.leafs.entry(*index).or_default(), version, leaf.clone());
+
+        // now recompute the path
+        let mut leaf_bytes = vec![0u8; LEAF_METADATA_WIDTH + 32];
+        leaf_bytes[LEAF_METADATA_WIDTH..].copy_from_slice(leaf.value());
+
+        let leaf_index_bytes = leaf.current_index().to_be_bytes();
+        leaf_bytes[(LEAF_METADATA_WIDTH - 8)..LEAF_METADATA_WIDTH]
+            .copy_from_slice(&leaf_index_bytes);
+
+        let leaf_hash = H::leaf_hash(&leaf_bytes);
+
+        let mut current_hash = leaf_hash;
+        let mut path: Box<[[u8; 32]; DEPTH]> = Box::new([[0u8; 32]; DEPTH]);
+        for level in 0..DEPTH {
+            write_at_version(
+                self.layers[level]
+                    .entry(normalize_index(index, level))
+                    .or_default(),
+                version,
+                current_hash,
+            );
+            let pair_idx = create_neighbour_index(index, level);
+            let pair_node_hash = self.get_path_element_at(version, level, pair_idx);
+
+            path[level] = pair_node_hash;
+
+            let (l, r) = if is_right_side_node(index, level) {
+                (&pair_node_hash, &current_hash)
+            } else {
+                (&current_hash, &pair_node_hash)
+            };
+
+            let parent_node_hash = H::node_hash(level, l, r);
+            current_hash = parent_node_hash;
+        }
+
+        self.root = current_hash;
+
+        LeafQuery {
+            leaf,
+            first_write,
+            index: *index,
+            merkle_path: path,
+        }
+    }
+}
+
+impl<
+        const DEPTH: usize,
+        const INDEX_BYTES: usize,
+        const LEAF_METADATA_WIDTH: usize,
+        H: BinaryHasher<32>,
+        L: EnumeratedBinaryLeaf<32>,
+    > BinarySparseStorageTree<DEPTH, INDEX_BYTES, 32, LEAF_METADATA_WIDTH, 32, H, L>
+    for VersionedStorageTree<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>
+{
+    fn empty() -> Self {
+        Self::new()
+    }
+    fn next_enumeration_index(&self) -> u64 {
+        self.next_enumeration_index
+    }
+    fn set_next_enumeration_index(&mut self, value: u64) {
+        self.next_enumeration_index = value;
+    }
+    fn root(&self) -> [u8; 32] {
+        self.root
+    }
+    fn get_leaf(&mut self, index: &[u8; INDEX_BYTES]) -> LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L> {
+        self.query_at(self.current_version, index)
+    }
+    fn insert_leaf(
+        &mut self,
+        index: &[u8; INDEX_BYTES],
+        leaf: L,
+    ) -> LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L> {
+        Self::insert_leaf(self, index, leaf)
+    }
+    fn filter_renumerate<'a>(
+        &self,
+        indexes: impl Iterator<Item = &'a [u8; INDEX_BYTES]>,
+        leafs: impl Iterator<Item = L>,
+    ) -> (u64, Vec<([u8; INDEX_BYTES], L)>, Vec<L>) {
+        Self::filter_renumerate(&self, indexes, leafs)
+    }
+    fn verify_inclusion(root: &[u8; 32], query: &LeafQuery<DEPTH, INDEX_BYTES, 32, 32, L>) -> bool {
+        InMemoryStorageTree::<DEPTH, INDEX_BYTES, LEAF_METADATA_WIDTH, H, L>::verify_inclusion(
+            root, query,
+        )
+    }
+    fn leaf_by_enumeration_index(&self, enumeration_index: u64) -> Option<([u8; INDEX_BYTES], L)> {
+        let key = self.key_index.key_by_enumeration_index(enumeration_index)?;
+        let leaf = self.get_leaf_value_at(self.current_version, key).unwrap();
+
+        Some((*key, leaf))
+    }
+    fn leafs_in_range(
+        &self,
+        range: impl std::ops::RangeBounds<[u8; INDEX_BYTES]>,
+    ) -> Vec<([u8; INDEX_BYTES], L)> {
+        self.key_index
+            .keys_in_range(range)
+            .map(|key| {
+                (
+                    *key,
+                    self.get_leaf_value_at(self.current_version, key).unwrap(),
+                )
+            })
+            .collect()
+    }
+    fn num_occupied_leafs(&self) -> usize {
+        self.key_index.len()
+    }
+}
+
+impl<
+        const DEPTH: usize,
+        const LEAF_METADATA_WIDTH: usize,
+        H: BinaryHasher<32>,
+        L: EnumeratedBinaryLeaf<32>,
+    > VersionedStorageTree<DEPTH, 32, LEAF_METADATA_WIDTH, H, L>
+{
+    // writes that transform the tree at `from_version` into the tree at `to_version`
+    pub fn state_diff_between(&self, from_version: u64, to_version: u64) -> StateDiff {
+        assert!(from_version <= to_version);
+        let _ = self.assert_committed(from_version);
+        let _ = self.assert_committed(to_version);
+
+        let changes = self.leafs.iter().filter_map(|(index, history)| {
+            let current = value_at_version(history, to_version)?;
+            let previous = value_at_version(history, from_version);
+
+            Some((*index, previous.cloned(), current.clone()))
+        });
+
+        super::dump::state_diff_from_changes(changes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_versioned_tree_history() {
+        let mut tree = ZKSyncVersionedTree::empty();
+        let mut reference = ZKSyncTestingTree::empty();
+        let empty_root = tree.root();
+
+        // first block
+        for i in 0..4u8 {
+            let leaf = ZkSyncStorageLeaf::from_value([i + 1; 32]);
+            tree.insert_leaf(&[i; 32], leaf);
+            reference.insert_leaf(&[i; 32], leaf);
+        }
+        assert_eq!(tree.root(), reference.root());
+        let first = tree.commit_version();
+        let first_root = tree.root();
+
+        // second block overwrites and adds
+        for i in 2..6u8 {
+            let leaf = ZkSyncStorageLeaf::from_value([i + 100; 32]);
+            tree.insert_leaf(&[i; 32], leaf);
+            reference.insert_leaf(&[i; 32], leaf);
+        }
+        assert_eq!(tree.root(), reference.root());
+        let second = tree.commit_version();
+        let second_root = tree.root();
+
+        assert_eq!(tree.root_at(0), empty_root);
+        assert_eq!(tree.root_at(first), first_root);
+        assert_eq!(tree.root_at(second), second_root);
+
+        let query = tree.get_leaf_at(first, &[3u8; 32]);
+        assert_eq!(query.leaf.value(), &[4u8; 32]);
+        assert!(ZKSyncVersionedTree::verify_inclusion(&first_root, &query));
+        let query = tree.get_leaf_at(first, &[5u8; 32]);
+        assert_eq!(query.leaf.value(), &[0u8; 32]);
+        assert!(ZKSyncVersionedTree::verify_inclusion(&first_root, &query));
+
+        let query = tree.get_leaf_at(second, &[3u8; 32]);
+        assert_eq!(query.leaf.value(), &[103u8; 32]);
+        assert!(ZKSyncVersionedTree::verify_inclusion(&second_root, &query));
+
+        let diff = tree.state_diff_between(first, second);
+        assert_eq!(diff.initial_writes.len(), 2);
+        assert_eq!(diff.initial_writes[0].key, [4u8; 32]);
+        assert_eq!(diff.initial_writes[1].key, [5u8; 32]);
+        assert_eq!(diff.repeated_writes.len(), 2);
+        assert_eq!(diff.repeated_writes[0].index, 3);
+        assert_eq!(diff.repeated_writes[1].value, [103u8; 32]);
+
+        // uncommitted changes and the second block are dropped
+        tree.insert_leaf(&[10u8; 32], ZkSyncStorageLeaf::from_value([1u8; 32]));
+        tree.rollback_to(first);
+        assert_eq!(tree.root(), first_root);
+        assert_eq!(tree.next_enumeration_index(), 5);
+        let query = tree.get_leaf(&[3u8; 32]);
+        assert_eq!(query.leaf.value(), &[4u8; 32]);
+        assert!(ZKSyncVersionedTree::verify_inclusion(&first_root, &query));
+
+        // history before the first version is not needed anymore
+        tree.prune(first);
+        assert!(tree.committed_versions.get(&0).is_none());
+        let query = tree.get_leaf_at(first, &[0u8; 32]);
+        assert!(ZKSyncVersionedTree::verify_inclusion(&first_root, &query));
+    }
+}
@@ -0,0 +1,434 @@
+use crate::pairing::Engine;
+use crate::toolset::GeometryConfig;
+use crate::witness::full_block_artifact::FullBlockArtifacts;
+use crate::witness::storage_access_report::{
+    INITIAL_WRITE_PUBDATA_BYTES, REPEATED_WRITE_PUBDATA_BYTES,
+};
+use crate::witness::tree::{
+    BinaryHasher, BinarySparseStorageTree, EnumeratedBinaryLeaf, ZkSyncStorageLeaf,
+};
+use derivative::Derivative;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::ops::Range;
+use zk_evm::aux_structures::LogQuery;
+use zk_evm::ethereum_types::{Address, U256};
+
+// serialization width of the L1 message in the pubdata
+pub const L1_MESSAGE_PUBDATA_BYTES: usize = 88;
+
+// splits the cycles of the block into the ranges of the transactions, from the points
+// where the transaction number has changed as observed by the tracer
+pub fn tx_cycle_ranges(tx_number_changes: &[(u32, u16)], end_cycle: u32) -> Vec<(u16, Range<u32>)> {
+    let mut result = Vec::with_capacity(tx_number_changes.len());
+    for (idx, (start, tx_number)) in tx_number_changes.iter().enumerate() {
+        let end = tx_number_changes
+            .get(idx + 1)
+            .map(|el| el.0)
+            .unwrap_or(end_cycle);
+        assert!(
+            *start <= end,
+            "transaction number changes must be ordered by cycle"
+        );
+        result.push((*tx_number, *start..end));
+    }
+
+    result
+}
+
+// anything before the first transaction (e.g. non-deterministic heap writes) is attributed to it
+fn tx_number_at_cycle(tx_cycle_ranges: &[(u16, Range<u32>)], cycle: u32) -> u16 {
+    let idx = tx_cycle_ranges.partition_point(|(_, range)| range.end <= cycle);
+    tx_cycle_ranges
+        .get(idx.min(tx_cycle_ranges.len().saturating_sub(1)))
+        .map(|el| el.0)
+        .unwrap_or(0)
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq)]
+pub struct MarginalCircuitUsage {
+    pub circuit: String,
+    pub units: u64,
+    pub capacity: u32,
+    // fraction of the circuit instance used by the transaction
+    pub circuits: f64,
+}
+
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq, Default)]
+pub struct TxResourceUsage {
+    pub tx_number: u16,
+    pub cycles: u32,
+    pub memory_queries: u64,
+    pub decommittments: u64,
+    pub decommitted_words: u64,
+    pub keccak_rounds: u64,
+    pub sha256_rounds: u64,
+    pub ecrecover_calls: u64,
+    // all queries of the log queue, including rollbacks
+    pub log_queries: u64,
+    pub storage_queries: u64,
+    pub storage_writes: u64,
+    pub events: u64,
+    pub l1_messages: u64,
+    // net writes of the block to the slots that were last written by this transaction
+    pub initial_writes: u64,
+    pub repeated_writes: u64,
+    pub protective_reads: u64,
+    pub pubdata_bytes: u64,
+    pub marginal_circuits: Vec<MarginalCircuitUsage>,
+}
+
+impl TxResourceUsage {
+    fn new(tx_number: u16) -> Self {
+        Self {
+            tx_number,
+            ..Self::default()
+        }
+    }
+
+    fn compute_marginal_circuits(&mut self, geometry: &GeometryConfig) {
+        // precompile memory accesses are not attributed, so RAM permutation is slightly underestimated
+        let usage = [
+            (
+                "Main VM",
+                self.cycles as u64,
+                geometry.cycles_per_vm_snapshot,
+            ),
+            (
+                "Decommitts sorter",
+                self.decommittments,
+                geometry.cycles_per_code_decommitter_sorter,
+            ),
+            (
+                "Code decommitter",
+                (self.decommitted_words + 1) / 2,
+                geometry.cycles_per_code_decommitter,
+            ),
+            (
+                "Log demuxer",
+                self.log_queries,
+                geometry.cycles_per_log_demuxer,
+            ),
+            (
+                "Keccak",
+                self.keccak_rounds,
+                geometry.cycles_per_keccak256_circuit,
+            ),
+            (
+                "SHA256",
+                self.sha256_rounds,
+                geometry.cycles_per_sha256_circuit,
+            ),
+            (
+                "ECRecover",
+                self.ecrecover_calls,
+                geometry.cycles_per_ecrecover_circuit,
+            ),
+            (
+                "RAM permutation",
+                self.memory_queries + self.decommitted_words,
+                geometry.cycles_per_ram_permutation,
+            ),
+            (
+                "Storage sorter",
+                self.storage_queries,
+                geometry.cycles_per_storage_sorter,
+            ),
+            (
+                "Storage application",
+                self.initial_writes + self.repeated_writes + self.protective_reads,
+                geometry.cycles_per_storage_application,
+            ),
+            (
+                "Events sorter",
+                self.events,
+                geometry.cycles_per_events_or_l1_messages_sorter,
+            ),
+            (
+                "L1 messages sorter",
+                self.l1_messages,
+                geometry.cycles_per_events_or_l1_messages_sorter,
+            ),
+            (
+                "L1 messages merklizer",
+                self.l1_messages,
+                geometry.limit_for_l1_messages_merklizer,
+            ),
+            (
+                "Initial writes pubdata rehasher",
+                self.initial_writes,
+                geometry.limit_for_initial_writes_pubdata_hasher,
+            ),
+            (
+                "Repeated writes pubdata rehasher",
+                self.repeated_writes,
+                geometry.limit_for_repeated_writes_pubdata_hasher,
+            ),
+            (
+                "L1 messages rehasher",
+                self.l1_messages,
+                geometry.limit_for_l1_messages_pudata_hasher,
+            ),
+        ];
+
+        self.marginal_circuits = usage
+            .into_iter()
+            .map(|(circuit, units, capacity)| {
+                assert!(capacity > 0, "capacity of {} circuit is zero", circuit);
+                MarginalCircuitUsage {
+                    circuit: circuit.to_owned(),
+                    units,
+                    capacity,
+                    circuits: units as f64 / capacity as f64,
+                }
+            })
+            .collect();
+    }
+}
+
+// Resources used by every transaction of the block. Anything that has a cycle is attributed by
+// the transaction number of the VM at that cycle, log queries carry the transaction number
+// themselves. Net storage writes of the block are attributed to the transaction that made
+// the last write that was not rolled back
+#[derive(Derivative, serde::Serialize, serde::Deserialize)]
+#[derivative(Clone, Debug, PartialEq)]
+pub struct TxAttributionReport {
+    pub transactions: Vec<TxResourceUsage>,
+}
+
+fn usage_of_tx(
+    transactions: &mut BTreeMap<u16, TxResourceUsage>,
+    tx_number: u16,
+) -> &mut TxResourceUsage {
+    transactions
+        .entry(tx_number)
+        .or_insert_with(|| TxResourceUsage::new(tx_number))
+}
+
+impl TxAttributionReport {
+    // `previous_block_tree` is the state before the block, to split writes into initial and repeated ones
+    pub fn from_block_artifacts<E: Engine, H: BinaryHasher<32>>(
+        artifacts: &FullBlockArtifacts<E>,
+        geometry: &GeometryConfig,
+        previous_block_tree: &impl BinarySparseStorageTree<256, 32, 32, 8, 32, H, ZkSyncStorageLeaf>,
+    ) -> Self {
+        assert!(artifacts.is_processed, "artifacts must be processed");
+
+        let ranges = &artifacts.tx_cycle_ranges;
+        let mut transactions = BTreeMap::new();
+        for (tx_number, range) in ranges.iter() {
+            usage_of_tx(&mut transactions, *tx_number).cycles += range.end - range.start;
+        }
+
+        for (cycle, _) in artifacts.vm_memory_queries_accumulated.iter() {
+            usage_of_tx(&mut transactions, tx_number_at_cycle(ranges, *cycle)).memory_queries += 1;
+        }
+        for (cycle, query, _) in artifacts.all_decommittment_queries.iter() {
+            let usage = usage_of_tx(&mut transactions, tx_number_at_cycle(ranges, *cycle));
+            usage.decommittments += 1;
+            if query.is_fresh {
+                usage.decommitted_words += query.decommitted_length as u64;
+            }
+        }
+        for (cycle, _, rounds) in artifacts.keccak_round_function_witnesses.iter() {
+            usage_of_tx(&mut transactions, tx_number_at_cycle(ranges, *cycle)).keccak_rounds +=
+                rounds.len() as u64;
+        }
+        for (cycle, _, rounds) in artifacts.sha256_round_function_witnesses.iter() {
+            usage_of_tx(&mut transactions, tx_number_at_cycle(ranges, *cycle)).sha256_rounds +=
+                rounds.len() as u64;
+        }
+        for (cycle, _, _) in artifacts.ecrecover_witnesses.iter() {
+            usage_of_tx(&mut transactions, tx_number_at_cycle(ranges, *cycle)).ecrecover_calls += 1;
+        }
+
+        for (_, query) in artifacts.original_log_queue.iter() {
+            usage_of_tx(&mut transactions, query.tx_number_in_block).log_queries += 1;
+        }
+        for query in artifacts
+            .demuxed_rollup_storage_queries
+            .iter()
+            .chain(artifacts.demuxed_porter_storage_queries.iter())
+        {
+            let usage = usage_of_tx(&mut transactions, query.tx_number_in_block);
+            usage.storage_queries += 1;
+            if query.rw_flag && !query.rollback {
+                usage.storage_writes += 1;
+            }
+        }
+        for query in artifacts.demuxed_event_queries.iter() {
+            usage_of_tx(&mut transactions, query.tx_number_in_block).events += 1;
+        }
+        for query in artifacts.deduplicated_to_l1_queries.iter() {
+            let usage = usage_of_tx(&mut transactions, query.tx_number_in_block);
+            usage.l1_messages += 1;
+            usage.pubdata_bytes += L1_MESSAGE_PUBDATA_BYTES as u64;
+        }
+
+        let last_writers = last_storage_writers(&artifacts.demuxed_rollup_storage_queries);
+        let net_writes: Vec<_> = artifacts
+            .deduplicated_rollup_storage_queries
+            .iter()
+            .filter(|el| el.rw_flag)
+            .collect();
+        let net_write_keys: Vec<_> = net_writes
+            .iter()
+            .map(|el| el.derive_final_address())
+            .collect();
+        let (_, first_writes, _) = previous_block_tree.filter_renumerate(
+            net_write_keys.iter(),
+            net_writes.iter().map(|_| ZkSyncStorageLeaf::empty()),
+        );
+        let initial_keys: HashSet<_> = first_writes.into_iter().map(|(key, _)| key).collect();
+
+        for el in artifacts.deduplicated_rollup_storage_queries.iter() {
+            let tx_number = last_writers
+                .get(&(el.address, el.key))
+                .copied()
+                .unwrap_or(el.tx_number_in_block);
+            let usage = usage_of_tx(&mut transactions, tx_number);
+            if !el.rw_flag {
+                usage.protective_reads += 1;
+            } else if initial_keys.contains(&el.derive_final_address()) {
+                usage.initial_writes += 1;
+                usage.pubdata_bytes += INITIAL_WRITE_PUBDATA_BYTES as u64;
+            } else {
+                usage.repeated_writes += 1;
+                usage.pubdata_bytes += REPEATED_WRITE_PUBDATA_BYTES as u64;
+            }
+        }
+
+        let mut transactions: Vec<_> = transactions.into_values().collect();
+        for el in transactions.iter_mut() {
+            el.compute_marginal_circuits(geometry);
+        }
+
+        Self { transactions }
+    }
+
+    pub fn write_json<W: Write>(&self, dst: W) {
+        serde_json::to_writer_pretty(dst, self).expect("must serialize the attribution report");
+    }
+}
+
+// transaction number of the last write to every slot that was not rolled back. Rollbacks
+// are applied to the writes of the same slot in the reverse order
+fn last_storage_writers(storage_queries: &[LogQuery]) -> HashMap<(Address, U256), u16> {
+    let mut writes_stacks: HashMap<(Address, U256), Vec<u16>> = HashMap::new();
+    for el in storage_queries.iter().filter(|el| el.rw_flag) {
+        let stack = writes_stacks.entry((el.address, el.key)).or_default();
+        if el.rollback {
+            let _ = stack.pop().expect("rollback must follow the write");
+        } else {
+            stack.push(el.tx_number_in_block);
+        }
+    }
+
+    writes_stacks
+        .into_iter()
+        .filter_map(|(slot, stack)| stack.last().map(|el| (slot, *el)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bellman::bn256::Bn256;
+    use crate::witness::sort_storage_access::sort_storage_access_queries;
+    use crate::witness::tree::ZKSyncTestingTree;
+    use zk_evm::aux_structures::*;
+    use zk_evm::zkevm_opcode_defs::system_params::STORAGE_AUX_BYTE;
+
+    fn storage_write(tx_number: u16, key: u64, value: u64, rollback: bool) -> LogQuery {
+        LogQuery {
+            timestamp: Timestamp(0),
+            tx_number_in_block: tx_number,
+            aux_byte: STORAGE_AUX_BYTE,
+            shard_id: 0,
+            address: Address::from_low_u64_be(0x10000),
+            key: U256::from(key),
+            read_value: U256::from(value - 1),
+            written_value: U256::from(value),
+            rw_flag: true,
+            rollback,
+            is_service: false,
+        }
+    }
+
+    fn memory_query() -> MemoryQuery {
+        MemoryQuery {
+            timestamp: Timestamp(0),
+            location: MemoryLocation {
+                memory_type: MemoryType::Heap,
+                page: MemoryPage(0),
+                index: MemoryIndex(0),
+            },
+            rw_flag: false,
+            is_pended: false,
+            value: U256::zero(),
+            value_is_pointer: false,
+        }
+    }
+
+    #[test]
+    fn test_tx_attribution() {
+        let ranges = tx_cycle_ranges(&[(0, 0), (100, 1), (250, 2)], 300);
+        assert_eq!(ranges, vec![(0, 0..100), (1, 100..250), (2, 250..300)]);
+        assert_eq!(tx_number_at_cycle(&ranges, 99), 0);
+        assert_eq!(tx_number_at_cycle(&ranges, 100), 1);
+        assert_eq!(tx_number_at_cycle(&ranges, 1000), 2);
+
+        let queries = vec![
+            storage_write(0, 1, 1, false),
+            // second write to the same slot in the next transaction
+            storage_write(1, 1, 2, false),
+            // write that is rolled back, so slot is attributed to the first transaction
+            storage_write(0, 2, 1, false),
+            storage_write(2, 2, 2, false),
+            storage_write(2, 2, 2, true),
+        ];
+
+        let mut artifacts = FullBlockArtifacts::<Bn256>::default();
+        artifacts.is_processed = true;
+        artifacts.tx_cycle_ranges = ranges;
+        artifacts.vm_memory_queries_accumulated =
+            (0..300).map(|cycle| (cycle, memory_query())).collect();
+        artifacts.demuxed_rollup_storage_queries = queries.clone();
+        let (_, deduplicated) = sort_storage_access_queries(queries.iter());
+        artifacts.deduplicated_rollup_storage_queries = deduplicated;
+
+        let mut tree = ZKSyncTestingTree::empty();
+        let _ = tree.insert_leaf(
+            &queries[2].derive_final_address(),
+            ZkSyncStorageLeaf::from_value([0u8; 32]),
+        );
+
+        let geometry = crate::geometry_config::get_geometry_config();
+        let report = TxAttributionReport::from_block_artifacts(&artifacts, &geometry, &tree);
+        let transactions = &report.transactions;
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(
+            transactions.iter().map(|el| el.cycles).collect::<Vec<_>>(),
+            vec![100, 150, 50]
+        );
+        assert_eq!(transactions[1].memory_queries, 150);
+        assert_eq!(transactions[0].storage_writes, 2);
+        assert_eq!(transactions[0].repeated_writes, 1);
+        assert_eq!(transactions[1].initial_writes, 1);
+        assert_eq!(transactions[2].storage_queries, 2);
+        assert_eq!(transactions[2].pubdata_bytes, 0);
+        assert_eq!(
+            transactions[1].pubdata_bytes,
+            INITIAL_WRITE_PUBDATA_BYTES as u64
+        );
+
+        let main_vm = &transactions[2].marginal_circuits[0];
+        assert_eq!(main_vm.units, 50);
+        assert_eq!(
+            main_vm.circuits,
+            50.0 / geometry.cycles_per_vm_snapshot as f64
+        );
+    }
+}